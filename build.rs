@@ -0,0 +1,9 @@
+fn main() {
+    // The gRPC code is only generated when the "grpc" feature is enabled, so the default build
+    // doesn't need protoc at all
+    #[cfg(feature = "grpc")]
+    {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+        tonic_prost_build::compile_protos("proto/analyzer.proto").unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    process::Command,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::analysis::{Band, FftFrame, Summary};
+
+/**
+ * A rule like "alert if 2-4 kHz stays above -10 dB for 3 seconds", written as
+ * "[name=]low-high:threshold_db[:seconds]" (e.g. "hiss=2000-4000:-10:3"). Without a name the
+ * range is its name, and without seconds it fires as soon as the level crosses the threshold.
+ */
+#[derive(Clone, Debug)]
+pub struct AlarmRule {
+    pub name: String,
+    pub band: Band,
+    pub threshold_db: f32,
+    pub hold: Duration,
+}
+
+impl FromStr for AlarmRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, rule) = match s.split_once('=') {
+            Some((name, rule)) => (Some(name.trim()), rule),
+            None => (None, s),
+        };
+        let mut parts = rule.split(':');
+        let band_text = parts.next().unwrap_or_default().trim();
+        let band: Band = band_text.parse()?;
+        let threshold_db: f32 = parts
+            .next()
+            .ok_or_else(|| {
+                format!("Invalid alarm \"{s}\", expected something like 2000-4000:-10:3")
+            })?
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid alarm threshold in \"{s}\""))?;
+        let hold_secs: f32 = match parts.next() {
+            Some(seconds) => seconds
+                .trim()
+                .parse()
+                .ok()
+                .filter(|seconds: &f32| *seconds >= 0.0)
+                .ok_or_else(|| format!("Invalid alarm duration in \"{s}\""))?,
+            None => 0.0,
+        };
+        if parts.next().is_some() {
+            return Err(format!("Invalid alarm \"{s}\", too many fields"));
+        }
+
+        Ok(Self {
+            name: name.unwrap_or(band_text).into(),
+            band,
+            threshold_db,
+            hold: Duration::from_secs_f32(hold_secs),
+        })
+    }
+}
+
+/**
+ * A plain http:// URL the alarms are POSTed to as JSON
+ */
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    // host:port, the port defaulting to 80
+    address: String,
+    host: String,
+    path: String,
+}
+
+impl FromStr for Webhook {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("Invalid webhook \"{s}\", only http:// URLs are supported"))?;
+        let (host, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if host.is_empty() {
+            return Err(format!("Invalid webhook \"{s}\", the host is missing"));
+        }
+        let address = match host.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => host.into(),
+            _ => format!("{host}:80"),
+        };
+
+        Ok(Self {
+            address,
+            host: host.into(),
+            path: path.into(),
+        })
+    }
+}
+
+impl Webhook {
+    fn post(&self, body: &str) -> std::io::Result<()> {
+        let mut stream = TcpStream::connect(&self.address)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+            Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            self.path,
+            self.host,
+            body.len()
+        )?;
+
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line)?;
+        match status_line.split_whitespace().nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            _ => Err(std::io::Error::other(format!(
+                "the webhook answered \"{}\"",
+                status_line.trim()
+            ))),
+        }
+    }
+}
+
+pub struct AlarmConfig {
+    pub rules: Vec<AlarmRule>,
+    // Rings the terminal bell when an alarm fires
+    pub bell: bool,
+    // Shell command run when an alarm fires, with the details in ALARM_* variables
+    pub command: Option<String>,
+    pub webhook: Option<Webhook>,
+    pub sample_rate: u32,
+}
+
+/**
+ * The alarms currently firing, shared with the window so it can highlight their bands
+ */
+pub struct AlarmHandle {
+    rules: Vec<AlarmRule>,
+    firing: Mutex<Vec<bool>>,
+}
+
+impl AlarmHandle {
+    pub fn firing(&self) -> Vec<AlarmRule> {
+        self.firing
+            .lock()
+            .unwrap()
+            .iter()
+            .zip(&self.rules)
+            .filter(|(firing, _)| **firing)
+            .map(|(_, rule)| rule.clone())
+            .collect()
+    }
+}
+
+/**
+ * Spawns a thread that checks the level of every alarm band on each new FFT result. An alarm
+ * fires once its band stayed above the threshold for the whole hold time, and fires again only
+ * after the level went back below it.
+ */
+pub fn spawn(
+    config: AlarmConfig,
+    fft_transform: Arc<Mutex<FftFrame>>,
+) -> (JoinHandle<()>, Arc<AlarmHandle>) {
+    let handle = Arc::new(AlarmHandle {
+        rules: config.rules.clone(),
+        firing: Mutex::new(vec![false; config.rules.len()]),
+    });
+    let shared_handle = handle.clone();
+    let bands: Vec<Band> = config.rules.iter().map(|rule| rule.band.clone()).collect();
+
+    let thread = std::thread::spawn(move || {
+        let mut above_since: Vec<Option<Instant>> = vec![None; config.rules.len()];
+        let mut last_generation = 0;
+
+        loop {
+            std::thread::sleep(Duration::from_millis(50));
+            let frame = fft_transform.lock().unwrap().clone();
+            if frame.generation == last_generation {
+                continue;
+            }
+            last_generation = frame.generation;
+
+            let Some(summary) =
+                Summary::from_magnitudes(&frame.magnitudes, config.sample_rate, &bands)
+            else {
+                continue;
+            };
+
+            let now = Instant::now();
+            for (i, (rule, level_db)) in config.rules.iter().zip(summary.band_levels_db).enumerate()
+            {
+                let mut firing = handle.firing.lock().unwrap();
+                if level_db < rule.threshold_db {
+                    above_since[i] = None;
+                    if firing[i] {
+                        firing[i] = false;
+                        println!("\nAlarm \"{}\" cleared ({level_db:.1} dB)", rule.name);
+                    }
+                    continue;
+                }
+
+                let since = *above_since[i].get_or_insert(now);
+                if !firing[i] && now.duration_since(since) >= rule.hold {
+                    firing[i] = true;
+                    drop(firing);
+                    fire(&config, rule, level_db);
+                }
+            }
+        }
+    });
+
+    (thread, shared_handle)
+}
+
+/**
+ * Reports an alarm on the terminal and through the command and the webhook. Those run on their
+ * own threads, a slow script or server shouldn't delay the other alarms.
+ */
+fn fire(config: &AlarmConfig, rule: &AlarmRule, level_db: f32) {
+    println!(
+        "\n{}Alarm \"{}\": {:.0}-{:.0} Hz at {level_db:.1} dB, above {} dB for {:.1} s",
+        if config.bell { "\x07" } else { "" },
+        rule.name,
+        rule.band.low_hz,
+        rule.band.high_hz,
+        rule.threshold_db,
+        rule.hold.as_secs_f32()
+    );
+
+    if let Some(command) = config.command.clone() {
+        let rule = rule.clone();
+        std::thread::spawn(move || {
+            let status = shell(&command)
+                .env("ALARM_NAME", &rule.name)
+                .env("ALARM_LOW_HZ", rule.band.low_hz.to_string())
+                .env("ALARM_HIGH_HZ", rule.band.high_hz.to_string())
+                .env("ALARM_LEVEL_DB", format!("{level_db:.1}"))
+                .env("ALARM_THRESHOLD_DB", rule.threshold_db.to_string())
+                .status();
+            match status {
+                Ok(status) if !status.success() => {
+                    eprintln!("\nThe alarm command exited with {status}")
+                }
+                Err(error) => eprintln!("\nCould not run the alarm command: {error}"),
+                _ => {}
+            }
+        });
+    }
+
+    if let Some(webhook) = config.webhook.clone() {
+        let body = serde_json::json!({
+            "name": rule.name,
+            "low_hz": rule.band.low_hz,
+            "high_hz": rule.band.high_hz,
+            "level_db": level_db,
+            "threshold_db": rule.threshold_db,
+            "hold_secs": rule.hold.as_secs_f32(),
+            "time": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        })
+        .to_string();
+        std::thread::spawn(move || {
+            if let Err(error) = webhook.post(&body) {
+                eprintln!("\nCould not call the alarm webhook: {error}");
+            }
+        });
+    }
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
@@ -0,0 +1,1182 @@
+use std::{ops::Range, str::FromStr, sync::Arc, time::Duration};
+
+use ndarray::Array1;
+use num_complex::Complex;
+use serde::Serialize;
+
+// How low the normalized difference has to dip for a lag to count as the period, from the YIN
+// paper
+const YIN_THRESHOLD: f32 = 0.15;
+// Longest stretch of samples compared, bigger FFT windows don't make the pitch more precise
+const MAX_YIN_WINDOW: usize = 2048;
+
+// Range of the FFT sizes that can be switched to at runtime
+pub const MIN_FFT_SIZE: usize = 2usize.pow(10);
+pub const MAX_FFT_SIZE: usize = 2usize.pow(16);
+
+/**
+ * The result of one FFT run together with the samples that produced it.
+ * Readers keep their own clone of the Arcs, so they can hold on to a frame without holding the
+ * lock, and compare the generation to know whether there is a new one.
+ */
+#[derive(Clone, Default)]
+pub struct FftFrame {
+    pub samples: Arc<Vec<f32>>,
+    // Magnitudes of the full FFT output, both halves
+    pub magnitudes: Arc<Vec<f32>>,
+    // The one-sided amplitude spectrum of the same result, see one_sided_amplitudes
+    pub amplitudes: Arc<Vec<f32>>,
+    // Increases by one for every FFT result, 0 means there is no result yet
+    pub generation: u64,
+    // When the first sample was captured, on the clock::CaptureClock of the analyzer
+    pub captured_at: Duration,
+}
+
+/**
+ * Converts between bin indexes and frequencies for an FFT of `fft_len` points over a signal
+ * sampled at `sample_rate`.
+ * It's the length of the FFT that sets the width of the bins, not the number of captured
+ * samples, so everything that turns bins into Hz (or back) should go through here.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BinMapping {
+    pub sample_rate: u32,
+    pub fft_len: usize,
+}
+
+impl BinMapping {
+    pub fn new(sample_rate: u32, fft_len: usize) -> Self {
+        Self {
+            sample_rate,
+            fft_len,
+        }
+    }
+
+    pub fn bin_width_hz(&self) -> f32 {
+        self.sample_rate as f32 / self.fft_len.max(1) as f32
+    }
+
+    /**
+     * Gets the bin index and return the Real World frequency in Hz
+     */
+    pub fn frequency_in_hz(&self, bin_index: usize) -> f32 {
+        bin_index as f32 * self.bin_width_hz()
+    }
+
+    /**
+     * Returns the bin closest to the frequency, at most the one at the Nyquist frequency
+     */
+    pub fn bin_at(&self, frequency_in_hz: f32) -> usize {
+        ((frequency_in_hz / self.bin_width_hz()).round().max(0.0) as usize).min(self.fft_len / 2)
+    }
+
+    /**
+     * Returns how many bins, counting from DC, it takes to reach `frequency_in_hz`.
+     * It's at least one and at most the first half of the FFT output.
+     */
+    pub fn bins_up_to(&self, frequency_in_hz: f32) -> usize {
+        ((frequency_in_hz / self.bin_width_hz()).max(0.0) as usize)
+            .saturating_add(1)
+            .clamp(1, (self.fft_len / 2).max(1))
+    }
+}
+
+/**
+ * A range of frequencies in Hz, written on the command line as "low-high" (e.g. "20-250")
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Band {
+    pub low_hz: f32,
+    pub high_hz: f32,
+}
+
+impl FromStr for Band {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (low, high) = s
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid band \"{s}\", expected something like 20-250"))?;
+        let low_hz: f32 = low
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid band start \"{low}\""))?;
+        let high_hz: f32 = high
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid band end \"{high}\""))?;
+
+        if low_hz < 0.0 || high_hz <= low_hz {
+            return Err(format!(
+                "The band \"{s}\" must go from a lower to a higher frequency"
+            ));
+        }
+
+        Ok(Self { low_hz, high_hz })
+    }
+}
+
+impl Band {
+    /**
+     * Splits the range from `low_hz` to `high_hz` into `count` bands of the same width on a
+     * logarithmic scale, which is how our ears (and most LED spectrum displays) split it
+     */
+    pub fn log_spaced(count: usize, low_hz: f32, high_hz: f32) -> Vec<Self> {
+        let ratio = (high_hz / low_hz).powf(1.0 / count as f32);
+        (0..count)
+            .map(|i| Self {
+                low_hz: low_hz * ratio.powi(i as i32),
+                high_hz: low_hz * ratio.powi(i as i32 + 1),
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, frequency_in_hz: f32) -> bool {
+        (self.low_hz..=self.high_hz).contains(&frequency_in_hz)
+    }
+}
+
+/**
+ * A few numbers that describe a whole FFT frame.
+ * Meant to be sent to other programs, so they don't have to deal with thousands of bins.
+ */
+#[derive(Clone, Debug, Serialize)]
+pub struct Summary {
+    pub dominant_frequency_in_hz: f32,
+    pub dominant_level_db: f32,
+    pub level_db: f32,
+    pub band_levels_db: Vec<f32>,
+}
+
+impl Summary {
+    /**
+     * Gets the magnitudes of the full FFT output (both halves) and summarizes it.
+     * Returns None while there is no data yet.
+     */
+    pub fn from_magnitudes(magnitudes: &[f32], sample_rate: u32, bands: &[Band]) -> Option<Self> {
+        let n = magnitudes.len();
+        if n < 2 {
+            return None;
+        }
+
+        // Only the first half of the bins holds unique frequencies, the second one is mirrored
+        let one_sided = &magnitudes[0..n / 2];
+        let mapping = BinMapping::new(sample_rate, n);
+
+        // The DC bin is skipped, it's just the offset of the signal
+        let (dominant_bin, dominant_magnitude) = one_sided
+            .iter()
+            .enumerate()
+            .skip(1)
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())?;
+
+        // By Parseval's theorem the mean of the squared samples is sum(|X|^2) / N^2
+        let level = magnitudes.iter().map(|m| m * m).sum::<f32>() / (n * n) as f32;
+
+        let band_levels_db = bands
+            .iter()
+            .map(|band| {
+                let energy: f32 = one_sided
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| {
+                        let frequency = mapping.frequency_in_hz(*i);
+                        frequency >= band.low_hz && frequency < band.high_hz
+                    })
+                    .map(|(_, m)| 2.0 * m * m)
+                    .sum();
+                power_to_db(energy / (n * n) as f32)
+            })
+            .collect();
+
+        Some(Self {
+            dominant_frequency_in_hz: mapping.frequency_in_hz(dominant_bin),
+            dominant_level_db: magnitude_to_db(*dominant_magnitude, n),
+            level_db: power_to_db(level),
+            band_levels_db,
+        })
+    }
+}
+
+/**
+ * Converts a mean squared value into dB relative to a full scale sine wave (dBFS)
+ */
+pub fn power_to_db(power: f32) -> f32 {
+    // A full scale sine has a mean square of 0.5. The floor avoids returning -inf on silence
+    10.0 * (power / 0.5).max(1e-12).log10()
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Peak {
+    pub bin_index: usize,
+    pub frequency_in_hz: f32,
+    pub level_db: f32,
+}
+
+/**
+ * Gets the magnitudes of the full FFT output and returns the `count` strongest local maxima
+ * of the one-sided spectrum, the strongest one first
+ */
+pub fn find_peaks(magnitudes: &[f32], sample_rate: u32, count: usize) -> Vec<Peak> {
+    let n = magnitudes.len();
+    if n < 4 {
+        return vec![];
+    }
+    let one_sided = &magnitudes[0..n / 2];
+    let mapping = BinMapping::new(sample_rate, n);
+
+    let mut peaks: Vec<Peak> = one_sided
+        .windows(3)
+        .enumerate()
+        .filter(|(_, w)| w[1] > w[0] && w[1] >= w[2])
+        .map(|(i, w)| Peak {
+            bin_index: i + 1,
+            frequency_in_hz: mapping.frequency_in_hz(i + 1),
+            level_db: magnitude_to_db(w[1], n),
+        })
+        .collect();
+
+    peaks.sort_by(|a, b| b.level_db.partial_cmp(&a.level_db).unwrap());
+    peaks.truncate(count);
+    peaks
+}
+
+/**
+ * Gets the one-sided amplitude spectrum and averages the power of each bin with the bins
+ * within 1/`fraction` of an octave around it (1/3, 1/6...).
+ * Per bin, noise and room modes make the spectrum too jagged to compare with a curve, the
+ * smoothing keeps the overall shape while the bins get wider with the frequency like an RTA.
+ */
+pub fn octave_smoothed(amplitudes: &[f32], mapping: BinMapping, fraction: f32) -> Vec<f32> {
+    // Prefix sums of the powers, so every average costs the same no matter how wide it is
+    let mut power_sums = vec![0.0f64; amplitudes.len() + 1];
+    for (i, amplitude) in amplitudes.iter().enumerate() {
+        power_sums[i + 1] = power_sums[i] + (*amplitude as f64).powi(2);
+    }
+
+    let half_width = 2f32.powf(0.5 / fraction);
+    (0..amplitudes.len())
+        .map(|i| {
+            let frequency = mapping.frequency_in_hz(i);
+            let first = mapping.bin_at(frequency / half_width).min(i);
+            let last = mapping
+                .bin_at(frequency * half_width)
+                .clamp(i, amplitudes.len() - 1);
+            let mean = (power_sums[last + 1] - power_sums[first]) / (last + 1 - first) as f64;
+            mean.sqrt() as f32
+        })
+        .collect()
+}
+
+/**
+ * Gain in dB of the A-weighting curve (IEC 61672) at a frequency, which follows how loud
+ * quiet sounds seem to our ears and is what noise exposure limits are given in
+ */
+pub fn a_weighting_db(frequency_in_hz: f32) -> f32 {
+    let f2 = (frequency_in_hz as f64).powi(2);
+    let response = 12194f64.powi(2) * f2 * f2
+        / ((f2 + 20.6f64.powi(2))
+            * ((f2 + 107.7f64.powi(2)) * (f2 + 737.9f64.powi(2))).sqrt()
+            * (f2 + 12194f64.powi(2)));
+    // The curve is normalized to 0 dB at 1 kHz
+    (20.0 * response.max(1e-12).log10() + 2.0) as f32
+}
+
+/**
+ * Gets the one-sided amplitude spectrum and returns its A-weighted level in dBFS
+ */
+pub fn a_weighted_level_db(amplitudes: &[f32], mapping: BinMapping) -> f32 {
+    let power: f32 = amplitudes
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, amplitude)| {
+            amplitude * amplitude / 2.0
+                * 10f32.powf(a_weighting_db(mapping.frequency_in_hz(i)) / 10.0)
+        })
+        .sum();
+    power_to_db(power)
+}
+
+/**
+ * Converts a level in dBFS back into the amplitude of a sine
+ */
+pub fn db_to_amplitude(level_db: f32) -> f32 {
+    10f32.powf(level_db / 20.0)
+}
+
+#[derive(Clone, Debug)]
+pub struct Harmonic {
+    // 1 is the fundamental
+    pub number: usize,
+    pub bin_index: usize,
+    pub frequency_in_hz: f32,
+    pub level_db: f32,
+    // Level relative to the fundamental
+    pub ratio_db: f32,
+}
+
+/**
+ * Gets the one-sided amplitude spectrum and the bin of a fundamental and returns it followed
+ * by up to `count - 1` harmonics (its integer multiples) below the Nyquist frequency.
+ * Each harmonic is the loudest bin around its expected frequency, since the fundamental is
+ * only known to the precision of a bin and the error grows with every multiple.
+ */
+pub fn harmonics(
+    amplitudes: &[f32],
+    mapping: BinMapping,
+    fundamental_bin: usize,
+    count: usize,
+) -> Vec<Harmonic> {
+    if fundamental_bin == 0 || fundamental_bin >= amplitudes.len() {
+        return vec![];
+    }
+
+    let loudest_around = |bin: usize, reach: usize| {
+        let first = bin.saturating_sub(reach).max(1);
+        let last = (bin + reach).min(amplitudes.len() - 1);
+        (first..=last)
+            .max_by(|a, b| amplitudes[*a].partial_cmp(&amplitudes[*b]).unwrap())
+            .unwrap()
+    };
+
+    // The fundamental is moved to the top of the peak it was placed on
+    let fundamental_bin = loudest_around(fundamental_bin, 2);
+    let fundamental_db = amplitude_to_db(amplitudes[fundamental_bin]);
+
+    (1..=count)
+        .map(|number| (number, fundamental_bin * number))
+        .take_while(|(_, bin)| *bin < amplitudes.len())
+        .map(|(number, bin)| {
+            let bin_index = loudest_around(bin, number / 2);
+            let level_db = amplitude_to_db(amplitudes[bin_index]);
+            Harmonic {
+                number,
+                bin_index,
+                frequency_in_hz: mapping.frequency_in_hz(bin_index),
+                level_db,
+                ratio_db: level_db - fundamental_db,
+            }
+        })
+        .collect()
+}
+
+/**
+ * Gets the one-sided amplitude spectrum and a bin and returns it followed by its multiples up
+ * to `count` times its frequency, below the Nyquist frequency. Unlike `harmonics`, the bins are
+ * taken as they are, so they show what's actually under the multiples of the bin.
+ */
+pub fn bin_multiples(
+    amplitudes: &[f32],
+    mapping: BinMapping,
+    bin: usize,
+    count: usize,
+) -> Vec<Harmonic> {
+    if bin == 0 || bin >= amplitudes.len() {
+        return vec![];
+    }
+    let base_db = amplitude_to_db(amplitudes[bin]);
+    (1..=count)
+        .map(|number| (number, bin * number))
+        .take_while(|(_, bin_index)| *bin_index < amplitudes.len())
+        .map(|(number, bin_index)| {
+            let level_db = amplitude_to_db(amplitudes[bin_index]);
+            Harmonic {
+                number,
+                bin_index,
+                frequency_in_hz: mapping.frequency_in_hz(bin_index),
+                level_db,
+                ratio_db: level_db - base_db,
+            }
+        })
+        .collect()
+}
+
+/**
+ * Returns the musical interval from one frequency to another in cents, 100 cents being a
+ * semitone. It's negative when going down.
+ */
+pub fn cents_between(from_hz: f32, to_hz: f32) -> f32 {
+    1200.0 * (to_hz / from_hz).log2()
+}
+
+/**
+ * Estimates the fundamental frequency between `min_hz` and `max_hz` with the YIN method, which
+ * unlike the loudest bin of the spectrum doesn't jump to a harmonic when it's louder than the
+ * fundamental. Returns None when the samples have no clear period.
+ */
+pub fn yin_pitch(samples: &[f32], sample_rate: u32, min_hz: f32, max_hz: f32) -> Option<f32> {
+    yin_estimate(samples, sample_rate, min_hz, max_hz)
+        .filter(|(_, clarity)| *clarity > 1.0 - YIN_THRESHOLD)
+        .map(|(frequency_in_hz, _)| frequency_in_hz)
+}
+
+/**
+ * Same as `yin_pitch`, but also returns the best guess when the period isn't clear, together
+ * with its clarity: 1 minus the depth of the dip of the normalized difference, close to 1 for a
+ * clean tone and low for noise
+ */
+pub fn yin_estimate(
+    samples: &[f32],
+    sample_rate: u32,
+    min_hz: f32,
+    max_hz: f32,
+) -> Option<(f32, f32)> {
+    let min_lag = (sample_rate as f32 / max_hz) as usize;
+    let max_lag = ((sample_rate as f32 / min_hz) as usize).min(samples.len() / 2);
+    if min_lag < 2 || min_lag + 2 >= max_lag {
+        return None;
+    }
+    let window = (samples.len() - max_lag).min(MAX_YIN_WINDOW);
+
+    // Cumulative mean normalized difference, 1 at lag 0 and dipping near 0 at the period
+    let mut normalized = vec![1.0f32; max_lag + 1];
+    let mut running_sum = 0.0;
+    for lag in 1..=max_lag {
+        let difference: f32 = (0..window)
+            .map(|i| {
+                let delta = samples[i] - samples[i + lag];
+                delta * delta
+            })
+            .sum();
+        running_sum += difference;
+        if running_sum > 0.0 {
+            normalized[lag] = difference * lag as f32 / running_sum;
+        }
+    }
+
+    // Without a dip under the threshold, the deepest one is the guess
+    let mut lag = (min_lag..max_lag)
+        .find(|lag| normalized[*lag] < YIN_THRESHOLD)
+        .unwrap_or_else(|| {
+            (min_lag..max_lag)
+                .min_by(|a, b| normalized[*a].total_cmp(&normalized[*b]))
+                .unwrap()
+        });
+    while lag + 1 < max_lag && normalized[lag + 1] < normalized[lag] {
+        lag += 1;
+    }
+
+    // The period falls between two samples, a parabola through the dip finds where
+    let (before, at, after) = (normalized[lag - 1], normalized[lag], normalized[lag + 1]);
+    let curvature = before - 2.0 * at + after;
+    let shift = if curvature.abs() > f32::EPSILON {
+        0.5 * (before - after) / curvature
+    } else {
+        0.0
+    };
+    Some((
+        sample_rate as f32 / (lag as f32 + shift),
+        (1.0 - at).clamp(0.0, 1.0),
+    ))
+}
+
+/**
+ * Total harmonic distortion: the RMS sum of the harmonics over the fundamental, as a fraction
+ */
+pub fn total_harmonic_distortion(harmonics: &[Harmonic]) -> f32 {
+    harmonics
+        .iter()
+        .skip(1)
+        .map(|harmonic| 10f32.powf(harmonic.ratio_db / 10.0))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/**
+ * Keeps a list of the strongest peaks that doesn't reshuffle on every frame.
+ * A listed peak stays while there is still a peak within one bin of it, and a new peak only
+ * takes the place of the weakest listed one when it's `hysteresis_db` louder.
+ */
+pub struct PeakTracker {
+    count: usize,
+    hysteresis_db: f32,
+    peaks: Vec<Peak>,
+}
+
+impl PeakTracker {
+    pub fn new(count: usize, hysteresis_db: f32) -> Self {
+        Self {
+            count,
+            hysteresis_db,
+            peaks: vec![],
+        }
+    }
+
+    /**
+     * The tracked peaks, the strongest one first
+     */
+    pub fn peaks(&self) -> &[Peak] {
+        &self.peaks
+    }
+
+    /**
+     * Gets the peaks of a new frame (see find_peaks) and returns true when the tracked
+     * frequencies changed, not just their levels
+     */
+    pub fn update(&mut self, mut candidates: Vec<Peak>) -> bool {
+        let mut changed = false;
+
+        // Follows the listed peaks, they may move by a bin between frames
+        let mut kept = vec![];
+        for peak in &self.peaks {
+            let matching = candidates
+                .iter()
+                .position(|candidate| candidate.bin_index.abs_diff(peak.bin_index) <= 1);
+            match matching {
+                Some(i) => kept.push(candidates.remove(i)),
+                None => changed = true,
+            }
+        }
+        self.peaks = kept;
+
+        // candidates is still sorted from the strongest to the weakest
+        for candidate in candidates {
+            if self.peaks.len() < self.count {
+                self.peaks.push(candidate);
+                changed = true;
+                continue;
+            }
+            let weakest = self
+                .peaks
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.level_db.partial_cmp(&b.level_db).unwrap());
+            match weakest {
+                Some((i, peak)) if candidate.level_db > peak.level_db + self.hysteresis_db => {
+                    self.peaks[i] = candidate;
+                    changed = true;
+                }
+                _ => break,
+            }
+        }
+
+        self.peaks
+            .sort_by(|a, b| b.level_db.partial_cmp(&a.level_db).unwrap());
+        changed
+    }
+}
+
+/**
+ * Maps a level in dBFS into 0..=1, `floor_db` and below being 0 and 0 dBFS being 1.
+ * Used by the outputs that drive hardware with a fixed range of values.
+ */
+pub fn level_to_fraction(level_db: f32, floor_db: f32) -> f32 {
+    ((level_db - floor_db) / -floor_db).clamp(0.0, 1.0)
+}
+
+// Narrowest range between the top and the floor of a DbRange
+const MIN_DB_SPAN: f32 = 12.0;
+
+/**
+ * Levels in dBFS drawn from the bottom to the top of a graph. Unlike scaling each frame to its
+ * loudest bin, the same level is always drawn at the same height.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DbRange {
+    pub top_db: f32,
+    pub floor_db: f32,
+}
+
+impl DbRange {
+    /**
+     * The floor is lowered when it isn't at least MIN_DB_SPAN below the top
+     */
+    pub fn new(top_db: f32, floor_db: f32) -> Self {
+        Self {
+            top_db,
+            floor_db: floor_db.min(top_db - MIN_DB_SPAN),
+        }
+    }
+
+    /**
+     * Maps a level into 0..=1, the floor and below being 0 and the top and above 1
+     */
+    pub fn fraction(self, level_db: f32) -> f32 {
+        ((level_db - self.floor_db) / (self.top_db - self.floor_db)).clamp(0.0, 1.0)
+    }
+
+    pub fn move_top(&mut self, step_db: f32) {
+        self.top_db = (self.top_db + step_db).max(self.floor_db + MIN_DB_SPAN);
+    }
+
+    pub fn move_floor(&mut self, step_db: f32) {
+        self.floor_db = (self.floor_db + step_db).min(self.top_db - MIN_DB_SPAN);
+    }
+}
+
+/**
+ * Converts the magnitude of a single bin into the dBFS level of the sine that produced it
+ */
+pub fn magnitude_to_db(magnitude: f32, fft_len: usize) -> f32 {
+    // A sine of amplitude A shows up in its bin with a magnitude of A * N / 2
+    amplitude_to_db(2.0 * magnitude / fft_len as f32)
+}
+
+/**
+ * Converts the amplitude of a sine into its level in dBFS
+ */
+pub fn amplitude_to_db(amplitude: f32) -> f32 {
+    power_to_db(amplitude * amplitude / 2.0)
+}
+
+/**
+ * Level in dBFS of a single frequency over a block of samples, found with the Goertzel
+ * algorithm, which costs way less than a whole FFT when only a few frequencies matter
+ */
+pub fn goertzel_level_db(samples: &[f32], frequency_in_hz: f32, sample_rate: u32) -> f32 {
+    let coefficient =
+        2.0 * (2.0 * std::f32::consts::PI * frequency_in_hz / sample_rate as f32).cos();
+    let (mut previous, mut before_previous) = (0.0, 0.0);
+    for sample in samples {
+        let current = sample + coefficient * previous - before_previous;
+        before_previous = previous;
+        previous = current;
+    }
+    let power = previous * previous + before_previous * before_previous
+        - coefficient * previous * before_previous;
+    amplitude_to_db(2.0 * power.max(0.0).sqrt() / samples.len() as f32)
+}
+
+/**
+ * Gets the magnitudes of the full FFT output and returns the amplitude of the sine in each bin
+ * of the one-sided spectrum, from DC up to the Nyquist frequency (N / 2 + 1 bins).
+ * For real signals the second half of the output mirrors the first one, each sine is split
+ * between its bin and the mirrored one, so every bin but DC and Nyquist (which have no mirror)
+ * is doubled to get the whole amplitude back.
+ */
+pub fn one_sided_amplitudes(magnitudes: &[f32]) -> Vec<f32> {
+    let n = magnitudes.len();
+    if n == 0 {
+        return vec![];
+    }
+
+    magnitudes[0..=n / 2]
+        .iter()
+        .enumerate()
+        .map(|(i, magnitude)| {
+            if i == 0 || i == n / 2 {
+                magnitude / n as f32
+            } else {
+                2.0 * magnitude / n as f32
+            }
+        })
+        .collect()
+}
+
+/**
+ * Undoes one_sided_amplitudes, rebuilding the magnitudes of the full FFT output (with the
+ * mirrored half) for an FFT of (amplitudes.len() - 1) * 2 points
+ */
+pub fn two_sided_magnitudes(amplitudes: &[f32]) -> Vec<f32> {
+    if amplitudes.is_empty() {
+        return vec![];
+    }
+    let n = (amplitudes.len() - 1) * 2;
+    let mut magnitudes = vec![0.0; n.max(1)];
+    for (i, amplitude) in amplitudes.iter().enumerate() {
+        if i == 0 || i == n / 2 {
+            magnitudes[i] = amplitude * n as f32;
+        } else {
+            magnitudes[i] = amplitude * n as f32 / 2.0;
+            magnitudes[n - i] = magnitudes[i];
+        }
+    }
+    magnitudes
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "app", derive(clap::ValueEnum))]
+pub enum FrequencyScale {
+    Linear,
+    Logarithmic,
+}
+
+/**
+ * Neighboring bins shown as one bar of the graph or one LED of a strip
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct BinGroup {
+    pub bins: Range<usize>,
+    // The group stands for its loudest bin
+    pub loudest_bin: usize,
+}
+
+/**
+ * Squeezes the bins between `min_hz` and `max_hz` into `count` groups. `values` has a value
+ * per bin of `mapping`, like the magnitudes of the full FFT output or the one-sided amplitudes,
+ * and picks the loudest bin of each group.
+ * With the logarithmic scale every group covers the same musical interval, so the low groups
+ * hold just a few bins (or repeat the same one) while the high groups hold many.
+ */
+pub fn group_bins(
+    values: &[f32],
+    mapping: BinMapping,
+    count: usize,
+    min_hz: f32,
+    max_hz: f32,
+    scale: FrequencyScale,
+) -> Vec<BinGroup> {
+    let n = mapping.fft_len;
+    if n < 2 || values.len() < n / 2 {
+        return vec![];
+    }
+    let last_bin = n / 2 - 1;
+    let min_hz = min_hz.max(mapping.bin_width_hz() / 2.0);
+
+    let edge = |i: usize| -> f32 {
+        let t = i as f32 / count as f32;
+        match scale {
+            FrequencyScale::Linear => min_hz + (max_hz - min_hz) * t,
+            FrequencyScale::Logarithmic => min_hz * (max_hz / min_hz).powf(t),
+        }
+    };
+
+    (0..count)
+        .map(|i| {
+            let first = mapping.bin_at(edge(i)).min(last_bin);
+            // Always takes at least one bin, the closest one, when the group is too narrow
+            let last = mapping.bin_at(edge(i + 1)).clamp(first + 1, last_bin + 1);
+            let loudest_bin = (first..last)
+                .reduce(|loudest, bin| {
+                    if values[bin] > values[loudest] {
+                        bin
+                    } else {
+                        loudest
+                    }
+                })
+                .unwrap();
+            BinGroup {
+                bins: first..last,
+                loudest_bin,
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone)]
+pub struct NoteStatus {
+    frequency_in_hz: f32,
+    pub key_number: f32,
+    pub note_number: f32,
+    pub error_percentage: i8,
+}
+
+impl NoteStatus {
+    pub fn new(frequency_in_hz: f32) -> Self {
+        let key_number = Self::frequency_to_key_number(frequency_in_hz);
+        let note_number = Self::key_to_raw_note_number(key_number.round());
+        // Taken from the key numbers, the note numbers wrap around from B to C
+        let error_percentage = Self::get_error_percentage(key_number, key_number.round());
+
+        Self {
+            frequency_in_hz,
+            key_number,
+            note_number,
+            error_percentage,
+        }
+    }
+
+    pub fn get_frequency_in_hz(&self) -> f32 {
+        self.frequency_in_hz
+    }
+
+    /*
+     * Gets the frequency in Hz and returns the corresponding key number on the piano keyboard.
+     * Returns 1 for A0, 2 for A#0, 40 for C4, 49 for A4, etc...
+     */
+    fn frequency_to_key_number(freq: f32) -> f32 {
+        12.0 * (freq / 440.0).log2() + 49.0
+    }
+
+    /**
+     * This get's a key that might go from 1 until around 96
+     * and returns a number ranging from 1 to 12.
+     * 1 being C
+     * 2 being C#
+     * 3 being D
+     * and so on...
+     */
+    fn key_to_raw_note_number(key: f32) -> f32 {
+        // The keyboard starts at A, 9 semitones above C
+        (key - 1.0 + 9.0).rem_euclid(12.0) + 1.0
+    }
+
+    /**
+     * Gets a key that ranges from 1 until 12
+     * and returns the corresponding name
+     */
+    pub fn note_number_to_name(key: f32) -> String {
+        let notes_names: [&str; 12] = [
+            "C ", "C#", "D ", "D#", "E ", "F ", "F#", "G ", "G#", "A ", "A#", "B ",
+        ];
+        notes_names[(key - 1.0) as usize].into()
+    }
+
+    fn get_error_percentage(raw_note_number: f32, target_note_number: f32) -> i8 {
+        ((raw_note_number - target_note_number) * 100.0).round() as i8
+    }
+
+    /**
+     * Gets a key number that might range from 1 to around 96
+     * and returns the octave that the key belongs to.
+     */
+    pub fn get_octave_by_key_number(key_number: f32) -> u8 {
+        // Octaves start at C, key 4 (C1) being the first key of octave 1
+        ((key_number.round() + 8.0) / 12.0).floor() as u8
+    }
+}
+
+pub fn is_power_of_two(n: usize) -> bool {
+    n != 0 && (n & (n - 1)) == 0
+}
+
+/**
+ * Everything an FFT of one size can work out before seeing the signal, so running it again
+ * and again (like every frame of the mic) only goes through the stages. The FFT itself
+ * allocates nothing, it runs in place on the buffer it's given.
+ *
+ * The FFT splits the signal into its even and odd samples, all the way down to single samples,
+ * then combines pairs of FFTs into FFTs twice as long. Instead of recursing, the samples are put
+ * straight into the order the splits end up in (each index with its bits reversed), and the
+ * pairs are combined in place, one stage per doubling of the length.
+ */
+pub struct FftPlan {
+    // Index each sample is swapped with before the stages
+    bit_reversed: Vec<usize>,
+    // e^(-2πik/n) for the first half of the bins, a stage of FFTs of length m uses every
+    // (n/m)th one
+    twiddles: Vec<Complex<f32>>,
+}
+
+impl FftPlan {
+    pub fn new(size: usize) -> Self {
+        if !is_power_of_two(size) {
+            panic!("For this implementation of the FFT, the signal.len() must be a power of 2. You can pad with zeros the signal to reach the closest power of 2");
+        }
+        let bits = size.trailing_zeros();
+        Self {
+            bit_reversed: (0..size)
+                .map(|i| match bits {
+                    0 => i,
+                    _ => i.reverse_bits() >> (usize::BITS - bits),
+                })
+                .collect(),
+            // In f64, the errors of the angles of the last bins would add up otherwise
+            twiddles: (0..size / 2)
+                .map(|k| {
+                    let angle = -2.0 * std::f64::consts::PI * k as f64 / size as f64;
+                    Complex::new(angle.cos() as f32, angle.sin() as f32)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.bit_reversed.len()
+    }
+
+    /**
+     * Replaces the signal by its FFT. It has to have the size of the plan.
+     */
+    pub fn process(&self, signal: &mut [Complex<f32>]) {
+        let n = self.size();
+        assert_eq!(
+            signal.len(),
+            n,
+            "The signal doesn't have the size of the plan"
+        );
+        for (i, j) in self.bit_reversed.iter().enumerate() {
+            if *j > i {
+                signal.swap(i, *j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= n {
+            let half = len / 2;
+            let stride = n / len;
+            for start in (0..n).step_by(len) {
+                for k in 0..half {
+                    let even = signal[start + k];
+                    let odd = self.twiddles[k * stride] * signal[start + k + half];
+                    signal[start + k] = even + odd;
+                    signal[start + k + half] = even - odd;
+                }
+            }
+            len *= 2;
+        }
+    }
+}
+
+/**
+ * FFT of a signal whose length is a power of two, through a plan made for it and a copy of the
+ * signal. Both are made again on every call, keep an FftPlan and a buffer for repeated FFTs.
+ */
+pub fn fft(signal: &Array1<Complex<f32>>) -> Array1<Complex<f32>> {
+    let mut output = signal.to_owned();
+    FftPlan::new(signal.len()).process(output.as_slice_mut().unwrap());
+    output
+}
+
+/**
+ * Inverse of `fft`, through the same FFT on the conjugates
+ */
+pub fn ifft(spectrum: &Array1<Complex<f32>>) -> Array1<Complex<f32>> {
+    let n = spectrum.len() as f32;
+    fft(&spectrum.mapv(|x| x.conj())).mapv(|x| x.conj() / n)
+}
+
+/**
+ * Runs the FFT over real samples and returns the magnitude of every bin
+ */
+pub fn fft_magnitudes(samples: &[f32]) -> Vec<f32> {
+    fft(&Array1::<Complex<f32>>::from_iter(
+        samples.iter().map(Complex::from),
+    ))
+    .iter()
+    .map(|x| x.norm())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn db_range_keeps_its_ends_apart() {
+        let mut range = DbRange::new(0.0, -100.0);
+        assert_eq!(range.fraction(-50.0), 0.5);
+        assert_eq!(range.fraction(6.0), 1.0);
+        assert_eq!(range.fraction(-120.0), 0.0);
+        range.move_floor(90.0);
+        assert_eq!(range.floor_db, -MIN_DB_SPAN);
+        range.move_top(-30.0);
+        assert_eq!(range.top_db, 0.0);
+        assert_eq!(DbRange::new(-20.0, -10.0).floor_db, -32.0);
+    }
+
+    #[test]
+    fn bins_are_spaced_by_the_sample_rate_over_the_fft_length() {
+        let mapping = BinMapping::new(44100, 4096);
+        assert_eq!(mapping.frequency_in_hz(0), 0.0);
+        assert!((mapping.bin_width_hz() - 10.766602).abs() < 1e-4);
+        assert!((mapping.frequency_in_hz(2048) - 22050.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn zero_padding_narrows_the_bins() {
+        // 1024 captured samples padded to 4096 points have the bins of a 4096 point FFT
+        let padded = BinMapping::new(48000, 4096);
+        assert!((padded.frequency_in_hz(1) - 48000.0 / 4096.0).abs() < 1e-4);
+        assert_eq!(padded.bin_at(1000.0), 85);
+    }
+
+    #[test]
+    fn frequencies_map_back_to_their_bins() {
+        let mapping = BinMapping::new(44100, 8192);
+        for bin in [0, 1, 7, 100, 4095, 4096] {
+            assert_eq!(mapping.bin_at(mapping.frequency_in_hz(bin)), bin);
+        }
+        // Nothing past the Nyquist frequency
+        assert_eq!(mapping.bin_at(30000.0), 4096);
+        assert_eq!(mapping.bin_at(-5.0), 0);
+    }
+
+    #[test]
+    fn one_sided_amplitudes_match_the_amplitude_of_the_signal() {
+        let n = 1024;
+        let bin = 64;
+        let samples: Vec<f32> = (0..n)
+            .map(|i| {
+                let t = i as f32 / n as f32;
+                0.25 + 0.5 * (2.0 * std::f32::consts::PI * bin as f32 * t).sin()
+            })
+            .collect();
+
+        let amplitudes = one_sided_amplitudes(&crate::fft_magnitudes(&samples));
+        assert_eq!(amplitudes.len(), n / 2 + 1);
+        assert!((amplitudes[0] - 0.25).abs() < 1e-3);
+        assert!((amplitudes[bin] - 0.5).abs() < 1e-3);
+        assert!(amplitudes[bin + 10] < 1e-3);
+        // A full scale sine is 0 dBFS
+        assert!(amplitude_to_db(1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn two_sided_magnitudes_undo_one_sided_amplitudes() {
+        let magnitudes: Vec<f32> = [3.0, 1.0, 2.0, 5.0, 4.0, 5.0, 2.0, 1.0].to_vec();
+        assert_eq!(
+            two_sided_magnitudes(&one_sided_amplitudes(&magnitudes)),
+            magnitudes
+        );
+    }
+
+    fn peak(bin_index: usize, level_db: f32) -> Peak {
+        Peak {
+            bin_index,
+            frequency_in_hz: bin_index as f32,
+            level_db,
+        }
+    }
+
+    #[test]
+    fn tracked_peaks_are_only_replaced_by_clearly_louder_ones() {
+        let mut tracker = PeakTracker::new(2, 3.0);
+        assert!(tracker.update(vec![peak(10, -10.0), peak(20, -20.0), peak(30, -25.0)]));
+
+        // Moving by one bin and a slightly louder newcomer don't change the list
+        assert!(!tracker.update(vec![peak(11, -10.0), peak(30, -19.0), peak(20, -20.0)]));
+        assert_eq!(tracker.peaks()[0].bin_index, 11);
+        assert_eq!(tracker.peaks()[1].bin_index, 20);
+
+        assert!(tracker.update(vec![peak(30, -12.0), peak(11, -10.0), peak(20, -20.0)]));
+        let bins: Vec<usize> = tracker.peaks().iter().map(|peak| peak.bin_index).collect();
+        assert_eq!(bins, [11, 30]);
+
+        // Peaks that went away leave the list
+        assert!(tracker.update(vec![peak(11, -10.0)]));
+        assert_eq!(tracker.peaks().len(), 1);
+    }
+
+    #[test]
+    fn harmonics_are_found_at_the_multiples_of_the_fundamental() {
+        let n = 4096;
+        let samples: Vec<f32> = (0..n)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * 100.0 * i as f32 / n as f32;
+                0.5 * phase.sin() + 0.05 * (3.0 * phase).sin()
+            })
+            .collect();
+        let amplitudes = one_sided_amplitudes(&crate::fft_magnitudes(&samples));
+
+        // Clicking next to the fundamental still finds it
+        let found = harmonics(&amplitudes, BinMapping::new(4096, n), 101, 3);
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].bin_index, 100);
+        assert_eq!(found[2].bin_index, 300);
+        assert!((found[2].ratio_db + 20.0).abs() < 0.1);
+        assert!(found[1].ratio_db < -60.0);
+        assert!((total_harmonic_distortion(&found) - 0.1).abs() < 1e-3);
+
+        // Hovering next to it reads the bins as they are
+        let multiples = bin_multiples(&amplitudes, BinMapping::new(4096, n), 150, 4);
+        assert_eq!(
+            multiples.iter().map(|m| m.bin_index).collect::<Vec<_>>(),
+            [150, 300, 450, 600]
+        );
+        assert!((multiples[1].level_db - amplitude_to_db(0.05)).abs() < 0.1);
+        // 2100 is above the Nyquist bin, 2048
+        assert_eq!(
+            bin_multiples(&amplitudes, BinMapping::new(4096, n), 700, 4).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn smoothing_keeps_a_flat_spectrum_flat() {
+        let amplitudes = vec![0.5; 1025];
+        let smoothed = octave_smoothed(&amplitudes, BinMapping::new(48000, 2048), 3.0);
+        assert!(smoothed
+            .iter()
+            .all(|amplitude| (amplitude - 0.5).abs() < 1e-6));
+        assert!((db_to_amplitude(amplitude_to_db(0.25)) - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn a_weighting_matches_the_standard_table() {
+        for (frequency, expected_db) in [(1000.0, 0.0), (100.0, -19.1), (10000.0, -2.5)] {
+            assert!((a_weighting_db(frequency) - expected_db).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn bins_up_to_includes_the_bin_of_the_frequency() {
+        let mapping = BinMapping::new(44100, 4096);
+        assert_eq!(mapping.bins_up_to(0.0), 1);
+        assert_eq!(mapping.bins_up_to(mapping.frequency_in_hz(10)), 11);
+        assert_eq!(mapping.bins_up_to(3000.0), 279);
+        assert_eq!(mapping.bins_up_to(100_000.0), 2048);
+    }
+
+    #[test]
+    fn the_plan_matches_the_dft_every_time() {
+        let plan = FftPlan::new(64);
+        for seed in 1..4 {
+            let signal: Vec<Complex<f32>> = (0..64)
+                .map(|i| Complex::new(((i * seed * 37) % 11) as f32 - 5.0, (i % 3) as f32))
+                .collect();
+            // Straight from the formula
+            let expected: Vec<Complex<f32>> = (0..64)
+                .map(|k| {
+                    signal
+                        .iter()
+                        .enumerate()
+                        .fold(Complex::new(0.0, 0.0), |sum, (n, x)| {
+                            let angle = -2.0 * std::f32::consts::PI * (k * n) as f32 / 64.0;
+                            sum + x * Complex::new(angle.cos(), angle.sin())
+                        })
+                })
+                .collect();
+            let mut output = signal.clone();
+            plan.process(&mut output);
+            for (bin, expected) in output.iter().zip(&expected) {
+                assert!(
+                    (bin - expected).norm() < 1e-3,
+                    "{bin} instead of {expected}"
+                );
+            }
+        }
+        assert_eq!(FftPlan::new(1).size(), 1);
+    }
+
+    #[test]
+    fn log_groups_cover_the_same_interval_and_keep_the_loudest_bin() {
+        // 10 Hz bins
+        let mapping = BinMapping::new(1000, 100);
+        let mut values = vec![0.1; 51];
+        values[32] = 1.0;
+        let groups = group_bins(
+            &values,
+            mapping,
+            4,
+            25.0,
+            400.0,
+            FrequencyScale::Logarithmic,
+        );
+        // An octave each: 25-50, 50-100, 100-200 and 200-400 Hz, rounded to the bins
+        let bins: Vec<_> = groups.iter().map(|group| group.bins.clone()).collect();
+        assert_eq!(bins, [3..5, 5..10, 10..20, 20..40]);
+        assert_eq!(groups[3].loudest_bin, 32);
+        assert_eq!(groups[0].loudest_bin, 3);
+        assert!(group_bins(
+            &[],
+            BinMapping::new(1000, 0),
+            4,
+            25.0,
+            400.0,
+            FrequencyScale::Linear
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn yin_is_clear_on_a_tone_and_not_on_noise() {
+        let tone: Vec<f32> = (0..4096)
+            .map(|i| (2.0 * std::f32::consts::PI * 220.0 * i as f32 / 16000.0).sin())
+            .collect();
+        let (frequency_in_hz, clarity) = yin_estimate(&tone, 16000, 60.0, 1000.0).unwrap();
+        assert!((frequency_in_hz - 220.0).abs() < 1.0 && clarity > 0.95);
+
+        let mut seed = 5u32;
+        let noise: Vec<f32> = (0..4096)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                seed as f32 / u32::MAX as f32 - 0.5
+            })
+            .collect();
+        let (_, clarity) = yin_estimate(&noise, 16000, 60.0, 1000.0).unwrap();
+        assert!(clarity < 0.5, "{clarity}");
+        assert_eq!(yin_pitch(&noise, 16000, 60.0, 1000.0), None);
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::analysis::{level_to_fraction, Band, FftFrame, Summary};
+
+pub const ARTNET_PORT: u16 = 6454;
+
+/**
+ * A DMX channel (1 to 512) driven by the level of a band, written as "channel=low-high"
+ * (e.g. "1=20-250")
+ */
+#[derive(Clone, Debug)]
+pub struct DmxMapping {
+    pub channel: u16,
+    pub band: Band,
+}
+
+impl FromStr for DmxMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (channel, band) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid mapping \"{s}\", expected something like 1=20-250"))?;
+        let channel: u16 = channel
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid DMX channel \"{channel}\""))?;
+        if !(1..=512).contains(&channel) {
+            return Err(format!(
+                "The DMX channel {channel} is not between 1 and 512"
+            ));
+        }
+
+        Ok(Self {
+            channel,
+            band: band.parse()?,
+        })
+    }
+}
+
+/**
+ * Builds an ArtDmx packet, the one Art-Net uses to carry the 512 channels of a universe
+ */
+pub fn encode_art_dmx(universe: u16, sequence: u8, channels: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(18 + channels.len());
+    packet.extend_from_slice(b"Art-Net\0");
+    // OpDmx, the opcode is the only little endian number of the packet
+    packet.extend_from_slice(&0x5000u16.to_le_bytes());
+    // Protocol version 14
+    packet.extend_from_slice(&14u16.to_be_bytes());
+    packet.push(sequence);
+    // Physical input port, just informative
+    packet.push(0);
+    // SubUni (low byte) and Net (high 7 bits) of the universe
+    packet.push((universe & 0xFF) as u8);
+    packet.push(((universe >> 8) & 0x7F) as u8);
+    packet.extend_from_slice(&(channels.len() as u16).to_be_bytes());
+    packet.extend_from_slice(channels);
+    packet
+}
+
+pub struct ArtNetConfig {
+    pub target: SocketAddr,
+    pub universe: u16,
+    pub frames_per_second: u32,
+    pub floor_db: f32,
+}
+
+/**
+ * Spawns a thread that sends the mapped band levels to the node `frames_per_second` times
+ * per second. Channels that aren't mapped are kept at 0. Fails without any mapping, there
+ * would be nothing to send.
+ */
+pub fn spawn(
+    config: ArtNetConfig,
+    mappings: Vec<DmxMapping>,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+) -> std::io::Result<JoinHandle<()>> {
+    if mappings.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Art-Net needs at least one --dmx mapping or --band",
+        ));
+    }
+    let bind_address: SocketAddr = if config.target.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+    let socket = UdpSocket::bind(bind_address)?;
+    // Lets the target be a broadcast address like 2.255.255.255, IPv6 has no broadcast
+    if config.target.is_ipv4() {
+        socket.set_broadcast(true)?;
+    }
+
+    let bands: Vec<Band> = mappings
+        .iter()
+        .map(|mapping| mapping.band.clone())
+        .collect();
+    // The length of an ArtDmx packet has to be even
+    let highest_channel = mappings
+        .iter()
+        .map(|mapping| mapping.channel)
+        .max()
+        .unwrap_or_default();
+    let mut channels = vec![0u8; (highest_channel as usize).div_ceil(2) * 2];
+    let interval = Duration::from_secs_f32(1.0 / config.frames_per_second.max(1) as f32);
+
+    Ok(std::thread::spawn(move || {
+        // 0 disables sequencing, so it goes from 1 to 255
+        let mut sequence = 1u8;
+
+        loop {
+            let magnitudes = fft_transform.lock().unwrap().magnitudes.clone();
+            let summary = Summary::from_magnitudes(&magnitudes, sample_rate, &bands);
+
+            if let Some(summary) = summary {
+                for (mapping, level) in mappings.iter().zip(&summary.band_levels_db) {
+                    channels[mapping.channel as usize - 1] =
+                        (level_to_fraction(*level, config.floor_db) * 255.0).round() as u8;
+                }
+
+                let packet = encode_art_dmx(config.universe, sequence, &channels);
+                if let Err(error) = socket.send_to(&packet, config.target) {
+                    eprintln!("Could not send the Art-Net packet: {error}");
+                }
+                sequence = sequence % 255 + 1;
+            }
+
+            std::thread::sleep(interval);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn art_dmx_packets_match_the_spec() {
+        // Net 2, SubUni 0x13
+        let packet = encode_art_dmx(0x0213, 7, &[0x10, 0x20, 0x30, 0x40]);
+        assert_eq!(
+            packet,
+            [
+                b'A', b'r', b't', b'-', b'N', b'e', b't', 0, // ID
+                0x00, 0x50, // OpDmx, little endian
+                0x00, 0x0E, // protocol version 14
+                0x07, // sequence
+                0x00, // physical port
+                0x13, 0x02, // SubUni, Net
+                0x00, 0x04, // length, big endian
+                0x10, 0x20, 0x30, 0x40,
+            ]
+        );
+    }
+
+    #[test]
+    fn the_universe_is_15_bits_and_the_length_big_endian() {
+        let packet = encode_art_dmx(0xFFFF, 0, &[0; 512]);
+        assert_eq!(packet.len(), 18 + 512);
+        assert_eq!(packet[14..18], [0xFF, 0x7F, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn the_node_can_be_an_ipv6_address() {
+        let node = UdpSocket::bind("[::1]:0").unwrap();
+        node.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let frame = FftFrame {
+            magnitudes: Arc::new(vec![0.0; 1024]),
+            ..FftFrame::default()
+        };
+        spawn(
+            ArtNetConfig {
+                target: node.local_addr().unwrap(),
+                universe: 1,
+                frames_per_second: 30,
+                floor_db: -60.0,
+            },
+            vec![DmxMapping {
+                channel: 1,
+                band: Band {
+                    low_hz: 100.0,
+                    high_hz: 1000.0,
+                },
+            }],
+            Arc::new(Mutex::new(frame)),
+            8000,
+        )
+        .unwrap();
+
+        let mut packet = [0; 64];
+        let len = node.recv(&mut packet).unwrap();
+        // A silent band is a channel at 0, padded to an even length
+        assert_eq!(len, 18 + 2);
+        assert_eq!(packet[..8], *b"Art-Net\0");
+        assert_eq!(packet[18..20], [0, 0]);
+    }
+}
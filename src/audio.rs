@@ -0,0 +1,401 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait},
+    StreamConfig,
+};
+use num_complex::Complex;
+
+use crate::{
+    analysis::{one_sided_amplitudes, FftFrame, FftPlan},
+    clock::{self, CaptureClock},
+    curve::Calibration,
+    profile::{self, Profiler},
+    windowing::WindowFunction,
+    MAX_FFT_SIZE,
+};
+
+// Everything the audio callback does with the samples, kept apart from the stream so a new
+// stream can take over the same state when the device comes back. It also gets when the first
+// of the samples was captured.
+pub type SampleCallback = Arc<Mutex<dyn FnMut(&[f32], Instant) + Send>>;
+
+/**
+ * Prints the input devices numbered as `--device` takes them, with the channels, sample rates
+ * and sample format of each configuration they support
+ */
+pub fn list_input_devices() -> Result<(), String> {
+    let host = cpal::default_host();
+    let default_name = host
+        .default_input_device()
+        .and_then(|device| device.name().ok());
+    let devices = host.input_devices().map_err(|error| error.to_string())?;
+    let mut found = false;
+    for (index, device) in devices.enumerate() {
+        found = true;
+        let name = device.name().unwrap_or_else(|_| "(unnamed)".into());
+        let default = if default_name.as_ref() == Some(&name) {
+            " (default)"
+        } else {
+            ""
+        };
+        println!("{index}: {name}{default}");
+        match device.supported_input_configs() {
+            Ok(configs) => {
+                for config in configs {
+                    println!(
+                        "     {} channels, {} - {} Hz, {}",
+                        config.channels(),
+                        config.min_sample_rate().0,
+                        config.max_sample_rate().0,
+                        config.sample_format()
+                    );
+                }
+            }
+            Err(error) => println!("     Could not read its configurations: {error}"),
+        }
+    }
+    if !found {
+        println!("No input device found");
+    }
+    Ok(())
+}
+
+/**
+ * Finds the input device numbered `name` in the list, or else whose name contains it, ignoring
+ * the case. The default one when there is no name.
+ */
+pub fn find_input_device(name: Option<&str>) -> Result<cpal::Device, String> {
+    let host = cpal::default_host();
+    let Some(name) = name else {
+        return host
+            .default_input_device()
+            .ok_or_else(|| "No input device found".into());
+    };
+    let devices: Vec<cpal::Device> = host
+        .input_devices()
+        .map_err(|error| error.to_string())?
+        .collect();
+    let names: Vec<String> = devices
+        .iter()
+        .map(|device| device.name().unwrap_or_default())
+        .collect();
+    match matching_device(&names, name) {
+        Some(index) => Ok(devices.into_iter().nth(index).unwrap()),
+        None if names.is_empty() => Err("No input device found".into()),
+        None => Err(format!(
+            "No input device matches \"{name}\", --list-devices shows them: {}",
+            names.join(", ")
+        )),
+    }
+}
+
+/**
+ * Index of the device numbered `name`, or named `name`, or else of the first one whose name
+ * contains it, ignoring the case
+ */
+fn matching_device(names: &[String], name: &str) -> Option<usize> {
+    if let Ok(index) = name.trim().parse::<usize>() {
+        return (index < names.len()).then_some(index);
+    }
+    let name = name.to_lowercase();
+    names
+        .iter()
+        .position(|candidate| candidate.to_lowercase() == name)
+        .or_else(|| {
+            names
+                .iter()
+                .position(|candidate| candidate.to_lowercase().contains(&name))
+        })
+}
+
+/**
+ * Opens the input device matching `device` (the default one when None) as a mono stream feeding
+ * `on_samples`. Returns the stream, which still has to be played, and the device.
+ */
+pub fn open_input_stream(
+    device: Option<&str>,
+    sample_rate: u32,
+    on_samples: SampleCallback,
+    on_error: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<(cpal::Stream, cpal::Device), String> {
+    open_stream(device, 1, sample_rate, on_samples, on_error)
+}
+
+/**
+ * Same as `open_input_stream` with two channels, `on_samples` gets them interleaved (left,
+ * right, left...)
+ */
+pub fn open_stereo_input_stream(
+    device: Option<&str>,
+    sample_rate: u32,
+    on_samples: SampleCallback,
+    on_error: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<(cpal::Stream, cpal::Device), String> {
+    open_stream(device, 2, sample_rate, on_samples, on_error)
+}
+
+fn open_stream(
+    device: Option<&str>,
+    channels: u16,
+    sample_rate: u32,
+    on_samples: SampleCallback,
+    on_error: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<(cpal::Stream, cpal::Device), String> {
+    let mic = find_input_device(device)?;
+    let stream = mic
+        .build_input_stream(
+            &StreamConfig {
+                channels,
+                buffer_size: cpal::BufferSize::Default,
+                sample_rate: cpal::SampleRate(sample_rate),
+            },
+            move |data: &[f32], info| {
+                on_samples.lock().unwrap()(data, clock::capture_instant(info))
+            },
+            on_error,
+            None,
+        )
+        .map_err(|error| error.to_string())?;
+    Ok((stream, mic))
+}
+
+pub struct FftPipelineConfig {
+    pub sample_rate: u32,
+    // Read on every block, so the FFT size can be changed while capturing
+    pub fft_size: Arc<AtomicUsize>,
+    pub window_function: Arc<Mutex<WindowFunction>>,
+    // Applied to the magnitudes of every frame, so every output gets the corrected spectrum
+    pub calibration: Option<Calibration>,
+    pub capture_clock: CaptureClock,
+    pub profiler: Option<Arc<Profiler>>,
+}
+
+/**
+ * Turns the captured samples into spectra. The samples fill a buffer of the FFT size, and once
+ * it's full it's multiplied by the window, goes through the FFT and becomes the latest frame.
+ * The frames follow each other without overlapping, the samples left over start the next one.
+ */
+pub struct FftPipeline {
+    config: FftPipelineConfig,
+    buffer: Arc<Mutex<Vec<f32>>>,
+    frame: Arc<Mutex<FftFrame>>,
+    // Made again only when the FFT size changes
+    fft_plan: FftPlan,
+    // The windowed samples the FFT runs in place on, kept from frame to frame
+    fft_output: Vec<Complex<f32>>,
+    // Coefficients and coherent gain of the window, made again when the window or the FFT size
+    // changes
+    window_coefficients: (WindowFunction, Vec<f32>, f32),
+}
+
+impl FftPipeline {
+    pub fn new(config: FftPipelineConfig) -> Self {
+        let fft_size = config.fft_size.load(Ordering::Relaxed);
+        Self {
+            config,
+            buffer: Arc::new(Mutex::new(Vec::with_capacity(MAX_FFT_SIZE))),
+            frame: Arc::new(Mutex::new(FftFrame::default())),
+            fft_plan: FftPlan::new(fft_size),
+            fft_output: Vec::with_capacity(fft_size),
+            window_coefficients: (WindowFunction::Rectangular, vec![], 1.0),
+        }
+    }
+
+    /**
+     * The latest frame, shared with everything that reads the analysis
+     */
+    pub fn frame(&self) -> Arc<Mutex<FftFrame>> {
+        self.frame.clone()
+    }
+
+    /**
+     * The samples waiting for the next frame, to show how long it still has to fill
+     */
+    pub fn buffer(&self) -> Arc<Mutex<Vec<f32>>> {
+        self.buffer.clone()
+    }
+
+    /**
+     * Takes the next captured samples, the first of them captured at `captured`. Returns the
+     * new frame when they completed one, still locked so it can be handed to the outputs that
+     * run on the audio thread before anyone else reads it.
+     */
+    pub fn process(&mut self, data: &[f32], captured: Instant) -> Option<MutexGuard<'_, FftFrame>> {
+        let profiler = &self.config.profiler;
+        let mut buf = self.buffer.lock().unwrap();
+        let buffer_size = self.config.fft_size.load(Ordering::Relaxed);
+
+        // The FFT size was lowered, only the most recent samples are kept
+        if buf.len() > buffer_size {
+            let excess = buf.len() - buffer_size;
+            buf.drain(0..excess);
+        }
+
+        let remaining = profile::time(profiler, "capture copy", || {
+            let mut remaining = vec![];
+            let sum_data = buf.len() + data.len();
+
+            // If the current data + the buf.len() will overflow the buffer then it
+            // appends the max amount data in the buffer and saves the remaining to append to the
+            // next DFT run
+            if buf.len() < buffer_size && sum_data >= buffer_size {
+                let max_i = data.len() - (sum_data - buffer_size);
+                if max_i > 0 {
+                    buf.append(&mut data[0..max_i].to_vec());
+                    remaining = data[max_i..].to_vec();
+                }
+            }
+            remaining
+        });
+
+        // If the buffer is not yet full, just appends it and waits for the next samples
+        if buf.len() != buffer_size {
+            profile::time(profiler, "capture copy", || buf.append(&mut data.to_vec()));
+            return None;
+        }
+
+        profile::time(profiler, "fft", || {
+            if self.fft_plan.size() != buffer_size {
+                self.fft_plan = FftPlan::new(buffer_size);
+            }
+            let window = *self.config.window_function.lock().unwrap();
+            if self.window_coefficients.0 != window
+                || self.window_coefficients.1.len() != buffer_size
+            {
+                self.window_coefficients = (
+                    window,
+                    window.coefficients(buffer_size),
+                    window.coherent_gain(buffer_size),
+                );
+            }
+            let (_, coefficients, coherent_gain) = &self.window_coefficients;
+            // Divided by the coherent gain, a sine reads the same level with any window
+            self.fft_output.clear();
+            self.fft_output.extend(
+                buf.iter().zip(coefficients).map(|(sample, coefficient)| {
+                    Complex::from(sample * coefficient / coherent_gain)
+                }),
+            );
+            self.fft_plan.process(&mut self.fft_output);
+        });
+
+        /*
+         * This project was made as a learning resource for the FFT algorithm
+         * My implementation is not even near as performant as
+         * the standard "rustfft" crate. So, in real world applications use the
+         * official "rustfft" crate instead of my "fft" implementation.
+         *
+         * Besides the HUGE difference in performance, the fft crate can calculate the
+         * FFT for buffers of any size. While my implementation only give correct
+         * results when running in a buffer that has a length that is a power of two.
+         *
+         * If you want to see how to use the "rustfft" crate, take a look at their
+         * docs, but if you just want to set it up in this example you can use the
+         * following code instead of my "fft" function and don't forget to remove the
+         * call to the fft in the line above:
+        // This is code is in the version rustfft = "6.2.0"
+        rustfft::FftPlanner::new()
+            .plan_fft_forward(fft_output.len())
+            .process(&mut fft_output);
+         */
+        let (magnitudes, amplitudes) = profile::time(profiler, "magnitude", || {
+            let mut magnitudes = self
+                .fft_output
+                .iter()
+                .map(|x| x.norm())
+                .collect::<Vec<f32>>();
+            if let Some(calibration) = self.config.calibration.as_mut() {
+                calibration.apply(&mut magnitudes);
+            }
+            let amplitudes = one_sided_amplitudes(&magnitudes);
+            (magnitudes, amplitudes)
+        });
+        // The frame ends on the last sample taken from this block
+        let sample_rate = self.config.sample_rate;
+        let frame_end = captured
+            + Duration::from_secs_f64((data.len() - remaining.len()) as f64 / sample_rate as f64);
+        let mut result = self.frame.lock().unwrap();
+        result.captured_at = self.config.capture_clock.since_start(clock::start_of(
+            frame_end,
+            buffer_size,
+            sample_rate,
+        ));
+        result.magnitudes = Arc::new(magnitudes);
+        result.amplitudes = Arc::new(amplitudes);
+        result.samples = Arc::new(std::mem::replace(&mut *buf, remaining));
+        result.generation += 1;
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn devices_match_by_part_of_their_name() {
+        let names = [
+            "Built-in Microphone".to_string(),
+            "USB Mic Pro".to_string(),
+            "usb mic".to_string(),
+        ];
+        // The exact name wins over the ones containing it
+        assert_eq!(matching_device(&names, "USB Mic"), Some(2));
+        assert_eq!(matching_device(&names, "pro"), Some(1));
+        assert_eq!(matching_device(&names, "built-in"), Some(0));
+        assert_eq!(matching_device(&names, "Line In"), None);
+        // Or by their number in the list
+        assert_eq!(matching_device(&names, "1"), Some(1));
+        assert_eq!(matching_device(&names, "3"), None);
+    }
+
+    #[test]
+    fn the_pipeline_cuts_the_samples_into_consecutive_frames() {
+        let sample_rate = 8000;
+        let fft_size = Arc::new(AtomicUsize::new(1024));
+        let mut pipeline = FftPipeline::new(FftPipelineConfig {
+            sample_rate,
+            fft_size: fft_size.clone(),
+            window_function: Arc::new(Mutex::new(WindowFunction::Hann)),
+            calibration: None,
+            capture_clock: CaptureClock::start(),
+            profiler: None,
+        });
+        // 1 kHz falls on bin 128
+        let sine: Vec<f32> = (0..4000)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 8000.0).sin())
+            .collect();
+
+        let mut frames = vec![];
+        for block in sine.chunks(300) {
+            if let Some(frame) = pipeline.process(block, Instant::now()) {
+                frames.push(frame.clone());
+            }
+        }
+        assert_eq!(frames.len(), 3);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.generation, i as u64 + 1);
+            assert_eq!(frame.samples[..], sine[i * 1024..(i + 1) * 1024]);
+            let peak = (0..frame.amplitudes.len())
+                .max_by(|a, b| frame.amplitudes[*a].total_cmp(&frame.amplitudes[*b]))
+                .unwrap();
+            assert_eq!(peak, 128);
+            // Corrected by the coherent gain of the window
+            assert!((frame.amplitudes[128] - 1.0).abs() < 0.01);
+        }
+        assert_eq!(pipeline.buffer().lock().unwrap().len(), 4000 - 3 * 1024);
+
+        // A smaller FFT keeps the most recent samples
+        fft_size.store(512, Ordering::Relaxed);
+        let frame = pipeline.process(&[0.0; 300], Instant::now()).unwrap();
+        assert_eq!(frame.samples.len(), 512);
+        assert_eq!(frame.magnitudes.len(), 512);
+    }
+}
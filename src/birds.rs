@@ -0,0 +1,293 @@
+use std::{
+    collections::VecDeque,
+    fs::OpenOptions,
+    io::{stdout, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    analysis::{amplitude_to_db, Band, BinMapping, FftFrame},
+    snapshot,
+};
+
+// Audio kept before the start of an event, so the clip doesn't begin in the middle of the song
+const PRE_ROLL_SECS: f32 = 1.0;
+// An event ends after this long without a tone, which also ends up as the tail of the clip
+const HANG_SECS: f32 = 0.7;
+// Shorter events are clicks or single noisy frames
+const MIN_TONAL_SECS: f32 = 0.15;
+// Longer events are cut into several clips
+const MAX_EVENT_SECS: f32 = 30.0;
+
+pub struct BirdConfig {
+    pub band: Band,
+    // How far above the median level of the band the strongest bin has to be
+    pub margin_db: f32,
+    pub directory: PathBuf,
+    pub sample_rate: u32,
+}
+
+/**
+ * A stretch of audio where a tone stood above the noise floor of the band
+ */
+#[derive(Debug)]
+pub struct BirdEvent {
+    // Seconds since the start of the capture
+    pub start_secs: f32,
+    pub tonal_secs: f32,
+    pub peak_frequency_in_hz: f32,
+    pub peak_level_db: f32,
+    pub samples: Vec<f32>,
+}
+
+struct Ongoing {
+    start_secs: f32,
+    last_tonal_secs: f32,
+    tonal_secs: f32,
+    peak_frequency_in_hz: f32,
+    peak_level_db: f32,
+}
+
+/**
+ * Follows the frames and cuts out the events with their pre-roll
+ */
+pub struct EventDetector {
+    band: Band,
+    margin_db: f32,
+    sample_rate: u32,
+    // Just the pre-roll between the events, the whole event and its pre-roll during one
+    audio: VecDeque<f32>,
+    elapsed_secs: f32,
+    ongoing: Option<Ongoing>,
+}
+
+impl EventDetector {
+    pub fn new(band: Band, margin_db: f32, sample_rate: u32) -> Self {
+        Self {
+            band,
+            margin_db,
+            sample_rate,
+            audio: VecDeque::new(),
+            elapsed_secs: 0.0,
+            ongoing: None,
+        }
+    }
+
+    /**
+     * Returns the frequency and level of the strongest bin of the band when it stands out of
+     * the median of the band, like birdsong does over wind and traffic
+     */
+    fn tone(&self, amplitudes: &[f32], mapping: BinMapping) -> Option<(f32, f32)> {
+        let low = mapping.bin_at(self.band.low_hz).max(1);
+        let high = mapping
+            .bin_at(self.band.high_hz)
+            .min(amplitudes.len().saturating_sub(1));
+        if low >= high {
+            return None;
+        }
+        let levels_db: Vec<f32> = amplitudes[low..=high]
+            .iter()
+            .map(|a| amplitude_to_db(*a))
+            .collect();
+        let (offset, loudest_db) = levels_db
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        let mut sorted = levels_db.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let median_db = sorted[sorted.len() / 2];
+        (*loudest_db >= median_db + self.margin_db)
+            .then(|| (mapping.frequency_in_hz(low + offset), *loudest_db))
+    }
+
+    /**
+     * Takes the samples of a frame and its spectrum, and returns the event that just ended
+     */
+    pub fn process(&mut self, samples: &[f32], amplitudes: &[f32]) -> Option<BirdEvent> {
+        let mapping = BinMapping::new(self.sample_rate, samples.len());
+        let frame_secs = samples.len() as f32 / self.sample_rate as f32;
+        let tone = self.tone(amplitudes, mapping);
+        let frame_start_secs = self.elapsed_secs;
+        self.elapsed_secs += frame_secs;
+        self.audio.extend(samples);
+
+        match (self.ongoing.as_mut(), tone) {
+            (None, Some((frequency_in_hz, level_db))) => {
+                self.ongoing = Some(Ongoing {
+                    start_secs: frame_start_secs,
+                    last_tonal_secs: self.elapsed_secs,
+                    tonal_secs: frame_secs,
+                    peak_frequency_in_hz: frequency_in_hz,
+                    peak_level_db: level_db,
+                });
+            }
+            (Some(ongoing), Some((frequency_in_hz, level_db))) => {
+                ongoing.last_tonal_secs = self.elapsed_secs;
+                ongoing.tonal_secs += frame_secs;
+                if level_db > ongoing.peak_level_db {
+                    ongoing.peak_frequency_in_hz = frequency_in_hz;
+                    ongoing.peak_level_db = level_db;
+                }
+            }
+            _ => {}
+        }
+
+        let ended = self.ongoing.as_ref().is_some_and(|ongoing| {
+            self.elapsed_secs - ongoing.last_tonal_secs >= HANG_SECS
+                || self.elapsed_secs - ongoing.start_secs >= MAX_EVENT_SECS
+        });
+        let event = if ended {
+            let ongoing = self.ongoing.take().unwrap();
+            let samples: Vec<f32> = self.audio.drain(..).collect();
+            (ongoing.tonal_secs >= MIN_TONAL_SECS).then_some(BirdEvent {
+                start_secs: ongoing.start_secs,
+                tonal_secs: ongoing.tonal_secs,
+                peak_frequency_in_hz: ongoing.peak_frequency_in_hz,
+                peak_level_db: ongoing.peak_level_db,
+                samples,
+            })
+        } else {
+            None
+        };
+
+        if self.ongoing.is_none() {
+            let pre_roll = (PRE_ROLL_SECS * self.sample_rate as f32) as usize;
+            let excess = self.audio.len().saturating_sub(pre_roll);
+            self.audio.drain(..excess);
+        }
+        event
+    }
+}
+
+/**
+ * Saves the clip of the event and adds a line to the events.csv of the directory. Returns the
+ * path of the clip.
+ */
+fn save(event: &BirdEvent, config: &BirdConfig) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(&config.directory).map_err(|error| error.to_string())?;
+    // The clip ends now and starts a pre-roll before the event
+    let clip_secs = event.samples.len() as f32 / config.sample_rate as f32;
+    let started = SystemTime::now() - Duration::from_secs_f32((clip_secs - PRE_ROLL_SECS).max(0.0));
+    // Colons are not allowed in Windows file names
+    let name = humantime::format_rfc3339_seconds(started)
+        .to_string()
+        .replace(':', "-");
+    let path = config.directory.join(format!(
+        "bird-{name}-{:.0}hz.wav",
+        event.peak_frequency_in_hz
+    ));
+    snapshot::write_wav(&path, &event.samples, config.sample_rate)
+        .map_err(|error| error.to_string())?;
+
+    let log_path = config.directory.join("events.csv");
+    let is_new = !log_path.exists();
+    let mut log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .map_err(|error| error.to_string())?;
+    if is_new {
+        writeln!(log, "time,tonal_s,peak_hz,peak_db,clip").map_err(|error| error.to_string())?;
+    }
+    writeln!(
+        log,
+        "{},{:.2},{:.0},{:.1},{}",
+        humantime::format_rfc3339_seconds(started),
+        event.tonal_secs,
+        event.peak_frequency_in_hz,
+        event.peak_level_db,
+        path.file_name().unwrap().to_string_lossy()
+    )
+    .map_err(|error| error.to_string())?;
+    Ok(path)
+}
+
+/**
+ * Spawns a thread that looks for events in every frame and saves them as they end
+ */
+pub fn spawn(config: BirdConfig, fft_transform: Arc<Mutex<FftFrame>>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut detector =
+            EventDetector::new(config.band.clone(), config.margin_db, config.sample_rate);
+        let mut last_generation = 0;
+        loop {
+            // Every frame is needed, the clips are made of their samples
+            std::thread::sleep(Duration::from_millis(5));
+            let frame = fft_transform.lock().unwrap().clone();
+            if frame.generation == last_generation {
+                continue;
+            }
+            last_generation = frame.generation;
+
+            let Some(event) = detector.process(&frame.samples, &frame.amplitudes) else {
+                continue;
+            };
+            match save(&event, &config) {
+                Ok(path) => println!(
+                    "\nBird {:.0} s in, at {:.0} Hz ({:.1} dBFS, {:.1} s of song) saved to {}",
+                    event.start_secs,
+                    event.peak_frequency_in_hz,
+                    event.peak_level_db,
+                    event.tonal_secs,
+                    path.display()
+                ),
+                Err(error) => eprintln!("\nCould not save the bird clip: {error}"),
+            }
+            stdout().flush().unwrap();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::one_sided_amplitudes, fft_magnitudes};
+
+    #[test]
+    fn chirp_over_noise_becomes_one_clip_with_its_pre_roll() {
+        let sample_rate = 44100;
+        let frame_len = 4096;
+        let mut detector = EventDetector::new(
+            Band {
+                low_hz: 1000.0,
+                high_hz: 12000.0,
+            },
+            20.0,
+            sample_rate,
+        );
+
+        let mut noise_state = 0x2545_F491u32;
+        let mut events = vec![];
+        // 3 s of noise, 0.5 s of a 4 kHz chirp, then 3 s of noise
+        for frame in 0..(6.5 * sample_rate as f32 / frame_len as f32) as usize {
+            let samples: Vec<f32> = (0..frame_len)
+                .map(|i| {
+                    let n = frame * frame_len + i;
+                    let t = n as f32 / sample_rate as f32;
+                    noise_state ^= noise_state << 13;
+                    noise_state ^= noise_state >> 17;
+                    noise_state ^= noise_state << 5;
+                    let noise = (noise_state as f32 / u32::MAX as f32 - 0.5) * 0.01;
+                    let chirp = if (3.0..3.5).contains(&t) {
+                        0.3 * (2.0 * std::f32::consts::PI * 4000.0 * t).sin()
+                    } else {
+                        0.0
+                    };
+                    noise + chirp
+                })
+                .collect();
+            let amplitudes = one_sided_amplitudes(&fft_magnitudes(&samples));
+            events.extend(detector.process(&samples, &amplitudes));
+        }
+
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert!((event.peak_frequency_in_hz - 4000.0).abs() < 20.0);
+        assert!((event.start_secs - 3.0).abs() < 0.1);
+        let clip_secs = event.samples.len() as f32 / sample_rate as f32;
+        assert!(clip_secs > PRE_ROLL_SECS + 0.5 + HANG_SECS - 0.2);
+    }
+}
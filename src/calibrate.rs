@@ -0,0 +1,146 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use cpal::traits::StreamTrait;
+
+use crate::{
+    analysis::amplitude_to_db, cli::CalibrateLevelArgs, curve::Curve, filter::Biquad,
+    open_input_stream, SampleCallback,
+};
+
+// The tone is measured in blocks of this length, which have to agree with each other
+const BLOCK_SECS: f32 = 0.25;
+// Narrow enough to leave the noise out, wide enough for an oscillator a bit off its frequency
+const TONE_Q: f32 = 4.0;
+// Share of the power of a block the tone has to have, otherwise it's something else
+const MIN_TONE_SHARE: f32 = 0.9;
+// How much the level of the blocks may vary, a fader moving or a tone starting spoil it
+const MAX_SPREAD_DB: f32 = 0.5;
+const MIN_BLOCKS: usize = 4;
+
+/**
+ * Finds the level in dBFS (a full scale sine being 0 dBFS) of a steady tone at
+ * `frequency_in_hz`. Fails when the samples are mostly something else or the level moves.
+ */
+pub fn measure_tone(
+    samples: &[f32],
+    frequency_in_hz: f32,
+    sample_rate: u32,
+) -> Result<f32, String> {
+    let mut band_pass = Biquad::band_pass(frequency_in_hz, TONE_Q, sample_rate);
+    let filtered: Vec<f32> = samples.iter().map(|s| band_pass.process(*s)).collect();
+    let block_len = (BLOCK_SECS * sample_rate as f32) as usize;
+    let power = |block: &[f32]| block.iter().map(|s| s * s).sum::<f32>() / block.len() as f32;
+
+    // The first block has the filter settling
+    let levels_db: Vec<f32> = samples
+        .chunks_exact(block_len)
+        .zip(filtered.chunks_exact(block_len))
+        .skip(1)
+        .filter(|(block, tone)| power(tone) >= power(block) * MIN_TONE_SHARE)
+        // The peak of a sine is sqrt(2) times its RMS
+        .map(|(_, tone)| amplitude_to_db((power(tone) * 2.0).sqrt()))
+        .collect();
+    if levels_db.len() < MIN_BLOCKS {
+        return Err(format!("No steady tone was heard at {frequency_in_hz} Hz"));
+    }
+
+    let (low_db, high_db) = levels_db
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(low, high), level| {
+            (low.min(*level), high.max(*level))
+        });
+    if high_db - low_db > MAX_SPREAD_DB {
+        return Err(format!(
+            "The tone wasn't steady, it went from {low_db:.1} to {high_db:.1} dBFS"
+        ));
+    }
+    Ok(levels_db.iter().sum::<f32>() / levels_db.len() as f32)
+}
+
+/**
+ * Listens to the reference tone and writes the calibration file that makes its reading match
+ * the reference level
+ */
+pub fn run(args: &CalibrateLevelArgs) -> Result<(), String> {
+    let curve = match &args.mic_calibration {
+        Some(path) => Curve::load(path)?,
+        None => Curve::default(),
+    };
+
+    let recording = Arc::new(Mutex::new(Vec::<f32>::new()));
+    let stream_recording = recording.clone();
+    let on_samples: SampleCallback = Arc::new(Mutex::new(move |data: &[f32], _: Instant| {
+        stream_recording.lock().unwrap().extend_from_slice(data)
+    }));
+    let (input, _) = open_input_stream(None, args.sample_rate, on_samples, |error| {
+        eprintln!("\nInput error: {error}")
+    })?;
+    println!(
+        "Listening to the {} Hz reference tone for {} s",
+        args.reference_hz, args.secs
+    );
+    input.play().map_err(|error| error.to_string())?;
+    std::thread::sleep(Duration::from_secs_f32(args.secs));
+    drop(input);
+
+    let samples = recording.lock().unwrap().clone();
+    // The reading of the analysis, with the calibration it already had
+    let read_db = measure_tone(&samples, args.reference_hz, args.sample_rate)?
+        - curve.db_at(args.reference_hz);
+    let offset_db = read_db - args.reference_db;
+    println!(
+        "Read {read_db:.2} dBFS for a {} dBFS reference, {:+.2} dB off",
+        args.reference_db, -offset_db
+    );
+
+    curve
+        .offset(offset_db)
+        .save(
+            &args.out,
+            &format!(
+                "Level calibrated with {} Hz at {} dBFS",
+                args.reference_hz, args.reference_db
+            ),
+        )
+        .map_err(|error| error.to_string())?;
+    println!(
+        "Saved to {}, use it with --mic-calibration",
+        args.out.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::db_to_amplitude;
+
+    fn tone_with_noise(level_db: f32, noise: f32) -> Vec<f32> {
+        let sample_rate = 48000;
+        let mut noise_state = 0x1234_5678u32;
+        (0..sample_rate * 2)
+            .map(|i| {
+                noise_state ^= noise_state << 13;
+                noise_state ^= noise_state >> 17;
+                noise_state ^= noise_state << 5;
+                let white = noise_state as f32 / u32::MAX as f32 - 0.5;
+                let phase = 2.0 * std::f32::consts::PI * 1003.0 * i as f32 / sample_rate as f32;
+                db_to_amplitude(level_db) * phase.sin() + white * noise
+            })
+            .collect()
+    }
+
+    #[test]
+    fn measures_a_tone_slightly_off_its_frequency() {
+        let level_db = measure_tone(&tone_with_noise(-26.3, 0.001), 1000.0, 48000).unwrap();
+        assert!((level_db - -26.3).abs() < 0.1, "{level_db}");
+    }
+
+    #[test]
+    fn noise_is_not_a_tone() {
+        assert!(measure_tone(&tone_with_noise(-60.0, 0.1), 1000.0, 48000).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    collections::VecDeque,
+    io::{stdout, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use tract_onnx::prelude::*;
+
+use crate::analysis::{BinMapping, FftFrame};
+
+// Range of the mel bands, the one YAMNet and most sound event models are trained with
+const MEL_LOW_HZ: f32 = 125.0;
+const MEL_HIGH_HZ: f32 = 7500.0;
+// Added before the log so silent bands don't go to minus infinity
+const LOG_OFFSET: f32 = 0.001;
+
+pub struct ClassifierConfig {
+    pub model: PathBuf,
+    pub labels: Option<PathBuf>,
+    pub mels: usize,
+    // Shape of the input tensor, its last dimension is the mel bands and the others hold frames
+    pub input_shape: Vec<usize>,
+    pub threshold: f32,
+}
+
+fn hz_to_mel(frequency_in_hz: f32) -> f32 {
+    2595.0 * (1.0 + frequency_in_hz / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+/**
+ * Triangular filters spaced evenly on the mel scale, turning a spectrum into the log mel bands
+ * the models take
+ */
+pub struct MelFilterbank {
+    // Bins and weights of each filter
+    filters: Vec<Vec<(usize, f32)>>,
+}
+
+impl MelFilterbank {
+    pub fn new(mels: usize, mapping: BinMapping) -> Self {
+        let high_hz = MEL_HIGH_HZ.min(mapping.sample_rate as f32 / 2.0);
+        let (low_mel, high_mel) = (hz_to_mel(MEL_LOW_HZ), hz_to_mel(high_hz));
+        let edges: Vec<f32> = (0..mels + 2)
+            .map(|i| mel_to_hz(low_mel + (high_mel - low_mel) * i as f32 / (mels + 1) as f32))
+            .collect();
+
+        let filters = edges
+            .windows(3)
+            .map(|edge| {
+                let (low, center, high) = (edge[0], edge[1], edge[2]);
+                (mapping.bin_at(low)..=mapping.bin_at(high))
+                    .filter_map(|bin| {
+                        let frequency_in_hz = mapping.frequency_in_hz(bin);
+                        let weight = if frequency_in_hz <= center {
+                            (frequency_in_hz - low) / (center - low)
+                        } else {
+                            (high - frequency_in_hz) / (high - center)
+                        };
+                        (weight > 0.0).then_some((bin, weight))
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { filters }
+    }
+
+    pub fn apply(&self, amplitudes: &[f32]) -> Vec<f32> {
+        self.filters
+            .iter()
+            .map(|filter| {
+                let energy: f32 = filter
+                    .iter()
+                    .filter_map(|(bin, weight)| amplitudes.get(*bin).map(|a| a * a * weight))
+                    .sum();
+                (energy + LOG_OFFSET).ln()
+            })
+            .collect()
+    }
+}
+
+/**
+ * Reads the class names, one per line. For a CSV like YAMNet's class map, the last column of
+ * each line after the header is taken.
+ */
+fn load_labels(path: &std::path::Path) -> Result<Vec<String>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|error| format!("Could not read {}: {error}", path.display()))?;
+    let is_csv = content
+        .lines()
+        .next()
+        .is_some_and(|line| line.contains(','));
+    Ok(content
+        .lines()
+        .skip(is_csv as usize)
+        .map(|line| match is_csv {
+            // The names themselves may contain quoted commas
+            true => line
+                .splitn(3, ',')
+                .last()
+                .unwrap_or(line)
+                .trim_matches('"')
+                .to_string(),
+            false => line.trim().to_string(),
+        })
+        .collect())
+}
+
+/**
+ * Loads the model and spawns a thread that classifies the last frames every time a new FFT
+ * result comes, printing the best label whenever it changes
+ */
+pub fn spawn(
+    config: ClassifierConfig,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+) -> Result<JoinHandle<()>, String> {
+    let input_len: usize = config.input_shape.iter().product();
+    if config.mels == 0 || !input_len.is_multiple_of(config.mels) {
+        return Err(format!(
+            "The input shape {:?} doesn't hold whole frames of {} mel bands",
+            config.input_shape, config.mels
+        ));
+    }
+    let frames = input_len / config.mels;
+    let model = tract_onnx::onnx()
+        .model_for_path(&config.model)
+        .and_then(|model| model.with_input_fact(0, f32::fact(&config.input_shape).into()))
+        .and_then(|model| model.into_optimized())
+        .and_then(|model| model.into_runnable())
+        .map_err(|error| format!("Could not load {}: {error}", config.model.display()))?;
+    let labels = match &config.labels {
+        Some(path) => load_labels(path)?,
+        None => vec![],
+    };
+
+    Ok(std::thread::spawn(move || {
+        let mut filterbank: Option<(usize, MelFilterbank)> = None;
+        let mut history: VecDeque<Vec<f32>> = VecDeque::new();
+        let mut last_label: Option<usize> = None;
+        let mut last_generation = 0;
+        loop {
+            std::thread::sleep(Duration::from_millis(5));
+            let frame = fft_transform.lock().unwrap().clone();
+            if frame.generation == last_generation {
+                continue;
+            }
+            last_generation = frame.generation;
+
+            let fft_len = frame.magnitudes.len();
+            if filterbank.as_ref().is_none_or(|(len, _)| *len != fft_len) {
+                let mapping = BinMapping::new(sample_rate, fft_len);
+                filterbank = Some((fft_len, MelFilterbank::new(config.mels, mapping)));
+                history.clear();
+            }
+            let (_, filterbank) = filterbank.as_ref().unwrap();
+            history.push_back(filterbank.apply(&frame.amplitudes));
+            if history.len() > frames {
+                history.pop_front();
+            }
+            if history.len() < frames {
+                continue;
+            }
+
+            let input: Vec<f32> = history.iter().flatten().copied().collect();
+            let scores =
+                tract_ndarray::ArrayD::from_shape_vec(config.input_shape.as_slice(), input)
+                    .map_err(|error| error.to_string())
+                    .and_then(|input| {
+                        model
+                            .run(tvec!(Tensor::from(input).into()))
+                            .map_err(|error| error.to_string())
+                    })
+                    .and_then(|outputs| {
+                        outputs[0]
+                            .as_slice::<f32>()
+                            .map(<[f32]>::to_vec)
+                            .map_err(|error| error.to_string())
+                    });
+            let scores = match scores {
+                Ok(scores) => scores,
+                Err(error) => {
+                    eprintln!("\nClassification failed: {error}");
+                    continue;
+                }
+            };
+
+            let best = scores
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .filter(|(_, score)| **score >= config.threshold)
+                .map(|(index, _)| index);
+            if best != last_label {
+                last_label = best;
+                if let Some(index) = best {
+                    let name = labels
+                        .get(index)
+                        .cloned()
+                        .unwrap_or_else(|| format!("class {index}"));
+                    println!("\nSound: {name} ({:.2})", scores[index]);
+                    stdout().flush().unwrap();
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mel_filters_pick_up_a_tone_in_its_own_band() {
+        let sample_rate = 16000;
+        let fft_len = 1024;
+        let mapping = BinMapping::new(sample_rate, fft_len);
+        let filterbank = MelFilterbank::new(64, mapping);
+
+        let mut amplitudes = vec![0.0; fft_len / 2];
+        amplitudes[mapping.bin_at(1000.0)] = 1.0;
+        let mels = filterbank.apply(&amplitudes);
+
+        let loudest = (0..mels.len())
+            .max_by(|a, b| mels[*a].total_cmp(&mels[*b]))
+            .unwrap();
+        let center_mel = hz_to_mel(MEL_LOW_HZ)
+            + (hz_to_mel(MEL_HIGH_HZ) - hz_to_mel(MEL_LOW_HZ)) * (loudest + 1) as f32 / 65.0;
+        assert!((mel_to_hz(center_mel) / 1000.0 - 1.0).abs() < 0.05);
+        // The bands far from the tone only have the offset
+        assert_eq!(mels[0], LOG_OFFSET.ln());
+    }
+}
@@ -0,0 +1,1064 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::{
+    alarm::{AlarmRule, Webhook},
+    analysis::{Band, FrequencyScale},
+    artnet::DmxMapping,
+    dose::DoseStandard,
+    fsk::FskOutput,
+    intent::Intent,
+    meters::MeterBand,
+    serial::SerialFormat,
+    source::Input,
+    units::{AmplitudeUnit, FrequencyUnit},
+    vad::Vad,
+    whistle::WhistleCommand,
+    windowing::WindowFunction,
+    wled::Colormap,
+    MAX_FFT_SIZE, MIN_FFT_SIZE,
+};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Real time microphone frequency analyzer")]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Sends the analysis results as OSC messages to this address (e.g. 127.0.0.1:9000)
+    #[arg(long)]
+    pub osc: Option<SocketAddr>,
+
+    /// Address prefix of the OSC messages
+    #[arg(long, default_value = "/analyzer")]
+    pub osc_prefix: String,
+
+    /// Time between two OSC updates in milliseconds
+    #[arg(long, default_value_t = 50)]
+    pub osc_interval_ms: u64,
+
+    /// Publishes periodic summaries to this MQTT broker (host or host:port)
+    #[arg(long)]
+    pub mqtt: Option<String>,
+
+    /// MQTT topic the summaries are published to
+    #[arg(long, default_value = "fft_analyzer/summary")]
+    pub mqtt_topic: String,
+
+    /// Time between two MQTT summaries in seconds
+    #[arg(long, default_value_t = 10)]
+    pub mqtt_interval_secs: u64,
+
+    #[arg(long, requires = "mqtt_password")]
+    pub mqtt_username: Option<String>,
+
+    #[arg(long, requires = "mqtt_username")]
+    pub mqtt_password: Option<String>,
+
+    /// Serves the latest analysis as JSON on this address (e.g. 127.0.0.1:8080)
+    #[arg(long)]
+    pub http: Option<SocketAddr>,
+
+    /// Only sends the pitch over OSC, HTTP and MQTT while someone is singing or speaking: quiet frames and noisy ones
+    /// (breaths, hiss, the room) leave it out
+    #[arg(long)]
+    pub vad: bool,
+
+    /// Level in dBFS of the 80 - 4000 Hz band below which --vad counts a frame as silence
+    #[arg(long, default_value_t = -50.0, allow_hyphen_values = true)]
+    pub vad_level_db: f32,
+
+    /// Spectral flatness (0 for a pure tone, 1 for white noise) above which --vad counts a frame
+    /// as noise
+    #[arg(long, default_value_t = 0.3)]
+    pub vad_flatness: f32,
+
+    /// Streams the band levels to this serial port (e.g. /dev/ttyUSB0 or COM3)
+    #[arg(long)]
+    pub serial: Option<String>,
+
+    #[arg(long, default_value_t = 115200)]
+    pub serial_baud: u32,
+
+    #[arg(long, value_enum, default_value_t = SerialFormat::Binary)]
+    pub serial_format: SerialFormat,
+
+    /// Frames written to the serial port per second
+    #[arg(long, default_value_t = 30)]
+    pub serial_fps: u32,
+
+    /// Number of logarithmic bands sent to the serial port when no --band is given
+    #[arg(long, default_value_t = 16)]
+    pub serial_bands: usize,
+
+    /// Level in dBFS sent as 0 to the serial port
+    #[arg(long, default_value_t = -80.0, allow_hyphen_values = true)]
+    pub serial_floor_db: f32,
+
+    /// Renders the spectrum on a WLED strip at this address (ip or ip:port)
+    #[arg(long)]
+    pub wled: Option<String>,
+
+    /// Number of LEDs of the WLED strip
+    #[arg(long, default_value_t = 60)]
+    pub wled_leds: usize,
+
+    /// How the bins are spread over the LEDs
+    #[arg(long, value_enum, default_value_t = FrequencyScale::Logarithmic)]
+    pub wled_scale: FrequencyScale,
+
+    #[arg(long, value_enum, default_value_t = Colormap::Amplitude)]
+    pub wled_colormap: Colormap,
+
+    /// Frequency in Hz of the first LED
+    #[arg(long, default_value_t = 40.0)]
+    pub wled_min_freq: f32,
+
+    /// Frequency in Hz of the last LED
+    #[arg(long, default_value_t = 16000.0)]
+    pub wled_max_freq: f32,
+
+    #[arg(long, default_value_t = 30)]
+    pub wled_fps: u32,
+
+    /// The strip goes dark when the loudest LED is below this level in dBFS
+    #[arg(long, default_value_t = -70.0, allow_hyphen_values = true)]
+    pub wled_floor_db: f32,
+
+    /// Serves the analysis frames over gRPC on this address (e.g. 127.0.0.1:50051)
+    #[cfg(feature = "grpc")]
+    #[arg(long)]
+    pub grpc: Option<SocketAddr>,
+
+    /// ONNX model classifying the sound from log mel frames (e.g. a YAMNet style classifier)
+    #[cfg(feature = "onnx")]
+    #[arg(long)]
+    pub onnx_model: Option<PathBuf>,
+
+    /// Names of the classes of --onnx-model, one per line or the last column of a CSV
+    #[cfg(feature = "onnx")]
+    #[arg(long, requires = "onnx_model")]
+    pub onnx_labels: Option<PathBuf>,
+
+    /// Number of mel bands of each frame, from 125 to 7500 Hz
+    #[cfg(feature = "onnx")]
+    #[arg(long, default_value_t = 64)]
+    pub onnx_mels: usize,
+
+    /// Shape of the model input, the frames are laid out one after the other (e.g. 1,96,64)
+    #[cfg(feature = "onnx")]
+    #[arg(long, default_value = "1,96,64", value_delimiter = ',')]
+    pub onnx_input_shape: Vec<usize>,
+
+    /// Score the best class needs to be printed
+    #[cfg(feature = "onnx")]
+    #[arg(long, default_value_t = 0.3)]
+    pub onnx_threshold: f32,
+
+    /// TrueType font of the text of the window, a monospace font of the system when not given
+    #[cfg(feature = "ttf")]
+    #[arg(long)]
+    pub font: Option<PathBuf>,
+
+    /// Publishes every spectrum into this memory mapped file (e.g. /dev/shm/fft_analyzer)
+    #[arg(long)]
+    pub shm: Option<PathBuf>,
+
+    /// Sends the band levels as Art-Net to this node (ip or ip:port, broadcast addresses work)
+    #[arg(long)]
+    pub artnet: Option<String>,
+
+    #[arg(long, default_value_t = 0)]
+    pub artnet_universe: u16,
+
+    /// Art-Net packets sent per second
+    #[arg(long, default_value_t = 30)]
+    pub artnet_fps: u32,
+
+    /// Level in dBFS sent as 0 to the DMX channels
+    #[arg(long, default_value_t = -60.0, allow_hyphen_values = true)]
+    pub artnet_floor_db: f32,
+
+    /// DMX channel driven by a band (e.g. 1=20-250). Can be repeated. When missing, each --band
+    /// drives the channels in order starting from 1
+    #[arg(long = "dmx")]
+    pub dmx_mappings: Vec<DmxMapping>,
+
+    /// Input device to open instead of the default one, its number in --list-devices or its
+    /// name or part of it (e.g. "USB Mic")
+    #[arg(long)]
+    pub device: Option<String>,
+
+    /// Lists the input devices with their numbers and the configurations they support, then
+    /// exits
+    #[arg(long)]
+    pub list_devices: bool,
+
+    /// Sample rate the mic is opened with, 96000 or 192000 reach into the ultrasound on devices
+    /// that support them
+    #[arg(long, default_value_t = 44100)]
+    pub sample_rate: u32,
+
+    /// Number of samples of each FFT the graph starts with (4096 by default), a power of two
+    /// from 1024 to 65536. The [ and ] keys still change it
+    #[arg(long, value_parser = parse_fft_size, conflicts_with = "infrasound")]
+    pub fft_size: Option<usize>,
+
+    /// Window function the samples are multiplied by before each FFT (Hann by default, or the
+    /// one of --intent). The O key switches to the next one while running
+    #[arg(long, value_enum)]
+    pub window: Option<WindowFunction>,
+
+    /// Highest frequency on the graph in Hz (3000, or 20 with --infrasound), it stops at half
+    /// the sample rate anyway
+    #[arg(long)]
+    pub max_freq: Option<usize>,
+
+    /// Frequency (like 440, 1.2 kHz) or note (like A4, C#3) shown in the middle of the graph
+    /// and pinned with a line, the J key does the same with the clipboard
+    #[arg(long, value_parser = crate::units::parse_frequency)]
+    pub go_to: Option<f32>,
+
+    /// Analyzes 0.1 - 20 Hz: the samples are decimated to 100 Hz without their DC offset, the
+    /// FFT windows start at ~10 s and a slow spectrogram runs under the graph
+    #[arg(long)]
+    pub infrasound: bool,
+
+    /// Starts with the FFT size and the range suited to what is being done (e.g. bass-tuning),
+    /// see the suggest command for the reasoning
+    #[arg(long, value_enum, conflicts_with = "infrasound")]
+    pub intent: Option<Intent>,
+
+    /// Plays this band (e.g. 38000-46000) brought down to the audible range, like a heterodyne
+    /// bat detector
+    #[arg(long)]
+    pub heterodyne: Option<Band>,
+
+    /// Plays the input through the default output shifted by this many semitones (e.g. -12 for
+    /// an octave down), from -12 to 12
+    #[arg(long, allow_hyphen_values = true, value_parser = parse_semitones, conflicts_with = "heterodyne")]
+    pub pitch_shift: Option<f32>,
+
+    /// Q of the band-pass H plays the selected frequency through, higher is narrower
+    #[arg(long, default_value_t = 8.0)]
+    pub listen_q: f32,
+
+    /// Draws the pitch of the voice over time under the graph, with its median and range over
+    /// the session. Frames are left out as --vad would.
+    #[arg(long, conflicts_with = "infrasound")]
+    pub voice_training: bool,
+
+    /// Field recording of birds: shows up to 12 kHz over a minute long spectrogram, and saves
+    /// every tone standing out of --birds-band into a WAV clip in --birds-dir
+    #[arg(long, conflicts_with_all = ["infrasound", "voice_training"])]
+    pub birds: bool,
+
+    /// Measures the wow and flutter of a turntable or tape deck playing a test tone, drawing the
+    /// speed deviation under the graph
+    #[arg(long, conflicts_with_all = ["infrasound", "voice_training", "birds"])]
+    pub wow_flutter: bool,
+
+    /// Frequency of the test tone: 3150 Hz for IEC/DIN test records and tapes, 3000 Hz for JIS
+    #[arg(long, default_value_t = 3150.0, requires = "wow_flutter")]
+    pub wow_flutter_hz: f32,
+
+    /// Guides through the intonation of a guitar, comparing the 12th fret of each string with
+    /// the octave of the open string
+    #[arg(long, conflicts_with_all = ["infrasound", "voice_training", "birds", "wow_flutter"])]
+    pub guitar_intonation: bool,
+
+    /// Analyzes each frame through two window functions and draws both spectra one above the
+    /// other, to see how much each one leaks (e.g. --compare-windows rectangular hann)
+    #[arg(
+        long,
+        value_enum,
+        num_args = 2,
+        value_names = ["WINDOW_A", "WINDOW_B"],
+        conflicts_with_all = ["infrasound", "voice_training", "birds", "wow_flutter", "guitar_intonation", "drum_lugs"]
+    )]
+    pub compare_windows: Option<Vec<WindowFunction>>,
+
+    /// Runs a naive DFT and the FFT over each frame and draws both spectra with the time each
+    /// one took
+    #[arg(
+        long,
+        conflicts_with_all = ["infrasound", "voice_training", "birds", "wow_flutter", "guitar_intonation", "drum_lugs", "compare_windows"]
+    )]
+    pub compare_dft: bool,
+
+    /// Rows of the spectrogram of --infrasound and --birds kept for Shift+W to export, on top of
+    /// the ones on screen
+    #[arg(long, default_value_t = 10000)]
+    pub spectrogram_history_rows: usize,
+
+    /// Band (e.g. 1000-12000) the bird songs are looked for in
+    #[arg(long, default_value = "1000-12000")]
+    pub birds_band: Band,
+
+    /// How far in dB above the median level of --birds-band a tone has to be to be saved
+    #[arg(long, default_value_t = 20.0)]
+    pub birds_margin_db: f32,
+
+    /// Directory the bird clips and their events.csv are written to
+    #[arg(long, default_value = "birds")]
+    pub birds_dir: PathBuf,
+
+    /// Pitch range in Hz (e.g. 165-255) shaded on the voice pitch track
+    #[arg(long, requires = "voice_training")]
+    pub voice_target: Option<Band>,
+
+    /// CSV file the statistics of each voice training session are appended to at the end
+    #[arg(long, requires = "voice_training")]
+    pub voice_log: Option<PathBuf>,
+
+    /// Writes down every note held while running, and saves them to --report-dir as a CSV
+    /// timeline, a MIDI file and a piano roll image when the window is closed
+    #[arg(long)]
+    pub transcribe: bool,
+
+    /// Unit the frequencies are written in by the readouts, the tables and the reports (U key)
+    #[arg(long, value_enum, default_value_t = FrequencyUnit::Hz)]
+    pub frequency_unit: FrequencyUnit,
+
+    /// Unit the levels are written in by the readouts and the tables (Shift+U). dbu and dbv
+    /// need --full-scale-dbu, spl needs --spl-offset-db.
+    #[arg(long, value_enum, default_value_t = AmplitudeUnit::Dbfs)]
+    pub amplitude_unit: AmplitudeUnit,
+
+    /// Level in dBu of a full scale sine at the input of the interface, from its specifications
+    #[arg(long, default_value_t = 24.0, allow_hyphen_values = true)]
+    pub full_scale_dbu: f32,
+
+    /// Frames drawn per second while something on the screen is changing
+    #[arg(long, default_value_t = 60)]
+    pub fps: u32,
+
+    /// Time in milliseconds the bars take to glide towards a new height, 0 disables it
+    #[arg(long, default_value_t = 60)]
+    pub smoothing_ms: u64,
+
+    /// Starts with the scale locked to a sine at this level in dBFS, instead of the loudest bin
+    /// of each frame (N locks it to the current loudest bin)
+    #[arg(long, allow_hyphen_values = true, conflicts_with = "db_scale")]
+    pub lock_scale_db: Option<f32>,
+
+    /// Starts with the bars in dB between --db-top and --db-floor, instead of scaling every
+    /// frame to its loudest bin (Shift+N switches)
+    #[arg(long)]
+    pub db_scale: bool,
+
+    /// Starts with a logarithmic frequency axis, which L switches on and off
+    #[arg(long)]
+    pub log_frequency: bool,
+
+    /// Level in dBFS at the top of the graph with --db-scale (Shift+Up/Down)
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    pub db_top: f32,
+
+    /// Level in dBFS at the bottom of the graph with --db-scale (Page Up/Down)
+    #[arg(long, default_value_t = -100.0, allow_hyphen_values = true)]
+    pub db_floor: f32,
+
+    /// Shows the peak, RMS and crest factor of the input, and whether its gain should be raised
+    /// or lowered to use the range of the converter without clipping
+    #[arg(long)]
+    pub gain_advisor: bool,
+
+    /// Also prints the readout of the hovered bar and the pitch as a status line in the
+    /// terminal, they're always shown in the window
+    #[arg(long)]
+    pub status_line: bool,
+
+    /// Stops analyzing and drawing once the input stayed below --auto-pause-db for this many
+    /// seconds, and starts again as soon as it gets louder
+    #[arg(long, conflicts_with_all = ["daemon", "replay"])]
+    pub auto_pause_secs: Option<f32>,
+
+    /// Level in dBFS below which the input counts as silence for --auto-pause-secs
+    #[arg(long, default_value_t = -60.0, allow_hyphen_values = true)]
+    pub auto_pause_db: f32,
+
+    /// Also closes the mic while paused, it's then opened for a quarter of a second every
+    /// second to listen for a signal
+    #[arg(long, requires = "auto_pause_secs")]
+    pub auto_pause_release: bool,
+
+    /// Records a WAV file in this directory every time something with a pitch starts playing,
+    /// until it stayed silent for --take-silence-secs
+    #[arg(long, conflicts_with_all = ["daemon", "replay"])]
+    pub auto_takes: Option<PathBuf>,
+
+    /// Seconds without a pitch that end a take of --auto-takes
+    #[arg(long, default_value_t = 5.0, requires = "auto_takes", value_parser = parse_positive_secs)]
+    pub take_silence_secs: f32,
+
+    /// Records how long each stage of the pipeline takes and prints a report when quitting
+    #[arg(long)]
+    pub profile: bool,
+
+    /// Pauses the graph and saves a WAV when the level reaches this value in dBFS
+    #[arg(long, allow_hyphen_values = true)]
+    pub trigger: Option<f32>,
+
+    /// Band (e.g. 20-250) whose level is compared to --trigger instead of the whole spectrum
+    #[arg(long)]
+    pub trigger_band: Option<Band>,
+
+    /// Milliseconds of audio before the triggering frame saved along with it
+    #[arg(long, default_value_t = 500)]
+    pub pre_trigger_ms: u64,
+
+    /// Only analyzes this many milliseconds of a trigger capture, to leave out the room
+    /// reflections that arrive after the direct sound
+    #[arg(long, requires = "trigger")]
+    pub gate_ms: Option<f32>,
+
+    /// Where the gate starts, in milliseconds after the peak of the captured impulse
+    #[arg(long, default_value_t = -0.5, allow_hyphen_values = true)]
+    pub gate_start_ms: f32,
+
+    /// Drum tuning with a head of this many lugs: every --trigger capture is a tap near the
+    /// next lug, whose pitch is placed around a drum diagram instead of pausing the graph
+    #[arg(
+        long,
+        requires = "trigger",
+        conflicts_with_all = ["infrasound", "voice_training", "birds", "wow_flutter", "guitar_intonation"],
+        value_parser = clap::value_parser!(u8).range(2..=24)
+    )]
+    pub drum_lugs: Option<u8>,
+
+    /// Band (e.g. 50-1000) the pitch of the drum taps is looked for in
+    #[arg(long, default_value = "50-1000")]
+    pub drum_band: Band,
+
+    /// Band of the meter row (E key), as name=low-high (e.g. bass=60-250). Can be repeated.
+    /// Defaults to sub, bass, mid, presence and treble
+    #[arg(long = "meter-band")]
+    pub meter_bands: Vec<MeterBand>,
+
+    /// Level in dBFS of an empty meter
+    #[arg(long, default_value_t = -60.0, allow_hyphen_values = true)]
+    pub meter_floor_db: f32,
+
+    /// House curve for the pink noise target (G key), one "frequency dB" pair per line.
+    /// Defaults to flat
+    #[arg(long)]
+    pub target_curve: Option<PathBuf>,
+
+    /// Calibration file of the measurement mic (frequency and dB pairs, like the ones of
+    /// miniDSP and Dayton mics) used to correct the spectrum of the display and every output
+    #[arg(long)]
+    pub mic_calibration: Option<PathBuf>,
+
+    /// Number of frames in a row a peak has to grow for to be flagged as feedback (F key)
+    #[arg(long, default_value_t = 5)]
+    pub feedback_frames: usize,
+
+    /// How far in dB above the median of the spectrum a peak has to be to be flagged as feedback
+    #[arg(long, default_value_t = 25.0)]
+    pub feedback_margin_db: f32,
+
+    /// Maximum number of EQ filters suggested (Q key)
+    #[arg(long, default_value_t = 8)]
+    pub eq_filters: usize,
+
+    /// Number of peaks listed by the peak table (T key)
+    #[arg(long, default_value_t = 5)]
+    pub peak_count: usize,
+
+    /// Number of harmonics, counting the fundamental, placed by the harmonic cursors
+    #[arg(long, default_value_t = 10)]
+    pub harmonics: usize,
+
+    /// Number of spectra averaged by a measurement session (M key)
+    #[arg(long, default_value_t = 100)]
+    pub session_frames: usize,
+
+    /// Directory where the measurement session reports are written
+    #[arg(long, default_value = ".")]
+    pub report_dir: PathBuf,
+
+    /// Alarm on a band, as [name=]low-high:threshold_db[:seconds] (e.g. hiss=2000-4000:-10:3 fires
+    /// when 2-4 kHz stays above -10 dBFS for 3 seconds). Can be repeated.
+    #[arg(long = "alarm", allow_hyphen_values = true)]
+    pub alarms: Vec<AlarmRule>,
+
+    /// Rings the terminal bell when an alarm fires
+    #[arg(long)]
+    pub alarm_bell: bool,
+
+    /// Shell command run when an alarm fires, with ALARM_NAME, ALARM_LOW_HZ, ALARM_HIGH_HZ,
+    /// ALARM_LEVEL_DB and ALARM_THRESHOLD_DB set
+    #[arg(long)]
+    pub alarm_command: Option<String>,
+
+    /// http:// URL the alarms are POSTed to as JSON
+    #[arg(long)]
+    pub alarm_webhook: Option<Webhook>,
+
+    /// Logs the noise exposure (LAeq and daily dose) as CSV files inside this directory
+    #[arg(long)]
+    pub dose_log: Option<PathBuf>,
+
+    /// Offset in dB from the dBFS of the analysis to dB SPL, found by calibrating the mic with a
+    /// sound level meter or a 94 dB calibrator
+    #[arg(long, default_value_t = 120.0, allow_hyphen_values = true)]
+    pub spl_offset_db: f32,
+
+    /// Occupational standard the noise dose is computed with
+    #[arg(long, value_enum, default_value_t = DoseStandard::Niosh)]
+    pub dose_standard: DoseStandard,
+
+    /// Time between two rows of the noise log in seconds
+    #[arg(long, default_value_t = 60)]
+    pub dose_interval_secs: u64,
+
+    /// File of key bindings, one "action = key" line each (e.g. pause = Space), see the README
+    #[arg(long)]
+    pub keys: Option<PathBuf>,
+
+    /// Action triggered by whistling inside a band, as low-high=action with the action names of
+    /// --keys (e.g. 1500-2000=pause). Can be repeated.
+    #[arg(long = "whistle")]
+    pub whistle_commands: Vec<WhistleCommand>,
+
+    /// Milliseconds a whistle has to last to trigger its action
+    #[arg(long, default_value_t = 600)]
+    pub whistle_hold_ms: u64,
+
+    /// How far in dB above the median of the spectrum a tone has to be to count as a whistle
+    #[arg(long, default_value_t = 35.0)]
+    pub whistle_margin_db: f32,
+
+    /// Decodes Morse code keyed inside --morse-band and prints the text
+    #[arg(long)]
+    pub morse: bool,
+
+    /// Band (e.g. 400-1000) the Morse tone is looked for in
+    #[arg(long, default_value = "300-1500")]
+    pub morse_band: Band,
+
+    /// Demodulates two-tone FSK (RTTY by default) and prints the characters
+    #[arg(long)]
+    pub fsk: bool,
+
+    /// Frequency of the mark tone (1 bits and stop bits) in Hz
+    #[arg(long, default_value_t = 2125.0)]
+    pub fsk_mark_hz: f32,
+
+    /// Frequency of the space tone (0 bits and start bits) in Hz
+    #[arg(long, default_value_t = 2295.0)]
+    pub fsk_space_hz: f32,
+
+    /// Bits per second of the FSK signal
+    #[arg(long, default_value_t = 45.45)]
+    pub fsk_baud: f32,
+
+    /// Data bits of each FSK character, between the start and stop bits
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u8).range(1..=16))]
+    pub fsk_data_bits: u8,
+
+    /// How the FSK characters are printed
+    #[arg(long, value_enum, default_value_t = FskOutput::Baudot)]
+    pub fsk_output: FskOutput,
+
+    /// Replays a recording of spectra (W key) instead of listening to the mic
+    #[arg(long, conflicts_with = "daemon")]
+    pub replay: Option<PathBuf>,
+
+    /// Where the samples come from: mic, or synth: followed by tones and noise, like
+    /// synth:440,1000@-30,noise@-60 (tones in Hz at -20 dBFS unless given a level)
+    #[arg(long, default_value = "mic", conflicts_with_all = ["daemon", "replay", "auto_pause_release"])]
+    pub input: Input,
+
+    /// Runs without a window until killed, logging the levels to --log-dir and opening the
+    /// device again when it goes away. The other outputs keep working as usual.
+    #[arg(long)]
+    pub daemon: bool,
+
+    /// Directory of the level log written in daemon mode
+    #[arg(long, default_value = ".")]
+    pub log_dir: PathBuf,
+
+    /// Time between two rows of the daemon level log in seconds
+    #[arg(long, default_value_t = 60)]
+    pub log_interval_secs: u64,
+
+    /// Hours after which the daemon and noise logs start a new file
+    #[arg(long, default_value_t = 24)]
+    pub log_rotate_hours: u64,
+
+    /// Seconds between two attempts to open the device again in daemon mode
+    #[arg(long, default_value_t = 5)]
+    pub reconnect_secs: u64,
+
+    /// Directory where the WAV snapshots (S key) and the trigger captures are saved
+    #[arg(long, default_value = ".")]
+    pub snapshot_dir: PathBuf,
+
+    /// Spectrum saved earlier to draw over the live one, a measurement CSV or a frequency /
+    /// level curve. Repeat it to compare several takes, their names in the legend can be
+    /// clicked to show or hide them
+    #[arg(long)]
+    pub overlay: Vec<PathBuf>,
+
+    /// Directory where the timbre fingerprints (X key) are saved and compared from
+    #[arg(long, default_value = "timbres")]
+    pub timbre_dir: PathBuf,
+
+    /// Frequency band in Hz whose level is sent by the outputs (e.g. 20-250). Can be repeated
+    #[arg(long = "band")]
+    pub bands: Vec<Band>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Analyzes an audio file without opening a window
+    Analyze(AnalyzeArgs),
+    /// Reads raw PCM from stdin and writes one spectrum per frame to stdout
+    Pipe(PipeArgs),
+    /// Plays tones or narrowband noise to find the frequency and level of a tinnitus
+    Tinnitus(TinnitusArgs),
+    /// Plays tones at decreasing levels to plot the quietest one heard at each frequency
+    HearingTest(HearingTestArgs),
+    /// Checks the polarity and the frequency response of the drivers of a speaker
+    Speaker(SpeakerArgs),
+    /// Plays a slow sine sweep and lists the frequencies where the room or an object resonates
+    /// or rattles
+    Resonance(ResonanceArgs),
+    /// Listens to a reference tone of known level, like the 1 kHz oscillator of a console, and
+    /// writes a calibration file that makes the readings match it
+    CalibrateLevel(CalibrateLevelArgs),
+    /// Captures the same stimulus with some processing bypassed then engaged, and writes the
+    /// curve of what the processing does, like the tone controls of an amp
+    EqDiff(EqDiffArgs),
+    /// Freezes a few samples from the mic and shows the FFT computing their spectrum one step
+    /// at a time
+    FftSteps(FftStepsArgs),
+    /// Runs known tones through the analysis and checks the frequencies, levels and notes it
+    /// reads, without any audio hardware
+    SelfTest(SelfTestArgs),
+    /// Suggests the FFT size, window, overlap and range for what is being done, like tuning a
+    /// bass or measuring noise, with the command lines using them
+    Suggest(SuggestArgs),
+    /// Opens the input in stereo and shows where each octave band sits between the channels,
+    /// and which ones cancel out when summed to mono
+    Stereo(StereoArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct AnalyzeArgs {
+    /// WAV file to analyze
+    pub input: PathBuf,
+
+    /// Renders the spectrogram of the whole file into this PNG
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+
+    /// Writes the pitch and the strongest peaks of every frame into this CSV
+    #[arg(long)]
+    pub csv: Option<PathBuf>,
+
+    /// Number of samples of each FFT, a power of two from 1024 to 65536
+    #[arg(long, default_value_t = 4096, value_parser = parse_fft_size)]
+    pub fft_size: usize,
+
+    /// Number of samples between the start of two frames. Defaults to a quarter of the FFT size
+    #[arg(long)]
+    pub hop: Option<usize>,
+
+    /// Highest frequency in Hz drawn in the spectrogram. Defaults to half the sample rate
+    #[arg(long)]
+    pub max_freq: Option<f32>,
+
+    /// Window function each frame is multiplied by before the FFT
+    #[arg(long, value_enum, default_value_t = WindowFunction::Rectangular)]
+    pub window: WindowFunction,
+
+    /// Number of peaks per frame written to the CSV
+    #[arg(long, default_value_t = 5)]
+    pub peaks: usize,
+
+    /// Level in dBFS drawn as the darkest color of the spectrogram, below 0
+    #[arg(long, default_value_t = -100.0, allow_hyphen_values = true, value_parser = parse_floor_db)]
+    pub floor_db: f32,
+
+    /// Calibration file of the mic the file was recorded with, see the live option
+    #[arg(long)]
+    pub mic_calibration: Option<PathBuf>,
+
+    /// Leaves the pitch columns of the CSV empty unless someone is singing or speaking, and
+    /// adds a voiced column
+    #[arg(long)]
+    pub vad: bool,
+
+    /// Level in dBFS of the 80 - 4000 Hz band below which --vad counts a frame as silence
+    #[arg(long, default_value_t = -50.0, allow_hyphen_values = true)]
+    pub vad_level_db: f32,
+
+    /// Spectral flatness (0 for a pure tone, 1 for white noise) above which --vad counts a frame
+    /// as noise
+    #[arg(long, default_value_t = 0.3)]
+    pub vad_flatness: f32,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TinnitusArgs {
+    /// CSV file the matches are appended to
+    #[arg(long, default_value = "tinnitus.csv")]
+    pub out: PathBuf,
+
+    /// Frequency in Hz the matching starts from
+    #[arg(long, default_value_t = 4000.0)]
+    pub start_hz: f32,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct HearingTestArgs {
+    /// CSV file the thresholds are written to at the end of the test
+    #[arg(long, default_value = "hearing-test.csv")]
+    pub out: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SpeakerArgs {
+    #[command(subcommand)]
+    pub check: SpeakerCheck,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SpeakerCheck {
+    /// Plays clicks and tells whether the driver in front of the mic first pushes or pulls
+    Polarity,
+    /// Plays a sweep and saves the frequency response heard by the mic
+    Sweep {
+        /// File the response is written to, as "frequency level" lines
+        #[arg(long)]
+        out: PathBuf,
+
+        /// Length of the sweep in seconds
+        #[arg(long, default_value_t = 3.0)]
+        secs: f32,
+    },
+    /// Prints two saved responses side by side and where they cross
+    Compare { first: PathBuf, second: PathBuf },
+    /// Plays a sweep and estimates how intelligible speech is through the system, as a speech
+    /// transmission index from its impulse response
+    Sti {
+        /// Length of the sweep in seconds
+        #[arg(long, default_value_t = 5.0)]
+        secs: f32,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CalibrateLevelArgs {
+    /// Frequency of the reference tone in Hz
+    #[arg(long, default_value_t = 1000.0)]
+    pub reference_hz: f32,
+
+    /// Level the reference tone should read, in dBFS as shown by the meters of the source
+    #[arg(long, default_value_t = -20.0, allow_hyphen_values = true)]
+    pub reference_db: f32,
+
+    /// Seconds of the tone listened to
+    #[arg(long, default_value_t = 3.0)]
+    pub secs: f32,
+
+    /// Sample rate the input is opened with
+    #[arg(long, default_value_t = 44100)]
+    pub sample_rate: u32,
+
+    /// Calibration file already in use, whose curve is kept and moved by the offset
+    #[arg(long)]
+    pub mic_calibration: Option<PathBuf>,
+
+    /// Calibration file written, to be given to --mic-calibration
+    #[arg(long, default_value = "level-calibration.txt")]
+    pub out: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct StereoArgs {
+    /// Sample rate the input is opened with
+    #[arg(long, default_value_t = 44100)]
+    pub sample_rate: u32,
+
+    /// Input device with two channels, its number in --list-devices or part of its name
+    #[arg(long)]
+    pub device: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct EqDiffArgs {
+    /// Seconds of each capture, longer ones average out music or noise better
+    #[arg(long, default_value_t = 10.0)]
+    pub secs: f32,
+
+    /// Sample rate the input is opened with
+    #[arg(long, default_value_t = 44100)]
+    pub sample_rate: u32,
+
+    /// Input device, its number in --list-devices or part of its name
+    #[arg(long)]
+    pub device: Option<String>,
+
+    /// CSV file the curve is written to, with frequency_hz and level_db columns
+    #[arg(long, default_value = "eq-diff.csv")]
+    pub out: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ResonanceArgs {
+    /// Band (e.g. 30-300) swept from its low end to its high end
+    #[arg(long, default_value = "30-300")]
+    pub band: Band,
+
+    /// Length of the whole sweep in seconds
+    #[arg(long, default_value_t = 60.0)]
+    pub secs: f32,
+
+    /// Level of the tone in dBFS
+    #[arg(long, default_value_t = -20.0, allow_hyphen_values = true)]
+    pub level_db: f32,
+
+    /// Frequencies played per octave, the sweep moves from one to the next
+    #[arg(long, default_value_t = 24.0)]
+    pub steps_per_octave: f32,
+
+    /// Sample rate the mic is opened with
+    #[arg(long, default_value_t = 44100)]
+    pub sample_rate: u32,
+
+    /// CSV file the level and the residual of every step are written to
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SuggestArgs {
+    #[arg(value_enum)]
+    pub intent: Intent,
+
+    /// Sample rate the settings are worked out for
+    #[arg(long, default_value_t = 44100)]
+    pub sample_rate: u32,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SelfTestArgs {
+    #[arg(long, default_value_t = 48000)]
+    pub sample_rate: u32,
+
+    /// Length of the FFT, big enough to tell the notes of the low E of a guitar apart
+    #[arg(long, default_value_t = 32768, value_parser = parse_power_of_two)]
+    pub fft_size: usize,
+
+    #[arg(long, value_enum, default_value_t = WindowFunction::Hann)]
+    pub window: WindowFunction,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct FftStepsArgs {
+    /// Number of samples frozen, a power of two up to 64 so every value fits on the screen
+    #[arg(long, default_value_t = 16, value_parser = parse_small_power_of_two)]
+    pub size: usize,
+
+    /// Sample rate the mic is opened with
+    #[arg(long, default_value_t = 44100)]
+    pub sample_rate: u32,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct PipeArgs {
+    /// Sample rate of the incoming PCM
+    #[arg(long, default_value_t = 44100)]
+    pub sample_rate: u32,
+
+    /// Number of interleaved channels of the incoming PCM, they are mixed down to mono
+    #[arg(long, default_value_t = 1)]
+    pub channels: u16,
+
+    #[arg(long, value_enum, default_value_t = PcmFormat::F32le)]
+    pub input_format: PcmFormat,
+
+    #[arg(long, value_enum, default_value_t = SpectrumFormat::Json)]
+    pub output_format: SpectrumFormat,
+
+    /// Number of samples of each FFT, a power of two from 1024 to 65536
+    #[arg(long, default_value_t = 4096, value_parser = parse_fft_size)]
+    pub fft_size: usize,
+
+    /// Number of samples between the start of two frames. Defaults to the FFT size
+    #[arg(long)]
+    pub hop: Option<usize>,
+
+    /// Only bins up to this frequency in Hz are written. Defaults to half the sample rate
+    #[arg(long)]
+    pub max_freq: Option<f32>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum PcmFormat {
+    /// 32 bit float, little endian
+    F32le,
+    /// 16 bit signed integer, little endian
+    S16le,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum SpectrumFormat {
+    /// One JSON object per line
+    Json,
+    /// The number of bins as a little endian u32 followed by the magnitudes as little endian f32
+    Binary,
+}
+
+fn parse_small_power_of_two(s: &str) -> Result<usize, String> {
+    match parse_power_of_two(s)? {
+        n @ 2..=64 => Ok(n),
+        n => Err(format!("{n} is not between 2 and 64")),
+    }
+}
+
+fn parse_power_of_two(s: &str) -> Result<usize, String> {
+    let n: usize = s.parse().map_err(|_| format!("\"{s}\" is not a number"))?;
+    if !crate::is_power_of_two(n) {
+        return Err(format!("{n} is not a power of two"));
+    }
+    Ok(n)
+}
+
+fn parse_fft_size(s: &str) -> Result<usize, String> {
+    match parse_power_of_two(s)? {
+        n if (MIN_FFT_SIZE..=MAX_FFT_SIZE).contains(&n) => Ok(n),
+        n => Err(format!(
+            "{n} is not between {MIN_FFT_SIZE} and {MAX_FFT_SIZE}"
+        )),
+    }
+}
+
+/**
+ * Reads an IP address with a port (`2.0.0.10:6454`, `[fe80::1]:6454`) or without one
+ * (`2.0.0.10`, `fe80::1`), which then gets `default_port`. `name` is the protocol, for the
+ * error.
+ */
+fn address_with_default_port(
+    value: &str,
+    default_port: u16,
+    name: &str,
+) -> Result<SocketAddr, String> {
+    value
+        .parse::<SocketAddr>()
+        .or_else(|_| {
+            value
+                .parse::<IpAddr>()
+                .map(|ip| SocketAddr::new(ip, default_port))
+        })
+        .map_err(|_| format!("Invalid {name} address \"{value}\""))
+}
+
+fn parse_floor_db(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(floor_db) if floor_db < 0.0 && floor_db.is_finite() => Ok(floor_db),
+        Ok(floor_db) => Err(format!("{floor_db} dBFS is not below 0 dBFS")),
+        Err(_) => Err(format!("\"{s}\" is not a number")),
+    }
+}
+
+fn parse_positive_secs(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(secs) if secs > 0.0 && secs.is_finite() => Ok(secs),
+        Ok(secs) => Err(format!("{secs} is not a positive number of seconds")),
+        Err(_) => Err(format!("\"{s}\" is not a number")),
+    }
+}
+
+fn parse_semitones(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(semitones) if (-12.0..=12.0).contains(&semitones) => Ok(semitones),
+        Ok(semitones) => Err(format!("{semitones} is not between -12 and 12")),
+        Err(_) => Err(format!("\"{s}\" is not a number")),
+    }
+}
+
+impl AnalyzeArgs {
+    pub fn vad(&self) -> Option<Vad> {
+        self.vad.then_some(Vad {
+            min_level_db: self.vad_level_db,
+            max_flatness: self.vad_flatness,
+        })
+    }
+}
+
+impl Args {
+    pub fn vad(&self) -> Option<Vad> {
+        self.vad.then_some(Vad {
+            min_level_db: self.vad_level_db,
+            max_flatness: self.vad_flatness,
+        })
+    }
+    /**
+     * Resolves the --artnet value into an address, using the default Art-Net port when it's
+     * missing
+     */
+    pub fn artnet_address(&self) -> Option<Result<SocketAddr, String>> {
+        let artnet = self.artnet.as_ref()?;
+        Some(address_with_default_port(
+            artnet,
+            crate::artnet::ARTNET_PORT,
+            "Art-Net",
+        ))
+    }
+
+    /**
+     * Resolves the --wled value into an address, using the default WLED realtime port when
+     * it's missing
+     */
+    pub fn wled_address(&self) -> Option<Result<SocketAddr, String>> {
+        let wled = self.wled.as_ref()?;
+        Some(address_with_default_port(
+            wled,
+            crate::wled::WLED_PORT,
+            "WLED",
+        ))
+    }
+
+    /**
+     * Splits the --mqtt value into host and port, using the default MQTT port when it's missing
+     */
+    pub fn mqtt_address(&self) -> Option<(String, u16)> {
+        let mqtt = self.mqtt.as_ref()?;
+        match mqtt.rsplit_once(':') {
+            Some((host, port)) if port.parse::<u16>().is_ok() => {
+                Some((host.to_string(), port.parse().unwrap()))
+            }
+            _ => Some((mqtt.clone(), 1883)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_get_the_default_port() {
+        let address = |value| address_with_default_port(value, 6454, "Art-Net");
+        assert_eq!(address("2.0.0.10"), Ok("2.0.0.10:6454".parse().unwrap()));
+        assert_eq!(
+            address("2.0.0.10:7000"),
+            Ok("2.0.0.10:7000".parse().unwrap())
+        );
+        assert_eq!(address("fe80::1"), Ok("[fe80::1]:6454".parse().unwrap()));
+        assert_eq!(
+            address("[fe80::1]:7000"),
+            Ok("[fe80::1]:7000".parse().unwrap())
+        );
+        assert!(address("2.0.0.10:port").is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant, SystemTime};
+
+/**
+ * The monotonic clock the frames are timestamped with, counting from when the analyzer started.
+ * It never jumps when the system time is adjusted, and the wall time it started at is kept to
+ * place its readings in the day.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct CaptureClock {
+    started: Instant,
+    started_at: SystemTime,
+}
+
+impl CaptureClock {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+        }
+    }
+
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    /**
+     * Time of `instant` on this clock, zero for anything before its start
+     */
+    pub fn since_start(&self, instant: Instant) -> Duration {
+        instant.saturating_duration_since(self.started)
+    }
+
+    /**
+     * Wall time of a reading of this clock, as the system time was when it started
+     */
+    pub fn wall_time(&self, since_start: Duration) -> SystemTime {
+        self.started_at + since_start
+    }
+}
+
+/**
+ * Formats a reading of the clock in seconds, to the microsecond
+ */
+pub fn format_seconds(since_start: Duration) -> String {
+    format!("{:.6}", since_start.as_secs_f64())
+}
+
+/**
+ * When the first sample handed to an input callback was captured. The callback runs a bit after
+ * the device captured the samples, by the latency cpal reports; hosts that can't tell get the
+ * time the callback ran.
+ */
+pub fn capture_instant(info: &cpal::InputCallbackInfo) -> Instant {
+    let now = Instant::now();
+    let timestamp = info.timestamp();
+    timestamp
+        .callback
+        .duration_since(&timestamp.capture)
+        .and_then(|latency| now.checked_sub(latency))
+        .unwrap_or(now)
+}
+
+/**
+ * When the first of `len` samples was captured, given when the sample right after the last one
+ * would be
+ */
+pub fn start_of(end: Instant, len: usize, sample_rate: u32) -> Instant {
+    let duration = Duration::from_secs_f64(len as f64 / sample_rate as f64);
+    end.checked_sub(duration).unwrap_or(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readings_are_relative_to_the_start() {
+        let clock = CaptureClock::start();
+        let later = clock.started + Duration::from_millis(1500);
+        assert_eq!(clock.since_start(later), Duration::from_millis(1500));
+        assert_eq!(format_seconds(clock.since_start(later)), "1.500000");
+        assert_eq!(
+            clock.wall_time(Duration::from_millis(1500)),
+            clock.started_at() + Duration::from_millis(1500)
+        );
+        // Samples captured before the clock started, in the buffer of the first callback
+        assert_eq!(
+            clock.since_start(start_of(clock.started, 4800, 48000)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            start_of(later, 24000, 48000),
+            clock.started + Duration::from_millis(1000)
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::analysis::{amplitude_to_db, db_to_amplitude, octave_smoothed, BinMapping};
+
+/**
+ * A frequency response given as frequency (Hz) / level (dB) pairs, like the house curves used
+ * to tune PA systems or the calibration files of measurement mics.
+ * Values between the points are interpolated linearly over a logarithmic frequency axis, and
+ * the first and last values are held beyond the ends.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Curve {
+    // Sorted by frequency
+    points: Vec<(f32, f32)>,
+}
+
+impl Curve {
+    /**
+     * Parses one "frequency level" pair per line, separated by spaces, tabs, commas or
+     * semicolons. Any other column after the level (like the phase) is ignored, and so are
+     * empty lines, lines starting with * # or " and any text before the first pair, which is
+     * how the headers of miniDSP and Dayton calibration files look like.
+     */
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut points = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['*', '#', '"']) {
+                continue;
+            }
+
+            let mut values = line
+                .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+                .filter(|value| !value.is_empty())
+                .map(|value| value.parse::<f32>());
+            match (values.next(), values.next()) {
+                (Some(Ok(frequency)), Some(Ok(level))) if frequency > 0.0 => {
+                    points.push((frequency, level))
+                }
+                // Still in the header
+                _ if points.is_empty() => continue,
+                _ => {
+                    return Err(format!(
+                        "Line {} is not a frequency and a level: \"{line}\"",
+                        number + 1
+                    ))
+                }
+            }
+        }
+
+        if points.is_empty() {
+            return Err("The curve has no points".into());
+        }
+        Ok(Self::from_points(points))
+    }
+
+    /**
+     * Builds a curve from frequency / level pairs in any order
+     */
+    pub fn from_points(mut points: Vec<(f32, f32)>) -> Self {
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        Self { points }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| format!("Could not read {}: {error}", path.display()))?;
+        Self::parse(&text).map_err(|error| format!("{}: {error}", path.display()))
+    }
+
+    /**
+     * Writes the curve in the format parse reads, after a header line
+     */
+    pub fn save(&self, path: &Path, header: &str) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "* {header}")?;
+        for (frequency_in_hz, level_db) in &self.points {
+            writeln!(file, "{frequency_in_hz} {level_db:.2}")?;
+        }
+        file.flush()
+    }
+
+    /**
+     * Returns the curve moved up by `offset_db`. An empty curve becomes a flat one at that level.
+     */
+    pub fn offset(&self, offset_db: f32) -> Self {
+        let points = match self.points.is_empty() {
+            true => vec![(1000.0, offset_db)],
+            false => self
+                .points
+                .iter()
+                .map(|(frequency_in_hz, level_db)| (*frequency_in_hz, level_db + offset_db))
+                .collect(),
+        };
+        Self { points }
+    }
+
+    /**
+     * Returns the level of the curve at the frequency, 0 dB for an empty (flat) curve
+     */
+    pub fn db_at(&self, frequency_in_hz: f32) -> f32 {
+        let (first, last) = match (self.points.first(), self.points.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return 0.0,
+        };
+        if frequency_in_hz <= first.0 {
+            return first.1;
+        }
+        if frequency_in_hz >= last.0 {
+            return last.1;
+        }
+
+        let after = self
+            .points
+            .iter()
+            .position(|(frequency, _)| *frequency >= frequency_in_hz)
+            .unwrap();
+        let (low_hz, low_db) = self.points[after - 1];
+        let (high_hz, high_db) = self.points[after];
+        let t = (frequency_in_hz / low_hz).ln() / (high_hz / low_hz).ln();
+        low_db + (high_db - low_db) * t
+    }
+}
+
+/**
+ * Undoes the frequency response of a measurement mic given by its calibration curve.
+ * The gain of each bin is cached until the FFT size changes.
+ */
+#[derive(Clone)]
+pub struct Calibration {
+    curve: Curve,
+    sample_rate: u32,
+    // Gain of each bin of the full FFT output
+    gains: Vec<f32>,
+}
+
+impl Calibration {
+    pub fn new(curve: Curve, sample_rate: u32) -> Self {
+        Self {
+            curve,
+            sample_rate,
+            gains: vec![],
+        }
+    }
+
+    /**
+     * Corrects the magnitudes of the full FFT output in place, both halves
+     */
+    pub fn apply(&mut self, magnitudes: &mut [f32]) {
+        let n = magnitudes.len();
+        if self.gains.len() != n {
+            let mapping = BinMapping::new(self.sample_rate, n);
+            // The second half mirrors the first one, bin n - i has the frequency of bin i
+            self.gains = (0..n)
+                .map(|i| {
+                    let frequency = mapping.frequency_in_hz(i.min(n - i));
+                    1.0 / db_to_amplitude(self.curve.db_at(frequency))
+                })
+                .collect();
+        }
+
+        for (magnitude, gain) in magnitudes.iter_mut().zip(&self.gains) {
+            *magnitude *= gain;
+        }
+    }
+}
+
+/**
+ * Where each bin of the one-sided spectrum should be when pink noise is played through a
+ * system that follows `target`, and how far the measured spectrum is from it, in dB.
+ *
+ * Pink noise has the same power on every octave, which an RTA shows as a flat line, but each
+ * bin of an FFT has the same width in Hz, so per bin it falls 3 dB per octave. The target is
+ * moved up or down to sit on the measured spectrum between 100 Hz and 10 kHz, since only its
+ * shape matters. The spectrum is smoothed to 1/6 octave before comparing.
+ */
+pub fn fit_pink_target(
+    target: &Curve,
+    amplitudes: &[f32],
+    mapping: BinMapping,
+) -> (Vec<f32>, Vec<f32>) {
+    let smoothed_db: Vec<f32> = octave_smoothed(amplitudes, mapping, 6.0)
+        .into_iter()
+        .map(amplitude_to_db)
+        .collect();
+    let shape_db: Vec<f32> = (0..amplitudes.len())
+        .map(|i| {
+            // DC gets the level of the first bin, it has no octave
+            let frequency = mapping.frequency_in_hz(i).max(mapping.bin_width_hz());
+            target.db_at(frequency) - 10.0 * (frequency / 1000.0).log10()
+        })
+        .collect();
+
+    let fitted: Vec<f32> = (0..amplitudes.len())
+        .filter(|i| (100.0..=10000.0).contains(&mapping.frequency_in_hz(*i)))
+        .map(|i| smoothed_db[i] - shape_db[i])
+        .collect();
+    let offset_db = fitted.iter().sum::<f32>() / fitted.len().max(1) as f32;
+
+    let target_db: Vec<f32> = shape_db.iter().map(|db| db + offset_db).collect();
+    let deviation_db = smoothed_db
+        .iter()
+        .zip(&target_db)
+        .map(|(measured, target)| measured - target)
+        .collect();
+    (target_db, deviation_db)
+}
@@ -0,0 +1,194 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+
+use crate::{
+    analysis::{power_to_db, Band, FftFrame, Summary},
+    log::RotatingLog,
+    open_input_stream, SampleCallback,
+};
+
+pub struct DaemonConfig {
+    pub log_dir: PathBuf,
+    pub log_interval: Duration,
+    pub rotate_every: Duration,
+    // Bands whose levels get a column of their own in the log
+    pub bands: Vec<Band>,
+    // Time between two attempts to open the device after it went away
+    pub reconnect_every: Duration,
+    // Input device to open, the default one when None
+    pub device: Option<String>,
+    // Sample rate the device is opened with, and the one of the analysis, lower when the
+    // samples are decimated
+    pub capture_sample_rate: u32,
+    pub sample_rate: u32,
+    // A frame only comes every fft_size samples, 10 s apart with --infrasound
+    pub fft_size: usize,
+}
+
+// Some backends don't report unplugged devices, their streams just stop calling back
+const MIN_STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/**
+ * Time without a new frame after which the device is considered lost: at least two frames, so
+ * slow analyses aren't taken for a stalled stream
+ */
+fn stall_timeout(fft_size: usize, sample_rate: u32) -> Duration {
+    MIN_STALL_TIMEOUT.max(Duration::from_secs_f64(
+        2.0 * fft_size as f64 / sample_rate.max(1) as f64,
+    ))
+}
+
+/**
+ * Levels summed as power over a log interval, so the row is the average of the interval and
+ * not whatever the last frame happened to be
+ */
+#[derive(Default)]
+struct Interval {
+    frames: usize,
+    power: f32,
+    max_db: Option<f32>,
+    // Dominant frequency of the loudest frame
+    dominant_frequency_in_hz: f32,
+    band_powers: Vec<f32>,
+}
+
+impl Interval {
+    fn add(&mut self, summary: &Summary) {
+        if self.max_db.is_none_or(|max_db| summary.level_db > max_db) {
+            self.max_db = Some(summary.level_db);
+            self.dominant_frequency_in_hz = summary.dominant_frequency_in_hz;
+        }
+        self.power += 10f32.powf(summary.level_db / 10.0);
+        self.band_powers.resize(summary.band_levels_db.len(), 0.0);
+        for (power, level_db) in self.band_powers.iter_mut().zip(&summary.band_levels_db) {
+            *power += 10f32.powf(level_db / 10.0);
+        }
+        self.frames += 1;
+    }
+
+    fn row(&self) -> String {
+        let frames = self.frames.max(1) as f32;
+        let mut row = format!(
+            "{},{},{:.1},{:.1},{:.1}",
+            humantime::format_rfc3339_seconds(SystemTime::now()),
+            self.frames,
+            power_to_db(self.power / frames),
+            self.max_db.unwrap_or(f32::NAN),
+            self.dominant_frequency_in_hz
+        );
+        for power in &self.band_powers {
+            row += &format!(",{:.1}", power_to_db(power / frames));
+        }
+        row
+    }
+}
+
+/**
+ * Runs without a window until the process is killed. The analysis goes to the rotating log and
+ * to whatever outputs were started before (OSC, MQTT, alarms, noise dose...), and when the
+ * device fails or goes quiet it's opened again every `reconnect_every`, so a headless monitor
+ * keeps going through unplugged mics and sleeping USB hubs.
+ */
+pub fn run(
+    config: DaemonConfig,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    on_samples: SampleCallback,
+) -> ! {
+    let mut header = String::from("time,frames,level_db,max_level_db,dominant_frequency_hz");
+    for band in &config.bands {
+        header += &format!(",band_{}_{}_db", band.low_hz, band.high_hz);
+    }
+    let mut log = RotatingLog::create(&config.log_dir, "monitor", header, config.rotate_every)
+        .expect("Could not create the monitor log");
+    println!("Running as a daemon, logging to {}", log.path().display());
+
+    let mut interval = Interval::default();
+    let mut last_log = Instant::now();
+    let mut last_generation = 0;
+    let stall_timeout = stall_timeout(config.fft_size, config.sample_rate);
+
+    loop {
+        let failed = Arc::new(AtomicBool::new(false));
+        let stream_failed = failed.clone();
+        let opened = open_input_stream(
+            config.device.as_deref(),
+            config.capture_sample_rate,
+            on_samples.clone(),
+            move |error| {
+                eprintln!("Stream error: {error}");
+                stream_failed.store(true, Ordering::Relaxed);
+            },
+        )
+        .and_then(|(stream, device)| {
+            stream.play().map_err(|error| error.to_string())?;
+            Ok((stream, device))
+        });
+        let (stream, device) = match opened {
+            Ok(opened) => opened,
+            Err(error) => {
+                eprintln!(
+                    "{} Could not open the input device: {error}",
+                    humantime::format_rfc3339_seconds(SystemTime::now())
+                );
+                std::thread::sleep(config.reconnect_every);
+                continue;
+            }
+        };
+        println!(
+            "{} Using device {}",
+            humantime::format_rfc3339_seconds(SystemTime::now()),
+            device.name().unwrap_or_default()
+        );
+
+        let mut last_frame = Instant::now();
+        while !failed.load(Ordering::Relaxed) && last_frame.elapsed() < stall_timeout {
+            std::thread::sleep(Duration::from_millis(50));
+
+            let frame = fft_transform.lock().unwrap().clone();
+            if frame.generation != last_generation {
+                last_generation = frame.generation;
+                last_frame = Instant::now();
+                if let Some(summary) =
+                    Summary::from_magnitudes(&frame.magnitudes, config.sample_rate, &config.bands)
+                {
+                    interval.add(&summary);
+                }
+            }
+
+            if last_log.elapsed() >= config.log_interval {
+                last_log = Instant::now();
+                if let Err(error) = log.write_row(&interval.row()) {
+                    eprintln!("Could not write the monitor log: {error}");
+                }
+                interval = Interval::default();
+            }
+        }
+
+        drop(stream);
+        eprintln!(
+            "{} Lost the input device, reconnecting",
+            humantime::format_rfc3339_seconds(SystemTime::now())
+        );
+        std::thread::sleep(config.reconnect_every);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_stall_timeout_waits_for_two_frames() {
+        assert_eq!(stall_timeout(4096, 48000), MIN_STALL_TIMEOUT);
+        // The infrasound FFT takes 10.24 s at 100 Hz
+        assert_eq!(stall_timeout(1024, 100), Duration::from_secs_f64(20.48));
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "app")]
+use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window};
+
+use crate::{
+    analysis::{one_sided_amplitudes, BinMapping, FftFrame},
+    fft_magnitudes,
+};
+#[cfg(feature = "app")]
+use crate::{text, windowing::draw_spectrum};
+
+// Samples both transforms run over, the end of each frame. The DFT takes N² steps, with the
+// biggest frames it would take seconds.
+const MAX_SIZE: usize = 2048;
+
+/**
+ * Computes the DFT straight from its formula, X[k] = Σ x[n] e^(-2πikn/N), and returns the
+ * magnitude of every bin. The sines and cosines come from a table of N entries, as k * n
+ * wraps around every N, but it still takes N² multiplications.
+ */
+pub fn naive_dft_magnitudes(samples: &[f32]) -> Vec<f32> {
+    let n = samples.len();
+    let table: Vec<(f32, f32)> = (0..n)
+        .map(|i| (2.0 * PI * i as f32 / n as f32).sin_cos())
+        .collect();
+    (0..n)
+        .map(|k| {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (i, sample) in samples.iter().enumerate() {
+                let (sin, cos) = table[k * i % n];
+                re += sample * cos;
+                im -= sample * sin;
+            }
+            (re * re + im * im).sqrt()
+        })
+        .collect()
+}
+
+/**
+ * The last frame through the DFT and through the FFT, and how long each one took
+ */
+#[derive(Default)]
+pub struct DftComparison {
+    dft: Vec<f32>,
+    fft: Vec<f32>,
+    dft_time: Duration,
+    fft_time: Duration,
+    mapping: Option<BinMapping>,
+}
+
+impl DftComparison {
+    pub fn push(&mut self, samples: &[f32], sample_rate: u32) {
+        let samples = &samples[samples.len().saturating_sub(MAX_SIZE)..];
+
+        let start = Instant::now();
+        let dft = naive_dft_magnitudes(samples);
+        self.dft_time = start.elapsed();
+        let start = Instant::now();
+        let fft = fft_magnitudes(samples);
+        self.fft_time = start.elapsed();
+
+        self.dft = one_sided_amplitudes(&dft);
+        self.fft = one_sided_amplitudes(&fft);
+        self.mapping = Some(BinMapping::new(sample_rate, samples.len()));
+    }
+
+    /**
+     * Largest difference between the amplitudes of the two spectra, 1 being full scale
+     */
+    pub fn max_difference(&self) -> f32 {
+        self.dft
+            .iter()
+            .zip(&self.fft)
+            .fold(0.0, |max, (dft, fft)| max.max((dft - fft).abs()))
+    }
+
+    /**
+     * Draws the DFT in the top half of the area and the FFT in the bottom one, each with the
+     * time it took and the number of steps it needs
+     */
+    #[cfg(feature = "app")]
+    pub fn draw(&self, canvas: &mut Canvas<Window>, area: Rect, max_frequency_in_hz: f32) {
+        canvas.set_draw_color(Color::RGB(25, 25, 25));
+        canvas.fill_rect(area).unwrap();
+        let Some(mapping) = self.mapping else {
+            return;
+        };
+
+        let n = mapping.fft_len;
+        let half_height = area.height() / 2;
+        let millis = |time: Duration| time.as_secs_f64() * 1000.0;
+        let speedup = self.dft_time.as_secs_f64() / self.fft_time.as_secs_f64().max(1e-9);
+        let halves = [
+            (
+                &self.dft,
+                format!(
+                    "DFT of {n} samples, N² = {} multiplications: {:.2} ms",
+                    n * n,
+                    millis(self.dft_time)
+                ),
+                Color::RGB(255, 170, 60),
+            ),
+            (
+                &self.fft,
+                format!(
+                    "FFT of {n} samples, N log2 N = {} multiplications: {:.3} ms, {speedup:.0} times faster. Largest difference to the DFT: {:.1e}",
+                    n * n.trailing_zeros() as usize,
+                    millis(self.fft_time),
+                    self.max_difference()
+                ),
+                Color::RGB(6, 214, 160),
+            ),
+        ];
+        for (index, (spectrum, label, color)) in halves.into_iter().enumerate() {
+            let top = area.y() + (half_height * index as u32) as i32;
+            draw_spectrum(
+                canvas,
+                Rect::new(area.x(), top, area.width(), half_height),
+                spectrum,
+                mapping,
+                max_frequency_in_hz,
+                color,
+            );
+            text::draw_text(
+                canvas,
+                &label,
+                area.x() + 10,
+                top + 6,
+                1,
+                Color::RGB(230, 230, 230),
+            );
+        }
+        canvas.set_draw_color(Color::RGB(90, 90, 90));
+        let middle = area.y() + half_height as i32;
+        canvas
+            .draw_line((area.x(), middle), (area.right(), middle))
+            .unwrap();
+    }
+}
+
+/**
+ * Runs both transforms over every new frame on their own thread, the DFT being too slow for
+ * the drawing loop
+ */
+pub fn spawn(
+    comparison: Arc<Mutex<DftComparison>>,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut last_generation = 0;
+        loop {
+            std::thread::sleep(Duration::from_millis(5));
+            let frame = fft_transform.lock().unwrap().clone();
+            if frame.generation == last_generation || frame.samples.is_empty() {
+                continue;
+            }
+            last_generation = frame.generation;
+            // Computed outside of the lock, the window keeps drawing the previous frame meanwhile
+            let mut computed = DftComparison::default();
+            computed.push(&frame.samples, sample_rate);
+            *comparison.lock().unwrap() = computed;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_dft_and_the_fft_agree() {
+        let samples: Vec<f32> = (0..256)
+            .map(|i| {
+                let t = i as f32 / 256.0;
+                0.5 * (2.0 * PI * 10.0 * t).sin() + 0.1 * (2.0 * PI * 33.3 * t).cos()
+            })
+            .collect();
+        let mut comparison = DftComparison::default();
+        comparison.push(&samples, 256);
+        assert!(comparison.max_difference() < 1e-4);
+        // The tone between bins leaks a little into it
+        assert!((comparison.dft[10] - 0.5).abs() < 0.01);
+    }
+}
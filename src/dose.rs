@@ -0,0 +1,150 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    analysis::{a_weighted_level_db, BinMapping, FftFrame},
+    log::RotatingLog,
+};
+
+/**
+ * Occupational noise limits: the level allowed for a whole 8 hour day, and by how many dB it
+ * can go up each time the time is halved
+ */
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "app", derive(clap::ValueEnum))]
+pub enum DoseStandard {
+    /// NIOSH: 85 dBA for 8 hours, 3 dB exchange rate
+    Niosh,
+    /// OSHA: 90 dBA for 8 hours, 5 dB exchange rate
+    Osha,
+}
+
+impl DoseStandard {
+    fn criterion_db(self) -> f64 {
+        match self {
+            DoseStandard::Niosh => 85.0,
+            DoseStandard::Osha => 90.0,
+        }
+    }
+
+    fn exchange_rate_db(self) -> f64 {
+        match self {
+            DoseStandard::Niosh => 3.0,
+            DoseStandard::Osha => 5.0,
+        }
+    }
+
+    /**
+     * Fraction of the daily dose taken by spending `duration` at `level_db`
+     */
+    fn dose(self, level_db: f64, duration: Duration) -> f64 {
+        let allowed_hours =
+            8.0 / 2f64.powf((level_db - self.criterion_db()) / self.exchange_rate_db());
+        duration.as_secs_f64() / 3600.0 / allowed_hours
+    }
+}
+
+pub struct DoseConfig {
+    pub directory: PathBuf,
+    // Added to the dBFS of the analysis to get dB SPL, found by calibrating the mic
+    pub spl_offset_db: f32,
+    pub standard: DoseStandard,
+    pub log_interval: Duration,
+    pub rotate_every: Duration,
+}
+
+/**
+ * Energy of an A-weighted level integrated over time. Levels are averaged as energy, so a
+ * minute at 100 dBA weights as much as 10 minutes at 90 dBA.
+ */
+#[derive(Default)]
+struct Exposure {
+    energy: f64,
+    duration: Duration,
+    max_db: Option<f64>,
+    dose: f64,
+}
+
+impl Exposure {
+    fn add(&mut self, level_db: f64, duration: Duration, standard: DoseStandard) {
+        self.energy += 10f64.powf(level_db / 10.0) * duration.as_secs_f64();
+        self.duration += duration;
+        self.max_db = Some(self.max_db.map_or(level_db, |max| max.max(level_db)));
+        self.dose += standard.dose(level_db, duration);
+    }
+
+    fn leq_db(&self) -> Option<f64> {
+        (!self.duration.is_zero())
+            .then(|| 10.0 * (self.energy / self.duration.as_secs_f64()).log10())
+    }
+}
+
+/**
+ * Spawns a thread that integrates the A-weighted level of every new FFT result and writes one
+ * CSV row per `log_interval`, with the LAeq and LAmax of the interval and the LAeq and dose
+ * since the start. The dose is also projected to a whole 8 hour day at the current pace.
+ */
+pub fn spawn(
+    config: DoseConfig,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+) -> std::io::Result<JoinHandle<()>> {
+    let mut log = RotatingLog::create(
+        &config.directory,
+        "dose",
+        "time,laeq_db,lamax_db,laeq_since_start_db,dose_percent,projected_8h_dose_percent".into(),
+        config.rotate_every,
+    )?;
+    println!("Logging the noise exposure to {}", log.path().display());
+
+    Ok(std::thread::spawn(move || {
+        let mut total = Exposure::default();
+        let mut interval = Exposure::default();
+        let mut last_generation = 0;
+        let mut last_frame = Instant::now();
+        let mut last_log = Instant::now();
+
+        loop {
+            std::thread::sleep(Duration::from_millis(50));
+            let frame = fft_transform.lock().unwrap().clone();
+            if frame.generation == last_generation || frame.amplitudes.is_empty() {
+                continue;
+            }
+            last_generation = frame.generation;
+
+            // Each frame stands for the time since the previous one, so frames missed while
+            // the thread was asleep don't leave holes in the integration
+            let elapsed = last_frame.elapsed();
+            last_frame = Instant::now();
+            let mapping = BinMapping::new(sample_rate, frame.magnitudes.len());
+            let level_db =
+                (a_weighted_level_db(&frame.amplitudes, mapping) + config.spl_offset_db) as f64;
+            total.add(level_db, elapsed, config.standard);
+            interval.add(level_db, elapsed, config.standard);
+
+            if last_log.elapsed() < config.log_interval {
+                continue;
+            }
+            last_log = Instant::now();
+
+            let projected_dose = total.dose * 8.0 * 3600.0 / total.duration.as_secs_f64();
+            let row = log.write_row(&format!(
+                "{},{:.1},{:.1},{:.1},{:.2},{:.2}",
+                humantime::format_rfc3339_seconds(SystemTime::now()),
+                interval.leq_db().unwrap_or(f64::NAN),
+                interval.max_db.unwrap_or(f64::NAN),
+                total.leq_db().unwrap_or(f64::NAN),
+                total.dose * 100.0,
+                projected_dose * 100.0
+            ));
+            if let Err(error) = row {
+                eprintln!("\nCould not write the noise log: {error}");
+            }
+            interval = Exposure::default();
+        }
+    }))
+}
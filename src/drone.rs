@@ -0,0 +1,171 @@
+use std::{
+    f32::consts::PI,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use ndarray::Array1;
+use num_complex::Complex;
+
+use crate::{filter::Resampler, output};
+
+// Frames are a quarter of their length apart, Hann windows that far apart add up to 2
+const HOPS_PER_FRAME: usize = 4;
+const OVERLAP_GAIN: f32 = 2.0;
+// The thread making the drone keeps this much of it ahead of the output
+const AHEAD_SECS: f32 = 0.1;
+
+/**
+ * Turns a frozen spectrum back into sound that goes on forever: every frame is the inverse FFT
+ * of the frozen magnitudes with random phases, windowed and overlapped with the previous ones
+ * so they blend into a steady drone with the timbre of the spectrum
+ */
+pub struct Drone {
+    // Magnitude of each bin of the frozen spectrum, up to half the sample rate
+    magnitudes: Vec<f32>,
+    window: Vec<f32>,
+    // Sum of the frames still being added to, the first hop of it is complete
+    overlap: Vec<f32>,
+    noise_state: u32,
+}
+
+impl Drone {
+    /**
+     * `magnitudes` are those of the whole FFT, a power of two long
+     */
+    pub fn new(magnitudes: &[f32]) -> Self {
+        let n = magnitudes.len();
+        Self {
+            magnitudes: magnitudes[..n / 2 + 1].to_vec(),
+            // Periodic, so the overlapping windows add up to a constant
+            window: (0..n)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos())
+                .collect(),
+            overlap: vec![0.0; n],
+            noise_state: 0x9E37_79B9,
+        }
+    }
+
+    fn random_phase(&mut self) -> f32 {
+        // xorshift, plenty for phases
+        self.noise_state ^= self.noise_state << 13;
+        self.noise_state ^= self.noise_state >> 17;
+        self.noise_state ^= self.noise_state << 5;
+        self.noise_state as f32 / u32::MAX as f32 * 2.0 * PI
+    }
+
+    /**
+     * Adds a new frame and returns the samples it completed, a hop of them
+     */
+    pub fn next_hop(&mut self) -> Vec<f32> {
+        let n = self.window.len();
+        let mut spectrum = Array1::<Complex<f32>>::zeros(n);
+        for k in 0..self.magnitudes.len() {
+            spectrum[k] = match k {
+                // The first and last bins of a real signal have no phase
+                0 => Complex::from(self.magnitudes[k]),
+                k if k == n / 2 => Complex::from(self.magnitudes[k]),
+                _ => Complex::from_polar(self.magnitudes[k], self.random_phase()),
+            };
+            if k > 0 && k < n / 2 {
+                spectrum[n - k] = spectrum[k].conj();
+            }
+        }
+
+        let frame = crate::ifft(&spectrum);
+        for ((sum, sample), weight) in self.overlap.iter_mut().zip(&frame).zip(&self.window) {
+            *sum += sample.re * weight / OVERLAP_GAIN;
+        }
+        let hop = (n / HOPS_PER_FRAME).max(1);
+        let done = self.overlap.drain(..hop).collect();
+        self.overlap.resize(n, 0.0);
+        done
+    }
+}
+
+/**
+ * Plays a drone while it is kept, and stops when it is dropped
+ */
+pub struct DroneHandle {
+    _stream: cpal::Stream,
+    playing: Arc<AtomicBool>,
+}
+
+impl Drop for DroneHandle {
+    fn drop(&mut self) {
+        self.playing.store(false, Ordering::Relaxed);
+    }
+}
+
+/**
+ * Plays the drone of a spectrum taken at `sample_rate` through the default output. Frames of big
+ * FFTs take a while, so they are made on their own thread and queued ahead.
+ */
+pub fn play(magnitudes: &[f32], sample_rate: u32) -> Result<DroneHandle, String> {
+    let (stream, queue, output_sample_rate) = output::open("Drone")?;
+    let mut drone = Drone::new(magnitudes);
+    let mut resampler = Resampler::new(sample_rate, output_sample_rate);
+    let ahead = (output_sample_rate as f32 * AHEAD_SECS) as usize;
+    let playing = Arc::new(AtomicBool::new(true));
+    let thread_playing = playing.clone();
+
+    std::thread::spawn(move || {
+        while thread_playing.load(Ordering::Relaxed) {
+            if queue.queued() >= ahead {
+                std::thread::sleep(Duration::from_millis(5));
+                continue;
+            }
+            let mut output = vec![];
+            for sample in drone.next_hop() {
+                resampler.push(sample, &mut output);
+            }
+            queue.extend(output);
+        }
+    });
+    Ok(DroneHandle {
+        _stream: stream,
+        playing,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_inverse_fft_gives_the_samples_back() {
+        let samples: Vec<f32> = (0..64).map(|i| ((i * 7) % 11) as f32 - 5.0).collect();
+        let spectrum = crate::fft(&Array1::from_iter(samples.iter().map(Complex::from)));
+        let back = crate::ifft(&spectrum);
+        for (sample, back) in samples.iter().zip(&back) {
+            assert!((sample - back.re).abs() < 1e-4);
+            assert!(back.im.abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn the_drone_keeps_the_frequencies_of_the_spectrum() {
+        let n = 1024;
+        // A 0.5 sine right on bin 64
+        let sine: Vec<f32> = (0..n)
+            .map(|i| 0.5 * (2.0 * PI * 64.0 * i as f32 / n as f32).sin())
+            .collect();
+        let mut drone = Drone::new(&crate::fft_magnitudes(&sine));
+        let output: Vec<f32> = (0..HOPS_PER_FRAME * 8)
+            .flat_map(|_| drone.next_hop())
+            .collect();
+        assert_eq!(output.len(), 8 * n);
+
+        // Past the first frame, which fades in
+        let magnitudes = crate::fft_magnitudes(&output[4 * n..5 * n]);
+        let loudest = (0..n / 2)
+            .max_by(|a, b| magnitudes[*a].partial_cmp(&magnitudes[*b]).unwrap())
+            .unwrap();
+        assert_eq!(loudest, 64);
+        let peak = output[n..].iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(peak > 0.1 && peak < 1.0, "{peak}");
+    }
+}
@@ -0,0 +1,252 @@
+use std::f32::consts::PI;
+
+#[cfg(feature = "app")]
+use sdl2::{
+    pixels::Color,
+    rect::{Point, Rect},
+    render::Canvas,
+    video::Window,
+};
+
+#[cfg(feature = "app")]
+use crate::text;
+use crate::{
+    analysis::{cents_between, Band, BinMapping},
+    fft_magnitudes,
+    trigger::Capture,
+};
+
+// The stick noise right after the hit is left out, only the ringing of the head is analyzed
+const SKIP_ATTACK_MS: f32 = 5.0;
+// Shorter rings don't give a usable pitch
+const MIN_RING_MS: f32 = 20.0;
+// The ring is padded to at least this many points, which draws the peak finely enough for the
+// interpolation
+const MIN_FFT_SIZE: usize = 2usize.pow(15);
+// Lugs further than this from the median of the drum are shown as outliers
+const OUTLIER_CENTS: f32 = 20.0;
+
+/**
+ * Finds the strongest resonance inside `band` in the ringing after the hit of a capture
+ */
+pub fn lug_pitch(capture: &Capture, band: &Band, sample_rate: u32) -> Option<f32> {
+    let peak = capture.frame_start
+        + capture.samples[capture.frame_start..]
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))?
+            .0;
+    let start = peak + (SKIP_ATTACK_MS * sample_rate as f32 / 1000.0) as usize;
+    if (capture.samples.len().saturating_sub(start) as f32)
+        < MIN_RING_MS * sample_rate as f32 / 1000.0
+    {
+        return None;
+    }
+
+    let ring = &capture.samples[start..];
+    let fft_size = ring.len().next_power_of_two().max(MIN_FFT_SIZE);
+    let mut windowed: Vec<f32> = ring
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| sample * (0.5 - 0.5 * (2.0 * PI * i as f32 / ring.len() as f32).cos()))
+        .collect();
+    windowed.resize(fft_size, 0.0);
+    let magnitudes = fft_magnitudes(&windowed);
+
+    let mapping = BinMapping::new(sample_rate, fft_size);
+    let low = mapping.bin_at(band.low_hz).max(1);
+    let high = mapping.bin_at(band.high_hz).min(fft_size / 2 - 2);
+    let bin = (low..=high).max_by(|a, b| magnitudes[*a].total_cmp(&magnitudes[*b]))?;
+
+    // A parabola through the log magnitudes around the peak finds where it is between the bins
+    let [before, at, after] = [bin - 1, bin, bin + 1].map(|bin| magnitudes[bin].max(1e-12).ln());
+    let curvature = before - 2.0 * at + after;
+    let shift = if curvature.abs() > f32::EPSILON {
+        0.5 * (before - after) / curvature
+    } else {
+        0.0
+    };
+    Some((bin as f32 + shift) * mapping.bin_width_hz())
+}
+
+/**
+ * The pitch measured near each lug of a drum head. The taps go around the drum, each one being
+ * recorded for the next lug, and the one after the last lug starts over from the first.
+ */
+pub struct DrumTuning {
+    lugs: Vec<Option<f32>>,
+    current: usize,
+}
+
+impl DrumTuning {
+    pub fn new(lugs: usize) -> Self {
+        Self {
+            lugs: vec![None; lugs],
+            current: 0,
+        }
+    }
+
+    /**
+     * Records the pitch of a tap for the current lug and returns the index of that lug
+     */
+    pub fn record(&mut self, frequency_in_hz: f32) -> usize {
+        let lug = self.current;
+        self.lugs[lug] = Some(frequency_in_hz);
+        self.current = (self.current + 1) % self.lugs.len();
+        lug
+    }
+
+    pub fn median(&self) -> Option<f32> {
+        let mut measured: Vec<f32> = self.lugs.iter().flatten().copied().collect();
+        if measured.is_empty() {
+            return None;
+        }
+        measured.sort_by(|a, b| a.total_cmp(b));
+        Some(measured[measured.len() / 2])
+    }
+
+    /**
+     * How far each lug is from the median of the drum, in cents
+     */
+    fn deviations(&self) -> Vec<Option<f32>> {
+        let median = self.median();
+        self.lugs
+            .iter()
+            .map(|lug| Some(cents_between(median?, (*lug)?)))
+            .collect()
+    }
+
+    pub fn report(&self) -> String {
+        self.lugs
+            .iter()
+            .zip(self.deviations())
+            .enumerate()
+            .map(|(index, (lug, deviation))| match (lug, deviation) {
+                (Some(lug), Some(deviation)) => format!(
+                    "Lug {:>2}: {lug:>6.1} Hz {deviation:>+5.0} cents{}",
+                    index + 1,
+                    match deviation.abs() > OUTLIER_CENTS {
+                        true => "  <- outlier",
+                        false => "",
+                    }
+                ),
+                _ => format!("Lug {:>2}: -", index + 1),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /**
+     * Draws the head as a circle with the lugs around it, starting at the top and going
+     * clockwise, each with its pitch. Outliers are orange and the lug to tap next is pink.
+     */
+    #[cfg(feature = "app")]
+    pub fn draw(&self, canvas: &mut Canvas<Window>, area: Rect) {
+        canvas.set_draw_color(Color::RGB(25, 25, 25));
+        canvas.fill_rect(area).unwrap();
+
+        let center = area.center();
+        let radius = (area.width().min(area.height()) as f32 / 2.0 - 40.0).max(10.0);
+        let at_angle = |angle: f32, radius: f32| {
+            Point::new(
+                center.x() + (radius * angle.cos()) as i32,
+                center.y() + (radius * angle.sin()) as i32,
+            )
+        };
+        let circle: Vec<Point> = (0..=64)
+            .map(|i| at_angle(2.0 * PI * i as f32 / 64.0, radius))
+            .collect();
+        canvas.set_draw_color(Color::RGB(90, 90, 90));
+        canvas.draw_lines(circle.as_slice()).unwrap();
+
+        for (index, (lug, deviation)) in self.lugs.iter().zip(self.deviations()).enumerate() {
+            let angle = 2.0 * PI * index as f32 / self.lugs.len() as f32 - PI / 2.0;
+            let color = if index == self.current {
+                Color::RGB(200, 36, 104)
+            } else if deviation.is_some_and(|deviation| deviation.abs() > OUTLIER_CENTS) {
+                Color::RGB(230, 160, 60)
+            } else if lug.is_some() {
+                Color::RGB(90, 200, 110)
+            } else {
+                Color::RGB(150, 150, 150)
+            };
+            canvas.set_draw_color(color);
+            canvas
+                .fill_rect(Rect::from_center(at_angle(angle, radius), 9, 9))
+                .unwrap();
+
+            let label = match (lug, deviation) {
+                (Some(lug), Some(deviation)) => format!("{lug:.1} Hz {deviation:+.0}c"),
+                _ => format!("Lug {}", index + 1),
+            };
+            let position = at_angle(angle, radius + 22.0);
+            text::draw_text(
+                canvas,
+                &label,
+                position.x() - text::text_width(&label, 1) as i32 / 2,
+                position.y() - text::line_height(1) as i32 / 2,
+                1,
+                color,
+            );
+        }
+
+        let readout = match self.median() {
+            Some(median) => format!("Median {median:.1} Hz   Tap lug {}", self.current + 1),
+            None => format!("Tap lug {}", self.current + 1),
+        };
+        text::draw_text(
+            canvas,
+            &readout,
+            area.x() + 10,
+            area.y() + 8,
+            2,
+            Color::RGB(230, 230, 230),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::FftFrame;
+
+    #[test]
+    fn finds_the_ring_of_a_tap() {
+        let sample_rate = 44100;
+        // 0.1 s of silence before a tap ringing at 187.3 Hz with a harmonic
+        let samples: Vec<f32> = (0..sample_rate as usize / 10)
+            .map(|_| 0.0)
+            .chain((0..4096).map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                let decay = (-t * 20.0).exp();
+                decay * (0.5 * (2.0 * PI * 187.3 * t).sin() + 0.2 * (2.0 * PI * 299.0 * t).sin())
+            }))
+            .collect();
+        let capture = Capture {
+            frame: FftFrame::default(),
+            level_db: 0.0,
+            frame_start: samples.len() - 4096,
+            samples,
+        };
+        let band = Band {
+            low_hz: 50.0,
+            high_hz: 1000.0,
+        };
+        let pitch = lug_pitch(&capture, &band, sample_rate).unwrap();
+        assert!((pitch - 187.3).abs() < 0.5, "{pitch}");
+    }
+
+    #[test]
+    fn lugs_far_from_the_median_are_outliers() {
+        let mut drum = DrumTuning::new(4);
+        for frequency_in_hz in [200.0, 201.0, 210.0, 199.5] {
+            drum.record(frequency_in_hz);
+        }
+        assert_eq!(drum.median(), Some(201.0));
+        let report = drum.report();
+        assert_eq!(report.matches("outlier").count(), 1);
+        assert!(report.lines().nth(2).unwrap().contains("outlier"));
+        // The next tap goes to the first lug again
+        assert_eq!(drum.record(200.5), 0);
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    f32::consts::PI,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::analysis::BinMapping;
+
+/**
+ * A peaking (bell) filter of a parametric EQ
+ */
+#[derive(Clone, Debug)]
+pub struct PeakingFilter {
+    pub frequency_in_hz: f32,
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+impl PeakingFilter {
+    /**
+     * Gain in dB of the filter at a frequency, for the biquad of the Audio EQ Cookbook
+     */
+    pub fn response_db(&self, frequency_in_hz: f32, sample_rate: u32) -> f32 {
+        let a = 10f32.powf(self.gain_db / 40.0);
+        let w0 = 2.0 * PI * self.frequency_in_hz / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * self.q);
+        let (b0, b1, b2) = (1.0 + alpha * a, -2.0 * w0.cos(), 1.0 - alpha * a);
+        let (a0, a1, a2) = (1.0 + alpha / a, -2.0 * w0.cos(), 1.0 - alpha / a);
+
+        // |H(e^jw)|² of a biquad, from the real and imaginary parts of the polynomials
+        let w = 2.0 * PI * frequency_in_hz / sample_rate as f32;
+        let magnitude_squared = |c0: f32, c1: f32, c2: f32| {
+            let re = c0 + c1 * w.cos() + c2 * (2.0 * w).cos();
+            let im = -c1 * w.sin() - c2 * (2.0 * w).sin();
+            re * re + im * im
+        };
+        10.0 * (magnitude_squared(b0, b1, b2) / magnitude_squared(a0, a1, a2)).log10()
+    }
+}
+
+// Only this range is corrected, below and above it rooms and speakers can't be fixed by EQ
+const MIN_EQ_HZ: f32 = 60.0;
+const MAX_EQ_HZ: f32 = 12000.0;
+// Deviations smaller than this are left alone
+const TOLERANCE_DB: f32 = 2.0;
+// Cuts fix peaks, but boosting a dip (often a cancellation) mostly wastes headroom
+const MAX_CUT_DB: f32 = 12.0;
+const MAX_BOOST_DB: f32 = 6.0;
+
+/**
+ * Gets how far each bin of the one-sided spectrum is from the target (see fit_pink_target) and
+ * returns up to `max_filters` peaking filters that bring it closer to it.
+ * It's greedy: the biggest deviation left gets a filter as wide as the deviation itself, the
+ * filter is subtracted and it starts over.
+ */
+pub fn suggest_filters(
+    deviation_db: &[f32],
+    mapping: BinMapping,
+    max_filters: usize,
+) -> Vec<PeakingFilter> {
+    let first = mapping.bin_at(MIN_EQ_HZ).max(1);
+    let last = mapping
+        .bin_at(MAX_EQ_HZ)
+        .min(deviation_db.len().saturating_sub(1));
+    if first >= last {
+        return vec![];
+    }
+    let mut residual_db = deviation_db.to_vec();
+    let mut filters = vec![];
+
+    while filters.len() < max_filters {
+        let worst = (first..=last)
+            .max_by(|a, b| {
+                residual_db[*a]
+                    .abs()
+                    .partial_cmp(&residual_db[*b].abs())
+                    .unwrap()
+            })
+            .unwrap();
+        let worst_db = residual_db[worst];
+        if worst_db.abs() < TOLERANCE_DB {
+            break;
+        }
+
+        // The bandwidth is where the deviation falls to half of its worst value
+        let half = |bin: &usize| (residual_db[*bin] / worst_db) < 0.5;
+        let low = (first..worst).rev().find(half).unwrap_or(first);
+        let high = (worst + 1..=last).find(half).unwrap_or(last);
+        let bandwidth_octaves = (mapping.frequency_in_hz(high) / mapping.frequency_in_hz(low))
+            .log2()
+            .max(0.1);
+        let q = 2f32.powf(bandwidth_octaves).sqrt() / (2f32.powf(bandwidth_octaves) - 1.0);
+
+        let filter = PeakingFilter {
+            frequency_in_hz: mapping.frequency_in_hz(worst),
+            gain_db: (-worst_db).clamp(-MAX_CUT_DB, MAX_BOOST_DB),
+            q: q.clamp(0.3, 10.0),
+        };
+        for (i, residual) in residual_db.iter_mut().enumerate().skip(1) {
+            *residual += filter.response_db(mapping.frequency_in_hz(i), mapping.sample_rate);
+        }
+        // A boost clamped to nothing would be found again on the next round
+        if (residual_db[worst] - worst_db).abs() < 0.1 {
+            break;
+        }
+        filters.push(filter);
+    }
+
+    filters.sort_by(|a, b| a.frequency_in_hz.partial_cmp(&b.frequency_in_hz).unwrap());
+    filters
+}
+
+/**
+ * Writes the filters in the text format of Equalizer APO, which REW, miniDSP and most
+ * parametric EQs can import or be typed from. A preamp keeps boosts from clipping.
+ */
+pub fn write_filters(path: &Path, filters: &[PeakingFilter]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    let highest_boost = filters
+        .iter()
+        .map(|filter| filter.gain_db)
+        .fold(0.0, f32::max);
+    writeln!(file, "Preamp: {:.1} dB", -highest_boost)?;
+    for (i, filter) in filters.iter().enumerate() {
+        writeln!(
+            file,
+            "Filter {}: ON PK Fc {:.1} Hz Gain {:.1} dB Q {:.2}",
+            i + 1,
+            filter.frequency_in_hz,
+            filter.gain_db,
+            filter.q
+        )?;
+    }
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_peak_gets_a_cut_at_its_frequency() {
+        let mapping = BinMapping::new(48000, 8192);
+        let peak = PeakingFilter {
+            frequency_in_hz: 1000.0,
+            gain_db: 8.0,
+            q: 2.0,
+        };
+        let deviation_db: Vec<f32> = (0..=4096)
+            .map(|i| peak.response_db(mapping.frequency_in_hz(i), 48000))
+            .collect();
+
+        let filters = suggest_filters(&deviation_db, mapping, 4);
+        assert!(!filters.is_empty());
+        let main = &filters[0];
+        assert!((main.frequency_in_hz - 1000.0).abs() < 10.0);
+        assert!((main.gain_db + 8.0).abs() < 0.5);
+        assert!((main.q - 2.0).abs() < 0.8);
+    }
+}
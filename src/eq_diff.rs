@@ -0,0 +1,189 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use cpal::traits::StreamTrait;
+
+use crate::{
+    analysis::{amplitude_to_db, octave_smoothed, BinMapping},
+    cli::EqDiffArgs,
+    open_input_stream,
+    windowing::WindowFunction,
+    SampleCallback,
+};
+
+// Length of the frames averaged into the spectrum of a capture, a bin under 6 Hz wide at 48 kHz
+const FRAME_LEN: usize = 8192;
+const LOW_HZ: f32 = 20.0;
+const HIGH_HZ: f32 = 20000.0;
+// Where the stimulus is this quiet before the processing, there is nothing to compare
+const MIN_LEVEL_DB: f32 = -90.0;
+
+/**
+ * Average one-sided amplitude spectrum of the samples, from Hann frames overlapping by half
+ */
+fn average_amplitudes(samples: &[f32]) -> Vec<f32> {
+    let mut power = vec![0.0f32; FRAME_LEN / 2];
+    let mut frames = 0;
+    for start in (0..samples.len().saturating_sub(FRAME_LEN - 1)).step_by(FRAME_LEN / 2) {
+        let amplitudes = WindowFunction::Hann.amplitudes(&samples[start..start + FRAME_LEN]);
+        for (sum, amplitude) in power.iter_mut().zip(amplitudes) {
+            *sum += amplitude * amplitude;
+        }
+        frames += 1;
+    }
+    power
+        .iter()
+        .map(|sum| (sum / frames.max(1) as f32).sqrt())
+        .collect()
+}
+
+/**
+ * What the processing did to the stimulus, in dB at 1/12 octave steps: the 1/6 octave smoothed
+ * spectrum of the capture with it engaged over the one with it bypassed. Frequencies the
+ * stimulus didn't reach are left out.
+ */
+pub fn transfer_curve(bypassed: &[f32], engaged: &[f32], sample_rate: u32) -> Vec<(f32, f32)> {
+    let mapping = BinMapping::new(sample_rate, FRAME_LEN);
+    let before = octave_smoothed(&average_amplitudes(bypassed), mapping, 6.0);
+    let after = octave_smoothed(&average_amplitudes(engaged), mapping, 6.0);
+    let high_hz = HIGH_HZ.min(sample_rate as f32 * 0.45);
+    (0..)
+        .map(|step| LOW_HZ * 2f32.powf(step as f32 / 12.0))
+        .take_while(|frequency_in_hz| *frequency_in_hz <= high_hz)
+        .filter_map(|frequency_in_hz| {
+            let bin = mapping.bin_at(frequency_in_hz);
+            let before_db = amplitude_to_db(before[bin]);
+            (before_db > MIN_LEVEL_DB)
+                .then(|| (frequency_in_hz, amplitude_to_db(after[bin]) - before_db))
+        })
+        .collect()
+}
+
+fn write_csv(path: &Path, points: &[(f32, f32)]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "frequency_hz,level_db")?;
+    for (frequency_in_hz, level_db) in points {
+        writeln!(file, "{frequency_in_hz:.1},{level_db:.2}")?;
+    }
+    file.flush()
+}
+
+/**
+ * Waits for Enter on the terminal after telling what to set up
+ */
+fn prompt(message: &str) -> Result<(), String> {
+    print!("{message}, then press Enter ");
+    std::io::stdout()
+        .flush()
+        .map_err(|error| error.to_string())?;
+    std::io::stdin()
+        .read_line(&mut String::new())
+        .map_err(|error| error.to_string())?;
+    Ok(())
+}
+
+fn capture(args: &EqDiffArgs) -> Result<Vec<f32>, String> {
+    let recording = Arc::new(Mutex::new(Vec::<f32>::new()));
+    let stream_recording = recording.clone();
+    let on_samples: SampleCallback = Arc::new(Mutex::new(move |data: &[f32], _: Instant| {
+        stream_recording.lock().unwrap().extend_from_slice(data)
+    }));
+    let (input, _) = open_input_stream(
+        args.device.as_deref(),
+        args.sample_rate,
+        on_samples,
+        |error| eprintln!("\nInput error: {error}"),
+    )?;
+    println!("Capturing for {} s", args.secs);
+    input.play().map_err(|error| error.to_string())?;
+    std::thread::sleep(Duration::from_secs_f32(args.secs));
+    drop(input);
+    let samples = recording.lock().unwrap().clone();
+    if samples.len() < FRAME_LEN {
+        return Err("The capture is too short to analyze".into());
+    }
+    Ok(samples)
+}
+
+/**
+ * Captures the stimulus through the chain with the processing bypassed then engaged, and
+ * writes what the processing does to it
+ */
+pub fn run(args: &EqDiffArgs) -> Result<(), String> {
+    prompt("Bypass the processing and start the stimulus (pink noise is best)")?;
+    let bypassed = capture(args)?;
+    prompt("Engage the processing, keeping the same stimulus and volume")?;
+    let engaged = capture(args)?;
+
+    let curve = transfer_curve(&bypassed, &engaged, args.sample_rate);
+    if curve.is_empty() {
+        return Err("The stimulus was too quiet to compare the captures".into());
+    }
+    for (frequency_in_hz, level_db) in curve.iter().step_by(4) {
+        println!("{frequency_in_hz:8.0} Hz {level_db:+7.1} dB");
+    }
+    let (boost, cut) = curve
+        .iter()
+        .fold((curve[0], curve[0]), |(boost, cut), point| {
+            (
+                if point.1 > boost.1 { *point } else { boost },
+                if point.1 < cut.1 { *point } else { cut },
+            )
+        });
+    println!(
+        "Most boosted {:+.1} dB at {:.0} Hz, most cut {:+.1} dB at {:.0} Hz",
+        boost.1, boost.0, cut.1, cut.0
+    );
+    write_csv(&args.out, &curve).map_err(|error| error.to_string())?;
+    println!(
+        "Saved to {}, draw it over the graph with --overlay",
+        args.out.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::Biquad;
+
+    #[test]
+    fn a_low_pass_is_found_back() {
+        let sample_rate = 48000;
+        let mut seed = 7u32;
+        let noise: Vec<f32> = (0..sample_rate * 2)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                seed as f32 / u32::MAX as f32 - 0.5
+            })
+            .collect();
+        let mut low_pass = Biquad::low_pass(1000.0, sample_rate);
+        let filtered: Vec<f32> = noise.iter().map(|s| low_pass.process(*s)).collect();
+
+        let curve = transfer_curve(&noise, &filtered, sample_rate);
+        let level_at = |frequency_in_hz: f32| {
+            curve
+                .iter()
+                .min_by(|a, b| {
+                    (a.0 - frequency_in_hz)
+                        .abs()
+                        .total_cmp(&(b.0 - frequency_in_hz).abs())
+                })
+                .unwrap()
+                .1
+        };
+        assert!(level_at(100.0).abs() < 1.0, "{}", level_at(100.0));
+        assert!((level_at(1000.0) + 3.0).abs() < 1.0, "{}", level_at(1000.0));
+        // Butterworth, 12 dB per octave above the cutoff
+        assert!(
+            (level_at(4000.0) + 24.0).abs() < 2.0,
+            "{}",
+            level_at(4000.0)
+        );
+    }
+}
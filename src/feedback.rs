@@ -0,0 +1,133 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::analysis::{amplitude_to_db, find_peaks, BinMapping};
+
+#[derive(Clone, Debug)]
+pub struct FeedbackEvent {
+    pub bin_index: usize,
+    pub frequency_in_hz: f32,
+    pub level_db: f32,
+    // When it was first flagged
+    pub at: Instant,
+    pub last_seen: Instant,
+}
+
+/**
+ * Flags the frequencies that are feeding back: a peak that keeps growing frame after frame
+ * and stands well above the rest of the spectrum, which is how a ringing mic/speaker loop
+ * builds up. Music peaks also stand out, but they rarely grow for several frames in a row.
+ */
+pub struct FeedbackDetector {
+    // Number of consecutive frames a peak has to grow for
+    growth_frames: usize,
+    // How far above the median of the spectrum a peak has to be
+    margin_db: f32,
+    // Levels in dB of the last growth_frames frames, the newest one last
+    history: VecDeque<Vec<f32>>,
+    recent: VecDeque<FeedbackEvent>,
+}
+
+// Number of events kept in the recent list
+const RECENT_EVENTS: usize = 10;
+// A frequency flagged again within this time is the same event
+const EVENT_MERGE_TIME: Duration = Duration::from_secs(5);
+
+impl FeedbackDetector {
+    pub fn new(growth_frames: usize, margin_db: f32) -> Self {
+        Self {
+            growth_frames: growth_frames.max(2),
+            margin_db,
+            history: VecDeque::new(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    /**
+     * The latest events, the newest one first
+     */
+    pub fn recent(&self) -> impl Iterator<Item = &FeedbackEvent> {
+        self.recent.iter()
+    }
+
+    /**
+     * Events seen in the last `within`, the ones worth showing on the screen
+     */
+    pub fn active(&self, within: Duration) -> impl Iterator<Item = &FeedbackEvent> {
+        self.recent
+            .iter()
+            .filter(move |event| event.last_seen.elapsed() < within)
+    }
+
+    /**
+     * Gets the magnitudes of the full FFT output and its one-sided amplitudes, and returns the
+     * events that just started
+     */
+    pub fn process(
+        &mut self,
+        magnitudes: &[f32],
+        amplitudes: &[f32],
+        mapping: BinMapping,
+    ) -> Vec<FeedbackEvent> {
+        let levels_db: Vec<f32> = amplitudes.iter().map(|a| amplitude_to_db(*a)).collect();
+        // The FFT size changed, the old levels can't be compared
+        if self
+            .history
+            .front()
+            .is_some_and(|levels| levels.len() != levels_db.len())
+        {
+            self.history.clear();
+        }
+        self.history.push_back(levels_db);
+        if self.history.len() > self.growth_frames {
+            self.history.pop_front();
+        }
+        if self.history.len() < self.growth_frames {
+            return vec![];
+        }
+
+        let current = self.history.back().unwrap();
+        let mut sorted = current.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let median_db = sorted[sorted.len() / 2];
+
+        let now = Instant::now();
+        let mut started = vec![];
+        for peak in find_peaks(magnitudes, mapping.sample_rate, 8) {
+            let bin = peak.bin_index;
+            let level_db = current[bin];
+            let growing = self
+                .history
+                .iter()
+                .zip(self.history.iter().skip(1))
+                .all(|(before, after)| after[bin] > before[bin]);
+            if !growing || level_db < median_db + self.margin_db {
+                continue;
+            }
+
+            match self.recent.iter_mut().find(|event| {
+                event.bin_index.abs_diff(bin) <= 1 && now - event.last_seen < EVENT_MERGE_TIME
+            }) {
+                Some(event) => {
+                    event.last_seen = now;
+                    event.level_db = event.level_db.max(level_db);
+                }
+                None => {
+                    let event = FeedbackEvent {
+                        bin_index: bin,
+                        frequency_in_hz: mapping.frequency_in_hz(bin),
+                        level_db,
+                        at: now,
+                        last_seen: now,
+                    };
+                    self.recent.push_front(event.clone());
+                    self.recent.truncate(RECENT_EVENTS);
+                    started.push(event);
+                }
+            }
+        }
+        started
+    }
+}
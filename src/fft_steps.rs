@@ -0,0 +1,323 @@
+use std::{
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use cpal::traits::StreamTrait;
+use num_complex::Complex;
+use sdl2::{event::Event, keyboard::Keycode, pixels::Color, render::Canvas, video::Window};
+
+use crate::{cli::FftStepsArgs, open_input_stream, text, SampleCallback};
+
+// Where the columns of values and the butterflies between them are drawn
+const INPUT_X: i32 = 20;
+const LINES_FROM_X: i32 = 300;
+const LINES_TO_X: i32 = 620;
+const OUTPUT_X: i32 = 640;
+const TOP_Y: i32 = 90;
+
+/**
+ * One butterfly of a stage: the two points it combines and its twiddle factor W = e^(-2πik/N),
+ * written as k and N
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Butterfly {
+    pub top: usize,
+    pub bottom: usize,
+    pub twiddle_k: usize,
+    pub twiddle_n: usize,
+}
+
+/**
+ * The points of the FFT after a step, and how they were obtained from the previous ones
+ */
+pub struct Stage {
+    pub title: String,
+    pub values: Vec<Complex<f32>>,
+    // The bit reversal moves the points without combining them, its butterflies are empty
+    pub butterflies: Vec<Butterfly>,
+}
+
+fn bit_reverse(index: usize, bits: u32) -> usize {
+    match bits {
+        0 => index,
+        _ => index.reverse_bits() >> (usize::BITS - bits),
+    }
+}
+
+/**
+ * Runs the iterative radix-2 decimation in time FFT over the samples, a power of two of them,
+ * keeping every intermediate step. The first stage is the input itself, the second one the bit
+ * reversal that splits it into even and odd samples all the way down, then each stage combines
+ * pairs of FFTs into FFTs twice as long until a single one is left.
+ */
+pub fn fft_stages(samples: &[f32]) -> Vec<Stage> {
+    let n = samples.len();
+    assert!(
+        n.is_power_of_two(),
+        "The FFT needs a power of two of samples"
+    );
+    let bits = n.trailing_zeros();
+
+    let input: Vec<Complex<f32>> = samples.iter().map(Complex::from).collect();
+    let reordered: Vec<Complex<f32>> = (0..n).map(|i| input[bit_reverse(i, bits)]).collect();
+    let mut stages = vec![
+        Stage {
+            title: format!("Input: {n} samples"),
+            values: input,
+            butterflies: vec![],
+        },
+        Stage {
+            title: "Bit reversal: each sample moves to the index with its bits reversed, so every \
+                    stage combines neighbours"
+                .into(),
+            values: reordered,
+            butterflies: vec![],
+        },
+    ];
+
+    let mut size = 2;
+    while size <= n {
+        let mut values = stages.last().unwrap().values.clone();
+        let mut butterflies = vec![];
+        for start in (0..n).step_by(size) {
+            for k in 0..size / 2 {
+                let (top, bottom) = (start + k, start + k + size / 2);
+                let twiddle = Complex::new(0.0, -2.0 * PI * k as f32 / size as f32).exp();
+                let product = twiddle * values[bottom];
+                (values[top], values[bottom]) = (values[top] + product, values[top] - product);
+                butterflies.push(Butterfly {
+                    top,
+                    bottom,
+                    twiddle_k: k,
+                    twiddle_n: size,
+                });
+            }
+        }
+        stages.push(Stage {
+            title: format!(
+                "Stage {} of {bits}: {} FFTs of {size} points out of pairs of FFTs of {} points",
+                size.trailing_zeros(),
+                n / size,
+                size / 2
+            ),
+            values,
+            butterflies,
+        });
+        size *= 2;
+    }
+    stages
+}
+
+fn format_value(value: Complex<f32>) -> String {
+    format!("{:+.3} {:+.3}j", value.re, value.im)
+}
+
+/**
+ * Draws the points before the step on the left, after it on the right, and lines showing where
+ * each point went: straight ones for the bit reversal, crossed ones for the butterflies
+ */
+fn draw_stage(canvas: &mut Canvas<Window>, stages: &[Stage], index: usize, height: u32) {
+    canvas.set_draw_color(Color::RGB(30, 30, 30));
+    canvas.clear();
+    let stage = &stages[index];
+    let previous = &stages[index.saturating_sub(1)];
+    let n = stage.values.len();
+    let row_height = ((height as i32 - TOP_Y - 10) / n as i32).max(1);
+    let text_y = (row_height - text::line_height(1) as i32) / 2;
+    let row_y = |row: usize| TOP_Y + row as i32 * row_height + row_height / 2;
+
+    text::draw_text(
+        canvas,
+        &format!("{} / {}  {}", index + 1, stages.len(), stage.title),
+        INPUT_X,
+        12,
+        2,
+        Color::RGB(230, 230, 230),
+    );
+    text::draw_text(
+        canvas,
+        "Left/Right: previous/next step   Space: freeze a new buffer   Esc: quit",
+        INPUT_X,
+        12 + text::line_height(2) as i32,
+        1,
+        Color::RGB(150, 150, 150),
+    );
+    if let Some(butterfly) = stage.butterflies.first() {
+        text::draw_text(
+            canvas,
+            &format!(
+                "Each butterfly: top = top + W * bottom, bottom = top - W * bottom, with W{}^k = e^(-2 pi i k / {})",
+                butterfly.twiddle_n, butterfly.twiddle_n
+            ),
+            INPUT_X,
+            12 + text::line_height(2) as i32 + text::line_height(1) as i32 + 4,
+            1,
+            Color::RGB(255, 170, 60),
+        );
+    }
+
+    let bits = n.trailing_zeros();
+    let mut connect = |from: usize, to: usize, color: Color| {
+        canvas.set_draw_color(color);
+        canvas
+            .draw_line((LINES_FROM_X, row_y(from)), (LINES_TO_X, row_y(to)))
+            .unwrap();
+    };
+    match index {
+        0 => {}
+        1 => (0..n).for_each(|i| connect(bit_reverse(i, bits), i, Color::RGB(90, 90, 90))),
+        _ => {
+            for butterfly in &stage.butterflies {
+                // Alternate colors tell the FFTs of the stage apart
+                let color = match (butterfly.top / butterfly.twiddle_n) % 2 {
+                    0 => Color::RGB(6, 214, 160),
+                    _ => Color::RGB(200, 90, 160),
+                };
+                connect(butterfly.top, butterfly.top, color);
+                connect(butterfly.bottom, butterfly.top, color);
+                connect(butterfly.top, butterfly.bottom, color);
+                connect(butterfly.bottom, butterfly.bottom, color);
+            }
+        }
+    }
+
+    let is_last = index == stages.len() - 1;
+    for row in 0..n {
+        let y = row_y(row) - row_height / 2 + text_y;
+        if index > 0 {
+            text::draw_text(
+                canvas,
+                &format!("{row:>2}: {}", format_value(previous.values[row])),
+                INPUT_X,
+                y,
+                1,
+                Color::RGB(180, 180, 180),
+            );
+        }
+        let value = stage.values[row];
+        let mut output = format!("{row:>2}: {}", format_value(value));
+        if is_last {
+            output += &format!("  |X| = {:.3}", value.norm());
+        }
+        if let Some(butterfly) = stage.butterflies.iter().find(|b| b.bottom == row) {
+            if n <= 16 {
+                output += &format!("  W{}^{}", butterfly.twiddle_n, butterfly.twiddle_k);
+            }
+        }
+        text::draw_text(canvas, &output, OUTPUT_X, y, 1, Color::RGB(230, 230, 230));
+    }
+    canvas.present();
+}
+
+/**
+ * Freezes a small buffer from the mic and walks through the steps of its FFT
+ */
+pub fn run(args: &FftStepsArgs) -> Result<(), String> {
+    let latest = Arc::new(Mutex::new(Vec::<f32>::new()));
+    let stream_latest = latest.clone();
+    let size = args.size;
+    let on_samples: SampleCallback = Arc::new(Mutex::new(move |data: &[f32], _: Instant| {
+        let mut latest = stream_latest.lock().unwrap();
+        latest.extend_from_slice(data);
+        let excess = latest.len().saturating_sub(size);
+        latest.drain(..excess);
+    }));
+    let (input, _) = open_input_stream(None, args.sample_rate, on_samples, |error| {
+        eprintln!("\nInput error: {error}")
+    })?;
+    input.play().map_err(|error| error.to_string())?;
+
+    let sdl_context = sdl2::init()?;
+    let window = sdl_context
+        .video()?
+        .window("FFT step by step", 1200, 820)
+        .position_centered()
+        .resizable()
+        .build()
+        .map_err(|error| error.to_string())?;
+    let mut canvas = window
+        .into_canvas()
+        .build()
+        .map_err(|error| error.to_string())?;
+    let mut event_pump = sdl_context.event_pump()?;
+
+    let freeze = || {
+        let latest = latest.lock().unwrap();
+        (latest.len() == size).then(|| fft_stages(&latest))
+    };
+    let mut stages = None;
+    let mut index: usize = 0;
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => {
+                    stages = freeze();
+                    index = 0;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Right),
+                    ..
+                } => index += 1,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Left),
+                    ..
+                } => index = index.saturating_sub(1),
+                _ => {}
+            }
+        }
+        if stages.is_none() {
+            stages = freeze();
+        }
+
+        match &stages {
+            Some(stages) => {
+                index = index.min(stages.len() - 1);
+                let height = canvas.window().size().1;
+                draw_stage(&mut canvas, stages, index, height);
+            }
+            None => {
+                canvas.set_draw_color(Color::RGB(30, 30, 30));
+                canvas.clear();
+                canvas.present();
+            }
+        }
+        std::thread::sleep(Duration::from_millis(16));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array1;
+
+    #[test]
+    fn the_last_stage_is_the_fft() {
+        let samples: Vec<f32> = (0..16)
+            .map(|i| (2.0 * PI * 3.0 * i as f32 / 16.0).sin() + 0.25 * (i % 3) as f32)
+            .collect();
+        let stages = fft_stages(&samples);
+        // Input, bit reversal and log2(16) stages of butterflies
+        assert_eq!(stages.len(), 6);
+        assert_eq!(stages[1].values[1], Complex::from(samples[8]));
+        assert!(stages
+            .iter()
+            .skip(2)
+            .all(|stage| stage.butterflies.len() == 8));
+
+        let expected = crate::fft(&Array1::from_iter(samples.iter().map(Complex::from)));
+        for (value, expected) in stages.last().unwrap().values.iter().zip(expected.iter()) {
+            assert!((value - expected).norm() < 1e-4, "{value} {expected}");
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::f32::consts::PI;
+
+/**
+ * A biquad of the Audio EQ Cookbook, run one sample at a time
+ */
+pub struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl Biquad {
+    /**
+     * Low-pass with a Q of 1/sqrt(2) (Butterworth)
+     */
+    pub fn low_pass(cutoff_in_hz: f32, sample_rate: u32) -> Self {
+        let w0 = 2.0 * PI * cutoff_in_hz / sample_rate as f32;
+        let alpha = w0.sin() / 2.0f32.sqrt();
+        let b1 = 1.0 - w0.cos();
+        Self::new(
+            [b1 / 2.0, b1, b1 / 2.0],
+            [1.0 + alpha, -2.0 * w0.cos(), 1.0 - alpha],
+        )
+    }
+
+    /**
+     * High-pass with a Q of 1/sqrt(2) (Butterworth)
+     */
+    pub fn high_pass(cutoff_in_hz: f32, sample_rate: u32) -> Self {
+        let w0 = 2.0 * PI * cutoff_in_hz / sample_rate as f32;
+        let alpha = w0.sin() / 2.0f32.sqrt();
+        let b1 = 1.0 + w0.cos();
+        Self::new(
+            [b1 / 2.0, -b1, b1 / 2.0],
+            [1.0 + alpha, -2.0 * w0.cos(), 1.0 - alpha],
+        )
+    }
+
+    /**
+     * Band-pass with a gain of 1 at its center
+     */
+    pub fn band_pass(center_in_hz: f32, q: f32, sample_rate: u32) -> Self {
+        let w0 = 2.0 * PI * center_in_hz / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * q);
+        Self::new(
+            [alpha, 0.0, -alpha],
+            [1.0 + alpha, -2.0 * w0.cos(), 1.0 - alpha],
+        )
+    }
+
+    /**
+     * Notch taking out the center, with a gain of 1 far from it
+     */
+    pub fn notch(center_in_hz: f32, q: f32, sample_rate: u32) -> Self {
+        let w0 = 2.0 * PI * center_in_hz / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * q);
+        Self::new(
+            [1.0, -2.0 * w0.cos(), 1.0],
+            [1.0 + alpha, -2.0 * w0.cos(), 1.0 - alpha],
+        )
+    }
+
+    fn new(b: [f32; 3], a: [f32; 3]) -> Self {
+        Self {
+            b: b.map(|b| b / a[0]),
+            a: [a[1] / a[0], a[2] / a[0]],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let output = self.b[0] * sample + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [sample, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/**
+ * Brings samples to another sample rate by linear interpolation, good enough for listening
+ */
+pub struct Resampler {
+    // Input samples per output sample
+    step: f32,
+    // Position in the input of the next output sample, relative to the last input sample
+    position: f32,
+    last_sample: f32,
+}
+
+impl Resampler {
+    pub fn new(input_sample_rate: u32, output_sample_rate: u32) -> Self {
+        Self {
+            step: input_sample_rate as f32 / output_sample_rate as f32,
+            position: 0.0,
+            last_sample: 0.0,
+        }
+    }
+
+    /**
+     * Takes the next input sample and adds the output samples falling before it to `output`
+     */
+    pub fn push(&mut self, sample: f32, output: &mut Vec<f32>) {
+        self.position -= 1.0;
+        while self.position <= 0.0 {
+            let t = self.position + 1.0;
+            output.push(self.last_sample + (sample - self.last_sample) * t);
+            self.position += self.step;
+        }
+        self.last_sample = sample;
+    }
+}
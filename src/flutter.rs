@@ -0,0 +1,410 @@
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+#[cfg(feature = "app")]
+use sdl2::{
+    pixels::Color,
+    rect::{Point, Rect},
+    render::Canvas,
+    video::Window,
+};
+
+#[cfg(feature = "app")]
+use crate::text;
+use crate::{
+    analysis::{BinMapping, FftFrame},
+    filter::Biquad,
+};
+
+// Rate of the frequency readings, far above the 200 Hz the flutter goes up to
+const READING_RATE: u32 = 1000;
+// The tone is demodulated within this band around it
+const DEMODULATION_CUTOFF_HZ: f32 = 300.0;
+// Below this amplitude there's no tone to follow
+const MIN_TONE_AMPLITUDE: f32 = 0.0005;
+
+// The tone may be this far from the nominal frequency, a tape running fast or a wrong speed
+const LOCK_RANGE: f32 = 0.1;
+
+// The weighting of IEC 60386 peaks at 4 Hz, where the ear is the most sensitive to speed
+// variations, and falls on both sides. A band-pass there is a close approximation of it.
+const WEIGHTING_CENTER_HZ: f32 = 4.0;
+const WEIGHTING_Q: f32 = 0.6;
+// Speed variations below this are wow, above it flutter
+const WOW_FLUTTER_SPLIT_HZ: f32 = 6.0;
+
+// The figures are computed over this much of the latest readings
+const WINDOW_SECS: usize = 10;
+// The filters settle during the first readings, which are left out
+const SETTLE_READINGS: usize = READING_RATE as usize * 2;
+// Time shown by the deviation plot
+#[cfg(feature = "app")]
+const PLOT_SECS: usize = 5;
+
+/**
+ * Follows the frequency of a tone by mixing it down to 0 Hz and measuring how fast the phase of
+ * what's left turns
+ */
+pub struct FrequencyTracker {
+    sample_rate: u32,
+    mixer_in_hz: f32,
+    mixer_phase: f32,
+    low_passes: [[Biquad; 2]; 2],
+    decimation: usize,
+    count: usize,
+    last_angle: Option<f32>,
+}
+
+impl FrequencyTracker {
+    pub fn new(frequency_in_hz: f32, sample_rate: u32) -> Self {
+        let low_pass = || {
+            [
+                Biquad::low_pass(DEMODULATION_CUTOFF_HZ, sample_rate),
+                Biquad::low_pass(DEMODULATION_CUTOFF_HZ, sample_rate),
+            ]
+        };
+        Self {
+            sample_rate,
+            mixer_in_hz: frequency_in_hz,
+            mixer_phase: 0.0,
+            low_passes: [low_pass(), low_pass()],
+            decimation: (sample_rate / READING_RATE).max(1) as usize,
+            count: 0,
+            last_angle: None,
+        }
+    }
+
+    /**
+     * Returns the frequency of the tone READING_RATE times per second of samples, leaving out
+     * the moments without a tone
+     */
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let step = 2.0 * PI * self.mixer_in_hz / self.sample_rate as f32;
+        let mut readings = vec![];
+        for sample in samples {
+            self.mixer_phase = (self.mixer_phase + step) % (2.0 * PI);
+            let [in_phase, quadrature] = &mut self.low_passes;
+            let i = in_phase
+                .iter_mut()
+                .fold(sample * self.mixer_phase.cos(), |x, stage| stage.process(x));
+            let q = quadrature
+                .iter_mut()
+                .fold(-sample * self.mixer_phase.sin(), |x, stage| {
+                    stage.process(x)
+                });
+
+            self.count += 1;
+            if self.count < self.decimation {
+                continue;
+            }
+            self.count = 0;
+
+            // Mixing halves the amplitude of the tone
+            if (i * i + q * q).sqrt() * 2.0 < MIN_TONE_AMPLITUDE {
+                self.last_angle = None;
+                continue;
+            }
+            let angle = q.atan2(i);
+            if let Some(last_angle) = self.last_angle {
+                let turn = (angle - last_angle + 3.0 * PI).rem_euclid(2.0 * PI) - PI;
+                let reading_rate = self.sample_rate as f32 / self.decimation as f32;
+                readings.push(self.mixer_in_hz + turn * reading_rate / (2.0 * PI));
+            }
+            self.last_angle = Some(angle);
+        }
+        readings
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlutterReport {
+    pub mean_frequency_in_hz: f32,
+    // How far the mean speed is from the nominal one
+    pub drift_percentage: f32,
+    pub weighted_rms_percentage: f32,
+    // Exceeded 5% of the time, like the 2 sigma figure of the peak weighted meters
+    pub weighted_peak_percentage: f32,
+    pub wow_rms_percentage: f32,
+    pub flutter_rms_percentage: f32,
+}
+
+impl FlutterReport {
+    pub fn readout(&self) -> String {
+        format!(
+            "{:.1} Hz ({:+.2}%)   Weighted: {:.3}% RMS, {:.3}% peak (2 sigma)   Unweighted: wow {:.3}%, flutter {:.3}% RMS",
+            self.mean_frequency_in_hz,
+            self.drift_percentage,
+            self.weighted_rms_percentage,
+            self.weighted_peak_percentage,
+            self.wow_rms_percentage,
+            self.flutter_rms_percentage
+        )
+    }
+}
+
+fn rms(values: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| {
+        (sum + value * value, count + 1)
+    });
+    (sum / count.max(1) as f32).sqrt()
+}
+
+/**
+ * Turns the frequency readings into wow and flutter figures, and keeps the latest deviations
+ * for the plot
+ */
+pub struct FlutterMeter {
+    nominal_in_hz: f32,
+    weighting: Biquad,
+    wow_low_pass: Biquad,
+    flutter_high_pass: Biquad,
+    readings: usize,
+    // Frequency, and the weighted, wow and flutter parts of the deviation of the latest readings
+    window: VecDeque<(f32, f32, f32, f32)>,
+}
+
+impl FlutterMeter {
+    pub fn new(nominal_in_hz: f32) -> Self {
+        Self {
+            nominal_in_hz,
+            weighting: Biquad::band_pass(WEIGHTING_CENTER_HZ, WEIGHTING_Q, READING_RATE),
+            wow_low_pass: Biquad::low_pass(WOW_FLUTTER_SPLIT_HZ, READING_RATE),
+            flutter_high_pass: Biquad::high_pass(WOW_FLUTTER_SPLIT_HZ, READING_RATE),
+            readings: 0,
+            window: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, frequency_in_hz: f32) {
+        let deviation = frequency_in_hz / self.nominal_in_hz - 1.0;
+        let weighted = self.weighting.process(deviation);
+        let wow = self.wow_low_pass.process(deviation);
+        let flutter = self.flutter_high_pass.process(deviation);
+        self.readings += 1;
+        if self.readings <= SETTLE_READINGS {
+            return;
+        }
+        self.window
+            .push_back((frequency_in_hz, weighted, wow, flutter));
+        if self.window.len() > WINDOW_SECS * READING_RATE as usize {
+            self.window.pop_front();
+        }
+    }
+
+    pub fn report(&self) -> Option<FlutterReport> {
+        if self.window.len() < READING_RATE as usize {
+            return None;
+        }
+        let len = self.window.len() as f32;
+        let mean_frequency_in_hz = self.window.iter().map(|(f, ..)| f).sum::<f32>() / len;
+        let mean_deviation = mean_frequency_in_hz / self.nominal_in_hz - 1.0;
+        let mean_wow = self.window.iter().map(|(_, _, wow, _)| wow).sum::<f32>() / len;
+
+        let mut weighted: Vec<f32> = self.window.iter().map(|(_, w, ..)| w.abs()).collect();
+        weighted.sort_by(|a, b| a.total_cmp(b));
+        Some(FlutterReport {
+            mean_frequency_in_hz,
+            drift_percentage: mean_deviation * 100.0,
+            weighted_rms_percentage: rms(self.window.iter().map(|(_, w, ..)| *w)) * 100.0,
+            weighted_peak_percentage: weighted[(weighted.len() - 1) * 95 / 100] * 100.0,
+            wow_rms_percentage: rms(self.window.iter().map(|(_, _, wow, _)| wow - mean_wow))
+                * 100.0,
+            flutter_rms_percentage: rms(self.window.iter().map(|(.., flutter)| *flutter)) * 100.0,
+        })
+    }
+
+    /**
+     * Draws the deviation from the mean speed over the last PLOT_SECS, with the figures above
+     */
+    #[cfg(feature = "app")]
+    pub fn draw(&self, canvas: &mut Canvas<Window>, area: Rect) {
+        canvas.set_draw_color(Color::RGB(25, 25, 25));
+        canvas.fill_rect(area).unwrap();
+        let center_y = area.y() + area.height() as i32 / 2;
+        canvas.set_draw_color(Color::RGB(70, 70, 70));
+        canvas
+            .draw_line((area.x(), center_y), (area.right(), center_y))
+            .unwrap();
+
+        let Some(report) = self.report() else {
+            text::draw_text(
+                canvas,
+                &format!("Waiting for the {} Hz test tone", self.nominal_in_hz),
+                area.x() + 10,
+                area.y() + 8,
+                2,
+                Color::RGB(230, 230, 230),
+            );
+            return;
+        };
+
+        let shown = (PLOT_SECS * READING_RATE as usize).min(self.window.len());
+        let recent = self.window.range(self.window.len() - shown..);
+        let deviations: Vec<f32> = recent
+            .map(|(f, ..)| (f / report.mean_frequency_in_hz - 1.0) * 100.0)
+            .collect();
+        // At least +-0.1% so a good deck doesn't look terrible
+        let range = deviations
+            .iter()
+            .fold(0.1f32, |range, deviation| range.max(deviation.abs()));
+        let points: Vec<Point> = deviations
+            .iter()
+            .enumerate()
+            .map(|(i, deviation)| {
+                Point::new(
+                    area.x() + (i * area.width() as usize / shown.max(1)) as i32,
+                    center_y - (deviation / range * area.height() as f32 / 2.2) as i32,
+                )
+            })
+            .collect();
+        canvas.set_draw_color(Color::RGB(230, 230, 230));
+        canvas.draw_lines(points.as_slice()).unwrap();
+
+        text::draw_text(
+            canvas,
+            &report.readout(),
+            area.x() + 10,
+            area.y() + 8,
+            2,
+            Color::RGB(230, 230, 230),
+        );
+        text::draw_text(
+            canvas,
+            &format!("+{range:.2}%"),
+            area.x() + 10,
+            area.y() + 30,
+            1,
+            Color::RGB(150, 150, 150),
+        );
+        text::draw_text(
+            canvas,
+            &format!("-{range:.2}%"),
+            area.x() + 10,
+            area.bottom() - 14,
+            1,
+            Color::RGB(150, 150, 150),
+        );
+    }
+}
+
+/**
+ * Spawns a thread that locks onto the test tone near `nominal_in_hz` and feeds every reading to
+ * the meter
+ */
+pub fn spawn(
+    nominal_in_hz: f32,
+    meter: Arc<Mutex<FlutterMeter>>,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut tracker: Option<FrequencyTracker> = None;
+        let mut last_generation = 0;
+        loop {
+            // The phase has to go on from one frame to the next, none can be missed
+            std::thread::sleep(Duration::from_millis(5));
+            let frame = fft_transform.lock().unwrap().clone();
+            if frame.generation == last_generation {
+                continue;
+            }
+            last_generation = frame.generation;
+
+            // The mixer starts on the strongest bin near the nominal frequency, and moves to it
+            // again if the tone went too far for the demodulation
+            let mapping = BinMapping::new(sample_rate, frame.magnitudes.len());
+            let low = mapping.bin_at(nominal_in_hz * (1.0 - LOCK_RANGE));
+            let high = mapping
+                .bin_at(nominal_in_hz * (1.0 + LOCK_RANGE))
+                .min(frame.amplitudes.len().saturating_sub(1));
+            let strongest = (low..=high)
+                .max_by(|a, b| frame.amplitudes[*a].total_cmp(&frame.amplitudes[*b]))
+                .filter(|bin| frame.amplitudes[*bin] >= MIN_TONE_AMPLITUDE)
+                .map(|bin| mapping.frequency_in_hz(bin));
+            if let Some(tone_in_hz) = strongest {
+                if tracker.as_ref().is_none_or(|tracker| {
+                    (tracker.mixer_in_hz - tone_in_hz).abs() > DEMODULATION_CUTOFF_HZ / 2.0
+                }) {
+                    tracker = Some(FrequencyTracker::new(tone_in_hz, sample_rate));
+                }
+            }
+
+            if let Some(tracker) = tracker.as_mut() {
+                let readings = tracker.process(&frame.samples);
+                let mut meter = meter.lock().unwrap();
+                for reading in readings {
+                    meter.push(reading);
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modulated_tone(modulation_hz: f32, depth: f32, secs: f32) -> Vec<f32> {
+        let sample_rate = 48000.0;
+        let mut phase = 0.0f32;
+        (0..(secs * sample_rate) as usize)
+            .map(|i| {
+                let t = i as f32 / sample_rate;
+                let frequency = 3180.0 * (1.0 + depth * (2.0 * PI * modulation_hz * t).sin());
+                phase = (phase + 2.0 * PI * frequency / sample_rate) % (2.0 * PI);
+                0.5 * phase.sin()
+            })
+            .collect()
+    }
+
+    fn measure(samples: &[f32]) -> FlutterReport {
+        let mut tracker = FrequencyTracker::new(3180.0, 48000);
+        let mut meter = FlutterMeter::new(3150.0);
+        // Fed in blocks, like the frames
+        for block in samples.chunks(4096) {
+            for reading in tracker.process(block) {
+                meter.push(reading);
+            }
+        }
+        meter.report().unwrap()
+    }
+
+    // 0.1% peak deviation is 0.0707% RMS
+
+    #[test]
+    fn variations_at_4_hz_get_the_full_weight() {
+        let report = measure(&modulated_tone(4.0, 0.001, 8.0));
+        assert!((report.drift_percentage - 0.952).abs() < 0.01, "{report:?}");
+        assert!(
+            (report.weighted_rms_percentage - 0.0707).abs() < 0.007,
+            "{report:?}"
+        );
+    }
+
+    #[test]
+    fn slow_wow_of_an_off_center_record() {
+        // A record at 33 1/3 rpm turns 0.56 times a second
+        let report = measure(&modulated_tone(0.56, 0.001, 8.0));
+        assert!(
+            (report.wow_rms_percentage - 0.0707).abs() < 0.007,
+            "{report:?}"
+        );
+        assert!(report.flutter_rms_percentage < 0.005, "{report:?}");
+        assert!(report.weighted_rms_percentage < 0.03, "{report:?}");
+    }
+
+    #[test]
+    fn fast_flutter_is_weighted_down() {
+        let report = measure(&modulated_tone(60.0, 0.001, 8.0));
+        assert!(report.wow_rms_percentage < 0.01, "{report:?}");
+        assert!(
+            (report.flutter_rms_percentage - 0.0707).abs() < 0.007,
+            "{report:?}"
+        );
+        assert!(report.weighted_rms_percentage < 0.03, "{report:?}");
+    }
+}
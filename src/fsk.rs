@@ -0,0 +1,256 @@
+use std::{
+    io::{stdout, Write},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::analysis::{goertzel_level_db, FftFrame};
+
+// Each bit is looked at this many times, to find where the start bits begin
+const STEPS_PER_BIT: usize = 8;
+
+// Below this neither tone is on the air
+const SQUELCH_DB: f32 = -70.0;
+
+// ITA2 with the US figures, indexed by the 5 bit code
+const LETTERS: &[u8; 32] = b"\0E\nA SIU\rDRJNFCKTZLWHYPQOBG\0MXV\0";
+const FIGURES: &[u8; 32] = b"\x003\n- \x0787\r$4',!:(5\")2#6019?&\0./;\0";
+const SHIFT_TO_FIGURES: u8 = 0x1B;
+const SHIFT_TO_LETTERS: u8 = 0x1F;
+
+/**
+ * What the received characters are
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "app", derive(clap::ValueEnum))]
+pub enum FskOutput {
+    /// Baudot (ITA2) text, as sent by RTTY with 5 data bits
+    Baudot,
+    /// ASCII text
+    Ascii,
+    /// The data bits of each character, in the order they were received
+    Bits,
+}
+
+pub struct FskConfig {
+    pub mark_hz: f32,
+    pub space_hz: f32,
+    pub baud: f32,
+    pub data_bits: usize,
+    pub output: FskOutput,
+    pub sample_rate: u32,
+}
+
+/**
+ * Two-tone FSK demodulator: the mark tone is a 1 and the space tone a 0, and characters are
+ * framed like a serial port, a space start bit, the data bits from the least significant one,
+ * then a mark stop bit.
+ */
+pub struct FskDecoder {
+    config: FskConfig,
+    step_len: usize,
+    bit_len: usize,
+    // The last bit worth of samples, the tones are compared over a whole bit
+    history: Vec<f32>,
+    // Samples since the last step
+    pending: usize,
+    // Whether the last step heard the mark tone, None when neither tone was on the air
+    last_mark: Option<bool>,
+    // Steps since the start of the current character, None while waiting for a start bit
+    steps_in_character: Option<usize>,
+    bits: Vec<bool>,
+    figures: bool,
+}
+
+impl FskDecoder {
+    pub fn new(config: FskConfig) -> Self {
+        let bit_len = (config.sample_rate as f32 / config.baud).round() as usize;
+        Self {
+            step_len: (bit_len / STEPS_PER_BIT).max(1),
+            bit_len,
+            config,
+            history: vec![],
+            pending: 0,
+            last_mark: None,
+            steps_in_character: None,
+            bits: vec![],
+            figures: false,
+        }
+    }
+
+    /**
+     * Demodulates new samples and returns the characters they completed
+     */
+    pub fn process(&mut self, samples: &[f32]) -> String {
+        let mut text = String::new();
+        for sample in samples {
+            self.history.push(*sample);
+            self.pending += 1;
+            if self.pending < self.step_len || self.history.len() < self.bit_len {
+                continue;
+            }
+            self.pending = 0;
+            let start = self.history.len() - self.bit_len;
+            self.history.drain(..start);
+
+            let sample_rate = self.config.sample_rate;
+            let mark_db = goertzel_level_db(&self.history, self.config.mark_hz, sample_rate);
+            let space_db = goertzel_level_db(&self.history, self.config.space_hz, sample_rate);
+            let mark = (mark_db.max(space_db) > SQUELCH_DB).then_some(mark_db > space_db);
+            if let Some(character) = self.step(mark) {
+                text += &character;
+            }
+        }
+        text
+    }
+
+    fn step(&mut self, mark: Option<bool>) -> Option<String> {
+        let last_mark = std::mem::replace(&mut self.last_mark, mark);
+        let Some(mark) = mark else {
+            // Lost the signal, what was being received is garbage
+            self.steps_in_character = None;
+            return None;
+        };
+        let Some(steps) = self.steps_in_character.as_mut() else {
+            if last_mark == Some(true) && !mark {
+                self.steps_in_character = Some(0);
+                self.bits.clear();
+            }
+            return None;
+        };
+        *steps += 1;
+        let steps = *steps;
+
+        // Bits are read in their middle, the start bit is read again to skip glitches
+        let half_bit = STEPS_PER_BIT / 2;
+        if steps < half_bit || !(steps - half_bit).is_multiple_of(STEPS_PER_BIT) {
+            return None;
+        }
+        let bit = (steps - half_bit) / STEPS_PER_BIT;
+        if bit == 0 && mark {
+            self.steps_in_character = None;
+        } else if (1..=self.config.data_bits).contains(&bit) {
+            self.bits.push(mark);
+        } else if bit > self.config.data_bits {
+            self.steps_in_character = None;
+            // Without its stop bit the character is out of frame
+            if mark {
+                return self.character();
+            }
+        }
+        None
+    }
+
+    fn character(&mut self) -> Option<String> {
+        let code = self
+            .bits
+            .iter()
+            .rev()
+            .fold(0u32, |code, bit| code << 1 | *bit as u32);
+        match self.config.output {
+            FskOutput::Bits => Some(
+                self.bits
+                    .iter()
+                    .map(|bit| if *bit { '1' } else { '0' })
+                    .chain([' '])
+                    .collect(),
+            ),
+            FskOutput::Ascii => char::from_u32(code)
+                .filter(|character| !character.is_control() || *character == '\n')
+                .map(String::from),
+            FskOutput::Baudot => {
+                let code = code as u8 & 0x1F;
+                match code {
+                    SHIFT_TO_FIGURES => self.figures = true,
+                    SHIFT_TO_LETTERS => self.figures = false,
+                    _ => {}
+                }
+                let table = if self.figures { FIGURES } else { LETTERS };
+                let character = table[code as usize];
+                // Most stations expect the letters to come back after a space
+                if character == b' ' {
+                    self.figures = false;
+                }
+                (character == b'\n' || (b' '..=b'~').contains(&character))
+                    .then(|| String::from(character as char))
+            }
+        }
+    }
+}
+
+/**
+ * Spawns a thread demodulating the samples of every FFT result, printing the characters as they
+ * are received
+ */
+pub fn spawn(config: FskConfig, fft_transform: Arc<Mutex<FftFrame>>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut decoder = FskDecoder::new(config);
+        let mut last_generation = 0;
+        loop {
+            // Frames have to be read as they come, the samples of a missed one would be a gap
+            std::thread::sleep(Duration::from_millis(5));
+            let frame = fft_transform.lock().unwrap().clone();
+            if frame.generation == last_generation {
+                continue;
+            }
+            last_generation = frame.generation;
+
+            let text = decoder.process(&frame.samples);
+            if !text.is_empty() {
+                print!("{text}");
+                stdout().flush().unwrap();
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rtty_decodes_to_text() {
+        let config = FskConfig {
+            mark_hz: 2125.0,
+            space_hz: 2295.0,
+            baud: 45.45,
+            data_bits: 5,
+            output: FskOutput::Baudot,
+            sample_rate: 44100,
+        };
+        let bit_len = config.sample_rate as f32 / config.baud;
+
+        // Idle on mark first, then each character with its start and 1.5 stop bits
+        let mut bits = vec![(true, 5.0)];
+        for code in [0x0A, 0x15, 0x04, 0x1B, 0x13, 0x01, 0x04, 0x0E, 0x17] {
+            bits.push((false, 1.0));
+            bits.extend((0..5).map(|bit| (code >> bit & 1 == 1, 1.0)));
+            bits.push((true, 1.5));
+        }
+        bits.push((true, 3.0));
+
+        let mut samples = vec![];
+        let mut phase = 0.0f32;
+        let mut end = 0.0;
+        for (mark, length) in bits {
+            end += length * bit_len;
+            let frequency_in_hz = if mark {
+                config.mark_hz
+            } else {
+                config.space_hz
+            };
+            while (samples.len() as f32) < end {
+                phase += 2.0 * std::f32::consts::PI * frequency_in_hz / config.sample_rate as f32;
+                samples.push(phase.sin() * 0.5);
+            }
+        }
+
+        let mut decoder = FskDecoder::new(config);
+        let text: String = samples
+            .chunks(4096)
+            .map(|frame| decoder.process(frame))
+            .collect();
+        assert_eq!(text, "RY 23 CQ");
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::VecDeque;
+
+#[cfg(feature = "app")]
+use sdl2::{pixels::Color, render::Canvas, video::Window};
+
+use crate::analysis::amplitude_to_db;
+#[cfg(feature = "app")]
+use crate::text;
+
+// The levels are measured in blocks, the advice looks at the last WINDOW_BLOCKS of them
+const BLOCK_SECS: f32 = 0.1;
+const WINDOW_BLOCKS: usize = 30;
+// A different advice has to hold this many blocks in a row to replace the one shown
+const HOLD_BLOCKS: usize = 20;
+
+// Samples this close to full scale were clipped, by the interface if not by the converter
+const CLIP_LEVEL: f32 = 0.999;
+// The peaks are best between these levels: enough headroom for a louder moment, and far enough
+// above the noise of the converter
+const HIGH_PEAK_DB: f32 = -3.0;
+const LOW_PEAK_DB: f32 = -30.0;
+// How far back inside the range the peaks have to come before the advice goes back to good
+const HYSTERESIS_DB: f32 = 6.0;
+// Where the advice aims the peaks
+const TARGET_PEAK_DB: f32 = -12.0;
+// Quieter than this, there is nothing playing to set the gain with
+const SILENCE_DB: f32 = -80.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GainAdvice {
+    Waiting,
+    Good,
+    // By about this many dB
+    Raise(f32),
+    Lower(f32),
+    Clipping,
+}
+
+/**
+ * Watches the peak and RMS levels of the input and tells whether its gain should go up or down
+ * to get the most out of the converter without clipping
+ */
+pub struct GainAdvisor {
+    block_len: usize,
+    // Peak and sum of the squares of the block being measured, and its length so far
+    block: (f32, f32, usize),
+    // Peak and mean square of the last blocks, the newest last
+    blocks: VecDeque<(f32, f32)>,
+    advice: GainAdvice,
+    // Advice differing from the one shown, and for how many blocks in a row
+    candidate: Option<(GainAdvice, usize)>,
+}
+
+impl GainAdvisor {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            block_len: ((BLOCK_SECS * sample_rate as f32) as usize).max(1),
+            block: (0.0, 0.0, 0),
+            blocks: VecDeque::new(),
+            advice: GainAdvice::Waiting,
+            candidate: None,
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) {
+        for sample in samples {
+            self.block.0 = self.block.0.max(sample.abs());
+            self.block.1 += sample * sample;
+            self.block.2 += 1;
+            if self.block.2 == self.block_len {
+                let (peak, sum, len) = self.block;
+                self.blocks.push_back((peak, sum / len as f32));
+                if self.blocks.len() > WINDOW_BLOCKS {
+                    self.blocks.pop_front();
+                }
+                self.block = (0.0, 0.0, 0);
+                self.update();
+            }
+        }
+    }
+
+    /**
+     * Peak and RMS levels of the last blocks in dBFS, and the crest factor between them in dB
+     */
+    pub fn levels(&self) -> (f32, f32, f32) {
+        let peak = self
+            .blocks
+            .iter()
+            .fold(0.0f32, |peak, block| peak.max(block.0));
+        let mean_square =
+            self.blocks.iter().map(|block| block.1).sum::<f32>() / self.blocks.len().max(1) as f32;
+        // The RMS of a full scale sine reads 0 dBFS, like its peak
+        let peak_db = amplitude_to_db(peak);
+        let rms_db = amplitude_to_db((mean_square * 2.0).sqrt());
+        (peak_db, rms_db, peak_db - rms_db + 3.01)
+    }
+
+    pub fn advice(&self) -> GainAdvice {
+        self.advice
+    }
+
+    /**
+     * What the last blocks call for, with the thresholds moved towards the inside of the range
+     * when the advice shown is already to move that way
+     */
+    fn wanted(&self) -> GainAdvice {
+        let (peak_db, _, _) = self.levels();
+        let last_peak = self.blocks.back().map_or(0.0, |block| block.0);
+        let (high_db, low_db) = match self.advice {
+            GainAdvice::Lower(_) | GainAdvice::Clipping => {
+                (HIGH_PEAK_DB - HYSTERESIS_DB, LOW_PEAK_DB)
+            }
+            GainAdvice::Raise(_) => (HIGH_PEAK_DB, LOW_PEAK_DB + HYSTERESIS_DB),
+            _ => (HIGH_PEAK_DB, LOW_PEAK_DB),
+        };
+        if last_peak >= CLIP_LEVEL {
+            GainAdvice::Clipping
+        } else if peak_db < SILENCE_DB {
+            GainAdvice::Waiting
+        } else if peak_db > high_db {
+            GainAdvice::Lower((peak_db - TARGET_PEAK_DB).round())
+        } else if peak_db < low_db {
+            GainAdvice::Raise((TARGET_PEAK_DB - peak_db).round())
+        } else {
+            GainAdvice::Good
+        }
+    }
+
+    fn update(&mut self) {
+        let wanted = self.wanted();
+        let same_kind = std::mem::discriminant(&wanted) == std::mem::discriminant(&self.advice);
+        if same_kind {
+            // The amount can follow the levels right away
+            self.advice = wanted;
+            self.candidate = None;
+            return;
+        }
+        let count = match self.candidate {
+            Some((candidate, count))
+                if std::mem::discriminant(&candidate) == std::mem::discriminant(&wanted) =>
+            {
+                count + 1
+            }
+            _ => 1,
+        };
+        // Clipping can't wait
+        if count >= HOLD_BLOCKS || wanted == GainAdvice::Clipping {
+            self.advice = wanted;
+            self.candidate = None;
+        } else {
+            self.candidate = Some((wanted, count));
+        }
+    }
+
+    /**
+     * Draws the levels and the advice as a line of text
+     */
+    #[cfg(feature = "app")]
+    pub fn draw(&self, canvas: &mut Canvas<Window>, x: i32, y: i32) {
+        let (peak_db, rms_db, crest_db) = self.levels();
+        let (advice, color) = match self.advice {
+            GainAdvice::Waiting => (
+                "waiting for a signal".to_string(),
+                Color::RGB(120, 120, 120),
+            ),
+            GainAdvice::Good => ("gain is good".to_string(), Color::RGB(6, 160, 120)),
+            GainAdvice::Raise(db) => (
+                format!("raise the gain {db:.0} dB"),
+                Color::RGB(220, 150, 0),
+            ),
+            GainAdvice::Lower(db) => (
+                format!("lower the gain {db:.0} dB"),
+                Color::RGB(220, 150, 0),
+            ),
+            GainAdvice::Clipping => (
+                "CLIPPING, lower the gain".to_string(),
+                Color::RGB(220, 30, 30),
+            ),
+        };
+        text::draw_text(
+            canvas,
+            &format!(
+                "Peak {peak_db:6.1} dBFS  RMS {rms_db:6.1} dBFS  Crest {crest_db:4.1} dB  {advice}"
+            ),
+            x,
+            y,
+            2,
+            color,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(amplitude: f32, secs: f32) -> Vec<f32> {
+        (0..(secs * 8000.0) as usize)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 8000.0).sin())
+            .collect()
+    }
+
+    #[test]
+    fn the_advice_settles_and_holds() {
+        let mut advisor = GainAdvisor::new(8000);
+        advisor.process(&tone(0.01, 3.0));
+        // A sine at -40 dBFS, 28 dB under the target
+        assert_eq!(advisor.advice(), GainAdvice::Raise(28.0));
+        let (peak_db, rms_db, crest_db) = advisor.levels();
+        assert!((peak_db + 40.0).abs() < 0.1 && (rms_db + 40.0).abs() < 0.1);
+        assert!((crest_db - 3.0).abs() < 0.1);
+
+        // Raised to -27 dBFS: still under the threshold it has to come back above
+        advisor.process(&tone(0.045, 3.0));
+        assert!(matches!(advisor.advice(), GainAdvice::Raise(_)));
+        advisor.process(&tone(0.25, 3.0));
+        assert_eq!(advisor.advice(), GainAdvice::Good);
+
+        // A short burst of silence doesn't change the advice, a clipped sample does at once
+        advisor.process(&tone(0.0, 0.5));
+        assert_eq!(advisor.advice(), GainAdvice::Good);
+        advisor.process(
+            &tone(1.2, 0.1)
+                .iter()
+                .map(|s| s.clamp(-1.0, 1.0))
+                .collect::<Vec<f32>>(),
+        );
+        assert_eq!(advisor.advice(), GainAdvice::Clipping);
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use crate::{
+    analysis::{one_sided_amplitudes, FftFrame},
+    curve::Calibration,
+    fft_magnitudes,
+    trigger::Capture,
+};
+
+/**
+ * A time window placed relative to the peak of a captured impulse. Only what's inside it gets
+ * analyzed, so the reflections that arrive after it (walls, floor, furniture) are left out of
+ * the spectrum. That is how loudspeakers are measured in rooms that aren't anechoic.
+ */
+pub struct Gate {
+    // Can be negative to start a bit before the peak
+    pub start_ms: f32,
+    pub length_ms: f32,
+}
+
+impl Gate {
+    /**
+     * Cuts the gated part of the capture and returns its spectrum, zero padded to at least
+     * `min_fft_size` points. Returns None when the gate falls outside of the capture.
+     */
+    pub fn apply(
+        &self,
+        capture: &Capture,
+        sample_rate: u32,
+        min_fft_size: usize,
+        calibration: Option<&mut Calibration>,
+    ) -> Option<FftFrame> {
+        // The impulse is the loudest sample of the triggering frame
+        let peak = capture.frame_start
+            + capture.samples[capture.frame_start..]
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.abs().partial_cmp(&b.abs()).unwrap())?
+                .0;
+
+        let to_samples = |ms: f32| (ms * sample_rate as f32 / 1000.0).round() as isize;
+        let start = peak as isize + to_samples(self.start_ms);
+        let end = start + to_samples(self.length_ms);
+        if start < 0 || end as usize > capture.samples.len() || end <= start {
+            return None;
+        }
+
+        let mut gated = capture.samples[start as usize..end as usize].to_vec();
+        taper(&mut gated);
+
+        // The resolution is set by the length of the gate, padding just interpolates between
+        // the bins
+        let fft_size = gated.len().next_power_of_two().max(min_fft_size);
+        gated.resize(fft_size, 0.0);
+
+        let mut magnitudes = fft_magnitudes(&gated);
+        if let Some(calibration) = calibration {
+            calibration.apply(&mut magnitudes);
+        }
+        Some(FftFrame {
+            amplitudes: Arc::new(one_sided_amplitudes(&magnitudes)),
+            magnitudes: Arc::new(magnitudes),
+            samples: Arc::new(gated),
+            generation: capture.frame.generation,
+            captured_at: capture.frame.captured_at,
+        })
+    }
+}
+
+/**
+ * Fades both edges with half a Hann window over 10% of the samples each, cutting the signal
+ * abruptly would add ripples of its own to the spectrum
+ */
+fn taper(samples: &mut [f32]) {
+    let fade = samples.len() / 10;
+    let len = samples.len();
+    for i in 0..fade {
+        let gain = 0.5 - 0.5 * (std::f32::consts::PI * i as f32 / fade as f32).cos();
+        samples[i] *= gain;
+        samples[len - 1 - i] *= gain;
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    StreamConfig,
+};
+
+use crate::{analysis::db_to_amplitude, filter::Biquad};
+
+// Width of the narrowband noise, a third of an octave
+const NOISE_Q: f32 = 4.32;
+
+// Time the level takes to move to a new sound, so changes don't click
+const RAMP_SECS: f32 = 0.01;
+
+/**
+ * What the generator plays. Levels are in dBFS, for the noise it's the level of a sine with
+ * the same power.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sound {
+    Silence,
+    Tone { frequency_in_hz: f32, level_db: f32 },
+    NarrowbandNoise { frequency_in_hz: f32, level_db: f32 },
+}
+
+/**
+ * Makes the samples of the current sound, sample by sample
+ */
+pub struct Synth {
+    sample_rate: u32,
+    sound: Sound,
+    phase: f32,
+    gain: f32,
+    noise_state: u32,
+    band_pass: Option<(f32, Biquad)>,
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            sound: Sound::Silence,
+            phase: 0.0,
+            gain: 0.0,
+            noise_state: 0x9E37_79B9,
+            band_pass: None,
+        }
+    }
+
+    pub fn set_sound(&mut self, sound: Sound) {
+        self.sound = sound;
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let (target_gain, sample) = match self.sound {
+            Sound::Silence => (0.0, 0.0),
+            Sound::Tone {
+                frequency_in_hz,
+                level_db,
+            } => {
+                self.phase = (self.phase + 2.0 * PI * frequency_in_hz / self.sample_rate as f32)
+                    % (2.0 * PI);
+                (db_to_amplitude(level_db), self.phase.sin())
+            }
+            Sound::NarrowbandNoise {
+                frequency_in_hz,
+                level_db,
+            } => (db_to_amplitude(level_db), self.noise(frequency_in_hz)),
+        };
+        let step = 1.0 / (RAMP_SECS * self.sample_rate as f32);
+        self.gain += (target_gain - self.gain).clamp(-step, step);
+        sample * self.gain
+    }
+
+    /**
+     * White noise through a band-pass at `frequency_in_hz`, scaled to the power of a sine of
+     * amplitude 1
+     */
+    fn noise(&mut self, frequency_in_hz: f32) -> f32 {
+        if self
+            .band_pass
+            .as_ref()
+            .is_none_or(|(center, _)| *center != frequency_in_hz)
+        {
+            self.band_pass = Some((
+                frequency_in_hz,
+                Biquad::band_pass(frequency_in_hz, NOISE_Q, self.sample_rate),
+            ));
+        }
+        // xorshift, plenty for noise
+        self.noise_state ^= self.noise_state << 13;
+        self.noise_state ^= self.noise_state >> 17;
+        self.noise_state ^= self.noise_state << 5;
+        let white = self.noise_state as f32 / u32::MAX as f32 * 2.0 - 1.0;
+
+        // White noise from -1 to 1 has a power of 1/3 spread up to the Nyquist frequency, the
+        // band-pass keeps its equivalent bandwidth of pi/2 * f/Q of it
+        let bandwidth_in_hz = PI / 2.0 * frequency_in_hz / NOISE_Q;
+        let power = bandwidth_in_hz / (self.sample_rate as f32 / 2.0) / 3.0;
+        let (_, band_pass) = self.band_pass.as_mut().unwrap();
+        band_pass.process(white) * (0.5 / power).sqrt()
+    }
+}
+
+/**
+ * Plays sounds through the default output device until dropped
+ */
+pub struct Generator {
+    synth: Arc<Mutex<Synth>>,
+    _stream: cpal::Stream,
+}
+
+impl Generator {
+    pub fn open() -> Result<Self, String> {
+        let speaker = cpal::default_host()
+            .default_output_device()
+            .ok_or("No output device found")?;
+        let config: StreamConfig = speaker
+            .default_output_config()
+            .map_err(|error| error.to_string())?
+            .into();
+        let channels = config.channels as usize;
+        let synth = Arc::new(Mutex::new(Synth::new(config.sample_rate.0)));
+        let stream_synth = synth.clone();
+
+        let stream = speaker
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _info| {
+                    let mut synth = stream_synth.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        frame.fill(synth.next_sample());
+                    }
+                },
+                |error| eprintln!("\nOutput error: {error}"),
+                None,
+            )
+            .map_err(|error| error.to_string())?;
+        stream.play().map_err(|error| error.to_string())?;
+        Ok(Self {
+            synth,
+            _stream: stream,
+        })
+    }
+
+    pub fn play(&self, sound: Sound) {
+        self.synth.lock().unwrap().set_sound(sound);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_has_the_power_of_a_sine_at_the_same_level() {
+        let sample_rate = 48000;
+        let level_db = -20.0;
+        let mut tone = Synth::new(sample_rate);
+        tone.set_sound(Sound::Tone {
+            frequency_in_hz: 4000.0,
+            level_db,
+        });
+        let mut noise = Synth::new(sample_rate);
+        noise.set_sound(Sound::NarrowbandNoise {
+            frequency_in_hz: 4000.0,
+            level_db,
+        });
+
+        let power = |synth: &mut Synth| {
+            let samples: Vec<f32> = (0..sample_rate * 4).map(|_| synth.next_sample()).collect();
+            // The ramp and the filter settle during the first second
+            let settled = &samples[sample_rate as usize..];
+            settled.iter().map(|s| s * s).sum::<f32>() / settled.len() as f32
+        };
+        let difference_db = 10.0 * (power(&mut noise) / power(&mut tone)).log10();
+        assert!(difference_db.abs() < 1.0, "{difference_db}");
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::analysis::{Band, BinMapping, FftFrame, Summary};
+
+pub mod proto {
+    tonic::include_proto!("analyzer");
+}
+
+use proto::{
+    analyzer_server::{Analyzer, AnalyzerServer},
+    AnalysisFrame, Pitch, StreamFramesRequest,
+};
+
+const DEFAULT_FRAMES_PER_SECOND: u32 = 20;
+
+struct AnalyzerService {
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+}
+
+/**
+ * Builds the message for the latest FFT result, or None while there is no data yet
+ */
+fn build_frame(
+    frame: &FftFrame,
+    sample_rate: u32,
+    max_frequency_hz: f32,
+    bands: &[Band],
+) -> Option<AnalysisFrame> {
+    let n = frame.magnitudes.len();
+    let summary = Summary::from_magnitudes(&frame.magnitudes, sample_rate, bands)?;
+    let mapping = BinMapping::new(sample_rate, n);
+
+    let bins = if max_frequency_hz > 0.0 {
+        mapping.bins_up_to(max_frequency_hz)
+    } else {
+        n / 2
+    };
+
+    Some(AnalysisFrame {
+        sequence: frame.generation,
+        captured_at_s: frame.captured_at.as_secs_f64(),
+        sample_rate,
+        fft_size: n as u32,
+        bin_width_hz: mapping.bin_width_hz(),
+        magnitudes: frame.magnitudes[0..bins].to_vec(),
+        pitch: Some(Pitch {
+            frequency_hz: summary.dominant_frequency_in_hz,
+            level_db: summary.dominant_level_db,
+        }),
+        level_db: summary.level_db,
+        band_levels_db: summary.band_levels_db,
+    })
+}
+
+#[tonic::async_trait]
+impl Analyzer for AnalyzerService {
+    type StreamFramesStream = ReceiverStream<Result<AnalysisFrame, Status>>;
+
+    async fn stream_frames(
+        &self,
+        request: Request<StreamFramesRequest>,
+    ) -> Result<Response<Self::StreamFramesStream>, Status> {
+        let request = request.into_inner();
+        let bands = request
+            .bands
+            .iter()
+            .map(|band| {
+                if band.low_hz < 0.0 || band.high_hz <= band.low_hz {
+                    return Err(Status::invalid_argument(
+                        "Bands must go from a lower to a higher frequency",
+                    ));
+                }
+                Ok(Band {
+                    low_hz: band.low_hz,
+                    high_hz: band.high_hz,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let frames_per_second = match request.frames_per_second {
+            0 => DEFAULT_FRAMES_PER_SECOND,
+            fps => fps,
+        };
+        let fft_transform = self.fft_transform.clone();
+        let sample_rate = self.sample_rate;
+        let (sender, receiver) = tokio::sync::mpsc::channel(4);
+
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs_f32(1.0 / frames_per_second as f32));
+            let mut last_generation = 0;
+
+            loop {
+                interval.tick().await;
+
+                let frame = fft_transform.lock().unwrap().clone();
+                // Nothing new since the last message
+                if frame.generation == last_generation {
+                    continue;
+                }
+                last_generation = frame.generation;
+
+                let message = build_frame(&frame, sample_rate, request.max_frequency_hz, &bands);
+                if let Some(message) = message {
+                    // The client went away
+                    if sender.send(Ok(message)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+/**
+ * Spawns a thread running its own tokio runtime with the gRPC server, so the rest of the app
+ * doesn't have to be async
+ */
+pub fn spawn(
+    address: SocketAddr,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+) -> JoinHandle<()> {
+    let service = AnalyzerService {
+        fft_transform,
+        sample_rate,
+    };
+
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let result = runtime.block_on(
+            Server::builder()
+                .add_service(AnalyzerServer::new(service))
+                .serve(address),
+        );
+        if let Err(error) = result {
+            eprintln!("gRPC server error: {error}");
+        }
+    })
+}
@@ -0,0 +1,356 @@
+use std::collections::VecDeque;
+
+#[cfg(feature = "app")]
+use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window};
+
+#[cfg(feature = "app")]
+use crate::text;
+use crate::{
+    analysis::{amplitude_to_db, cents_between, yin_estimate},
+    onset::NoteSegmenter,
+};
+
+// Standard tuning from the low E (6th string) to the high E (1st string)
+const STRINGS: [(&str, f32); 6] = [
+    ("6th (low E)", 82.41),
+    ("5th (A)", 110.0),
+    ("4th (D)", 146.83),
+    ("3rd (G)", 196.0),
+    ("2nd (B)", 246.94),
+    ("1st (high E)", 329.63),
+];
+
+// The open strings may be this far from standard tuning, enough for drop and lowered tunings
+const MAX_DETUNE_CENTS: f32 = 300.0;
+// The 12th fret note has to be this close to the octave of the open string
+const MAX_FRETTED_CENTS: f32 = 100.0;
+// Quieter frames are not played notes
+const MIN_LEVEL_DB: f32 = -50.0;
+// A note is measured once its pitch held that long within STEADY_CENTS of its median
+const STEADY_SECS: f32 = 0.4;
+const STEADY_CENTS: f32 = 10.0;
+// Saddles are usually left alone below this
+const IN_TUNE_CENTS: f32 = 3.0;
+// Less clear pitches are shown greyed out and not measured, the same as yin_pitch leaves out
+const MIN_CLARITY: f32 = 0.85;
+// Shorter stretches of sustain in a frame are too short to measure a low E with
+const MIN_SUSTAIN_SECS: f32 = 0.03;
+
+#[derive(Clone, Copy, Default)]
+struct StringResult {
+    open_hz: Option<f32>,
+    fretted_hz: Option<f32>,
+}
+
+impl StringResult {
+    /**
+     * How far the 12th fret is from the octave of the open string, positive when sharp
+     */
+    fn cents(&self) -> Option<f32> {
+        Some(cents_between(2.0 * self.open_hz?, self.fretted_hz?))
+    }
+}
+
+fn advice(cents: f32) -> &'static str {
+    if cents.abs() < IN_TUNE_CENTS {
+        "in tune"
+    } else if cents > 0.0 {
+        // Sharp at the 12th fret means the string is too short
+        "move the saddle away from the neck"
+    } else {
+        "move the saddle towards the neck"
+    }
+}
+
+/**
+ * Walks through the strings from the low E, asking for the open string and then for its 12th
+ * fret, and compares the fretted note with the octave of the open one. After the high E it
+ * starts over, so the strings can be checked again after moving the saddles.
+ */
+pub struct IntonationCheck {
+    // Even steps are open strings, odd ones their 12th fret
+    step: usize,
+    results: [StringResult; 6],
+    // Time and pitch of the latest readings of the note being played
+    readings: VecDeque<(f32, f32)>,
+    elapsed_secs: f32,
+    // Pitch and clarity of the last frame loud enough
+    last_pitch: Option<(f32, f32)>,
+    // Only the sustain of the notes is measured, the attack of a plucked string rings sharp
+    segmenter: NoteSegmenter,
+}
+
+impl IntonationCheck {
+    pub fn new() -> Self {
+        Self {
+            step: 0,
+            results: [StringResult::default(); 6],
+            readings: VecDeque::new(),
+            elapsed_secs: 0.0,
+            last_pitch: None,
+            segmenter: NoteSegmenter::new(MIN_LEVEL_DB),
+        }
+    }
+
+    #[cfg(feature = "app")]
+    fn prompt(&self) -> String {
+        let (name, _) = STRINGS[self.step / 2];
+        match self.step % 2 {
+            0 => format!("Play the open {name} string"),
+            _ => format!("Play the {name} string at the 12th fret"),
+        }
+    }
+
+    /**
+     * Whether a pitch could be the note asked for at this step
+     */
+    fn is_expected(&self, pitch_in_hz: f32) -> bool {
+        let string = &self.results[self.step / 2];
+        match (self.step % 2, string.open_hz) {
+            (0, _) => {
+                cents_between(STRINGS[self.step / 2].1, pitch_in_hz).abs() <= MAX_DETUNE_CENTS
+            }
+            (_, Some(open_hz)) => {
+                cents_between(2.0 * open_hz, pitch_in_hz).abs() <= MAX_FRETTED_CENTS
+            }
+            _ => false,
+        }
+    }
+
+    /**
+     * Takes the samples of a frame and returns a line to print when it finished a string
+     */
+    pub fn process(&mut self, samples: &[f32], sample_rate: u32) -> Option<String> {
+        let frame_secs = samples.len() as f32 / sample_rate as f32;
+        self.elapsed_secs += frame_secs;
+
+        let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt();
+        // The peak amplitude of a sine is sqrt(2) times its RMS
+        let estimate = (amplitude_to_db(rms * 2f32.sqrt()) >= MIN_LEVEL_DB)
+            .then(|| yin_estimate(samples, sample_rate, 60.0, 1000.0))
+            .flatten();
+        self.last_pitch = estimate;
+        let sustain = self.segmenter.process(samples, sample_rate);
+        let pitch_in_hz = (sustain.len() as f32 >= MIN_SUSTAIN_SECS * sample_rate as f32)
+            .then(|| yin_estimate(&samples[sustain], sample_rate, 60.0, 1000.0))
+            .flatten()
+            .filter(|(_, clarity)| *clarity >= MIN_CLARITY)
+            .map(|(pitch_in_hz, _)| pitch_in_hz);
+
+        let Some(pitch_in_hz) = pitch_in_hz.filter(|pitch| self.is_expected(*pitch)) else {
+            self.readings.clear();
+            return None;
+        };
+        self.readings.push_back((self.elapsed_secs, pitch_in_hz));
+
+        let mut sorted: Vec<f32> = self.readings.iter().map(|(_, pitch)| *pitch).collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let median_hz = sorted[sorted.len() / 2];
+        // The attack is often sharp, the readings before a jump are dropped
+        while self
+            .readings
+            .front()
+            .is_some_and(|(_, pitch)| cents_between(median_hz, *pitch).abs() > STEADY_CENTS)
+        {
+            self.readings.pop_front();
+        }
+        // A frame lasts frame_secs, the first one started that long before its time
+        let held_secs = self
+            .readings
+            .front()
+            .map_or(0.0, |(time, _)| self.elapsed_secs - time + frame_secs);
+        if held_secs < STEADY_SECS {
+            return None;
+        }
+
+        self.readings.clear();
+        let index = self.step / 2;
+        let (name, _) = STRINGS[index];
+        let result = &mut self.results[index];
+        let line = if self.step.is_multiple_of(2) {
+            *result = StringResult {
+                open_hz: Some(median_hz),
+                fretted_hz: None,
+            };
+            format!("{name} open: {median_hz:.2} Hz")
+        } else {
+            result.fretted_hz = Some(median_hz);
+            let cents = result.cents().unwrap();
+            format!(
+                "{name} 12th fret: {median_hz:.2} Hz, {cents:+.1} cents, {}",
+                advice(cents)
+            )
+        };
+        self.step = (self.step + 1) % (STRINGS.len() * 2);
+        Some(line)
+    }
+
+    /**
+     * Returns the strings measured so far as lines of text
+     */
+    pub fn report(&self) -> Vec<String> {
+        STRINGS
+            .iter()
+            .zip(&self.results)
+            .filter_map(|((name, _), result)| {
+                let cents = result.cents()?;
+                Some(format!(
+                    "{name:<13} open {:>7.2} Hz   12th fret {:>7.2} Hz   {cents:>+6.1} cents   {}",
+                    result.open_hz.unwrap(),
+                    result.fretted_hz.unwrap(),
+                    advice(cents)
+                ))
+            })
+            .collect()
+    }
+
+    /**
+     * Draws the step to play, the pitch heard and a line per string with its results
+     */
+    #[cfg(feature = "app")]
+    pub fn draw(&self, canvas: &mut Canvas<Window>, area: Rect) {
+        canvas.set_draw_color(Color::RGB(25, 25, 25));
+        canvas.fill_rect(area).unwrap();
+
+        let prompt = format!("{}   Heard: ", self.prompt());
+        text::draw_text(
+            canvas,
+            &prompt,
+            area.x() + 10,
+            area.y() + 8,
+            2,
+            Color::RGB(230, 230, 230),
+        );
+        let (heard, heard_color) = match self.last_pitch {
+            Some((pitch, clarity)) if clarity >= MIN_CLARITY => {
+                (format!("{pitch:.2} Hz"), Color::RGB(230, 230, 230))
+            }
+            Some((pitch, _)) => (format!("{pitch:.2} Hz?"), Color::RGB(110, 110, 110)),
+            None => ("-".into(), Color::RGB(230, 230, 230)),
+        };
+        text::draw_text(
+            canvas,
+            &heard,
+            area.x() + 10 + text::text_width(&prompt, 2) as i32,
+            area.y() + 8,
+            2,
+            heard_color,
+        );
+
+        let line_height = text::line_height(2) as i32;
+        let pitches_x = area.x() + 10 + text::text_width("1st (high E)   ", 2) as i32;
+        let status_x = pitches_x + text::text_width("open 000.00 Hz   12th 000.00 Hz   ", 2) as i32;
+        for (index, ((name, _), result)) in STRINGS.iter().zip(&self.results).enumerate() {
+            let y = area.y() + 16 + line_height * (index as i32 + 1);
+            let open = result
+                .open_hz
+                .map_or("-".into(), |hz| format!("{hz:.2} Hz"));
+            let fretted = result
+                .fretted_hz
+                .map_or("-".into(), |hz| format!("{hz:.2} Hz"));
+            let (status, color) = match result.cents() {
+                Some(cents) if cents.abs() < IN_TUNE_CENTS => (
+                    format!("{cents:+.1} cents, in tune"),
+                    Color::RGB(90, 200, 110),
+                ),
+                Some(cents) => (
+                    format!("{cents:+.1} cents, {}", advice(cents)),
+                    Color::RGB(230, 160, 60),
+                ),
+                None => (String::new(), Color::RGB(150, 150, 150)),
+            };
+            let name_color = match index == self.step / 2 {
+                true => Color::RGB(200, 36, 104),
+                false => Color::RGB(150, 150, 150),
+            };
+            text::draw_text(canvas, name, area.x() + 10, y, 2, name_color);
+            text::draw_text(
+                canvas,
+                &format!("open {open:>9}   12th {fretted:>9}"),
+                pitches_x,
+                y,
+                2,
+                Color::RGB(230, 230, 230),
+            );
+            text::draw_text(canvas, &status, status_x, y, 2, color);
+        }
+    }
+}
+
+impl Default for IntonationCheck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(check: &mut IntonationCheck, frequency_in_hz: f32, secs: f32) -> Vec<String> {
+        let sample_rate = 44100;
+        let frame_len = 4096;
+        let frames = (secs * sample_rate as f32 / frame_len as f32) as usize;
+        (0..frames)
+            .filter_map(|frame| {
+                let samples: Vec<f32> = (0..frame_len)
+                    .map(|i| {
+                        let t = (frame * frame_len + i) as f32 / sample_rate as f32;
+                        let phase = 2.0 * std::f32::consts::PI * frequency_in_hz * t;
+                        0.3 * phase.sin() + 0.2 * (2.0 * phase).sin()
+                    })
+                    .collect();
+                check.process(&samples, sample_rate)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sharp_12th_fret_asks_to_move_the_saddle_back() {
+        let mut check = IntonationCheck::new();
+        assert_eq!(play(&mut check, 82.0, 1.0).len(), 1);
+        // 8 cents sharp
+        let lines = play(&mut check, 164.0 * 2f32.powf(8.0 / 1200.0), 1.0);
+        assert_eq!(lines.len(), 1);
+        let cents = check.results[0].cents().unwrap();
+        assert!((cents - 8.0).abs() < 1.0, "{cents}");
+        assert!(lines[0].contains("away from the neck"));
+        assert_eq!(check.step, 2);
+    }
+
+    #[test]
+    fn the_sharp_attack_of_a_pluck_is_left_out() {
+        let sample_rate = 44100;
+        let mut phase = 0.0f32;
+        let samples: Vec<f32> = (0..sample_rate as usize)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                // 8 cents sharp while the level builds up for 250 ms, too little to be dropped
+                // as a jump but enough to pull the median
+                let (level, cents) = match t < 0.25 {
+                    true => (0.05 + 3.8 * t, 8.0),
+                    false => ((-(t - 0.25) / 1.5).exp(), 0.0),
+                };
+                phase += 2.0 * std::f32::consts::PI * 82.41 * 2f32.powf(cents / 1200.0)
+                    / sample_rate as f32;
+                level * (0.3 * phase.sin() + 0.2 * (2.0 * phase).sin())
+            })
+            .collect();
+        let mut check = IntonationCheck::new();
+        let lines: Vec<String> = samples
+            .chunks(4096)
+            .filter_map(|frame| check.process(frame, sample_rate))
+            .collect();
+        assert_eq!(lines.len(), 1);
+        let open_hz = check.results[0].open_hz.unwrap();
+        assert!(cents_between(82.41, open_hz).abs() < 1.0, "{open_hz}");
+    }
+
+    #[test]
+    fn notes_of_other_strings_are_ignored() {
+        let mut check = IntonationCheck::new();
+        // The open D while the open low E is asked for
+        assert!(play(&mut check, 146.83, 1.0).is_empty());
+        assert_eq!(check.step, 0);
+    }
+}
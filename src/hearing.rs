@@ -0,0 +1,374 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    time::{Duration, Instant, SystemTime},
+};
+
+use sdl2::{
+    event::Event,
+    keyboard::Keycode,
+    pixels::Color,
+    rect::{Point, Rect},
+    render::Canvas,
+    video::Window,
+};
+
+use crate::{
+    cli::HearingTestArgs,
+    generator::{Generator, Sound},
+    text,
+};
+
+// The usual audiometry frequencies, plus the high ones where age shows first
+const FREQUENCIES_IN_HZ: &[f32] = &[
+    250.0, 500.0, 1000.0, 2000.0, 3000.0, 4000.0, 6000.0, 8000.0, 10000.0, 12000.0, 14000.0,
+    16000.0,
+];
+
+const START_LEVEL_DB: i32 = -40;
+// Louder than this the tone isn't played, the frequency is marked as not heard
+const MAX_LEVEL_DB: i32 = -10;
+const MIN_LEVEL_DB: i32 = -100;
+
+const TONE_DURATION: Duration = Duration::from_millis(1000);
+// Answers still count this long after the tone stopped
+const ANSWER_DURATION: Duration = Duration::from_millis(1500);
+
+const CAVEATS: &[&str] = &[
+    "This is not a medical hearing test.",
+    "Levels are in dBFS: they depend on the volume and the headphones, so results only",
+    "compare with tests done with the same setup. See an audiologist for an audiogram.",
+];
+
+/**
+ * Finds the threshold at one frequency like the Hughson-Westlake method: 10 dB down after each
+ * tone heard, 5 dB up after each one missed, and the threshold is the first level heard twice
+ * while going up
+ */
+struct Staircase {
+    level_db: i32,
+    last_heard: Option<bool>,
+    // Levels heard while going up, with how many times
+    heard_going_up: Vec<(i32, usize)>,
+}
+
+impl Staircase {
+    fn new() -> Self {
+        Self {
+            level_db: START_LEVEL_DB,
+            last_heard: None,
+            heard_going_up: vec![],
+        }
+    }
+
+    /**
+     * Takes the answer to the tone at the current level. Returns the threshold once it's found,
+     * None inside when the tone wasn't heard even at the loudest level.
+     */
+    fn answer(&mut self, heard: bool) -> Option<Option<i32>> {
+        let going_up = self.last_heard == Some(false);
+        self.last_heard = Some(heard);
+        if !heard {
+            self.level_db += 5;
+            return (self.level_db > MAX_LEVEL_DB).then_some(None);
+        }
+
+        if going_up {
+            match self
+                .heard_going_up
+                .iter_mut()
+                .find(|(level_db, _)| *level_db == self.level_db)
+            {
+                Some((_, count)) => *count += 1,
+                None => self.heard_going_up.push((self.level_db, 1)),
+            }
+            if self
+                .heard_going_up
+                .iter()
+                .any(|(level_db, count)| *level_db == self.level_db && *count >= 2)
+            {
+                return Some(Some(self.level_db));
+            }
+        }
+        if self.level_db <= MIN_LEVEL_DB {
+            return Some(Some(self.level_db));
+        }
+        self.level_db = (self.level_db - 10).max(MIN_LEVEL_DB);
+        None
+    }
+}
+
+enum Phase {
+    // Silence before the next tone, of a random length so the tones can't be anticipated
+    Pause { until: Instant },
+    Tone { started: Instant },
+    Done,
+}
+
+/**
+ * Plays tones of decreasing and increasing levels at each frequency, keeps the quietest ones
+ * the user answered with Space, then plots them
+ */
+pub fn run(args: &HearingTestArgs) -> Result<(), String> {
+    let generator = Generator::open()?;
+    let sdl_context = sdl2::init()?;
+    let window = sdl_context
+        .video()?
+        .window("Hearing range self-test", 1100, 600)
+        .position_centered()
+        .build()
+        .map_err(|error| error.to_string())?;
+    let mut canvas = window
+        .into_canvas()
+        .build()
+        .map_err(|error| error.to_string())?;
+    let mut event_pump = sdl_context.event_pump()?;
+
+    let mut thresholds: Vec<(f32, Option<i32>)> = vec![];
+    let mut staircase = Staircase::new();
+    let mut phase = Phase::Pause {
+        until: Instant::now() + Duration::from_secs(2),
+    };
+    let mut started = false;
+    let mut saved = false;
+
+    'running: loop {
+        let mut pressed = false;
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    repeat: false,
+                    ..
+                } => pressed = true,
+                _ => {}
+            }
+        }
+        // The first Space only starts the test
+        if !started && pressed {
+            started = true;
+            pressed = false;
+            phase = Phase::Pause {
+                until: Instant::now() + Duration::from_secs(1),
+            };
+        }
+
+        let frequency_in_hz = FREQUENCIES_IN_HZ.get(thresholds.len()).copied();
+        phase = match (phase, frequency_in_hz) {
+            (_, None) => Phase::Done,
+            (phase, _) if !started => phase,
+            (Phase::Pause { until }, Some(_)) if Instant::now() < until => Phase::Pause { until },
+            (Phase::Pause { .. }, Some(_)) => Phase::Tone {
+                started: Instant::now(),
+            },
+            (Phase::Tone { started }, Some(frequency_in_hz)) => {
+                let elapsed = started.elapsed();
+                if pressed || elapsed >= TONE_DURATION + ANSWER_DURATION {
+                    if let Some(threshold_db) = staircase.answer(pressed) {
+                        println!(
+                            "{frequency_in_hz} Hz: {}",
+                            threshold_db
+                                .map_or("not heard".into(), |level_db| format!("{level_db} dBFS"))
+                        );
+                        thresholds.push((frequency_in_hz, threshold_db));
+                        staircase = Staircase::new();
+                    }
+                    Phase::Pause {
+                        until: Instant::now() + random_pause(),
+                    }
+                } else {
+                    Phase::Tone { started }
+                }
+            }
+            (Phase::Done, _) => Phase::Done,
+        };
+
+        if matches!(phase, Phase::Done) && !saved {
+            saved = true;
+            match save(&args.out, &thresholds) {
+                Ok(()) => println!("Saved the results to {}", args.out.display()),
+                Err(error) => eprintln!("Could not save the results: {error}"),
+            }
+        }
+
+        generator.play(match (&phase, frequency_in_hz) {
+            (Phase::Tone { started }, Some(frequency_in_hz))
+                if started.elapsed() < TONE_DURATION =>
+            {
+                Sound::Tone {
+                    frequency_in_hz,
+                    level_db: staircase.level_db as f32,
+                }
+            }
+            _ => Sound::Silence,
+        });
+
+        canvas.set_draw_color(Color::RGB(30, 30, 30));
+        canvas.clear();
+        let scale = 2;
+        let line_height = text::line_height(scale) as i32;
+        for (i, caveat) in CAVEATS.iter().enumerate() {
+            text::draw_text(
+                &mut canvas,
+                caveat,
+                20,
+                20 + line_height * i as i32,
+                scale,
+                Color::RGB(255, 170, 60),
+            );
+        }
+        let status = match (&phase, frequency_in_hz) {
+            (_, Some(_)) if !started => {
+                "Put headphones on, set a low volume and press Space to start".to_string()
+            }
+            (Phase::Done, _) | (_, None) => "Done. Esc to quit".into(),
+            (_, Some(frequency_in_hz)) => format!(
+                "Press Space whenever you hear a tone. Testing {frequency_in_hz} Hz ({} of {})",
+                thresholds.len() + 1,
+                FREQUENCIES_IN_HZ.len()
+            ),
+        };
+        text::draw_text(
+            &mut canvas,
+            &status,
+            20,
+            20 + line_height * (CAVEATS.len() as i32 + 1),
+            scale,
+            Color::RGB(230, 230, 230),
+        );
+        let top = 20 + line_height * (CAVEATS.len() as i32 + 3);
+        let (width, height) = canvas.window().size();
+        draw_plot(
+            &mut canvas,
+            Rect::new(80, top, width - 120, height - top as u32 - 50),
+            &thresholds,
+        );
+        canvas.present();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    generator.play(Sound::Silence);
+    std::thread::sleep(Duration::from_millis(50));
+    Ok(())
+}
+
+fn random_pause() -> Duration {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    Duration::from_millis(1000 + (nanos % 1500) as u64)
+}
+
+fn save(path: &std::path::Path, thresholds: &[(f32, Option<i32>)]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "frequency_hz,threshold_dbfs")?;
+    for (frequency_in_hz, threshold_db) in thresholds {
+        match threshold_db {
+            Some(threshold_db) => writeln!(file, "{frequency_in_hz},{threshold_db}")?,
+            None => writeln!(file, "{frequency_in_hz},")?,
+        }
+    }
+    file.flush()
+}
+
+/**
+ * Draws the thresholds like an audiogram: frequencies on a log axis, and quieter thresholds
+ * higher up. Frequencies that weren't heard at all are crosses at the bottom.
+ */
+fn draw_plot(canvas: &mut Canvas<Window>, area: Rect, thresholds: &[(f32, Option<i32>)]) {
+    let scale = 1;
+    let label_color = Color::RGB(150, 150, 150);
+    let (low_hz, high_hz) = (200.0f32, 20000.0f32);
+    let x_of = |frequency_in_hz: f32| {
+        area.x()
+            + ((frequency_in_hz / low_hz).ln() / (high_hz / low_hz).ln() * area.width() as f32)
+                as i32
+    };
+    let y_of = |level_db: i32| {
+        area.y()
+            + ((level_db - MIN_LEVEL_DB) as f32 / (MAX_LEVEL_DB - MIN_LEVEL_DB) as f32
+                * area.height() as f32) as i32
+    };
+
+    canvas.set_draw_color(Color::RGB(60, 60, 60));
+    for level_db in (MIN_LEVEL_DB..=MAX_LEVEL_DB).step_by(10) {
+        let y = y_of(level_db);
+        canvas.draw_line((area.x(), y), (area.right(), y)).unwrap();
+        text::draw_text(
+            canvas,
+            &format!("{level_db}"),
+            area.x() - 40,
+            y - 3,
+            scale,
+            label_color,
+        );
+    }
+    for frequency_in_hz in FREQUENCIES_IN_HZ {
+        let x = x_of(*frequency_in_hz);
+        canvas.set_draw_color(Color::RGB(60, 60, 60));
+        canvas.draw_line((x, area.y()), (x, area.bottom())).unwrap();
+        let label = if *frequency_in_hz >= 1000.0 {
+            format!("{}k", frequency_in_hz / 1000.0)
+        } else {
+            format!("{frequency_in_hz}")
+        };
+        text::draw_text(canvas, &label, x - 8, area.bottom() + 8, scale, label_color);
+    }
+
+    let points: Vec<Point> = thresholds
+        .iter()
+        .filter_map(|(frequency_in_hz, threshold_db)| {
+            threshold_db.map(|level_db| Point::new(x_of(*frequency_in_hz), y_of(level_db)))
+        })
+        .collect();
+    canvas.set_draw_color(Color::RGB(200, 36, 104));
+    canvas.draw_lines(points.as_slice()).unwrap();
+    for point in &points {
+        canvas.fill_rect(Rect::from_center(*point, 8, 8)).unwrap();
+    }
+    for (frequency_in_hz, _) in thresholds
+        .iter()
+        .filter(|(_, threshold)| threshold.is_none())
+    {
+        let (x, y) = (x_of(*frequency_in_hz), area.bottom() - 6);
+        canvas.draw_line((x - 5, y - 5), (x + 5, y + 5)).unwrap();
+        canvas.draw_line((x - 5, y + 5), (x + 5, y - 5)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn staircase_finds_the_threshold_within_5_db() {
+        for threshold_db in [-95, -73, -57, -31] {
+            let mut staircase = Staircase::new();
+            let found = loop {
+                let heard = staircase.level_db >= threshold_db;
+                if let Some(found) = staircase.answer(heard) {
+                    break found;
+                }
+            };
+            let found = found.unwrap();
+            assert!(found >= threshold_db && found - threshold_db < 5, "{found}");
+        }
+    }
+
+    #[test]
+    fn staircase_gives_up_above_the_loudest_level() {
+        let mut staircase = Staircase::new();
+        let found = loop {
+            if let Some(found) = staircase.answer(false) {
+                break found;
+            }
+        };
+        assert_eq!(found, None);
+    }
+}
@@ -0,0 +1,108 @@
+use std::f32::consts::PI;
+
+use crate::{
+    analysis::Band,
+    filter::{Biquad, Resampler},
+    output,
+};
+
+/**
+ * Brings a band down to the audible range like a heterodyne bat detector: the samples are
+ * multiplied by a tone at the middle of the band, which moves every frequency of the band to
+ * its distance from the middle, and the rest is filtered out. The result is resampled to the
+ * sample rate of the output.
+ */
+pub struct Heterodyne {
+    oscillator_in_hz: f32,
+    input_sample_rate: u32,
+    phase: f32,
+    // Two biquads in a row, a single one lets too much of the next band through
+    low_passes: [Biquad; 2],
+    resampler: Resampler,
+}
+
+impl Heterodyne {
+    pub fn new(band: &Band, input_sample_rate: u32, output_sample_rate: u32) -> Self {
+        let cutoff_in_hz =
+            ((band.high_hz - band.low_hz) / 2.0).clamp(100.0, output_sample_rate as f32 * 0.45);
+        Self {
+            oscillator_in_hz: (band.low_hz + band.high_hz) / 2.0,
+            input_sample_rate,
+            phase: 0.0,
+            low_passes: [
+                Biquad::low_pass(cutoff_in_hz, input_sample_rate),
+                Biquad::low_pass(cutoff_in_hz, input_sample_rate),
+            ],
+            resampler: Resampler::new(input_sample_rate, output_sample_rate),
+        }
+    }
+
+    /**
+     * Gets input samples and returns the output samples they make
+     */
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let phase_step = 2.0 * PI * self.oscillator_in_hz / self.input_sample_rate as f32;
+        let mut output = vec![];
+
+        for sample in samples {
+            // Mixing halves the amplitude, the other half goes to the sum of the frequencies
+            let mixed = 2.0 * sample * self.phase.cos();
+            self.phase = (self.phase + phase_step) % (2.0 * PI);
+            let filtered = self
+                .low_passes
+                .iter_mut()
+                .fold(mixed, |sample, low_pass| low_pass.process(sample));
+            self.resampler.push(filtered, &mut output);
+        }
+        output
+    }
+}
+
+/**
+ * Opens the default output device and plays what `Heterodyne::process` makes of the samples
+ * given to the returned function
+ */
+pub fn play(
+    band: &Band,
+    input_sample_rate: u32,
+) -> Result<(cpal::Stream, impl FnMut(&[f32]) + Send), String> {
+    let (stream, queue, output_sample_rate) = output::open("Heterodyne")?;
+    let mut heterodyne = Heterodyne::new(band, input_sample_rate, output_sample_rate);
+    let on_samples = move |samples: &[f32]| queue.extend(heterodyne.process(samples));
+    Ok((stream, on_samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ultrasonic_tone_comes_down_to_its_distance_from_the_middle() {
+        let input_sample_rate = 192000;
+        let output_sample_rate = 48000;
+        let band = Band {
+            low_hz: 38000.0,
+            high_hz: 44000.0,
+        };
+        let samples: Vec<f32> = (0..input_sample_rate)
+            .map(|i| (2.0 * PI * 42000.0 * i as f32 / input_sample_rate as f32).sin() * 0.5)
+            .collect();
+
+        let mut heterodyne = Heterodyne::new(&band, input_sample_rate, output_sample_rate);
+        let output = heterodyne.process(&samples);
+        assert!(output.len().abs_diff(output_sample_rate as usize) <= 1);
+
+        // Past the settling of the filters, the output is a 1 kHz sine of the same amplitude
+        let fft_len = 8192;
+        let magnitudes = crate::fft_magnitudes(&output[output.len() - fft_len..]);
+        let amplitudes = crate::analysis::one_sided_amplitudes(&magnitudes);
+        let (loudest, amplitude) = amplitudes
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .unwrap();
+        let mapping = crate::analysis::BinMapping::new(output_sample_rate, fft_len);
+        assert!((mapping.frequency_in_hz(loudest) - 1000.0).abs() < mapping.bin_width_hz());
+        assert!(*amplitude > 0.25 && *amplitude < 0.55);
+    }
+}
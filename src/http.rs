@@ -0,0 +1,203 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    analysis::{find_peaks, Band, BinMapping, FftFrame, Summary},
+    vad::Vad,
+};
+
+/*
+ * A tiny HTTP/1.1 server that only understands GET requests. Every response is JSON built from
+ * the latest FFT result and the connection is closed right after it, which is all that
+ * `curl` or a polling script needs.
+ */
+
+// A client that sends or reads nothing for this long is dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize)]
+struct SpectrumResponse<'a> {
+    captured_at_s: f64,
+    sample_rate: u32,
+    fft_size: usize,
+    bin_width_hz: f32,
+    magnitudes: &'a [f32],
+}
+
+#[derive(Serialize)]
+struct PitchResponse {
+    captured_at_s: f64,
+    // Null while --vad hears no voice
+    frequency_in_hz: Option<f32>,
+    level_db: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    voiced: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct SummaryResponse {
+    captured_at_s: f64,
+    #[serde(flatten)]
+    summary: Summary,
+}
+
+struct Endpoints {
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+    bands: Vec<Band>,
+    vad: Option<Vad>,
+}
+
+impl Endpoints {
+    /**
+     * Returns the status code and the JSON body for a request path like "/peaks?count=5"
+     */
+    fn route(&self, path: &str) -> (u16, String) {
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        let frame = self.fft_transform.lock().unwrap().clone();
+        let magnitudes = frame.magnitudes;
+        // Seconds on the capture clock, to line the answers up with other recordings
+        let captured_at_s = frame.captured_at.as_secs_f64();
+        let n = magnitudes.len();
+
+        if n < 2 {
+            return (503, error_body("No FFT result yet"));
+        }
+
+        match path {
+            "/spectrum" => {
+                let response = SpectrumResponse {
+                    captured_at_s,
+                    sample_rate: self.sample_rate,
+                    fft_size: n,
+                    bin_width_hz: BinMapping::new(self.sample_rate, n).bin_width_hz(),
+                    // The second half is just a mirror of the first one
+                    magnitudes: &magnitudes[0..n / 2],
+                };
+                (200, serde_json::to_string(&response).unwrap())
+            }
+            "/pitch" => {
+                let summary = Summary::from_magnitudes(&magnitudes, self.sample_rate, &[]).unwrap();
+                let voiced = self
+                    .vad
+                    .map(|vad| vad.is_voiced(&magnitudes, self.sample_rate));
+                let reported = voiced.unwrap_or(true);
+                let response = PitchResponse {
+                    captured_at_s,
+                    frequency_in_hz: reported.then_some(summary.dominant_frequency_in_hz),
+                    level_db: reported.then_some(summary.dominant_level_db),
+                    voiced,
+                };
+                (200, serde_json::to_string(&response).unwrap())
+            }
+            "/peaks" => {
+                let count = query_value(query, "count")
+                    .and_then(|count| count.parse().ok())
+                    .unwrap_or(10);
+                let peaks = find_peaks(&magnitudes, self.sample_rate, count);
+                (200, serde_json::to_string(&peaks).unwrap())
+            }
+            "/summary" => {
+                let response = SummaryResponse {
+                    captured_at_s,
+                    summary: Summary::from_magnitudes(&magnitudes, self.sample_rate, &self.bands)
+                        .unwrap(),
+                };
+                (200, serde_json::to_string(&response).unwrap())
+            }
+            _ => (404, error_body("Unknown endpoint")),
+        }
+    }
+
+    fn handle(&self, stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+
+        // The headers are not needed, but they have to be read before answering
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (status, body) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some(path)) => self.route(path),
+            _ => (405, error_body("Only GET is supported")),
+        };
+
+        let reason = match status {
+            200 => "OK",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Service Unavailable",
+        };
+
+        let mut stream = stream;
+        write!(
+            stream,
+            "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )?;
+        stream.flush()
+    }
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn query_value<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| value)
+}
+
+/**
+ * Binds the address and spawns a thread that accepts the connections, each one being answered
+ * on its own thread so a slow client doesn't hold up the others
+ */
+pub fn spawn(
+    address: SocketAddr,
+    bands: Vec<Band>,
+    vad: Option<Vad>,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+) -> std::io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(address)?;
+    let endpoints = Arc::new(Endpoints {
+        fft_transform,
+        sample_rate,
+        bands,
+        vad,
+    });
+
+    Ok(std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    eprintln!("HTTP error: {error}");
+                    continue;
+                }
+            };
+            let endpoints = endpoints.clone();
+            std::thread::spawn(move || {
+                if let Err(error) = endpoints.handle(stream) {
+                    eprintln!("HTTP error: {error}");
+                }
+            });
+        }
+    }))
+}
@@ -0,0 +1,49 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+/**
+ * Gets the amplitude of a bar relative to the loudest one (0 to 100) and returns its color,
+ * going from blue on the quiet bars to red on the loud ones
+ */
+pub fn amplitude_color(amplitude_percentage: u8) -> (u8, u8, u8) {
+    let max_red = 200.0;
+    let min_red = 63.0;
+
+    let max_blue = 184.0;
+    let min_blue = 104.0;
+    let amplitude_percentage = amplitude_percentage as f64 / 100.0;
+    (
+        (amplitude_percentage * (max_red - min_red) + min_red).round() as u8,
+        36,
+        (((1.0 - amplitude_percentage) * (max_blue - min_blue) + min_blue).round()) as u8,
+    )
+}
+
+/**
+ * Gets a value from 0 to 1 and returns a color going from dark blue to the same red used by the
+ * bars of the live graph
+ */
+pub fn spectrogram_color(t: f32) -> [u8; 3] {
+    let t = t.clamp(0.0, 1.0);
+    let max_red = 200.0;
+    let min_red = 63.0;
+    let max_blue = 184.0;
+    let min_blue = 104.0;
+
+    // Quiet bins fade into the background instead of all looking purple
+    [
+        ((t * (max_red - min_red) + min_red) * t).round() as u8,
+        (36.0 * t).round() as u8,
+        (((1.0 - t) * (max_blue - min_blue) + min_blue) * t.sqrt()).round() as u8,
+    ]
+}
+
+pub fn write_png(path: &Path, width: u32, height: u32, rgb: &[u8]) -> Result<(), String> {
+    let file = File::create(path).map_err(|error| error.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(rgb))
+        .map_err(|error| error.to_string())
+}
@@ -0,0 +1,90 @@
+// Sample rate the infrasound is analyzed at. The FFT sizes of the window then go from ~10 s to
+// ~11 minutes, with bins from 0.1 Hz down to 0.0015 Hz wide
+pub const ANALYSIS_SAMPLE_RATE: u32 = 100;
+
+// Frequencies below this are removed, sensors and interfaces often have a DC offset that would
+// hide everything else
+const DC_CUTOFF_HZ: f32 = 0.02;
+
+/**
+ * Brings the samples down to ANALYSIS_SAMPLE_RATE by averaging them in groups, and removes the
+ * DC offset
+ */
+pub struct Decimator {
+    factor: usize,
+    sum: f32,
+    count: usize,
+    // State of the one-pole DC blocker
+    last_input: f32,
+    last_output: f32,
+}
+
+impl Decimator {
+    pub fn new(capture_sample_rate: u32) -> Self {
+        Self {
+            factor: (capture_sample_rate / ANALYSIS_SAMPLE_RATE).max(1) as usize,
+            sum: 0.0,
+            count: 0,
+            last_input: 0.0,
+            last_output: 0.0,
+        }
+    }
+
+    /**
+     * Sample rate of the decimated samples, as close to ANALYSIS_SAMPLE_RATE as the capture
+     * sample rate allows
+     */
+    pub fn output_sample_rate(capture_sample_rate: u32) -> u32 {
+        capture_sample_rate / (capture_sample_rate / ANALYSIS_SAMPLE_RATE).max(1)
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let pole = 1.0 - 2.0 * std::f32::consts::PI * DC_CUTOFF_HZ / ANALYSIS_SAMPLE_RATE as f32;
+        let mut output = Vec::with_capacity(samples.len() / self.factor + 1);
+        for sample in samples {
+            // The average of a group has no response at the multiples of the output sample
+            // rate, which is where the aliases would come from
+            self.sum += sample;
+            self.count += 1;
+            if self.count < self.factor {
+                continue;
+            }
+            let average = self.sum / self.factor as f32;
+            self.sum = 0.0;
+            self.count = 0;
+
+            self.last_output = average - self.last_input + pole * self.last_output;
+            self.last_input = average;
+            output.push(self.last_output);
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimator_keeps_slow_waves_and_drops_the_offset() {
+        let capture_sample_rate = 44100;
+        let samples: Vec<f32> = (0..capture_sample_rate * 200)
+            .map(|i| {
+                let t = i as f32 / capture_sample_rate as f32;
+                0.3 + 0.5 * (2.0 * std::f32::consts::PI * 2.0 * t).sin()
+            })
+            .collect();
+
+        let mut decimator = Decimator::new(capture_sample_rate);
+        let output = decimator.process(&samples);
+        assert_eq!(Decimator::output_sample_rate(capture_sample_rate), 100);
+        assert_eq!(output.len(), 200 * 100);
+
+        // Once the blocker settled, what's left is the 2 Hz wave around zero
+        let last_second = &output[output.len() - 100..];
+        let mean = last_second.iter().sum::<f32>() / 100.0;
+        let peak = last_second.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(mean.abs() < 0.02);
+        assert!((peak - 0.5).abs() < 0.02);
+    }
+}
@@ -0,0 +1,197 @@
+use clap::ValueEnum;
+
+use crate::{cli::SuggestArgs, windowing::WindowFunction, MAX_FFT_SIZE, MIN_FFT_SIZE};
+
+/**
+ * What the analyzer is being used for, in the words of someone who doesn't know what an FFT
+ * size is
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Intent {
+    /// Tuning a bass guitar, down to the low B of a 5 string one
+    BassTuning,
+    /// Tuning a guitar, from its low E
+    GuitarTuning,
+    /// Watching the pitch and the formants of speech or singing
+    Speech,
+    /// Measuring the levels of broadband noise, like a room or a machine
+    Noise,
+    /// Looking at music as it plays
+    Music,
+}
+
+/**
+ * The settings picked for an intent at a sample rate, with the reasoning behind them
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Suggestion {
+    pub fft_size: usize,
+    pub window: WindowFunction,
+    // Fraction of a frame shared with the next one
+    pub overlap: f32,
+    pub max_frequency_in_hz: f32,
+    pub reason: &'static str,
+}
+
+/**
+ * Smallest FFT size whose bins are at most `resolution_in_hz` wide, within the sizes the live
+ * graph can switch to
+ */
+fn fft_size_for_resolution(sample_rate: u32, resolution_in_hz: f32) -> usize {
+    let needed = (sample_rate as f32 / resolution_in_hz).ceil() as usize;
+    needed.next_power_of_two().clamp(MIN_FFT_SIZE, MAX_FFT_SIZE)
+}
+
+/**
+ * Half the distance from a note to the one a semitone above, the resolution a tuner needs to
+ * tell them apart
+ */
+fn half_semitone_above(frequency_in_hz: f32) -> f32 {
+    frequency_in_hz * (2f32.powf(1.0 / 12.0) - 1.0) / 2.0
+}
+
+impl Intent {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::BassTuning => "tuning a bass guitar",
+            Self::GuitarTuning => "tuning a guitar",
+            Self::Speech => "watching speech",
+            Self::Noise => "measuring noise",
+            Self::Music => "looking at music",
+        }
+    }
+
+    pub fn suggest(self, sample_rate: u32) -> Suggestion {
+        let nyquist = sample_rate as f32 / 2.0;
+        match self {
+            Self::BassTuning => Suggestion {
+                fft_size: fft_size_for_resolution(sample_rate, half_semitone_above(30.87)),
+                window: WindowFunction::Hann,
+                overlap: 0.75,
+                max_frequency_in_hz: 500.0_f32.min(nyquist),
+                reason: "The notes of the low B are 1.8 Hz apart, it takes bins under a hertz and a long frame to split them. The overlap keeps the reading moving while the frame fills.",
+            },
+            Self::GuitarTuning => Suggestion {
+                fft_size: fft_size_for_resolution(sample_rate, half_semitone_above(82.41)),
+                window: WindowFunction::Hann,
+                overlap: 0.75,
+                max_frequency_in_hz: 1500.0_f32.min(nyquist),
+                reason: "The notes of the low E are 4.9 Hz apart, the bins have to be half of that. Up to 1500 Hz shows the first harmonics of the high strings.",
+            },
+            Self::Speech => Suggestion {
+                // A frame of about 25 ms, the syllables change faster than a longer one
+                fft_size: (sample_rate as usize / 40)
+                    .next_power_of_two()
+                    .clamp(MIN_FFT_SIZE, MAX_FFT_SIZE),
+                window: WindowFunction::Hamming,
+                overlap: 0.5,
+                max_frequency_in_hz: 4000.0_f32.min(nyquist),
+                reason: "Speech changes every few tens of milliseconds, short frames follow it at the cost of resolution. The pitch and the formants that tell vowels apart are below 4 kHz.",
+            },
+            Self::Noise => Suggestion {
+                fft_size: fft_size_for_resolution(sample_rate, 10.0),
+                window: WindowFunction::Hann,
+                overlap: 0.5,
+                max_frequency_in_hz: nyquist.min(20000.0),
+                reason: "Broadband noise needs the whole audible range more than fine bins. Half overlapping Hann frames count every sample with the same weight.",
+            },
+            Self::Music => Suggestion {
+                fft_size: fft_size_for_resolution(sample_rate, 11.0),
+                window: WindowFunction::Hann,
+                overlap: 0.75,
+                max_frequency_in_hz: 5000.0_f32.min(nyquist),
+                reason: "A compromise between telling the notes of the melody apart and following the rhythm, with the fundamentals and first harmonics of most instruments in view.",
+            },
+        }
+    }
+}
+
+impl Suggestion {
+    /**
+     * Samples between the start of two frames
+     */
+    pub fn hop(&self) -> usize {
+        ((self.fft_size as f32 * (1.0 - self.overlap)).round() as usize).max(1)
+    }
+}
+
+/**
+ * Prints the settings for an intent, what they give, and the command lines using them
+ */
+pub fn run(args: &SuggestArgs) -> Result<(), String> {
+    let suggestion = args.intent.suggest(args.sample_rate);
+    let name = args
+        .intent
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default();
+    println!(
+        "Settings for {} at {} Hz:",
+        args.intent.describe(),
+        args.sample_rate
+    );
+    println!(
+        "  FFT size  {} ({:.2} Hz per bin, {:.0} ms per frame)",
+        suggestion.fft_size,
+        args.sample_rate as f32 / suggestion.fft_size as f32,
+        suggestion.fft_size as f32 * 1000.0 / args.sample_rate as f32
+    );
+    println!("  Window    {}", suggestion.window.name());
+    println!(
+        "  Overlap   {:.0}% (a new frame every {} samples)",
+        suggestion.overlap * 100.0,
+        suggestion.hop()
+    );
+    println!("  Range     up to {} Hz", suggestion.max_frequency_in_hz);
+    println!("\n{}", suggestion.reason);
+    println!(
+        "\nLive, with the FFT size, the window and the range (the live graph has no overlap):"
+    );
+    println!(
+        "  mic_frequencies_analyzer --sample-rate {} --intent {name}",
+        args.sample_rate
+    );
+    println!("A recording:");
+    println!(
+        "  mic_frequencies_analyzer analyze <file.wav> --out spectrogram.png --fft-size {} --hop {} --window {} --max-freq {}",
+        suggestion.fft_size,
+        suggestion.hop(),
+        suggestion
+            .window
+            .to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default(),
+        suggestion.max_frequency_in_hz
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tuning_bins_split_the_lowest_semitone() {
+        for (intent, lowest_in_hz) in [(Intent::BassTuning, 30.87), (Intent::GuitarTuning, 82.41)] {
+            let suggestion = intent.suggest(44100);
+            let bin_width = 44100.0 / suggestion.fft_size as f32;
+            assert!(bin_width <= half_semitone_above(lowest_in_hz), "{intent:?}");
+            // Not twice as long as needed either
+            assert!(
+                bin_width * 2.0 > half_semitone_above(lowest_in_hz),
+                "{intent:?}"
+            );
+        }
+        assert_eq!(Intent::BassTuning.suggest(44100).fft_size, 65536);
+        assert_eq!(Intent::GuitarTuning.suggest(44100).hop(), 8192);
+    }
+
+    #[test]
+    fn speech_frames_stay_short() {
+        let suggestion = Intent::Speech.suggest(48000);
+        assert_eq!(suggestion.fft_size, 2048);
+        assert_eq!(suggestion.hop(), 1024);
+        // The range never goes past what the sample rate can hold
+        assert_eq!(Intent::Noise.suggest(22050).max_frequency_in_hz, 11025.0);
+    }
+}
@@ -0,0 +1,259 @@
+use std::{collections::HashMap, fmt, path::Path, str::FromStr};
+
+use sdl2::keyboard::{Keycode, Mod};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Help,
+    Pause,
+    CopyReadout,
+    SmallerFft,
+    BiggerFft,
+    Snapshot,
+    Record,
+    ExportSpectrogram,
+    ReplayBack,
+    ReplayForward,
+    ReplayFaster,
+    ReplaySlower,
+    Marker,
+    LabeledMarker,
+    Measure,
+    SetReference,
+    ClearReference,
+    DeltaView,
+    CursorA,
+    CursorB,
+    GoTo,
+    BinTable,
+    SaveBinTable,
+    ClearGoTo,
+    PinkTarget,
+    Feedback,
+    SuggestEq,
+    Meters,
+    PeakTable,
+    FrequencyUnit,
+    AmplitudeUnit,
+    LockScale,
+    DbScale,
+    Window,
+    LogFrequency,
+    Spectrogram,
+    ListenBand,
+    ListenNotch,
+    Drone,
+    SaveTimbre,
+    CompareTimbre,
+    Overlay,
+    RaiseDbTop,
+    LowerDbTop,
+    RaiseDbFloor,
+    LowerDbFloor,
+}
+
+// Name used in the bindings file, description shown by the help and default binding of each
+// action, in the order the help lists them
+#[rustfmt::skip]
+const ACTIONS: &[(Action, &str, &str, Keycode, bool)] = &[
+    (Action::Help, "help", "Show/hide this help", Keycode::Slash, true),
+    (Action::Pause, "pause", "Pause/resume the graph", Keycode::P, false),
+    (Action::CopyReadout, "copy_readout", "Copy the readout of the hovered bar", Keycode::C, false),
+    (Action::SmallerFft, "smaller_fft", "Halve the FFT size", Keycode::LeftBracket, false),
+    (Action::BiggerFft, "bigger_fft", "Double the FFT size", Keycode::RightBracket, false),
+    (Action::Measure, "measure", "Start/stop a measurement session", Keycode::M, false),
+    (Action::SetReference, "set_reference", "Use the last measurement or the spectrum as the reference", Keycode::R, false),
+    (Action::ClearReference, "clear_reference", "Clear the reference", Keycode::R, true),
+    (Action::DeltaView, "delta_view", "Toggle the difference to the reference", Keycode::D, false),
+    (Action::Feedback, "feedback", "Start/stop the feedback detection", Keycode::F, false),
+    (Action::PinkTarget, "pink_target", "Show/hide the pink noise target", Keycode::G, false),
+    (Action::SuggestEq, "suggest_eq", "Suggest EQ filters toward the target", Keycode::Q, false),
+    (Action::Meters, "meters", "Show/hide the band meters", Keycode::E, false),
+    (Action::PeakTable, "peak_table", "Show/hide the peak table", Keycode::T, false),
+    (Action::FrequencyUnit, "frequency_unit", "Switch the frequency unit (Hz, kHz, note, MIDI)", Keycode::U, false),
+    (Action::AmplitudeUnit, "amplitude_unit", "Switch the amplitude unit (linear, dBFS, dBu, dBV, dB SPL)", Keycode::U, true),
+    (Action::ListenBand, "listen_band", "Play only the selected frequency, or stop", Keycode::H, false),
+    (Action::ListenNotch, "listen_notch", "Play everything but the selected frequency, or stop", Keycode::H, true),
+    (Action::Drone, "drone", "Freeze the spectrum into a drone played through the output, or stop", Keycode::Z, false),
+    (Action::SaveTimbre, "save_timbre", "Save the harmonics under the cursors as a timbre fingerprint", Keycode::X, false),
+    (Action::CompareTimbre, "compare_timbre", "Compare the harmonics under the cursors to the saved timbres", Keycode::X, true),
+    (Action::Overlay, "overlay", "Show or hide the takes given with --overlay", Keycode::V, false),
+    (Action::LockScale, "lock_scale", "Lock the scale to the loudest bar, or unlock it", Keycode::N, false),
+    (Action::DbScale, "db_scale", "Switch between bars scaled to the loudest one and bars in dB", Keycode::N, true),
+    (Action::Window, "window", "Switch to the next window function", Keycode::O, false),
+    (Action::LogFrequency, "log_frequency", "Switch between a linear and a logarithmic frequency axis", Keycode::L, false),
+    (Action::Spectrogram, "spectrogram", "Show the spectrogram instead of the bars, or the bars again", Keycode::Y, false),
+    (Action::RaiseDbTop, "raise_db_top", "Raise the top of the dB scale", Keycode::Up, true),
+    (Action::LowerDbTop, "lower_db_top", "Lower the top of the dB scale", Keycode::Down, true),
+    (Action::RaiseDbFloor, "raise_db_floor", "Raise the floor of the dB scale", Keycode::PageUp, false),
+    (Action::LowerDbFloor, "lower_db_floor", "Lower the floor of the dB scale", Keycode::PageDown, false),
+    (Action::Snapshot, "snapshot", "Save the samples as a WAV file", Keycode::S, false),
+    (Action::Record, "record", "Start/stop recording the spectra", Keycode::W, false),
+    (Action::ExportSpectrogram, "export_spectrogram", "Save the whole spectrogram history as PNG", Keycode::W, true),
+    (Action::ReplayBack, "replay_back", "Jump 5 seconds back in a replay", Keycode::Left, false),
+    (Action::ReplayForward, "replay_forward", "Jump 5 seconds forward in a replay", Keycode::Right, false),
+    (Action::ReplayFaster, "replay_faster", "Double the speed of a replay", Keycode::Up, false),
+    (Action::ReplaySlower, "replay_slower", "Halve the speed of a replay", Keycode::Down, false),
+    (Action::Marker, "marker", "Drop a marker", Keycode::K, false),
+    (Action::LabeledMarker, "labeled_marker", "Drop a marker labeled with the clipboard", Keycode::K, true),
+    (Action::CursorA, "cursor_a", "Place the first delta cursor", Keycode::A, false),
+    (Action::CursorB, "cursor_b", "Place the second delta cursor", Keycode::B, false),
+    (Action::BinTable, "bin_table", "Print every displayed bin as a table", Keycode::I, false),
+    (Action::SaveBinTable, "save_bin_table", "Save every displayed bin as a table", Keycode::I, true),
+    (Action::GoTo, "go_to", "Go to the frequency or note in the clipboard and pin it", Keycode::J, false),
+    (Action::ClearGoTo, "clear_go_to", "Unpin the frequency and restore the view", Keycode::J, true),
+    (Action::Quit, "quit", "Quit", Keycode::Escape, false),
+];
+
+impl FromStr for Action {
+    type Err = String;
+
+    /**
+     * Parses the name of an action, as written in the bindings file
+     */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ACTIONS
+            .iter()
+            .find(|(_, name, ..)| *name == s.trim())
+            .map(|(action, ..)| *action)
+            .ok_or_else(|| format!("Unknown action \"{}\"", s.trim()))
+    }
+}
+
+/**
+ * A key, with or without Shift, written like "P", "Shift+R" or "Left" (SDL key names)
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    pub keycode: Keycode,
+    pub shift: bool,
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (shift, name) = match s.trim().split_once('+') {
+            Some((modifier, name)) if modifier.trim().eq_ignore_ascii_case("shift") => {
+                (true, name.trim())
+            }
+            _ => (false, s.trim()),
+        };
+        let keycode = Keycode::from_name(name).ok_or_else(|| format!("Unknown key \"{name}\""))?;
+        Ok(Self { keycode, shift })
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        write!(f, "{}", self.keycode.name())
+    }
+}
+
+/**
+ * Which action each key triggers
+ */
+pub struct KeyBindings {
+    actions: HashMap<KeyBinding, Action>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            actions: ACTIONS
+                .iter()
+                .map(|(action, _, _, keycode, shift)| {
+                    let binding = KeyBinding {
+                        keycode: *keycode,
+                        shift: *shift,
+                    };
+                    (binding, *action)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    /**
+     * Starts from the defaults and applies a file of "action = key" lines on top of them, like
+     * "pause = Space" or "clear_reference = Shift+Backspace". Empty lines and lines starting
+     * with # are skipped.
+     */
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| format!("Could not read {}: {error}", path.display()))?;
+        let mut bindings = Self::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("{}:{}: {message}", path.display(), number + 1);
+            let (name, key) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("Expected \"action = key\", got \"{line}\"")))?;
+            let action: Action = name.parse().map_err(error)?;
+            let binding: KeyBinding = key.parse().map_err(error)?;
+
+            // The action moves to its new key, and whatever the key did before is unbound
+            bindings.actions.retain(|_, bound| *bound != action);
+            if let Some(replaced) = bindings.actions.insert(binding, action) {
+                eprintln!(
+                    "{binding} now triggers {} instead of {}",
+                    name.trim(),
+                    action_name(replaced)
+                );
+            }
+        }
+        Ok(bindings)
+    }
+
+    /**
+     * Returns the action of a key press. Keys without a Shift binding do the same with or
+     * without Shift.
+     */
+    pub fn action(&self, keycode: Keycode, keymod: Mod) -> Option<Action> {
+        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+        self.actions
+            .get(&KeyBinding { keycode, shift })
+            .or_else(|| {
+                shift
+                    .then(|| {
+                        self.actions.get(&KeyBinding {
+                            keycode,
+                            shift: false,
+                        })
+                    })
+                    .flatten()
+            })
+            .copied()
+    }
+
+    /**
+     * Returns the bound key and the description of every action that has one, for the help
+     */
+    pub fn help(&self) -> Vec<(KeyBinding, &'static str)> {
+        ACTIONS
+            .iter()
+            .filter_map(|(action, _, description, ..)| {
+                self.actions
+                    .iter()
+                    .find(|(_, bound)| *bound == action)
+                    .map(|(binding, _)| (*binding, *description))
+            })
+            .collect()
+    }
+}
+
+fn action_name(action: Action) -> &'static str {
+    ACTIONS
+        .iter()
+        .find(|(bound, ..)| *bound == action)
+        .map_or("", |(_, name, ..)| name)
+}
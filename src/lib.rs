@@ -0,0 +1,108 @@
+// The modules behind the app feature are the window and the command line of the binary,
+// and the modes built on them. The rest works without SDL2 and clap.
+pub mod alarm;
+pub mod analysis;
+pub mod artnet;
+pub mod audio;
+pub mod birds;
+#[cfg(feature = "app")]
+pub mod calibrate;
+#[cfg(feature = "onnx")]
+pub mod classify;
+#[cfg(feature = "app")]
+pub mod cli;
+pub mod clock;
+pub mod curve;
+pub mod daemon;
+pub mod dft;
+pub mod dose;
+pub mod drone;
+pub mod drum;
+pub mod eq;
+#[cfg(feature = "app")]
+pub mod eq_diff;
+pub mod feedback;
+#[cfg(feature = "app")]
+pub mod fft_steps;
+pub mod filter;
+pub mod flutter;
+pub mod fsk;
+pub mod gain;
+pub mod gate;
+pub mod generator;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod guitar;
+#[cfg(feature = "app")]
+pub mod hearing;
+pub mod heterodyne;
+pub mod http;
+pub mod image;
+pub mod infrasound;
+#[cfg(feature = "app")]
+pub mod intent;
+#[cfg(feature = "app")]
+pub mod keys;
+pub mod listen;
+#[cfg(feature = "app")]
+pub mod live;
+pub mod log;
+pub mod marker;
+pub mod meters;
+pub mod morse;
+pub mod mqtt;
+pub mod noise_rating;
+#[cfg(feature = "app")]
+pub mod offline;
+pub mod onset;
+pub mod osc;
+pub mod output;
+#[cfg(feature = "app")]
+pub mod overlay;
+#[cfg(feature = "app")]
+pub mod pipe;
+pub mod pitch;
+pub mod profile;
+pub mod recording;
+#[cfg(feature = "app")]
+pub mod render;
+#[cfg(feature = "app")]
+pub mod resonance;
+#[cfg(feature = "app")]
+pub mod selftest;
+pub mod serial;
+pub mod session;
+pub mod shm;
+pub mod silence;
+pub mod snapshot;
+pub mod source;
+#[cfg(feature = "app")]
+pub mod speaker;
+#[cfg(feature = "app")]
+pub mod spectrogram;
+#[cfg(feature = "app")]
+pub mod stereo;
+pub mod sti;
+pub mod takes;
+pub mod text;
+pub mod timbre;
+#[cfg(feature = "app")]
+pub mod tinnitus;
+pub mod transcription;
+pub mod trigger;
+pub mod units;
+pub mod vad;
+pub mod vocoder;
+pub mod voice;
+#[cfg(feature = "app")]
+pub mod waterfall;
+#[cfg(feature = "app")]
+pub mod whistle;
+pub mod windowing;
+pub mod wled;
+
+// The core of the analysis, at the root where the modules have always found it
+pub use analysis::{
+    fft, fft_magnitudes, ifft, is_power_of_two, NoteStatus, MAX_FFT_SIZE, MIN_FFT_SIZE,
+};
+pub use audio::{open_input_stream, FftPipeline, FftPipelineConfig, SampleCallback};
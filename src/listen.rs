@@ -0,0 +1,209 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    filter::{Biquad, Resampler},
+    output::{self, OutputQueue},
+};
+
+/**
+ * What is heard of the selected frequency
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListenMode {
+    // Only the band around it, to hear what it sounds like
+    Band,
+    // Everything but the band around it, to hear whether it is the noise that stands out
+    Notch,
+}
+
+impl ListenMode {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Band => "only",
+            Self::Notch => "everything but",
+        }
+    }
+}
+
+/**
+ * Plays the input filtered around one frequency, to hear what a bar of the graph is
+ */
+pub struct Monitor {
+    center_in_hz: f32,
+    mode: ListenMode,
+    // Two filters in a row, a single band-pass still lets the loud neighbours through and a
+    // single notch only takes a few dB off the sides of the peak
+    filters: [Biquad; 2],
+    resampler: Resampler,
+    queue: OutputQueue,
+}
+
+/**
+ * The monitor the input callback feeds, set and cleared from the window
+ */
+pub type SharedMonitor = Arc<Mutex<Option<Monitor>>>;
+
+impl Monitor {
+    pub fn new(
+        center_in_hz: f32,
+        mode: ListenMode,
+        q: f32,
+        input_sample_rate: u32,
+        output_sample_rate: u32,
+        queue: OutputQueue,
+    ) -> Self {
+        let filter = || match mode {
+            ListenMode::Band => Biquad::band_pass(center_in_hz, q, input_sample_rate),
+            ListenMode::Notch => Biquad::notch(center_in_hz, q, input_sample_rate),
+        };
+        Self {
+            center_in_hz,
+            mode,
+            filters: [filter(), filter()],
+            resampler: Resampler::new(input_sample_rate, output_sample_rate),
+            queue,
+        }
+    }
+
+    /**
+     * Frequency and mode being listened to
+     */
+    pub fn listening(&self) -> (f32, ListenMode) {
+        (self.center_in_hz, self.mode)
+    }
+
+    /**
+     * Gets input samples and returns the output samples they make
+     */
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let mut output = vec![];
+        for sample in samples {
+            let filtered = self
+                .filters
+                .iter_mut()
+                .fold(*sample, |sample, filter| filter.process(sample));
+            self.resampler.push(filtered, &mut output);
+        }
+        output
+    }
+
+    pub fn play(&mut self, samples: &[f32]) {
+        let output = self.process(samples);
+        self.queue.extend(output);
+    }
+}
+
+/**
+ * The window side of the monitor: opens the output the first time it is needed and points the
+ * monitor at the selected frequency
+ */
+pub struct Listener {
+    monitor: SharedMonitor,
+    // Kept open once opened, it plays silence while nothing is listened to
+    output: Option<(cpal::Stream, OutputQueue, u32)>,
+    q: f32,
+    input_sample_rate: u32,
+}
+
+impl Listener {
+    pub fn new(q: f32, input_sample_rate: u32) -> Self {
+        Self {
+            monitor: Arc::new(Mutex::new(None)),
+            output: None,
+            q,
+            input_sample_rate,
+        }
+    }
+
+    /**
+     * The monitor to feed with the input samples
+     */
+    pub fn monitor(&self) -> SharedMonitor {
+        self.monitor.clone()
+    }
+
+    /**
+     * Frequency and mode being listened to, if any
+     */
+    pub fn listening(&self) -> Option<(f32, ListenMode)> {
+        self.monitor
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(Monitor::listening)
+    }
+
+    pub fn listen_to(&mut self, center_in_hz: f32, mode: ListenMode) -> Result<(), String> {
+        if self.output.is_none() {
+            self.output = Some(output::open("Monitor")?);
+        }
+        let (_, queue, output_sample_rate) = self.output.as_ref().unwrap();
+        *self.monitor.lock().unwrap() = Some(Monitor::new(
+            center_in_hz,
+            mode,
+            self.q,
+            self.input_sample_rate,
+            *output_sample_rate,
+            queue.clone(),
+        ));
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        *self.monitor.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    fn level_through(monitor: &mut Monitor, frequency_in_hz: f32) -> f32 {
+        let samples: Vec<f32> = (0..48000)
+            .map(|i| (2.0 * PI * frequency_in_hz * i as f32 / 48000.0).sin())
+            .collect();
+        let output = monitor.process(&samples);
+        // Past the settling of the filters
+        output[output.len() / 2..]
+            .iter()
+            .fold(0.0, |peak: f32, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn only_the_selected_frequency_is_heard() {
+        let monitor = || {
+            Monitor::new(
+                1000.0,
+                ListenMode::Band,
+                8.0,
+                48000,
+                44100,
+                OutputQueue::new(44100),
+            )
+        };
+        assert!(level_through(&mut monitor(), 1000.0) > 0.9);
+        // An octave away is over 30 dB down
+        assert!(level_through(&mut monitor(), 2000.0) < 0.03);
+        assert!(level_through(&mut monitor(), 500.0) < 0.03);
+    }
+
+    #[test]
+    fn the_notch_removes_only_the_selected_frequency() {
+        let monitor = || {
+            Monitor::new(
+                1000.0,
+                ListenMode::Notch,
+                8.0,
+                48000,
+                44100,
+                OutputQueue::new(44100),
+            )
+        };
+        assert!(level_through(&mut monitor(), 1000.0) < 0.01);
+        // A third of an octave away is barely touched
+        assert!(level_through(&mut monitor(), 1260.0) > 0.8);
+        assert!(level_through(&mut monitor(), 794.0) > 0.8);
+    }
+}
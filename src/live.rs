@@ -0,0 +1,2740 @@
+use std::{
+    collections::HashMap,
+    io::{stdout, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use sdl2::{
+    clipboard::ClipboardUtil,
+    event::{Event, WindowEvent},
+    mouse::MouseButton,
+    pixels::Color,
+    rect::Rect,
+    render::{Canvas, TextureCreator},
+    video::{Window, WindowContext},
+};
+
+#[cfg(feature = "onnx")]
+use crate::classify;
+#[cfg(feature = "grpc")]
+use crate::grpc;
+use crate::{
+    alarm,
+    analysis::{self, BinMapping, FftFrame},
+    artnet, audio, birds,
+    cli::Args,
+    clock, curve, daemon, dft, dose, drone, drum, eq, feedback, flutter, fsk, gain, gate, guitar,
+    heterodyne, http,
+    image::amplitude_color,
+    infrasound, intent, keys, listen, marker, meters, morse, mqtt, open_input_stream, osc, overlay,
+    pitch, profile, recording,
+    render::{
+        bar_color, draw_db_axis, draw_frequency_axis, draw_help, draw_multiples,
+        draw_percentage_axis, DisplayColors, FrequencyData, Graph, GraphBar,
+    },
+    serial, session, shm, silence, snapshot, source, spectrogram, takes, text, timbre,
+    transcription, trigger, units, vad, vocoder, voice, waterfall, whistle, windowing, wled,
+    NoteStatus, SampleCallback, MAX_FFT_SIZE, MIN_FFT_SIZE,
+};
+
+// How far the keys move the top or the floor of the dB scale
+const DB_RANGE_STEP: f32 = 6.0;
+
+// Rows of the infrasound waterfall, 20 minutes with the smallest FFT size
+const WATERFALL_ROWS: usize = 120;
+// Rows of the bird waterfall, about a minute with the default FFT size
+const BIRDS_WATERFALL_ROWS: usize = 600;
+// Frames side by side in the spectrogram view, about 40 seconds with the default FFT size
+const SPECTROGRAM_COLUMNS: usize = 400;
+
+// Time after a drum tap before the trigger is armed again, so its ringing doesn't fire it
+const DRUM_REARM_DELAY: Duration = Duration::from_millis(700);
+
+/**
+ * Formats the peaks as a table with their frequency, note, tuning error and level
+ */
+fn peak_table(
+    peaks: &[analysis::Peak],
+    unit: units::FrequencyUnit,
+    amplitude_scale: units::AmplitudeScale,
+) -> String {
+    let mut table = format!(
+        "{:>4} {:>10} {:>6} {:>6} {:>8}",
+        "#",
+        unit.header(),
+        "note",
+        "cents",
+        amplitude_scale.header()
+    );
+    for (rank, peak) in peaks.iter().enumerate() {
+        let note_status = NoteStatus::new(peak.frequency_in_hz);
+        table += &format!(
+            "\n{:>4} {:>10} {:>4}{:<2} {:>+6} {:>8}",
+            rank + 1,
+            unit.value(peak.frequency_in_hz),
+            NoteStatus::note_number_to_name(note_status.note_number).trim(),
+            NoteStatus::get_octave_by_key_number(note_status.key_number),
+            note_status.error_percentage,
+            amplitude_scale.value(peak.level_db)
+        );
+    }
+    table
+}
+
+/**
+ * Formats every displayed bin as a table with its frequency, note, tuning error and level
+ */
+fn bin_table(
+    bars: &[GraphBar],
+    captured_at: Duration,
+    capture_clock: clock::CaptureClock,
+    unit: units::FrequencyUnit,
+    amplitude_scale: units::AmplitudeScale,
+) -> String {
+    let mut table = format!(
+        "Captured at {} s ({})\n{:>6} {:>12} {:>6} {:>6} {:>10}",
+        clock::format_seconds(captured_at),
+        humantime::format_rfc3339_micros(capture_clock.wall_time(captured_at)),
+        "bin",
+        unit.header(),
+        "note",
+        "cents",
+        amplitude_scale.header()
+    );
+    for bar in bars {
+        let data = &bar.frequency_data;
+        table += &format!(
+            "\n{:>6} {:>12} {:>4}{:<2} {:>+6} {:>10}",
+            data.analyzing_bin_index,
+            unit.value(data.note_status.get_frequency_in_hz()),
+            NoteStatus::note_number_to_name(data.note_status.note_number).trim(),
+            NoteStatus::get_octave_by_key_number(data.note_status.key_number),
+            data.note_status.error_percentage,
+            amplitude_scale.value(data.level_db)
+        );
+    }
+    table
+}
+
+/**
+ * Writes a take on its own thread, writing a long one would hold up the capture
+ */
+fn save_take(directory: PathBuf, take: Vec<f32>, sample_rate: u32) -> JoinHandle<()> {
+    std::thread::spawn(
+        move || match takes::save_take(&directory, &take, sample_rate) {
+            Ok(path) => println!("\nTake saved to {}", path.display()),
+            Err(error) => eprintln!("\nCouldn't save the take: {error}"),
+        },
+    )
+}
+
+/**
+ * Describes the pitch being played with how sure it is, leaving the note out when it's more
+ * likely noise
+ */
+fn pitch_readout(pitch: &pitch::PitchEstimate, unit: units::FrequencyUnit) -> String {
+    if !pitch.is_confident() {
+        return format!("Pitch: - ({:3.0}% sure)", pitch.confidence * 100.0);
+    }
+    let note_status = NoteStatus::new(pitch.frequency_in_hz);
+    format!(
+        "Pitch: {} ({}{}, {:3.0}% sure)",
+        unit.format(pitch.frequency_in_hz),
+        NoteStatus::note_number_to_name(note_status.note_number).trim(),
+        NoteStatus::get_octave_by_key_number(note_status.key_number),
+        pitch.confidence * 100.0
+    )
+}
+
+/**
+ * Describes how far the second cursor is from the first one, in frequency, musical interval
+ * and level
+ */
+fn delta_readout(
+    a: &FrequencyData,
+    b: &FrequencyData,
+    unit: units::FrequencyUnit,
+    amplitude_scale: units::AmplitudeScale,
+) -> String {
+    let a_hz = a.note_status.get_frequency_in_hz();
+    let b_hz = b.note_status.get_frequency_in_hz();
+    if a_hz <= 0.0 || b_hz <= 0.0 {
+        return format!(
+            "A: {}, B: {}, the interval to DC is undefined",
+            unit.format(a_hz),
+            unit.format(b_hz)
+        );
+    }
+
+    let cents = analysis::cents_between(a_hz, b_hz);
+    format!(
+        "A: {} ({}), B: {} ({}). B - A: {:+.2} Hz, {:+.2} semitones ({:+.0} cents), {:+.1} dB",
+        unit.format(a_hz),
+        amplitude_scale.format(a.level_db),
+        unit.format(b_hz),
+        amplitude_scale.format(b.level_db),
+        b_hz - a_hz,
+        cents / 100.0,
+        cents,
+        b.level_db - a.level_db
+    )
+}
+
+/**
+ * Prints the suggested EQ filters and writes them into a text file in `report_dir`
+ */
+fn save_eq_suggestions(
+    filters: &[eq::PeakingFilter],
+    source: &str,
+    report_dir: &std::path::Path,
+    unit: units::FrequencyUnit,
+) {
+    if filters.is_empty() {
+        println!("\n{source} is already within 2 dB of the target, no EQ needed");
+        return;
+    }
+
+    println!("\nEQ to bring {source} to the target:");
+    for filter in filters {
+        println!(
+            "{:>14} {:>+6.1} dB Q {:.2}",
+            unit.format(filter.frequency_in_hz),
+            filter.gain_db,
+            filter.q
+        );
+    }
+
+    let timestamp = humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string();
+    let path = report_dir.join(format!("eq-{}.txt", timestamp.replace(':', "-")));
+    match std::fs::create_dir_all(report_dir).and_then(|_| eq::write_filters(&path, filters)) {
+        Ok(()) => println!("Saved the filters to {}", path.display()),
+        Err(error) => eprintln!("Could not save the filters: {error}"),
+    }
+}
+
+/**
+ * Lists the latest feedback events with their note, so they can be notched on an EQ
+ */
+fn feedback_list(
+    detector: &feedback::FeedbackDetector,
+    unit: units::FrequencyUnit,
+    amplitude_scale: units::AmplitudeScale,
+) -> String {
+    let mut list = String::from("FEEDBACK, latest first:");
+    for event in detector.recent() {
+        let note_status = NoteStatus::new(event.frequency_in_hz);
+        list += &format!(
+            "\n{:>14} {:>2}{} {:>+4} cents {:>14}, {}s ago",
+            unit.format(event.frequency_in_hz),
+            NoteStatus::note_number_to_name(note_status.note_number).trim(),
+            NoteStatus::get_octave_by_key_number(note_status.key_number),
+            note_status.error_percentage,
+            amplitude_scale.format(event.level_db),
+            event.at.elapsed().as_secs()
+        );
+    }
+    list
+}
+
+/**
+ * The frequency the user picked: the clicked fundamental, or else the one pinned with J
+ */
+fn selected_frequency(
+    fundamental_bin: Option<usize>,
+    pinned_frequency: Option<f32>,
+    mapping: BinMapping,
+) -> Option<f32> {
+    fundamental_bin
+        .map(|bin| mapping.frequency_in_hz(bin))
+        .or(pinned_frequency)
+}
+
+/**
+ * Formats the harmonics as a table with their level and their ratio to the fundamental,
+ * followed by the THD
+ */
+fn harmonic_table(
+    harmonics: &[analysis::Harmonic],
+    unit: units::FrequencyUnit,
+    amplitude_scale: units::AmplitudeScale,
+) -> String {
+    let mut table = format!(
+        "{:>4} {:>10} {:>8} {:>9} {:>9}",
+        "#",
+        unit.header(),
+        amplitude_scale.header(),
+        "dB vs f0",
+        "% of f0"
+    );
+    for harmonic in harmonics {
+        table += &format!(
+            "\n{:>4} {:>10} {:>8} {:>9.1} {:>9.2}",
+            harmonic.number,
+            unit.value(harmonic.frequency_in_hz),
+            amplitude_scale.value(harmonic.level_db),
+            harmonic.ratio_db,
+            10f32.powf(harmonic.ratio_db / 20.0) * 100.0
+        );
+    }
+    table += &format!(
+        "\nTHD: {:.2}%",
+        analysis::total_harmonic_distortion(harmonics) * 100.0
+    );
+    table
+}
+
+/**
+ * Writes the report of a measurement session and tells where it went
+ */
+fn finish_session(
+    session: &session::Session,
+    report_dir: &std::path::Path,
+    unit: units::FrequencyUnit,
+) {
+    if session.frames() == 0 {
+        println!("\nThe measurement was stopped before any spectrum was averaged");
+        return;
+    }
+    match session.write_report(report_dir, unit) {
+        Ok((csv, html)) => println!(
+            "\nAveraged {} spectra, wrote {} and {}",
+            session.frames(),
+            csv.display(),
+            html.display()
+        ),
+        Err(error) => eprintln!("\nCould not write the measurement report: {error}"),
+    }
+    for (criterion, rating) in session.noise_ratings() {
+        println!(
+            "{}-{} (set by the {} Hz band)",
+            criterion.name(),
+            rating.value,
+            rating.band_hz
+        );
+    }
+}
+
+/**
+ * What the live mode runs with, worked out from the arguments and the input before anything
+ * starts
+ */
+#[derive(Clone, Copy)]
+struct Setup {
+    capture_sample_rate: u32,
+    // Lower than the capture one when the infrasound decimator runs
+    stream_sample_rate: u32,
+    // FFT size the mode starts with
+    buffer_size: usize,
+    max_displayed_frequency: usize,
+    capture_clock: clock::CaptureClock,
+}
+
+/**
+ * The callback the input feeds, and the state the window shares with it
+ */
+struct Capture {
+    on_samples: SampleCallback,
+    // The FFT size can be changed with hotkeys, the audio callback picks it up on its next run
+    fft_size: Arc<AtomicUsize>,
+    window_function: Arc<Mutex<windowing::WindowFunction>>,
+    // Result Buffer containing the FFT of the data
+    fft_transform: Arc<Mutex<FftFrame>>,
+    // internal buffer
+    fft_transform_buffer: Arc<Mutex<Vec<f32>>>,
+    profiler: Option<Arc<profile::Profiler>>,
+    listener: listen::Listener,
+    trigger_handle: Option<Arc<trigger::TriggerHandle>>,
+    silence_asleep: Option<Arc<AtomicBool>>,
+    gain_advisor: Option<Arc<Mutex<gain::GainAdvisor>>>,
+    // Shared with the callback so the take being recorded can be saved when quitting
+    take_recorder: Option<(Arc<Mutex<takes::TakeRecorder>>, PathBuf)>,
+    // Takes being written, waited for before quitting
+    take_saves: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    // The gated captures are analyzed on the main thread, which needs its own copy
+    gate_calibration: Option<curve::Calibration>,
+    // The output streams have to stay alive as long as the input one
+    _heterodyne_stream: Option<cpal::Stream>,
+    _pitch_shift_stream: Option<cpal::Stream>,
+}
+
+impl Capture {
+    /**
+     * Builds the callback: the outputs playing the input, the gain advisor, the takes and the
+     * silence pause get the samples as they come, the FFT pipeline the decimated ones
+     */
+    fn start(
+        args: &Args,
+        setup: Setup,
+        window_function: windowing::WindowFunction,
+        infrasound: bool,
+    ) -> Self {
+        let Setup {
+            capture_sample_rate,
+            stream_sample_rate,
+            buffer_size,
+            capture_clock,
+            ..
+        } = setup;
+        let fft_size = Arc::new(AtomicUsize::new(buffer_size));
+        let window_function = Arc::new(Mutex::new(window_function));
+        let profiler = args.profile.then(|| Arc::new(profile::Profiler::default()));
+
+        // Written from the audio callback itself, so readers get each frame as soon as possible
+        let mut shm_publisher = args.shm.as_ref().map(|path| {
+            println!("Publishing the spectrum in {}", path.display());
+            shm::ShmPublisher::create(path, stream_sample_rate, buffer_size, MAX_FFT_SIZE)
+                .expect("Could not create the shared memory region")
+        });
+        let (mut trigger, trigger_handle) = match args.trigger {
+            Some(threshold_db) => {
+                let (trigger, handle) = trigger::Trigger::new(trigger::TriggerConfig {
+                    threshold_db,
+                    band: args.trigger_band.clone(),
+                    pre_trigger_samples: (args.pre_trigger_ms * stream_sample_rate as u64 / 1000)
+                        as usize,
+                    sample_rate: stream_sample_rate,
+                });
+                (Some(trigger), Some(handle))
+            }
+            None => (None, None),
+        };
+        let mic_calibration = args.mic_calibration.as_ref().map(|path| {
+            let curve = curve::Curve::load(path).unwrap_or_else(|error| panic!("{error}"));
+            println!("Correcting the spectrum with {}", path.display());
+            curve::Calibration::new(curve, stream_sample_rate)
+        });
+        let gate_calibration = mic_calibration.clone();
+        let listener = listen::Listener::new(args.listen_q, capture_sample_rate);
+        let band_monitor = listener.monitor();
+        let (heterodyne_stream, mut heterodyne_samples) = match &args.heterodyne {
+            Some(band) => {
+                let (stream, on_samples) = heterodyne::play(band, capture_sample_rate)
+                    .unwrap_or_else(|error| panic!("Could not open the output device: {error}"));
+                println!(
+                    "Playing {} - {} Hz brought down by {} Hz",
+                    band.low_hz,
+                    band.high_hz,
+                    (band.low_hz + band.high_hz) / 2.0
+                );
+                (Some(stream), Some(on_samples))
+            }
+            None => (None, None),
+        };
+
+        let (pitch_shift_stream, mut pitch_shift_samples) = match args.pitch_shift {
+            Some(semitones) => {
+                let (stream, on_samples) = vocoder::play(semitones, capture_sample_rate)
+                    .unwrap_or_else(|error| panic!("Could not open the output device: {error}"));
+                println!("Playing the input shifted by {semitones:+} semitones");
+                (Some(stream), Some(on_samples))
+            }
+            None => (None, None),
+        };
+
+        let mut decimator = infrasound.then(|| infrasound::Decimator::new(capture_sample_rate));
+        let mut silence_detector = args.auto_pause_secs.map(|secs| {
+            silence::SilenceDetector::new(
+                args.auto_pause_db,
+                Duration::from_secs_f32(secs),
+                capture_sample_rate,
+            )
+        });
+        let silence_asleep = silence_detector.as_ref().map(|detector| detector.asleep());
+        let take_recorder = args.auto_takes.clone().map(|directory| {
+            let recorder = takes::TakeRecorder::new(
+                capture_sample_rate,
+                Duration::from_secs_f32(args.take_silence_secs),
+            );
+            (Arc::new(Mutex::new(recorder)), directory)
+        });
+        let callback_take_recorder = take_recorder.clone();
+        let take_saves: Arc<Mutex<Vec<JoinHandle<()>>>> = Arc::new(Mutex::new(vec![]));
+        let callback_take_saves = take_saves.clone();
+        let gain_advisor = args
+            .gain_advisor
+            .then(|| Arc::new(Mutex::new(gain::GainAdvisor::new(capture_sample_rate))));
+        let callback_gain_advisor = gain_advisor.clone();
+        let mut fft_pipeline = audio::FftPipeline::new(audio::FftPipelineConfig {
+            sample_rate: stream_sample_rate,
+            fft_size: fft_size.clone(),
+            window_function: window_function.clone(),
+            calibration: mic_calibration,
+            capture_clock,
+            profiler: profiler.clone(),
+        });
+        let fft_transform = fft_pipeline.frame();
+        let fft_transform_buffer = fft_pipeline.buffer();
+
+        let on_samples: SampleCallback =
+            Arc::new(Mutex::new(move |data: &[f32], captured: Instant| {
+                if let Some(play) = heterodyne_samples.as_mut() {
+                    play(data);
+                }
+                if let Some(play) = pitch_shift_samples.as_mut() {
+                    play(data);
+                }
+                if let Some(monitor) = band_monitor.lock().unwrap().as_mut() {
+                    monitor.play(data);
+                }
+                if let Some(advisor) = &callback_gain_advisor {
+                    advisor.lock().unwrap().process(data);
+                }
+                if let Some((recorder, directory)) = &callback_take_recorder {
+                    if let Some(take) = recorder.lock().unwrap().process(data) {
+                        let save = save_take(directory.clone(), take, capture_sample_rate);
+                        callback_take_saves.lock().unwrap().push(save);
+                    }
+                }
+                if let Some(detector) = silence_detector.as_mut() {
+                    if !detector.process(data) {
+                        return;
+                    }
+                }
+                let decimated;
+                let data = match decimator.as_mut() {
+                    Some(decimator) => {
+                        decimated = decimator.process(data);
+                        decimated.as_slice()
+                    }
+                    None => data,
+                };
+                let Some(result) = fft_pipeline.process(data, captured) else {
+                    return;
+                };
+                if let Some(publisher) = shm_publisher.as_mut() {
+                    publisher.publish(&result.magnitudes);
+                }
+                if let Some(trigger) = trigger.as_mut() {
+                    trigger.process(&result);
+                }
+            }));
+
+        Self {
+            on_samples,
+            fft_size,
+            window_function,
+            fft_transform,
+            fft_transform_buffer,
+            profiler,
+            listener,
+            trigger_handle,
+            silence_asleep,
+            gain_advisor,
+            take_recorder,
+            take_saves,
+            gate_calibration,
+            _heterodyne_stream: heterodyne_stream,
+            _pitch_shift_stream: pitch_shift_stream,
+        }
+    }
+
+    /**
+     * Saves the take being recorded and waits for all the takes to be written
+     */
+    fn finish_takes(&self, capture_sample_rate: u32) {
+        if let Some((recorder, directory)) = &self.take_recorder {
+            if let Some(take) = recorder.lock().unwrap().finish() {
+                let save = save_take(directory.clone(), take, capture_sample_rate);
+                self.take_saves.lock().unwrap().push(save);
+            }
+        }
+        for save in self.take_saves.lock().unwrap().drain(..) {
+            let _ = save.join();
+        }
+    }
+}
+
+/**
+ * Starts the outputs that read the spectrum on their own threads, and returns the handle of the
+ * alarms when there are any
+ */
+fn spawn_outputs(
+    args: &Args,
+    fft_transform: &Arc<Mutex<FftFrame>>,
+    stream_sample_rate: u32,
+) -> Option<Arc<alarm::AlarmHandle>> {
+    if let Some(target) = args.osc {
+        let sender = osc::OscSender::new(target, args.osc_prefix.clone())
+            .expect("Could not open the OSC socket");
+        osc::spawn(
+            sender,
+            Duration::from_millis(args.osc_interval_ms),
+            args.bands.clone(),
+            args.vad(),
+            fft_transform.clone(),
+            stream_sample_rate,
+        );
+        println!("Sending OSC messages to {target}");
+    }
+
+    if let Some((host, port)) = args.mqtt_address() {
+        println!(
+            "Publishing summaries to mqtt://{host}:{port}/{}",
+            args.mqtt_topic
+        );
+        mqtt::spawn(
+            mqtt::MqttConfig {
+                host,
+                port,
+                topic: args.mqtt_topic.clone(),
+                interval: Duration::from_secs(args.mqtt_interval_secs),
+                credentials: args.mqtt_username.clone().zip(args.mqtt_password.clone()),
+            },
+            args.bands.clone(),
+            args.vad(),
+            fft_transform.clone(),
+            stream_sample_rate,
+        );
+    }
+
+    if let Some(address) = args.http {
+        http::spawn(
+            address,
+            args.bands.clone(),
+            args.vad(),
+            fft_transform.clone(),
+            stream_sample_rate,
+        )
+        .expect("Could not start the HTTP server");
+        println!("Serving the analysis on http://{address}");
+    }
+
+    if let Some(path) = &args.serial {
+        let bands = if args.bands.is_empty() {
+            analysis::Band::log_spaced(args.serial_bands, 40.0, 16000.0)
+        } else {
+            args.bands.clone()
+        };
+        serial::spawn(
+            serial::SerialConfig {
+                path: path.clone(),
+                baud_rate: args.serial_baud,
+                format: args.serial_format,
+                frames_per_second: args.serial_fps,
+                floor_db: args.serial_floor_db,
+            },
+            bands,
+            fft_transform.clone(),
+            stream_sample_rate,
+        )
+        .expect("Could not open the serial port");
+        println!("Streaming band levels to {path}");
+    }
+
+    if let Some(target) = args.artnet_address() {
+        let target = target.unwrap_or_else(|error| panic!("{error}"));
+        let mappings = if args.dmx_mappings.is_empty() {
+            args.bands
+                .iter()
+                .enumerate()
+                .map(|(i, band)| artnet::DmxMapping {
+                    channel: i as u16 + 1,
+                    band: band.clone(),
+                })
+                .collect()
+        } else {
+            args.dmx_mappings.clone()
+        };
+        artnet::spawn(
+            artnet::ArtNetConfig {
+                target,
+                universe: args.artnet_universe,
+                frames_per_second: args.artnet_fps,
+                floor_db: args.artnet_floor_db,
+            },
+            mappings,
+            fft_transform.clone(),
+            stream_sample_rate,
+        )
+        .unwrap_or_else(|error| panic!("Could not start the Art-Net output: {error}"));
+        println!(
+            "Sending Art-Net to {target}, universe {}",
+            args.artnet_universe
+        );
+    }
+
+    if let Some(target) = args.wled_address() {
+        let target = target.unwrap_or_else(|error| panic!("{error}"));
+        wled::spawn(
+            wled::WledConfig {
+                target,
+                leds: args.wled_leds,
+                scale: args.wled_scale,
+                colormap: args.wled_colormap,
+                min_hz: args.wled_min_freq,
+                max_hz: args.wled_max_freq,
+                frames_per_second: args.wled_fps,
+                floor_db: args.wled_floor_db,
+            },
+            fft_transform.clone(),
+            stream_sample_rate,
+        )
+        .expect("Could not open the WLED socket");
+        println!("Rendering the spectrum on the WLED strip at {target}");
+    }
+
+    #[cfg(feature = "grpc")]
+    if let Some(address) = args.grpc {
+        grpc::spawn(address, fft_transform.clone(), stream_sample_rate);
+        println!("Serving the analysis over gRPC on {address}");
+    }
+
+    if args.birds {
+        birds::spawn(
+            birds::BirdConfig {
+                band: args.birds_band.clone(),
+                margin_db: args.birds_margin_db,
+                directory: args.birds_dir.clone(),
+                sample_rate: stream_sample_rate,
+            },
+            fft_transform.clone(),
+        );
+        println!(
+            "Saving the bird songs of {} - {} Hz to {}",
+            args.birds_band.low_hz,
+            args.birds_band.high_hz,
+            args.birds_dir.display()
+        );
+    }
+
+    #[cfg(feature = "onnx")]
+    if let Some(model) = &args.onnx_model {
+        classify::spawn(
+            classify::ClassifierConfig {
+                model: model.clone(),
+                labels: args.onnx_labels.clone(),
+                mels: args.onnx_mels,
+                input_shape: args.onnx_input_shape.clone(),
+                threshold: args.onnx_threshold,
+            },
+            fft_transform.clone(),
+            stream_sample_rate,
+        )
+        .unwrap_or_else(|error| panic!("{error}"));
+        println!("Classifying the sound with {}", model.display());
+    }
+
+    let alarm_handle = (!args.alarms.is_empty()).then(|| {
+        println!("Watching {} alarm(s)", args.alarms.len());
+        alarm::spawn(
+            alarm::AlarmConfig {
+                rules: args.alarms.clone(),
+                bell: args.alarm_bell,
+                command: args.alarm_command.clone(),
+                webhook: args.alarm_webhook.clone(),
+                sample_rate: stream_sample_rate,
+            },
+            fft_transform.clone(),
+        )
+        .1
+    });
+
+    if let Some(directory) = args.dose_log.clone() {
+        dose::spawn(
+            dose::DoseConfig {
+                directory,
+                spl_offset_db: args.spl_offset_db,
+                standard: args.dose_standard,
+                log_interval: Duration::from_secs(args.dose_interval_secs.max(1)),
+                rotate_every: Duration::from_secs(args.log_rotate_hours.max(1) * 3600),
+            },
+            fft_transform.clone(),
+            stream_sample_rate,
+        )
+        .expect("Could not create the noise log");
+    }
+
+    if args.morse {
+        morse::spawn(
+            args.morse_band.clone(),
+            fft_transform.clone(),
+            stream_sample_rate,
+        );
+        println!(
+            "Decoding Morse code between {} and {} Hz",
+            args.morse_band.low_hz, args.morse_band.high_hz
+        );
+    }
+
+    if args.fsk {
+        fsk::spawn(
+            fsk::FskConfig {
+                mark_hz: args.fsk_mark_hz,
+                space_hz: args.fsk_space_hz,
+                baud: args.fsk_baud,
+                data_bits: args.fsk_data_bits as usize,
+                output: args.fsk_output,
+                sample_rate: stream_sample_rate,
+            },
+            fft_transform.clone(),
+        );
+        println!(
+            "Decoding FSK at {} baud, mark {} Hz, space {} Hz",
+            args.fsk_baud, args.fsk_mark_hz, args.fsk_space_hz
+        );
+    }
+
+    alarm_handle
+}
+
+/**
+ * The panels of the modes that take the bottom of the window, under the graph
+ */
+struct Panels {
+    waterfall: Option<waterfall::Waterfall>,
+    voice_track: Option<voice::VoiceTrack>,
+    flutter_meter: Option<Arc<Mutex<flutter::FlutterMeter>>>,
+    intonation_check: Option<guitar::IntonationCheck>,
+    drum_tuning: Option<drum::DrumTuning>,
+    window_comparison: Option<windowing::WindowComparison>,
+    dft_comparison: Option<Arc<Mutex<dft::DftComparison>>>,
+}
+
+impl Panels {
+    fn new(args: &Args, fft_transform: &Arc<Mutex<FftFrame>>, setup: Setup) -> Self {
+        let waterfall_rows = match (args.infrasound, args.birds) {
+            (true, _) => Some(WATERFALL_ROWS),
+            (_, true) => Some(BIRDS_WATERFALL_ROWS),
+            _ => None,
+        };
+        let waterfall = waterfall_rows.map(|rows| {
+            waterfall::Waterfall::new(
+                setup.max_displayed_frequency as f32,
+                rows,
+                args.spectrogram_history_rows,
+            )
+        });
+
+        let dft_comparison = args.compare_dft.then(|| {
+            let comparison = Arc::new(Mutex::new(dft::DftComparison::default()));
+            dft::spawn(
+                comparison.clone(),
+                fft_transform.clone(),
+                setup.stream_sample_rate,
+            );
+            comparison
+        });
+
+        let flutter_meter = args.wow_flutter.then(|| {
+            let meter = Arc::new(Mutex::new(flutter::FlutterMeter::new(args.wow_flutter_hz)));
+            flutter::spawn(
+                args.wow_flutter_hz,
+                meter.clone(),
+                fft_transform.clone(),
+                setup.stream_sample_rate,
+            );
+            println!(
+                "Measuring the wow and flutter of a {} Hz test tone",
+                args.wow_flutter_hz
+            );
+            meter
+        });
+
+        Self {
+            waterfall,
+            voice_track: args
+                .voice_training
+                .then(|| voice::VoiceTrack::new(args.voice_target.clone())),
+            flutter_meter,
+            intonation_check: args.guitar_intonation.then(guitar::IntonationCheck::new),
+            drum_tuning: args
+                .drum_lugs
+                .map(|lugs| drum::DrumTuning::new(lugs as usize)),
+            window_comparison: args
+                .compare_windows
+                .as_ref()
+                .map(|windows| windowing::WindowComparison::new([windows[0], windows[1]])),
+            dft_comparison,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.waterfall.is_none()
+            && self.voice_track.is_none()
+            && self.flutter_meter.is_none()
+            && self.intonation_check.is_none()
+            && self.drum_tuning.is_none()
+            && self.window_comparison.is_none()
+            && self.dft_comparison.is_none()
+    }
+
+    fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        texture_creator: &TextureCreator<WindowContext>,
+        area: Rect,
+        max_frequency_in_hz: f32,
+    ) {
+        if let Some(waterfall) = &self.waterfall {
+            waterfall.draw(canvas, texture_creator, area);
+        }
+        if let Some(track) = &self.voice_track {
+            track.draw(canvas, area);
+        }
+        if let Some(meter) = &self.flutter_meter {
+            meter.lock().unwrap().draw(canvas, area);
+        }
+        if let Some(check) = &self.intonation_check {
+            check.draw(canvas, area);
+        }
+        if let Some(drum) = &self.drum_tuning {
+            drum.draw(canvas, area);
+        }
+        if let Some(comparison) = &self.window_comparison {
+            comparison.draw(canvas, area, max_frequency_in_hz);
+        }
+        if let Some(comparison) = &self.dft_comparison {
+            comparison
+                .lock()
+                .unwrap()
+                .draw(canvas, area, max_frequency_in_hz);
+        }
+    }
+
+    /**
+     * Prints what the panels measured over the whole session
+     */
+    fn report(&self, args: &Args) {
+        if let Some(stats) = self.voice_track.as_ref().and_then(voice::VoiceTrack::stats) {
+            println!("\nVoice: {}", stats.readout());
+            if let Some(path) = &args.voice_log {
+                match stats.append_to(path) {
+                    Ok(()) => println!("Saved the session to {}", path.display()),
+                    Err(error) => eprintln!("Could not save the session: {error}"),
+                }
+            }
+        }
+
+        if let Some(lines) = self
+            .intonation_check
+            .as_ref()
+            .map(guitar::IntonationCheck::report)
+        {
+            if !lines.is_empty() {
+                println!("\nIntonation:\n{}", lines.join("\n"));
+            }
+        }
+
+        if let Some(drum) = &self.drum_tuning {
+            println!("\nDrum tuning:\n{}", drum.report());
+        }
+
+        if let Some(report) = self
+            .flutter_meter
+            .as_ref()
+            .and_then(|meter| meter.lock().unwrap().report())
+        {
+            println!("\nWow and flutter: {}", report.readout());
+        }
+    }
+}
+
+/**
+ * Where the samples come from: the mic, a synthetic input or a recording
+ */
+struct Input {
+    // The recording takes the place of the mic, the stream is kept alive until the end unless
+    // --auto-pause-release closes it
+    stream: Option<cpal::Stream>,
+    device_name: String,
+    replay_handle: Option<Arc<recording::ReplayHandle>>,
+    paused: Arc<Mutex<bool>>,
+}
+
+/**
+ * Starts the input that feeds the capture
+ */
+fn open_input(
+    args: &Args,
+    replay: Option<recording::Recording>,
+    capture: &Capture,
+    capture_sample_rate: u32,
+) -> Input {
+    let paused = Arc::new(Mutex::new(false));
+    let (stream, device_name, replay_handle) = match (replay, &args.replay, &args.input) {
+        (Some(recording), Some(path), _) => {
+            println!("Replaying {}", path.display());
+            let handle =
+                recording::replay(recording, capture.fft_transform.clone(), paused.clone());
+            (None, format!("replay of {}", path.display()), Some(handle))
+        }
+        (_, _, source::Input::Synth(spec)) => {
+            let device_name = spec.to_string();
+            println!("Using the synthetic input {device_name}");
+            source::spawn(
+                spec.clone(),
+                capture_sample_rate,
+                capture.on_samples.clone(),
+            );
+            (None, device_name, None)
+        }
+        _ => {
+            let (stream, mic) = open_input_stream(
+                args.device.as_deref(),
+                capture_sample_rate,
+                capture.on_samples.clone(),
+                |error| panic!("Error: {:#?}", error),
+            )
+            .unwrap_or_else(|error| panic!("{error}"));
+            let device_name = mic.name().unwrap();
+            println!("Using device {device_name}");
+            println!("{:?}", mic.default_input_config());
+
+            stream.play().unwrap();
+            (Some(stream), device_name, None)
+        }
+    };
+    Input {
+        stream,
+        device_name,
+        replay_handle,
+        paused,
+    }
+}
+
+/**
+ * Everything the window shows and the keys change
+ */
+struct State<'a> {
+    args: &'a Args,
+    setup: Setup,
+    capture: Capture,
+    panels: Panels,
+    input: Input,
+    alarm_handle: Option<Arc<alarm::AlarmHandle>>,
+    clipboard: ClipboardUtil,
+    graph: Graph,
+    window_width: u32,
+    window_height: u32,
+    // The dB range while the bars are scaled to the loudest bin, so switching back keeps its moves
+    hidden_db_range: analysis::DbRange,
+    display_colors: DisplayColors,
+    target_curve: curve::Curve,
+    overlay: Option<overlay::Overlay>,
+    // Frozen spectrum played as a drone, see Z
+    drone: Option<drone::DroneHandle>,
+    // Data of the bar under the mouse on the last frame, used by the copy hotkey
+    hovered_frequency_data: Option<FrequencyData>,
+    // The bars are only rebuilt when something changed: new data, input or a resize
+    dirty: bool,
+    // While the bar heights are still moving towards their targets, frames keep being drawn
+    animating: bool,
+    bars: Vec<GraphBar>,
+    frame_duration: Duration,
+    smoothing: Duration,
+    last_frame: Instant,
+    measurement: Option<session::Session>,
+    // Averaged spectrum of the last measurement that finished, preferred as the reference
+    last_average: Option<Vec<f32>>,
+    // Strongest peaks of the spectrum, tracked while the peak table is shown
+    peak_tracker: Option<analysis::PeakTracker>,
+    // Bin clicked as the fundamental of the harmonic cursors, and the harmonics found for it
+    fundamental_bin: Option<usize>,
+    harmonic_cursors: Vec<analysis::Harmonic>,
+    // The hovered bin and its multiples, listed next to the cursor
+    hovered_multiples: Vec<analysis::Harmonic>,
+    // Frequency the view went to, pinned with a line until Shift+J
+    pinned_frequency: Option<f32>,
+    // Bins of the two delta cursors, placed with A and B
+    delta_cursors: [Option<usize>; 2],
+    meter_bands: Vec<meters::MeterBand>,
+    // Only runs while the feedback detection is on
+    feedback_detector: Option<feedback::FeedbackDetector>,
+    show_meters: bool,
+    frequency_unit: units::FrequencyUnit,
+    amplitude_scale: units::AmplitudeScale,
+    // Levels of the meter row, empty while it's hidden
+    meter_levels: Vec<f32>,
+    // Pitch of the last frame with its octave errors corrected, shown in the status line
+    pitch_tracker: pitch::PitchTracker,
+    pitch_estimate: Option<pitch::PitchEstimate>,
+    key_bindings: keys::KeyBindings,
+    show_help: bool,
+    whistle_detector: Option<whistle::WhistleDetector>,
+    // The whistles are listened to even while paused, one of them may be resuming
+    whistle_generation: u64,
+    marker_log: marker::MarkerLog,
+    // Writes the spectra to a file while running, toggled with W
+    recorder: Option<recording::Recorder>,
+    // Alarms firing the last time the window was drawn
+    firing_alarms: Vec<alarm::AlarmRule>,
+    // Fed even while hidden, so it shows the last frames as soon as it's switched to
+    spectrogram: spectrogram::Spectrogram,
+    show_spectrogram: bool,
+    // When the trigger is armed again after the last drum tap
+    drum_rearm_at: Option<Instant>,
+    transcriber: Option<transcription::Transcriber>,
+    // Voice training always skips the breaths and the noise between the phrases
+    voice_vad: vad::Vad,
+    // Seconds of the FFT window filled and its length, while a long one fills
+    progress: Option<(f32, f32)>,
+    last_shown_progress: Option<u32>,
+    // Whether the silence pause was on the last time the window was drawn
+    shown_asleep: bool,
+    capture_release: Option<silence::CaptureRelease>,
+}
+
+impl<'a> State<'a> {
+    fn new(
+        args: &'a Args,
+        setup: Setup,
+        capture: Capture,
+        panels: Panels,
+        input: Input,
+        alarm_handle: Option<Arc<alarm::AlarmHandle>>,
+        clipboard: ClipboardUtil,
+    ) -> Self {
+        let mut graph = Graph {
+            data_buffer: Arc::new(vec![]),
+            fft_len: 0,
+            magnitudes_buffer: Arc::new(vec![]),
+            samples_buffer: Arc::new(vec![]),
+            data_generation: 0,
+            captured_at: Duration::ZERO,
+            data_locker: capture.fft_transform.clone(),
+            // Set from the window before the first frame
+            width: 0,
+            height: 0,
+            max_displayed_frequency: setup.max_displayed_frequency,
+            buffer_size: setup.buffer_size,
+            mouse_x: Arc::new(Mutex::new(0)),
+            paused: input.paused.clone(),
+            note_statuses: vec![],
+            note_statuses_sample_rate: setup.stream_sample_rate,
+            target_heights: vec![],
+            displayed_heights: vec![],
+            reference: None,
+            delta_view: false,
+            amplitude_scale: 0.0,
+            db_range: args
+                .db_scale
+                .then(|| analysis::DbRange::new(args.db_top, args.db_floor)),
+            locked_amplitude: args.lock_scale_db.map(analysis::db_to_amplitude),
+            target: None,
+            target_amplitudes: vec![],
+            bar_width: 0.0,
+            bars_len: 0,
+            bins_len: 0,
+            log_frequency: args.log_frequency,
+            bar_groups: vec![],
+        };
+
+        let mut pinned_frequency = None;
+        if let Some(frequency_in_hz) = args.go_to {
+            match graph.go_to(frequency_in_hz, setup.stream_sample_rate) {
+                Ok(()) => pinned_frequency = Some(frequency_in_hz),
+                Err(error) => eprintln!("Could not go to the frequency: {error}"),
+            }
+        }
+
+        Self {
+            args,
+            setup,
+            capture,
+            panels,
+            input,
+            alarm_handle,
+            clipboard,
+            graph,
+            window_width: 0,
+            window_height: 0,
+            hidden_db_range: analysis::DbRange::new(args.db_top, args.db_floor),
+            display_colors: DisplayColors::Amplitude,
+            target_curve: match &args.target_curve {
+                Some(path) => curve::Curve::load(path).unwrap_or_else(|error| panic!("{error}")),
+                // Pink noise through the system should come out flat on an RTA
+                None => curve::Curve::default(),
+            },
+            overlay: (!args.overlay.is_empty()).then(|| {
+                overlay::Overlay::load(&args.overlay).unwrap_or_else(|error| panic!("{error}"))
+            }),
+            drone: None,
+            hovered_frequency_data: None,
+            dirty: true,
+            animating: false,
+            bars: vec![],
+            frame_duration: Duration::from_secs_f64(1.0 / args.fps.max(1) as f64),
+            smoothing: Duration::from_millis(args.smoothing_ms),
+            last_frame: Instant::now(),
+            measurement: None,
+            last_average: None,
+            peak_tracker: None,
+            fundamental_bin: None,
+            harmonic_cursors: vec![],
+            hovered_multiples: vec![],
+            pinned_frequency,
+            delta_cursors: [None, None],
+            meter_bands: if args.meter_bands.is_empty() {
+                meters::default_meter_bands()
+            } else {
+                args.meter_bands.clone()
+            },
+            feedback_detector: None,
+            show_meters: false,
+            frequency_unit: args.frequency_unit,
+            amplitude_scale: units::AmplitudeScale {
+                unit: args.amplitude_unit,
+                full_scale_dbu: args.full_scale_dbu,
+                spl_offset_db: args.spl_offset_db,
+            },
+            meter_levels: vec![],
+            pitch_tracker: pitch::PitchTracker::new(),
+            pitch_estimate: None,
+            key_bindings: match &args.keys {
+                Some(path) => {
+                    keys::KeyBindings::load(path).unwrap_or_else(|error| panic!("{error}"))
+                }
+                None => keys::KeyBindings::default(),
+            },
+            show_help: false,
+            whistle_detector: (!args.whistle_commands.is_empty()).then(|| {
+                whistle::WhistleDetector::new(
+                    args.whistle_commands.clone(),
+                    Duration::from_millis(args.whistle_hold_ms),
+                    args.whistle_margin_db,
+                )
+            }),
+            whistle_generation: 0,
+            marker_log: marker::MarkerLog::new(args.report_dir.clone(), setup.capture_clock),
+            recorder: None,
+            firing_alarms: vec![],
+            spectrogram: spectrogram::Spectrogram::new(SPECTROGRAM_COLUMNS),
+            show_spectrogram: false,
+            drum_rearm_at: None,
+            transcriber: args.transcribe.then(transcription::Transcriber::new),
+            voice_vad: vad::Vad {
+                min_level_db: args.vad_level_db,
+                max_flatness: args.vad_flatness,
+            },
+            progress: None,
+            last_shown_progress: None,
+            shown_asleep: false,
+            capture_release: args.auto_pause_release.then(silence::CaptureRelease::new),
+        }
+    }
+
+    /**
+     * Fits the graph to the window, leaving the bottom to the waterfall or the panel of the
+     * current mode
+     */
+    fn resize(&mut self, width: u32, height: u32) {
+        self.window_width = width;
+        self.window_height = height;
+        let graph_height = if self.panels.is_empty() {
+            height
+        } else {
+            height * 3 / 5
+        };
+        if self.graph.width != width || self.graph.height != graph_height {
+            self.dirty = true;
+        }
+        self.graph.width = width;
+        self.graph.height = graph_height;
+    }
+
+    /**
+     * Long windows take seconds to fill, the time left is shown until the next result
+     */
+    fn update_progress(&mut self) {
+        let stream_sample_rate = self.setup.stream_sample_rate;
+        let filled = self.capture.fft_transform_buffer.lock().unwrap().len();
+        let window_secs =
+            self.capture.fft_size.load(Ordering::Relaxed) as f32 / stream_sample_rate as f32;
+        self.progress = (self.input.replay_handle.is_none() && window_secs >= 1.0)
+            .then(|| (filled as f32 / stream_sample_rate as f32, window_secs));
+        let shown_progress = self
+            .progress
+            .map(|(filled_secs, _)| (filled_secs * 10.0) as u32);
+        if shown_progress != self.last_shown_progress {
+            self.last_shown_progress = shown_progress;
+            self.animating = true;
+        }
+    }
+
+    /**
+     * Actions of the whistles heard since the last frame
+     */
+    fn whistle_actions(&mut self) -> Vec<keys::Action> {
+        let mut actions = vec![];
+        if let Some(detector) = self.whistle_detector.as_mut() {
+            let frame = self.graph.data_locker.lock().unwrap().clone();
+            if frame.generation != self.whistle_generation {
+                self.whistle_generation = frame.generation;
+                let mapping =
+                    BinMapping::new(self.setup.stream_sample_rate, frame.magnitudes.len());
+                for action in detector.process(&frame.amplitudes, mapping) {
+                    println!("\nWhistle: {action:?}");
+                    actions.push(action);
+                }
+            }
+        }
+        actions
+    }
+
+    /**
+     * Handles the mouse and the window events, and returns the action of a key pressed
+     */
+    fn handle_event(&mut self, event: Event) -> Option<keys::Action> {
+        match event {
+            Event::KeyDown {
+                keycode: Some(keycode),
+                keymod,
+                ..
+            } => return self.key_bindings.action(keycode, keymod),
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } => {
+                self.dirty = true;
+                // A click on the legend of the overlay only shows or hides a take
+                if self
+                    .overlay
+                    .as_mut()
+                    .and_then(|overlay| overlay.toggle_at(x, y))
+                    .is_some()
+                {
+                    return None;
+                }
+                self.fundamental_bin = self.graph.bin_at_x(x);
+                self.harmonic_cursors = self.graph.harmonics(
+                    self.fundamental_bin,
+                    self.args.harmonics,
+                    self.setup.stream_sample_rate,
+                );
+                if !self.harmonic_cursors.is_empty() {
+                    println!(
+                        "\n{}",
+                        harmonic_table(
+                            &self.harmonic_cursors,
+                            self.frequency_unit,
+                            self.amplitude_scale
+                        )
+                    );
+                }
+            }
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Right,
+                ..
+            } => {
+                self.fundamental_bin = None;
+                self.harmonic_cursors.clear();
+                self.dirty = true;
+            }
+            Event::MouseMotion { x, .. } => {
+                let mut m_lock = self.graph.mouse_x.lock().unwrap();
+                *m_lock = x;
+                self.dirty = true;
+            }
+            // The window was uncovered or restored and has to be drawn again
+            Event::Window {
+                win_event: WindowEvent::Exposed,
+                ..
+            } => self.dirty = true,
+            _ => {}
+        }
+        None
+    }
+
+    /**
+     * Runs the action of a key or a whistle. Quitting is left to the loop.
+     */
+    fn handle_action(&mut self, action: keys::Action) {
+        let args = self.args;
+        let stream_sample_rate = self.setup.stream_sample_rate;
+        let frequency_unit = self.frequency_unit;
+        let amplitude_scale = self.amplitude_scale;
+        match action {
+            keys::Action::Help => {
+                self.show_help = !self.show_help;
+                self.dirty = true;
+            }
+            keys::Action::Pause => {
+                let mut p_lock = self.input.paused.lock().unwrap();
+                *p_lock = !*p_lock;
+                self.dirty = true;
+                // Resuming after a capture waits for the next event
+                if let (false, Some(trigger_handle)) = (*p_lock, &self.capture.trigger_handle) {
+                    trigger_handle.arm();
+                }
+            }
+            action @ (keys::Action::SmallerFft | keys::Action::BiggerFft) => {
+                let current = self.capture.fft_size.load(Ordering::Relaxed);
+                let new_size = if action == keys::Action::SmallerFft {
+                    current / 2
+                } else {
+                    current * 2
+                }
+                .clamp(MIN_FFT_SIZE, MAX_FFT_SIZE);
+                if new_size == current {
+                    return;
+                }
+
+                // The graph rebuilds its note lookup table once a result of the new size
+                // arrives. Until then, the last result stays on the screen when shrinking,
+                // and the graph is empty when growing, just like when the app starts
+                self.capture.fft_size.store(new_size, Ordering::Relaxed);
+                self.graph.buffer_size = new_size;
+                self.dirty = true;
+                println!(
+                    "\nFFT size: {new_size} ({:.2} Hz per bin, {:.0} ms per result)",
+                    stream_sample_rate as f32 / new_size as f32,
+                    new_size as f32 * 1000.0 / stream_sample_rate as f32
+                );
+            }
+            keys::Action::Snapshot => {
+                // Saves the samples behind the spectrum on the screen, even when paused
+                let samples = self.graph.get_samples();
+                if samples.is_empty() {
+                    return;
+                }
+                match snapshot::save_wav(&args.snapshot_dir, samples, stream_sample_rate) {
+                    Ok(path) => println!("\nSaved snapshot to {}", path.display()),
+                    Err(error) => eprintln!("\nCould not save the snapshot: {error}"),
+                }
+            }
+            keys::Action::Record => match self.recorder.take() {
+                Some(recorder) => match recorder.stop() {
+                    Ok(frames) => println!("\nRecorded {frames} spectra"),
+                    Err(error) => eprintln!("\nCould not write the recording: {error}"),
+                },
+                None => {
+                    match recording::Recorder::start(
+                        &args.report_dir,
+                        self.graph.data_locker.clone(),
+                        stream_sample_rate,
+                    ) {
+                        Ok(started) => {
+                            println!("\nRecording the spectra to {}", started.path().display());
+                            self.recorder = Some(started);
+                        }
+                        Err(error) => eprintln!("\nCould not start the recording: {error}"),
+                    }
+                }
+            },
+            action @ (keys::Action::ReplayBack
+            | keys::Action::ReplayForward
+            | keys::Action::ReplayFaster
+            | keys::Action::ReplaySlower)
+                if self.input.replay_handle.is_some() =>
+            {
+                let replay_handle = self.input.replay_handle.as_ref().unwrap();
+                match action {
+                    keys::Action::ReplayBack => replay_handle.seek(-5000),
+                    keys::Action::ReplayForward => replay_handle.seek(5000),
+                    keys::Action::ReplayFaster => replay_handle.change_speed(2.0),
+                    _ => replay_handle.change_speed(0.5),
+                }
+            }
+            action @ (keys::Action::Marker | keys::Action::LabeledMarker) => {
+                // The label comes from the clipboard, typing in the window isn't possible
+                let label = (action == keys::Action::LabeledMarker)
+                    .then(|| self.clipboard.clipboard_text().ok())
+                    .flatten();
+                let marker = self.marker_log.add(label);
+                println!(
+                    "\nMarker \"{}\" at {}",
+                    marker.label,
+                    humantime::format_rfc3339_seconds(marker.at)
+                );
+                if let Err(error) = self.marker_log.save(&marker) {
+                    eprintln!(
+                        "Could not save the marker to {}: {error}",
+                        self.marker_log.path().display()
+                    );
+                }
+                if let Some(session) = self.measurement.as_mut() {
+                    session.add_marker(&marker);
+                }
+                if let Some(recorder) = &self.recorder {
+                    recorder.add_marker(&marker);
+                }
+            }
+            keys::Action::Measure => match self.measurement.take() {
+                // Stopping early still writes the report of what was averaged so far
+                Some(session) => {
+                    finish_session(&session, &args.report_dir, frequency_unit);
+                    self.last_average = Some(session.averaged_amplitudes());
+                }
+                None => {
+                    let metadata = session::SessionMetadata {
+                        device: self.input.device_name.clone(),
+                        sample_rate: stream_sample_rate,
+                        fft_size: self.capture.fft_size.load(Ordering::Relaxed),
+                        weighting: "Z (none)",
+                        mic_calibration: args
+                            .mic_calibration
+                            .as_ref()
+                            .map(|path| path.display().to_string()),
+                        spl_offset_db: args.spl_offset_db,
+                        started: std::time::SystemTime::now(),
+                    };
+                    println!(
+                        "\nMeasuring, averaging {} spectra. Press M again to stop early",
+                        args.session_frames
+                    );
+                    self.measurement = Some(session::Session::new(metadata, args.session_frames));
+                }
+            },
+            action @ (keys::Action::SetReference | keys::Action::ClearReference) => {
+                if action == keys::Action::ClearReference {
+                    self.graph.reference = None;
+                    self.graph.delta_view = false;
+                    println!("\nCleared the reference");
+                } else if let Some(average) = self.last_average.take() {
+                    self.graph.reference = Some(average);
+                    println!(
+                        "\nUsing the averaged spectrum of the last measurement as the reference"
+                    );
+                } else {
+                    self.graph.reference = Some(self.graph.get_amplitudes().to_vec());
+                    println!("\nUsing the current spectrum as the reference");
+                }
+                self.dirty = true;
+            }
+            action @ (keys::Action::RaiseDbTop
+            | keys::Action::LowerDbTop
+            | keys::Action::RaiseDbFloor
+            | keys::Action::LowerDbFloor) => match self.graph.db_range.as_mut() {
+                Some(range) => {
+                    match action {
+                        keys::Action::RaiseDbTop => range.move_top(DB_RANGE_STEP),
+                        keys::Action::LowerDbTop => range.move_top(-DB_RANGE_STEP),
+                        keys::Action::RaiseDbFloor => range.move_floor(DB_RANGE_STEP),
+                        _ => range.move_floor(-DB_RANGE_STEP),
+                    }
+                    println!(
+                        "\nShowing {} to {}",
+                        amplitude_scale.format(range.floor_db),
+                        amplitude_scale.format(range.top_db)
+                    );
+                    self.dirty = true;
+                }
+                None => println!("\nThe dB range only applies to the dB scale"),
+            },
+            keys::Action::DbScale => {
+                match self.graph.db_range.take() {
+                    Some(range) => {
+                        self.hidden_db_range = range;
+                        println!("\nScaling the bars to the loudest one");
+                    }
+                    None => {
+                        self.graph.db_range = Some(self.hidden_db_range);
+                        println!(
+                            "\nShowing {} to {}",
+                            amplitude_scale.format(self.hidden_db_range.floor_db),
+                            amplitude_scale.format(self.hidden_db_range.top_db)
+                        );
+                    }
+                }
+                self.dirty = true;
+            }
+            keys::Action::ExportSpectrogram => match &self.panels.waterfall {
+                Some(waterfall) => {
+                    match waterfall.export(&args.report_dir, &self.setup.capture_clock) {
+                        Ok(paths) => {
+                            for path in paths {
+                                println!("\nSaved the spectrogram to {}", path.display());
+                            }
+                        }
+                        Err(error) => eprintln!("\nCould not save the spectrogram: {error}"),
+                    }
+                }
+                None => {
+                    println!("\nThere is no spectrogram in this mode, see --infrasound and --birds")
+                }
+            },
+            keys::Action::LockScale if self.graph.db_range.is_some() => {
+                println!("\nThe dB scale doesn't move, there is nothing to lock");
+            }
+            keys::Action::LockScale => {
+                match self.graph.toggle_scale_lock() {
+                    Some(amplitude) => println!(
+                        "\nLocked the scale to {}",
+                        amplitude_scale.format(analysis::amplitude_to_db(amplitude))
+                    ),
+                    None => println!("\nThe scale follows the loudest bin again"),
+                }
+                self.dirty = true;
+            }
+            keys::Action::DeltaView if self.graph.reference.is_some() => {
+                self.graph.delta_view = !self.graph.delta_view;
+                self.dirty = true;
+            }
+            action @ (keys::Action::CursorA | keys::Action::CursorB) => {
+                let cursor = if action == keys::Action::CursorA {
+                    0
+                } else {
+                    1
+                };
+                let mouse_x = *self.graph.mouse_x.lock().unwrap();
+                self.delta_cursors[cursor] = self.graph.bin_at_x(mouse_x);
+                if let [Some(a), Some(b)] = self.delta_cursors {
+                    if let (Some(a), Some(b)) = (
+                        self.graph.bar_showing(&self.bars, a),
+                        self.graph.bar_showing(&self.bars, b),
+                    ) {
+                        println!(
+                            "\n{}",
+                            delta_readout(
+                                &a.frequency_data,
+                                &b.frequency_data,
+                                frequency_unit,
+                                amplitude_scale,
+                            )
+                        );
+                    }
+                }
+                self.dirty = true;
+            }
+            keys::Action::BinTable => {
+                println!(
+                    "\n{}",
+                    bin_table(
+                        &self.bars,
+                        self.graph.captured_at,
+                        self.setup.capture_clock,
+                        frequency_unit,
+                        amplitude_scale
+                    )
+                );
+            }
+            keys::Action::SaveBinTable => {
+                let timestamp =
+                    humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string();
+                let path = args
+                    .report_dir
+                    .join(format!("bins-{}.txt", timestamp.replace(':', "-")));
+                match std::fs::create_dir_all(&args.report_dir).and_then(|_| {
+                    std::fs::write(
+                        &path,
+                        bin_table(
+                            &self.bars,
+                            self.graph.captured_at,
+                            self.setup.capture_clock,
+                            frequency_unit,
+                            amplitude_scale,
+                        ) + "\n",
+                    )
+                }) {
+                    Ok(()) => {
+                        println!("\nSaved the {} bins to {}", self.bars.len(), path.display())
+                    }
+                    Err(error) => eprintln!("\nCould not save the bins: {error}"),
+                }
+            }
+            keys::Action::GoTo => {
+                let text = self.clipboard.clipboard_text().unwrap_or_default();
+                match units::parse_frequency(&text).and_then(|frequency_in_hz| {
+                    self.graph.go_to(frequency_in_hz, stream_sample_rate)?;
+                    Ok(frequency_in_hz)
+                }) {
+                    Ok(frequency_in_hz) => {
+                        println!("\nPinned {}", frequency_unit.format(frequency_in_hz));
+                        self.pinned_frequency = Some(frequency_in_hz);
+                    }
+                    Err(error) => eprintln!("\nCould not go to the clipboard: {error}"),
+                }
+                self.dirty = true;
+            }
+            keys::Action::ListenBand | keys::Action::ListenNotch => {
+                let mode = match action {
+                    keys::Action::ListenBand => listen::ListenMode::Band,
+                    _ => listen::ListenMode::Notch,
+                };
+                let listener = &mut self.capture.listener;
+                // The other key switches the mode, the same one stops
+                match listener.listening() {
+                    Some((_, listening_mode)) if listening_mode == mode => {
+                        listener.stop();
+                        println!("\nStopped listening");
+                    }
+                    _ => match selected_frequency(
+                        self.fundamental_bin,
+                        self.pinned_frequency,
+                        BinMapping::new(stream_sample_rate, self.graph.get_buffer_len()),
+                    ) {
+                        Some(frequency_in_hz) => match listener.listen_to(frequency_in_hz, mode) {
+                            Ok(()) => println!(
+                                "\nPlaying {} {}",
+                                mode.describe(),
+                                frequency_unit.format(frequency_in_hz)
+                            ),
+                            Err(error) => eprintln!("\nCould not open the output: {error}"),
+                        },
+                        None => {
+                            println!("\nClick a bar or pin a frequency with J to listen to it")
+                        }
+                    },
+                }
+            }
+            keys::Action::Drone => match self.drone.take() {
+                Some(_) => println!("\nStopped the drone"),
+                None if self.graph.get_magnitudes().len() < MIN_FFT_SIZE => {
+                    println!("\nNo spectrum to freeze yet")
+                }
+                None => match drone::play(self.graph.get_magnitudes(), stream_sample_rate) {
+                    Ok(handle) => {
+                        self.drone = Some(handle);
+                        println!("\nPlaying the frozen spectrum as a drone");
+                    }
+                    Err(error) => eprintln!("\nCould not open the output: {error}"),
+                },
+            },
+            action @ (keys::Action::SaveTimbre | keys::Action::CompareTimbre) => {
+                // Named after the clipboard, like the labeled markers
+                let name = self
+                    .clipboard
+                    .clipboard_text()
+                    .ok()
+                    .and_then(|text| timbre::file_name(&text))
+                    .unwrap_or_else(|| {
+                        let at = humantime::format_rfc3339_seconds(std::time::SystemTime::now());
+                        format!("timbre-{}", at.to_string().replace(':', "-"))
+                    });
+                match timbre::Fingerprint::from_harmonics(&name, &self.harmonic_cursors) {
+                    None => println!("\nClick the fundamental of a note first"),
+                    Some(fingerprint) if action == keys::Action::SaveTimbre => {
+                        match fingerprint.save(&args.timbre_dir) {
+                            Ok(path) => println!("\nSaved the timbre to {}", path.display()),
+                            Err(error) => eprintln!("\nCould not save the timbre: {error}"),
+                        }
+                    }
+                    Some(fingerprint) => match timbre::load_all(&args.timbre_dir) {
+                        Ok(saved) if saved.is_empty() => println!(
+                            "\nNo timbre saved in {} yet, X saves one",
+                            args.timbre_dir.display()
+                        ),
+                        Ok(saved) => {
+                            println!("\nSimilarity to the saved timbres:");
+                            for (other, similarity) in timbre::rank(&fingerprint, &saved) {
+                                println!(
+                                    "{:>6.1}%  {} ({})",
+                                    similarity * 100.0,
+                                    other.name,
+                                    frequency_unit.format(other.fundamental_in_hz)
+                                );
+                            }
+                        }
+                        Err(error) => eprintln!("\nCould not load the timbres: {error}"),
+                    },
+                }
+            }
+            keys::Action::Overlay => match self.overlay.as_mut() {
+                Some(overlay) => {
+                    overlay.shown = !overlay.shown;
+                    self.dirty = true;
+                }
+                None => println!("\nNo takes to overlay, see --overlay"),
+            },
+            keys::Action::ClearGoTo => {
+                self.pinned_frequency = None;
+                self.graph.max_displayed_frequency = self.setup.max_displayed_frequency;
+                self.dirty = true;
+            }
+            keys::Action::PinkTarget => {
+                self.graph.target = match self.graph.target {
+                    Some(_) => None,
+                    None => Some(self.target_curve.clone()),
+                };
+                self.dirty = true;
+            }
+            keys::Action::Feedback => {
+                self.feedback_detector = match self.feedback_detector {
+                    Some(_) => None,
+                    None => {
+                        println!("\nListening for feedback");
+                        Some(feedback::FeedbackDetector::new(
+                            args.feedback_frames,
+                            args.feedback_margin_db,
+                        ))
+                    }
+                };
+                self.dirty = true;
+            }
+            keys::Action::SuggestEq => {
+                let (amplitudes, source) = match &self.last_average {
+                    Some(average) => (average.as_slice(), "the last measurement"),
+                    None => (self.graph.get_amplitudes(), "the current spectrum"),
+                };
+                if amplitudes.is_empty() {
+                    return;
+                }
+                // An average always has the one-sided length of the FFT it was taken with
+                let mapping = BinMapping::new(stream_sample_rate, (amplitudes.len() - 1) * 2);
+                let (_, deviation_db) =
+                    curve::fit_pink_target(&self.target_curve, amplitudes, mapping);
+                let filters = eq::suggest_filters(&deviation_db, mapping, args.eq_filters);
+                save_eq_suggestions(&filters, source, &args.report_dir, frequency_unit);
+            }
+            keys::Action::Meters => {
+                self.show_meters = !self.show_meters;
+                self.dirty = true;
+            }
+            keys::Action::FrequencyUnit => {
+                self.frequency_unit = frequency_unit.next();
+                println!("\nFrequencies in {}", self.frequency_unit.header());
+                self.dirty = true;
+            }
+            keys::Action::AmplitudeUnit => {
+                self.amplitude_scale.unit = amplitude_scale.unit.next();
+                println!("\nLevels in {}", self.amplitude_scale.header());
+                self.dirty = true;
+            }
+            keys::Action::LogFrequency => {
+                self.graph.log_frequency = !self.graph.log_frequency;
+                self.dirty = true;
+            }
+            keys::Action::Spectrogram => {
+                self.show_spectrogram = !self.show_spectrogram;
+                self.dirty = true;
+            }
+            keys::Action::Window => {
+                let mut window = self.capture.window_function.lock().unwrap();
+                *window = window.next();
+                println!("\n{} window", window.name());
+            }
+            keys::Action::PeakTable => {
+                self.peak_tracker = match self.peak_tracker {
+                    Some(_) => None,
+                    None => Some(analysis::PeakTracker::new(args.peak_count, 3.0)),
+                };
+                self.dirty = true;
+            }
+            keys::Action::CopyReadout => {
+                if let Some(frequency_data) = &self.hovered_frequency_data {
+                    let readout = frequency_data.readout(frequency_unit, amplitude_scale);
+                    match self.clipboard.set_clipboard_text(&readout) {
+                        Ok(()) => println!("\nCopied: {readout}"),
+                        Err(error) => eprintln!("\nCould not copy the readout: {error}"),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /**
+     * Shows the frame the trigger fired on, or measures the lug of the drum that was tapped,
+     * and arms the trigger again once the drum stopped ringing
+     */
+    fn check_trigger(&mut self) {
+        let args = self.args;
+        let stream_sample_rate = self.setup.stream_sample_rate;
+        let capture = self
+            .capture
+            .trigger_handle
+            .as_ref()
+            .and_then(|trigger_handle| trigger_handle.take_capture());
+        if let (Some(capture), Some(drum)) = (&capture, self.panels.drum_tuning.as_mut()) {
+            match drum::lug_pitch(capture, &args.drum_band, stream_sample_rate) {
+                Some(frequency_in_hz) => {
+                    let lug = drum.record(frequency_in_hz);
+                    println!("\nLug {}: {frequency_in_hz:.1} Hz", lug + 1);
+                }
+                None => eprintln!("\nNo ringing found after the tap, try again"),
+            }
+            self.drum_rearm_at = Some(Instant::now() + DRUM_REARM_DELAY);
+            self.dirty = true;
+        } else if let Some(capture) = capture {
+            // Freezes the graph on the frame that fired, even if newer ones arrived since then
+            *self.input.paused.lock().unwrap() = true;
+            let gated_frame = args.gate_ms.and_then(|length_ms| {
+                let gate = gate::Gate {
+                    start_ms: args.gate_start_ms,
+                    length_ms,
+                };
+                let frame = gate.apply(
+                    &capture,
+                    stream_sample_rate,
+                    self.capture.fft_size.load(Ordering::Relaxed),
+                    self.capture.gate_calibration.as_mut(),
+                );
+                if frame.is_none() {
+                    eprintln!("\nThe gate doesn't fit in the capture, showing the whole frame");
+                }
+                frame
+            });
+            self.graph
+                .show_frame(gated_frame.as_ref().unwrap_or(&capture.frame));
+            self.dirty = true;
+            println!(
+                "\nTriggered at {:.1} dBFS, press P to resume and wait for the next one",
+                capture.level_db
+            );
+            match snapshot::save_wav(&args.snapshot_dir, &capture.samples, stream_sample_rate) {
+                Ok(path) => println!("Saved the capture to {}", path.display()),
+                Err(error) => eprintln!("Could not save the capture: {error}"),
+            }
+        }
+
+        if self.drum_rearm_at.is_some_and(|at| Instant::now() >= at) {
+            self.drum_rearm_at = None;
+            if let Some(trigger_handle) = &self.capture.trigger_handle {
+                trigger_handle.arm();
+            }
+        }
+    }
+
+    /**
+     * The monitor follows the selection, and stops when there is none left
+     */
+    fn follow_selection(&mut self) {
+        let listener = &mut self.capture.listener;
+        if let Some((center_in_hz, mode)) = listener.listening() {
+            match selected_frequency(
+                self.fundamental_bin,
+                self.pinned_frequency,
+                BinMapping::new(self.setup.stream_sample_rate, self.graph.get_buffer_len()),
+            ) {
+                Some(frequency_in_hz) if frequency_in_hz != center_in_hz => {
+                    if let Err(error) = listener.listen_to(frequency_in_hz, mode) {
+                        eprintln!("\nCould not open the output: {error}");
+                    }
+                }
+                Some(_) => {}
+                None => listener.stop(),
+            }
+        }
+    }
+
+    /**
+     * The alarms are checked on their own thread, so they change even while paused
+     */
+    fn check_alarms(&mut self) {
+        if let Some(alarm_handle) = &self.alarm_handle {
+            let firing = alarm_handle.firing();
+            if firing
+                .iter()
+                .map(|rule| &rule.name)
+                .ne(self.firing_alarms.iter().map(|rule| &rule.name))
+            {
+                self.firing_alarms = firing;
+                self.dirty = true;
+            }
+        }
+    }
+
+    /**
+     * No new spectrum comes while asleep, so the window stops drawing by itself. With
+     * --auto-pause-release the mic is closed while asleep and opened again to wake up.
+     */
+    fn check_silence(&mut self) {
+        let Some(asleep) = &self.capture.silence_asleep else {
+            return;
+        };
+        let asleep = asleep.load(Ordering::Relaxed);
+        if asleep != self.shown_asleep {
+            self.shown_asleep = asleep;
+            self.dirty = true;
+        }
+        match self
+            .capture_release
+            .as_mut()
+            .and_then(|release| release.update(asleep, Instant::now()))
+        {
+            Some(false) => self.input.stream = None,
+            Some(true) => {
+                match open_input_stream(
+                    self.args.device.as_deref(),
+                    self.setup.capture_sample_rate,
+                    self.capture.on_samples.clone(),
+                    |error| panic!("Error: {:#?}", error),
+                ) {
+                    Ok((new_stream, _)) => {
+                        new_stream.play().unwrap();
+                        self.input.stream = Some(new_stream);
+                    }
+                    Err(error) => eprintln!("\nCould not open the mic again: {error}"),
+                }
+            }
+            None => {}
+        }
+    }
+
+    /**
+     * The replay clock stops while paused, so a new spectrum then comes from seeking and is
+     * shown right away
+     */
+    fn show_seeked_frame(&mut self) {
+        if self.input.replay_handle.is_some() && *self.input.paused.lock().unwrap() {
+            let frame = self.graph.data_locker.lock().unwrap().clone();
+            if frame.generation != self.graph.data_generation {
+                self.graph.show_frame(&frame);
+                self.dirty = true;
+            }
+        }
+    }
+
+    /**
+     * Takes the newest spectrum and feeds it to the analyses that follow it over time
+     */
+    fn refresh(&mut self) {
+        if !self.graph.refresh_data() {
+            return;
+        }
+        let args = self.args;
+        let stream_sample_rate = self.setup.stream_sample_rate;
+        let graph = &self.graph;
+        let mapping = BinMapping::new(stream_sample_rate, graph.get_buffer_len());
+        self.dirty = true;
+        self.pitch_estimate = self.pitch_tracker.update(graph.get_amplitudes(), mapping);
+
+        if let Some(transcriber) = self.transcriber.as_mut() {
+            transcriber.push(
+                self.pitch_estimate,
+                graph.get_samples(),
+                stream_sample_rate,
+                graph.captured_at,
+            );
+        }
+
+        self.spectrogram.push(
+            graph.get_amplitudes(),
+            mapping,
+            graph.max_displayed_frequency as f32,
+        );
+
+        if let Some(waterfall) = self.panels.waterfall.as_mut() {
+            waterfall.push(graph.get_amplitudes(), mapping, graph.captured_at);
+        }
+
+        if let Some(track) = self.panels.voice_track.as_mut() {
+            let samples = graph.get_samples();
+            let f0_in_hz = self
+                .voice_vad
+                .is_voiced(graph.get_magnitudes(), stream_sample_rate)
+                .then(|| voice::estimate_f0(samples, stream_sample_rate))
+                .flatten();
+            track.push(f0_in_hz, samples.len() as f32 / stream_sample_rate as f32);
+        }
+
+        if let Some(comparison) = self.panels.window_comparison.as_mut() {
+            comparison.push(graph.get_samples(), stream_sample_rate);
+        }
+
+        if let Some(check) = self.panels.intonation_check.as_mut() {
+            if let Some(line) = check.process(graph.get_samples(), stream_sample_rate) {
+                println!("\n{line}");
+            }
+        }
+
+        if let Some(tracker) = self.peak_tracker.as_mut() {
+            let candidates = analysis::find_peaks(
+                graph.get_magnitudes(),
+                stream_sample_rate,
+                args.peak_count * 2,
+            );
+            // Only printed when the peaks themselves change, not on every level change
+            if tracker.update(candidates) {
+                println!(
+                    "\n{}",
+                    peak_table(tracker.peaks(), self.frequency_unit, self.amplitude_scale)
+                );
+            }
+        }
+
+        if let Some(detector) = self.feedback_detector.as_mut() {
+            let started = detector.process(graph.get_magnitudes(), graph.get_amplitudes(), mapping);
+            if !started.is_empty() {
+                println!(
+                    "\n{}",
+                    feedback_list(detector, self.frequency_unit, self.amplitude_scale)
+                );
+            }
+        }
+
+        if let Some(session) = self.measurement.as_mut() {
+            session.add(graph.get_amplitudes());
+            if session.is_complete() {
+                finish_session(session, &args.report_dir, self.frequency_unit);
+                self.last_average = Some(session.averaged_amplitudes());
+                self.measurement = None;
+            }
+        }
+    }
+
+    /**
+     * Builds the bars again and everything that depends on them, and prints the status line
+     */
+    fn rebuild(&mut self) {
+        let args = self.args;
+        let stream_sample_rate = self.setup.stream_sample_rate;
+        self.dirty = false;
+        self.animating = true;
+        let frequency_data_index;
+        (self.bars, frequency_data_index) =
+            profile::time(&self.capture.profiler, "bar build", || {
+                self.graph.run(stream_sample_rate)
+            });
+        self.hovered_frequency_data =
+            frequency_data_index.map(|index| self.bars[index].frequency_data.clone());
+        self.hovered_multiples = self
+            .hovered_frequency_data
+            .as_ref()
+            .map(|frequency_data| {
+                self.graph
+                    .bin_multiples(frequency_data.analyzing_bin_index, stream_sample_rate)
+            })
+            .unwrap_or_default();
+        self.harmonic_cursors =
+            self.graph
+                .harmonics(self.fundamental_bin, args.harmonics, stream_sample_rate);
+        self.meter_levels = if self.show_meters {
+            meters::meter_levels(
+                self.graph.get_magnitudes(),
+                stream_sample_rate,
+                &self.meter_bands,
+            )
+        } else {
+            vec![]
+        };
+
+        if let Some(frequency_data_index) = frequency_data_index.filter(|_| args.status_line) {
+            let frequency_unit = self.frequency_unit;
+            let frequency_data = &self.bars[frequency_data_index].frequency_data;
+            let analyzing_bin_index = frequency_data.analyzing_bin_index;
+            let real_frequency =
+                frequency_unit.format(frequency_data.note_status.get_frequency_in_hz());
+            print!(
+                "\r Buffer_len: {:6} Amplitude Percentage: {amplitude_percentage:3} ({level:>13}) Freq[{analyzing_bin_index:4}]: {real_frequency:>12} ({note}{octave}). Out of tune: {:4}%{pitch}{delta}{deviation}{meters} At: {captured_at} s{fix_line}",
+                self.graph.get_buffer_len(),
+                frequency_data.note_status.error_percentage,
+                amplitude_percentage=frequency_data.amplitude_percentage,
+                level = self.amplitude_scale.format(frequency_data.level_db),
+                note = NoteStatus::note_number_to_name(frequency_data.note_status.note_number),
+                octave= NoteStatus::get_octave_by_key_number(frequency_data.note_status.key_number),
+                pitch = self.pitch_estimate
+                    .map(|pitch| format!(" {}", pitch_readout(&pitch, frequency_unit)))
+                    .unwrap_or_default(),
+                delta = frequency_data
+                    .delta_db
+                    .map(|delta_db| format!(" Vs reference: {delta_db:+6.1} dB"))
+                    .unwrap_or_default(),
+                deviation = frequency_data
+                    .deviation_db
+                    .map(|deviation_db| format!(" Vs target: {deviation_db:+6.1} dB"))
+                    .unwrap_or_default(),
+                meters = if self.meter_levels.is_empty() {
+                    String::new()
+                } else {
+                    format!(" [{}]", meters::meter_readout(&self.meter_bands, &self.meter_levels))
+                },
+                captured_at = clock::format_seconds(self.graph.captured_at),
+                fix_line = (0..10).map(|_| " ").collect::<Vec<&str>>().join("")
+            );
+            stdout().flush().unwrap();
+        }
+    }
+
+    /**
+     * Moves the bars towards their targets and draws the next frame
+     */
+    fn draw(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        texture_creator: &TextureCreator<WindowContext>,
+    ) {
+        // A long idle time would make the bars jump straight to their targets
+        let elapsed = self.last_frame.elapsed().min(self.frame_duration * 2);
+        self.last_frame = Instant::now();
+        let smoothing = self.smoothing;
+        self.animating = profile::time(&self.capture.profiler, "animate", || {
+            self.graph.animate(&mut self.bars, elapsed, smoothing)
+        });
+        let render_start = Instant::now();
+
+        // Rendering:
+        // canvas.set_draw_color(Color::RGB(30, 30, 30));
+        canvas.set_draw_color(Color::RGB(240, 240, 240));
+        canvas.clear();
+
+        self.draw_graph(canvas);
+
+        // Takes the place of the bars and of everything drawn over them
+        if self.show_spectrogram {
+            self.spectrogram.draw(
+                canvas,
+                texture_creator,
+                Rect::new(0, 0, self.graph.width, self.graph.height),
+            );
+        }
+
+        let panel_height = self.window_height - self.graph.height;
+        if panel_height > 0 {
+            self.panels.draw(
+                canvas,
+                texture_creator,
+                Rect::new(0, self.graph.height as i32, self.window_width, panel_height),
+                self.graph.max_displayed_frequency as f32,
+            );
+        }
+
+        self.draw_labels(canvas);
+
+        canvas.present();
+        if let Some(profiler) = &self.capture.profiler {
+            profiler.record("render", render_start.elapsed());
+        }
+    }
+
+    /**
+     * Draws the bars with their axes, and the lines and the marks over them
+     */
+    fn draw_graph(&self, canvas: &mut Canvas<Window>) {
+        let graph = &self.graph;
+        let bars = &self.bars;
+        let stream_sample_rate = self.setup.stream_sample_rate;
+        let amplitude_scale = self.amplitude_scale;
+
+        // The labels make room for the meters when they're shown
+        draw_frequency_axis(
+            canvas,
+            &graph.frequency_axis(stream_sample_rate, self.frequency_unit),
+            graph.height,
+            self.meter_levels.is_empty(),
+        );
+
+        // Bars are grouped by color so each color takes a single draw call, instead of one
+        // call per bar, which adds up to thousands of calls with big FFTs
+        let mut rects_by_color: HashMap<Color, Vec<Rect>> = HashMap::new();
+        for bar in bars {
+            rects_by_color
+                .entry(bar_color(
+                    &if graph.target.is_some() {
+                        DisplayColors::Deviation
+                    } else {
+                        self.display_colors
+                    },
+                    bar,
+                ))
+                .or_default()
+                .push(Rect::new(bar.x, bar.y, bar.width, bar.height));
+        }
+        for (color, rects) in rects_by_color {
+            canvas.set_draw_color(color);
+            canvas.fill_rects(&rects).unwrap();
+        }
+
+        match graph.db_range {
+            _ if graph.delta_view => {}
+            Some(range) => draw_db_axis(canvas, range, graph.width, graph.height, amplitude_scale),
+            None => draw_percentage_axis(canvas, graph, amplitude_scale),
+        }
+
+        if graph.delta_view {
+            // The line where the spectrum matches the reference
+            let middle = 10 + (graph.height as i32 - 40) / 2;
+            canvas.set_draw_color(Color::RGB(120, 120, 120));
+            canvas
+                .draw_line((0, middle), (graph.width as i32, middle))
+                .unwrap();
+        } else {
+            let reference_line = graph.reference_line(bars);
+            if !reference_line.is_empty() {
+                canvas.set_draw_color(Color::RGB(6, 150, 110));
+                canvas.draw_lines(reference_line.as_slice()).unwrap();
+            }
+            let target_line = graph.target_line(bars);
+            if !target_line.is_empty() {
+                canvas.set_draw_color(Color::RGB(40, 40, 40));
+                canvas.draw_lines(target_line.as_slice()).unwrap();
+            }
+            if let Some(overlay) = &self.overlay {
+                for (take, color) in overlay.visible() {
+                    let line = graph.curve_line(&take.curve, bars);
+                    if !line.is_empty() {
+                        canvas.set_draw_color(color);
+                        canvas.draw_lines(line.as_slice()).unwrap();
+                    }
+                }
+                overlay.draw_legend(canvas);
+            }
+        }
+
+        for (bin, color) in self
+            .delta_cursors
+            .iter()
+            .zip([Color::RGB(120, 60, 200), Color::RGB(200, 60, 180)])
+        {
+            if let Some(bar) = bin.and_then(|bin| graph.bar_showing(bars, bin)) {
+                canvas.set_draw_color(color);
+                let center_x = bar.x + bar.width as i32 / 2;
+                canvas
+                    .draw_line((center_x, 0), (center_x, graph.height as i32))
+                    .unwrap();
+            }
+        }
+
+        // The meter row takes the strip below the bars, each meter filling up from the left
+        if !self.meter_levels.is_empty() {
+            let gap = 8;
+            let meter_width = graph.width as i32 / self.meter_levels.len() as i32 - gap;
+            let y = graph.height as i32 - 24;
+            for (i, level_db) in self.meter_levels.iter().enumerate() {
+                let fraction = analysis::level_to_fraction(*level_db, self.args.meter_floor_db);
+                let x = i as i32 * (meter_width + gap) + gap / 2;
+                canvas.set_draw_color(Color::RGB(210, 210, 210));
+                canvas
+                    .fill_rect(Rect::new(x, y, meter_width.max(1) as u32, 16))
+                    .unwrap();
+                let (r, g, b) = amplitude_color((fraction * 100.0).round() as u8);
+                canvas.set_draw_color(Color::RGB(r, g, b));
+                canvas
+                    .fill_rect(Rect::new(
+                        x,
+                        y,
+                        ((meter_width as f32 * fraction) as u32).max(1),
+                        16,
+                    ))
+                    .unwrap();
+            }
+        }
+
+        // Frequencies feeding back get a wide red column, hard to miss from the mixing desk
+        if let Some(detector) = &self.feedback_detector {
+            canvas.set_draw_color(Color::RGB(230, 30, 30));
+            for event in detector.active(Duration::from_secs(2)) {
+                if let Some(bar) = graph.bar_showing(bars, event.bin_index) {
+                    let width = bar.width.max(9);
+                    canvas
+                        .fill_rect(Rect::new(
+                            bar.x + bar.width as i32 / 2 - width as i32 / 2,
+                            0,
+                            width,
+                            graph.height,
+                        ))
+                        .unwrap();
+                }
+            }
+        }
+
+        // Bands of the firing alarms get a red strip along the top of the window
+        canvas.set_draw_color(Color::RGB(230, 30, 30));
+        for rule in &self.firing_alarms {
+            if let Some((first, last)) = graph
+                .bars_of_band(&rule.band, stream_sample_rate)
+                .and_then(|(first, last)| Some((bars.get(first)?, bars.get(last)?)))
+            {
+                canvas
+                    .fill_rect(Rect::new(
+                        first.x,
+                        0,
+                        (last.x + last.width as i32 - first.x).max(1) as u32,
+                        12,
+                    ))
+                    .unwrap();
+            }
+        }
+
+        // The harmonic cursors go through the whole height, the fundamental in a darker color
+        for harmonic in &self.harmonic_cursors {
+            if let Some(bar) = graph.bar_showing(bars, harmonic.bin_index) {
+                canvas.set_draw_color(if harmonic.number == 1 {
+                    Color::RGB(200, 90, 0)
+                } else {
+                    Color::RGB(255, 170, 60)
+                });
+                let center_x = bar.x + bar.width as i32 / 2;
+                canvas
+                    .draw_line((center_x, 0), (center_x, graph.height as i32))
+                    .unwrap();
+            }
+        }
+
+        if let Some(x) = self
+            .pinned_frequency
+            .and_then(|frequency| graph.x_at_frequency(frequency, stream_sample_rate))
+        {
+            let color = Color::RGB(120, 80, 220);
+            canvas.set_draw_color(color);
+            canvas.draw_line((x, 0), (x, graph.height as i32)).unwrap();
+            let label = self.frequency_unit.format(self.pinned_frequency.unwrap());
+            text::draw_text(canvas, &label, x + 4, 12, 1, color);
+        }
+
+        // Marks the peaks listed in the table above their bars, the strongest one the biggest
+        if let Some(tracker) = &self.peak_tracker {
+            canvas.set_draw_color(Color::RGB(6, 150, 110));
+            for (rank, peak) in tracker.peaks().iter().enumerate() {
+                if let Some(bar) = graph.bar_showing(bars, peak.bin_index) {
+                    let size = (12 - 2 * rank as i32).max(4);
+                    let center_x = bar.x + bar.width as i32 / 2;
+                    canvas
+                        .fill_rect(Rect::new(
+                            center_x - size / 2,
+                            bar.y - size - 4,
+                            size as u32,
+                            size as u32,
+                        ))
+                        .unwrap();
+                }
+            }
+        }
+
+        // Highlights the bar the readout refers to, with a line over it so it's still visible
+        // when the bar is too short or too thin to be seen
+        if let Some(bar) = self
+            .hovered_frequency_data
+            .as_ref()
+            .and_then(|frequency_data| graph.bar_showing(bars, frequency_data.analyzing_bin_index))
+        {
+            canvas.set_draw_color(Color::RGB(40, 40, 40));
+            canvas
+                .fill_rect(Rect::new(bar.x, bar.y, bar.width, bar.height))
+                .unwrap();
+            canvas.set_draw_color(Color::RGB(160, 160, 160));
+            let center_x = bar.x + bar.width as i32 / 2;
+            canvas.draw_line((center_x, 0), (center_x, bar.y)).unwrap();
+            draw_multiples(
+                canvas,
+                &bar.frequency_data,
+                &self.hovered_multiples,
+                center_x,
+                self.window_width,
+                self.frequency_unit,
+                amplitude_scale,
+            );
+        }
+    }
+
+    /**
+     * Draws the text over everything else: the progress of the FFT window, the capture time of
+     * a paused frame, the silence pause, the pitch, the gain advice and the help
+     */
+    fn draw_labels(&self, canvas: &mut Canvas<Window>) {
+        let window_width = self.window_width as i32;
+        if let Some((filled_secs, window_secs)) = self.progress {
+            let progress_text = format!(
+                "Filling the FFT window: {filled_secs:.1} / {window_secs:.1} s ({:.0}%)",
+                filled_secs / window_secs * 100.0
+            );
+            let scale = 2;
+            text::draw_text(
+                canvas,
+                &progress_text,
+                window_width - text::text_width(&progress_text, scale) as i32 - 10,
+                10,
+                scale,
+                Color::RGB(40, 40, 40),
+            );
+        }
+
+        // A paused frame can be matched with what happened outside at the time
+        if *self.input.paused.lock().unwrap() && self.graph.data_generation != 0 {
+            let label = format!(
+                "Captured at {} s",
+                clock::format_seconds(self.graph.captured_at)
+            );
+            let scale = 2;
+            text::draw_text(
+                canvas,
+                &label,
+                window_width - text::text_width(&label, scale) as i32 - 10,
+                10 + text::line_height(scale) as i32,
+                scale,
+                Color::RGB(40, 40, 40),
+            );
+        }
+
+        if self.shown_asleep {
+            let label = "Paused on silence";
+            let scale = 3;
+            text::draw_text(
+                canvas,
+                label,
+                (window_width - text::text_width(label, scale) as i32) / 2,
+                10,
+                scale,
+                Color::RGB(200, 36, 104),
+            );
+        }
+
+        if let Some(pitch) = &self.pitch_estimate {
+            text::draw_text(
+                canvas,
+                &pitch_readout(pitch, self.frequency_unit),
+                10,
+                10,
+                2,
+                Color::RGB(40, 40, 40),
+            );
+        }
+
+        if let Some(advisor) = &self.capture.gain_advisor {
+            advisor
+                .lock()
+                .unwrap()
+                .draw(canvas, 10, 10 + text::line_height(3) as i32 + 4);
+        }
+
+        if self.show_help {
+            draw_help(canvas, &self.key_bindings);
+        }
+    }
+
+    /**
+     * Prints the reports of the session and saves what was still being recorded
+     */
+    fn finish(mut self) {
+        let args = self.args;
+        self.panels.report(args);
+        self.capture.finish_takes(self.setup.capture_sample_rate);
+
+        if let Some(transcriber) = self.transcriber.as_mut() {
+            transcriber.finish(self.graph.captured_at);
+            match transcriber.export(&args.report_dir) {
+                Ok(paths) => {
+                    println!("\n{} notes written down", transcriber.notes().len());
+                    for path in paths {
+                        println!("Saved {}", path.display());
+                    }
+                }
+                Err(error) => eprintln!("\nCould not save the notes: {error}"),
+            }
+        }
+
+        // Closing the window in the middle of a recording still leaves a complete file
+        if let Some(recorder) = self.recorder {
+            if let Err(error) = recorder.stop() {
+                eprintln!("\nCould not write the recording: {error}");
+            }
+        }
+
+        if let Some(profiler) = &self.capture.profiler {
+            eprintln!("\n{}", profiler.report());
+        }
+    }
+}
+
+/**
+ * Number of points of the FFT: the smallest one for infrasound, whose samples are already
+ * decimated, then --fft-size, then the one suggested for the --intent
+ */
+fn buffer_size(args: &Args, suggestion: Option<intent::Suggestion>) -> usize {
+    if args.infrasound {
+        MIN_FFT_SIZE
+    } else if let Some(fft_size) = args.fft_size {
+        fft_size
+    } else if let Some(suggestion) = suggestion {
+        suggestion.fft_size
+    } else {
+        2usize.pow(12)
+    }
+}
+
+/**
+ * Highest frequency on the graph: --max-freq, else the top of what the mode listens to
+ */
+fn max_displayed_frequency(args: &Args, suggestion: Option<intent::Suggestion>) -> usize {
+    args.max_freq.unwrap_or(if args.infrasound {
+        20
+    } else if let Some(suggestion) = suggestion {
+        suggestion.max_frequency_in_hz as usize
+    } else if args.birds {
+        12000
+    } else if args.wow_flutter {
+        5000
+    } else {
+        3000
+    })
+}
+
+/**
+ * Shows the live spectrum of the mic, a synthetic input or a recording in a window, or runs
+ * headless with --daemon. Everything it can't start with is a panic, like the rest of the
+ * setup of the binary.
+ */
+pub fn run(args: &Args) {
+    let replay = args
+        .replay
+        .as_ref()
+        .map(|path| recording::Recording::load(path).unwrap_or_else(|error| panic!("{error}")));
+    let capture_sample_rate = replay
+        .as_ref()
+        .map_or(args.sample_rate, |recording| recording.sample_rate);
+    // Recordings are already at the sample rate of their analysis
+    let infrasound = args.infrasound && replay.is_none();
+    let stream_sample_rate = if infrasound {
+        infrasound::Decimator::output_sample_rate(capture_sample_rate)
+    } else {
+        capture_sample_rate
+    };
+    let suggestion = args.intent.map(|intent| intent.suggest(stream_sample_rate));
+    if let (Some(intent), Some(suggestion)) = (args.intent, suggestion) {
+        println!(
+            "Set up for {}: FFT of {} points, {} window, up to {} Hz",
+            intent.describe(),
+            suggestion.fft_size,
+            suggestion.window.name(),
+            suggestion.max_frequency_in_hz
+        );
+    }
+    let buffer_size = buffer_size(args, suggestion);
+    let window_function = args
+        .window
+        .or(suggestion.map(|suggestion| suggestion.window))
+        .unwrap_or(windowing::WindowFunction::Hann);
+    let max_displayed_frequency = max_displayed_frequency(args, suggestion);
+
+    let capture_clock = clock::CaptureClock::start();
+    println!(
+        "Frames are timestamped in seconds since {}",
+        humantime::format_rfc3339_micros(capture_clock.started_at())
+    );
+
+    let setup = Setup {
+        capture_sample_rate,
+        stream_sample_rate,
+        buffer_size,
+        max_displayed_frequency,
+        capture_clock,
+    };
+    let capture = Capture::start(args, setup, window_function, infrasound);
+    let alarm_handle = spawn_outputs(args, &capture.fft_transform, stream_sample_rate);
+    let panels = Panels::new(args, &capture.fft_transform, setup);
+
+    if args.daemon {
+        daemon::run(
+            daemon::DaemonConfig {
+                log_dir: args.log_dir.clone(),
+                log_interval: Duration::from_secs(args.log_interval_secs.max(1)),
+                rotate_every: Duration::from_secs(args.log_rotate_hours.max(1) * 3600),
+                bands: args.bands.clone(),
+                reconnect_every: Duration::from_secs(args.reconnect_secs.max(1)),
+                device: args.device.clone(),
+                capture_sample_rate,
+                sample_rate: stream_sample_rate,
+                fft_size: buffer_size,
+            },
+            capture.fft_transform.clone(),
+            capture.on_samples.clone(),
+        );
+    }
+
+    let input = open_input(args, replay, &capture, capture_sample_rate);
+
+    // SDL Config
+    let sdl_context = sdl2::init().unwrap();
+    #[cfg(feature = "ttf")]
+    if let Err(error) = text::use_font(args.font.as_deref()) {
+        eprintln!("Could not load the font, the text is drawn with the built-in one: {error}");
+    }
+    let video_subsystem = sdl_context.video().unwrap();
+    let window = video_subsystem
+        .window("Frequency Analyzer", 1500, 600)
+        .resizable()
+        .position_centered()
+        .build()
+        .unwrap();
+
+    let mut canvas = window.into_canvas().build().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    let mut state = State::new(
+        args,
+        setup,
+        capture,
+        panels,
+        input,
+        alarm_handle,
+        video_subsystem.clipboard(),
+    );
+
+    'running: loop {
+        let (width, height) = canvas.window().size();
+        state.resize(width, height);
+        state.update_progress();
+
+        // Actions of the keys pressed and the whistles heard since the last frame
+        let mut actions = state.whistle_actions();
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => break 'running,
+                event => actions.extend(state.handle_event(event)),
+            }
+        }
+        for action in actions {
+            if action == keys::Action::Quit {
+                break 'running;
+            }
+            state.handle_action(action);
+        }
+
+        state.check_trigger();
+        state.follow_selection();
+        state.check_alarms();
+        state.check_silence();
+        state.show_seeked_frame();
+        state.refresh();
+
+        if state.dirty {
+            state.rebuild();
+        }
+
+        if !state.animating {
+            std::thread::sleep(state.frame_duration);
+            continue;
+        }
+
+        state.draw(&mut canvas, &texture_creator);
+        std::thread::sleep(
+            state
+                .frame_duration
+                .saturating_sub(state.last_frame.elapsed()),
+        );
+    }
+
+    state.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn args_of(flags: &[&str]) -> Args {
+        Args::parse_from(std::iter::once("mic_frequencies_analyzer").chain(flags.iter().copied()))
+    }
+
+    #[test]
+    fn the_fft_size_comes_from_the_mode_the_flag_or_the_intent() {
+        let suggestion = Some(intent::Intent::BassTuning.suggest(44100));
+        assert_eq!(buffer_size(&args_of(&[]), None), 4096);
+        assert_eq!(buffer_size(&args_of(&["--infrasound"]), None), MIN_FFT_SIZE);
+        assert_eq!(
+            buffer_size(&args_of(&["--fft-size", "8192"]), suggestion),
+            8192
+        );
+        assert_eq!(buffer_size(&args_of(&[]), suggestion), 65536);
+    }
+
+    #[test]
+    fn the_graph_stops_at_the_top_of_what_the_mode_listens_to() {
+        let suggestion = Some(intent::Intent::BassTuning.suggest(44100));
+        assert_eq!(max_displayed_frequency(&args_of(&[]), None), 3000);
+        assert_eq!(
+            max_displayed_frequency(&args_of(&["--infrasound"]), None),
+            20
+        );
+        assert_eq!(max_displayed_frequency(&args_of(&["--birds"]), None), 12000);
+        assert_eq!(
+            max_displayed_frequency(&args_of(&["--wow-flutter"]), None),
+            5000
+        );
+        assert_eq!(max_displayed_frequency(&args_of(&[]), suggestion), 500);
+        assert_eq!(
+            max_displayed_frequency(&args_of(&["--birds", "--max-freq", "800"]), None),
+            800
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/**
+ * A CSV log inside a directory that moves on to a new file every `rotate_every`, so a monitor
+ * running for months doesn't end up with a single huge file. Each file is named after the time
+ * it was started (e.g. dose-2024-05-01T12-00-00Z.csv) and starts with the header.
+ */
+pub struct RotatingLog {
+    directory: PathBuf,
+    prefix: &'static str,
+    header: String,
+    rotate_every: Duration,
+    file: BufWriter<File>,
+    path: PathBuf,
+    opened: Instant,
+}
+
+impl RotatingLog {
+    pub fn create(
+        directory: &Path,
+        prefix: &'static str,
+        header: String,
+        rotate_every: Duration,
+    ) -> std::io::Result<Self> {
+        let (file, path) = open(directory, prefix, &header)?;
+        Ok(Self {
+            directory: directory.into(),
+            prefix,
+            header,
+            rotate_every,
+            file,
+            path,
+            opened: Instant::now(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /**
+     * Appends a row, flushing it right away so the file is up to date if the process gets
+     * killed. When the current file is old enough the row goes to a new one.
+     */
+    pub fn write_row(&mut self, row: &str) -> std::io::Result<()> {
+        if self.opened.elapsed() >= self.rotate_every {
+            let (file, path) = open(&self.directory, self.prefix, &self.header)?;
+            self.file = file;
+            self.path = path;
+            self.opened = Instant::now();
+            println!("\nStarted a new log in {}", self.path.display());
+        }
+        writeln!(self.file, "{row}")?;
+        self.file.flush()
+    }
+}
+
+fn open(
+    directory: &Path,
+    prefix: &str,
+    header: &str,
+) -> std::io::Result<(BufWriter<File>, PathBuf)> {
+    std::fs::create_dir_all(directory)?;
+    let started = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    let path = directory.join(format!("{prefix}-{}.csv", started.replace(':', "-")));
+    let mut file = BufWriter::new(OpenOptions::new().create(true).append(true).open(&path)?);
+    writeln!(file, "{header}")?;
+    file.flush()?;
+    Ok((file, path))
+}
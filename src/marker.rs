@@ -0,0 +1,83 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    time::{Instant, SystemTime},
+};
+
+use crate::clock::{self, CaptureClock};
+
+/**
+ * A point in time the user flagged while listening, like "that weird buzz at 14:32"
+ */
+#[derive(Clone, Debug)]
+pub struct Marker {
+    pub label: String,
+    pub at: SystemTime,
+    // Same moment, comparable with the Instants of sessions and recordings
+    pub instant: Instant,
+}
+
+/**
+ * Numbers the markers and appends them to a CSV file as they come, so they survive a crash
+ */
+pub struct MarkerLog {
+    path: PathBuf,
+    count: usize,
+    // Also times the markers like the frames, to find the ones around each marker
+    clock: CaptureClock,
+}
+
+impl MarkerLog {
+    pub fn new(directory: PathBuf, clock: CaptureClock) -> Self {
+        let started = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        Self {
+            path: directory.join(format!("markers-{}.csv", started.replace(':', "-"))),
+            count: 0,
+            clock,
+        }
+    }
+
+    /**
+     * Drops a marker now, numbered when it has no label
+     */
+    pub fn add(&mut self, label: Option<String>) -> Marker {
+        self.count += 1;
+        Marker {
+            label: label
+                .map(|label| label.trim().replace(['\n', '\r'], " "))
+                .filter(|label| !label.is_empty())
+                .unwrap_or_else(|| format!("Marker {}", self.count)),
+            at: SystemTime::now(),
+            instant: Instant::now(),
+        }
+    }
+
+    /**
+     * Appends a marker to the file, which is created with the first one
+     */
+    pub fn save(&self, marker: &Marker) -> std::io::Result<()> {
+        if let Some(directory) = self.path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let is_new = !self.path.exists();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        if is_new {
+            writeln!(file, "time,capture_s,label")?;
+        }
+        writeln!(
+            file,
+            "{},{},\"{}\"",
+            humantime::format_rfc3339_millis(marker.at),
+            clock::format_seconds(self.clock.since_start(marker.instant)),
+            marker.label.replace('"', "\"\"")
+        )
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+}
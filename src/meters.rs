@@ -0,0 +1,71 @@
+use std::str::FromStr;
+
+use crate::analysis::{Band, Summary};
+
+/**
+ * A band shown in the meter row, written as "name=low-high" (e.g. "bass=60-250") or just
+ * "low-high", in which case the range is its name
+ */
+#[derive(Clone, Debug)]
+pub struct MeterBand {
+    pub name: String,
+    pub band: Band,
+}
+
+impl FromStr for MeterBand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((name, band)) => Ok(Self {
+                name: name.trim().into(),
+                band: band.parse()?,
+            }),
+            None => Ok(Self {
+                name: s.trim().into(),
+                band: s.parse()?,
+            }),
+        }
+    }
+}
+
+/**
+ * The usual split of the spectrum used to talk about the balance of a mix
+ */
+pub fn default_meter_bands() -> Vec<MeterBand> {
+    [
+        ("sub", 20.0, 60.0),
+        ("bass", 60.0, 250.0),
+        ("mid", 250.0, 4000.0),
+        ("presence", 4000.0, 6000.0),
+        ("treble", 6000.0, 20000.0),
+    ]
+    .into_iter()
+    .map(|(name, low_hz, high_hz)| MeterBand {
+        name: name.into(),
+        band: Band { low_hz, high_hz },
+    })
+    .collect()
+}
+
+/**
+ * Gets the magnitudes of the full FFT output and returns the level of each meter in dBFS
+ */
+pub fn meter_levels(magnitudes: &[f32], sample_rate: u32, meters: &[MeterBand]) -> Vec<f32> {
+    let bands: Vec<Band> = meters.iter().map(|meter| meter.band.clone()).collect();
+    Summary::from_magnitudes(magnitudes, sample_rate, &bands)
+        .map(|summary| summary.band_levels_db)
+        .unwrap_or_default()
+}
+
+/**
+ * One line with the name and the level of every meter
+ */
+pub fn meter_readout(meters: &[MeterBand], levels_db: &[f32]) -> String {
+    meters
+        .iter()
+        .zip(levels_db)
+        .map(|(meter, level_db)| format!("{} {:6.1}", meter.name, level_db))
+        .collect::<Vec<String>>()
+        .join(" | ")
+}
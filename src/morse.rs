@@ -0,0 +1,279 @@
+use std::{
+    io::{stdout, Write},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::analysis::{amplitude_to_db, goertzel_level_db, Band, BinMapping, FftFrame};
+
+// The on/off keying is followed in blocks this long, FFT frames are way too long for a dot
+const BLOCK_MS: f32 = 5.0;
+
+// Dots at 5 WPM last 240 ms and dots at 60 WPM 20 ms, the usual range of hand and machine sent
+// code
+const MIN_DOT_MS: f32 = 20.0;
+const MAX_DOT_MS: f32 = 240.0;
+
+#[rustfmt::skip]
+const CODE: &[(&str, char)] = &[
+    (".-", 'A'), ("-...", 'B'), ("-.-.", 'C'), ("-..", 'D'), (".", 'E'), ("..-.", 'F'),
+    ("--.", 'G'), ("....", 'H'), ("..", 'I'), (".---", 'J'), ("-.-", 'K'), (".-..", 'L'),
+    ("--", 'M'), ("-.", 'N'), ("---", 'O'), (".--.", 'P'), ("--.-", 'Q'), (".-.", 'R'),
+    ("...", 'S'), ("-", 'T'), ("..-", 'U'), ("...-", 'V'), (".--", 'W'), ("-..-", 'X'),
+    ("-.--", 'Y'), ("--..", 'Z'), ("-----", '0'), (".----", '1'), ("..---", '2'),
+    ("...--", '3'), ("....-", '4'), (".....", '5'), ("-....", '6'), ("--...", '7'),
+    ("---..", '8'), ("----.", '9'), (".-.-.-", '.'), ("--..--", ','), ("..--..", '?'),
+    ("-..-.", '/'), ("-...-", '='), (".-.-.", '+'), ("-....-", '-'), (".--.-.", '@'),
+];
+
+/**
+ * Decodes Morse code sent as an on/off tone inside a band. The tone is found on the spectrum,
+ * then followed on the samples with a Goertzel filter, and the length of a dot is learned from
+ * what's received, so the speed doesn't have to be known.
+ */
+pub struct MorseDecoder {
+    sample_rate: u32,
+    band: Band,
+    tone_in_hz: Option<f32>,
+    // Samples not making a whole block yet
+    pending: Vec<f32>,
+    // Level of the tone while keyed and of the noise in between, they slowly move towards each
+    // other so the threshold follows fading signals
+    signal_db: f32,
+    noise_db: f32,
+    key_down: bool,
+    // Blocks since the key last changed
+    run_blocks: usize,
+    dot_blocks: f32,
+    symbol: String,
+    // Whether a letter came since the last space
+    in_word: bool,
+}
+
+impl MorseDecoder {
+    pub fn new(sample_rate: u32, band: Band) -> Self {
+        Self {
+            sample_rate,
+            band,
+            tone_in_hz: None,
+            pending: vec![],
+            signal_db: -120.0,
+            noise_db: 0.0,
+            key_down: false,
+            run_blocks: 0,
+            // 20 WPM until something is received
+            dot_blocks: 60.0 / BLOCK_MS,
+            symbol: String::new(),
+            in_word: false,
+        }
+    }
+
+    /**
+     * Speed of the code in words per minute, from the length of a dot ("PARIS" is 50 dots)
+     */
+    pub fn wpm(&self) -> f32 {
+        1200.0 / (self.dot_blocks * BLOCK_MS)
+    }
+
+    /**
+     * Gets the samples and the one-sided amplitudes of a new FFT result, and returns the
+     * characters decoded from it (spaces between words included)
+     */
+    pub fn process(&mut self, samples: &[f32], amplitudes: &[f32], mapping: BinMapping) -> String {
+        self.follow_tone(amplitudes, mapping);
+        let Some(tone_in_hz) = self.tone_in_hz else {
+            return String::new();
+        };
+
+        let block_len = (self.sample_rate as f32 * BLOCK_MS / 1000.0) as usize;
+        self.pending.extend_from_slice(samples);
+        let blocks: Vec<f32> = self
+            .pending
+            .chunks_exact(block_len)
+            .map(|block| goertzel_level_db(block, tone_in_hz, self.sample_rate))
+            .collect();
+        self.pending.drain(0..blocks.len() * block_len);
+
+        blocks
+            .into_iter()
+            .filter_map(|level_db| self.block(level_db))
+            .collect()
+    }
+
+    /**
+     * Moves to the loudest peak of the band when it clearly stands out. Keying makes the tone
+     * come and go, so the last one found is kept in between.
+     */
+    fn follow_tone(&mut self, amplitudes: &[f32], mapping: BinMapping) {
+        let low = mapping.bin_at(self.band.low_hz).max(1);
+        let high = mapping
+            .bin_at(self.band.high_hz)
+            .min(amplitudes.len().saturating_sub(1));
+        if low >= high {
+            return;
+        }
+        let Some((bin, peak)) = amplitudes[low..=high]
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+        else {
+            return;
+        };
+
+        let mut levels_db: Vec<f32> = amplitudes[low..=high]
+            .iter()
+            .map(|a| amplitude_to_db(*a))
+            .collect();
+        levels_db.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        if amplitude_to_db(*peak) >= levels_db[levels_db.len() / 2] + 20.0 {
+            self.tone_in_hz = Some(mapping.frequency_in_hz(low + bin));
+        }
+    }
+
+    /**
+     * Handles the level of the tone over one block, returning a character when one is complete
+     */
+    fn block(&mut self, level_db: f32) -> Option<char> {
+        // About 4 dB per second
+        let drift_db = 0.02;
+        self.signal_db = if level_db > self.signal_db {
+            level_db
+        } else {
+            self.signal_db - drift_db
+        };
+        self.noise_db = if level_db < self.noise_db {
+            level_db
+        } else {
+            self.noise_db + drift_db
+        };
+
+        let key_down = self.signal_db - self.noise_db > 12.0
+            && level_db > (self.signal_db + self.noise_db) / 2.0;
+        if key_down == self.key_down {
+            self.run_blocks += 1;
+            return if key_down { None } else { self.gap() };
+        }
+
+        let run = self.run_blocks as f32;
+        self.key_down = key_down;
+        self.run_blocks = 1;
+        if key_down {
+            return None;
+        }
+
+        // Blips way shorter than a dot are noise
+        if run < self.dot_blocks * 0.3 {
+            return None;
+        }
+        let (symbol, dot_blocks) = if run < self.dot_blocks * 2.0 {
+            ('.', run)
+        } else {
+            ('-', run / 3.0)
+        };
+        self.symbol.push(symbol);
+        self.dot_blocks = (self.dot_blocks * 0.8 + dot_blocks * 0.2)
+            .clamp(MIN_DOT_MS / BLOCK_MS, MAX_DOT_MS / BLOCK_MS);
+        None
+    }
+
+    /**
+     * Called on every silent block: a letter ends after 3 dots of silence and a word after 7,
+     * the thresholds sit between those and the 1 dot gap inside a letter
+     */
+    fn gap(&mut self) -> Option<char> {
+        let gap = self.run_blocks as f32;
+        if !self.symbol.is_empty() && gap >= self.dot_blocks * 2.0 {
+            self.in_word = true;
+            let symbol = std::mem::take(&mut self.symbol);
+            return Some(
+                CODE.iter()
+                    .find(|(code, _)| *code == symbol)
+                    .map_or('*', |(_, character)| *character),
+            );
+        }
+        if self.in_word && gap >= self.dot_blocks * 5.0 {
+            self.in_word = false;
+            return Some(' ');
+        }
+        None
+    }
+}
+
+/**
+ * Spawns a thread decoding every FFT result, printing the text so far each time a word ends
+ */
+pub fn spawn(band: Band, fft_transform: Arc<Mutex<FftFrame>>, sample_rate: u32) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut decoder = MorseDecoder::new(sample_rate, band);
+        let mut text = String::new();
+        let mut last_generation = 0;
+        loop {
+            // Frames have to be read as they come, the samples of a missed one would be a gap
+            std::thread::sleep(Duration::from_millis(5));
+            let frame = fft_transform.lock().unwrap().clone();
+            if frame.generation == last_generation {
+                continue;
+            }
+            last_generation = frame.generation;
+
+            let mapping = BinMapping::new(sample_rate, frame.magnitudes.len());
+            let decoded = decoder.process(&frame.samples, &frame.amplitudes, mapping);
+            text += &decoded;
+            if decoded.ends_with(' ') {
+                // Only the end of long messages fits on a line
+                let start = text.len().saturating_sub(80);
+                println!("\nMorse ({:.0} WPM): {}", decoder.wpm(), &text[start..]);
+                stdout().flush().unwrap();
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyed_tone_decodes_to_text() {
+        let sample_rate = 44100;
+        let dot = (sample_rate as f32 * 0.06) as usize;
+        // The decoder has to hear the noise between the keying before it can tell them apart
+        let mut keying = vec![(false, dot * 7)];
+        for word in ["PARIS", "SOS"] {
+            for letter in word.chars() {
+                let code = CODE.iter().find(|(_, c)| *c == letter).unwrap().0;
+                for symbol in code.chars() {
+                    keying.push((true, if symbol == '.' { dot } else { dot * 3 }));
+                    keying.push((false, dot));
+                }
+                keying.push((false, dot * 2));
+            }
+            keying.push((false, dot * 4));
+        }
+
+        let mut samples = vec![];
+        for (on, len) in keying {
+            for _ in 0..len {
+                let t = samples.len() as f32 / sample_rate as f32;
+                let tone = (2.0 * std::f32::consts::PI * 700.0 * t).sin() * 0.5;
+                samples.push(if on { tone } else { 0.0 } + 0.001 * (t * 12345.0).sin());
+            }
+        }
+
+        let mut decoder = MorseDecoder::new(
+            sample_rate,
+            Band {
+                low_hz: 400.0,
+                high_hz: 1000.0,
+            },
+        );
+        let mut text = String::new();
+        for frame in samples.chunks_exact(4096) {
+            let amplitudes = crate::analysis::one_sided_amplitudes(&crate::fft_magnitudes(frame));
+            text += &decoder.process(frame, &amplitudes, BinMapping::new(sample_rate, 4096));
+        }
+        assert_eq!(text.trim(), "PARIS SOS");
+        assert!((decoder.wpm() - 20.0).abs() < 2.0);
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use rumqttc::{Client, MqttOptions, QoS};
+use serde::Serialize;
+
+use crate::{
+    analysis::{power_to_db, Band, FftFrame, Summary},
+    vad::Vad,
+};
+
+#[derive(Serialize)]
+struct BandPayload {
+    low_hz: f32,
+    high_hz: f32,
+    level_db: f32,
+}
+
+/**
+ * What gets published on every interval. Levels are averaged in the power domain over the
+ * whole interval, so short spikes don't dominate a long-term monitoring graph.
+ */
+#[derive(Serialize)]
+struct SummaryPayload {
+    level_db: f32,
+    max_level_db: f32,
+    // Null when --vad heard no voice during the whole interval
+    dominant_frequency_in_hz: Option<f32>,
+    bands: Vec<BandPayload>,
+    frames: usize,
+    // Capture time of the last frame of the interval, in seconds on the capture clock
+    captured_at_s: f64,
+}
+
+#[derive(Default)]
+struct Accumulator {
+    level_power: f32,
+    max_level_db: Option<f32>,
+    band_powers: Vec<f32>,
+    frames: usize,
+    // Only the ones of the voiced frames
+    dominant_frequencies: Vec<f32>,
+    last_captured_at: Duration,
+}
+
+fn db_to_power(db: f32) -> f32 {
+    0.5 * 10f32.powf(db / 10.0)
+}
+
+impl Accumulator {
+    fn add(&mut self, summary: &Summary, voiced: bool) {
+        self.frames += 1;
+        self.level_power += db_to_power(summary.level_db);
+        self.max_level_db = Some(
+            self.max_level_db
+                .map_or(summary.level_db, |max| max.max(summary.level_db)),
+        );
+        self.band_powers.resize(summary.band_levels_db.len(), 0.0);
+        for (power, db) in self.band_powers.iter_mut().zip(&summary.band_levels_db) {
+            *power += db_to_power(*db);
+        }
+        if voiced {
+            self.dominant_frequencies
+                .push(summary.dominant_frequency_in_hz);
+        }
+    }
+
+    fn payload(&mut self, bands: &[Band]) -> Option<SummaryPayload> {
+        let frames = self.frames;
+        if frames == 0 {
+            return None;
+        }
+
+        // The median is used since a single loud click would move the mean a lot
+        self.dominant_frequencies
+            .sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        Some(SummaryPayload {
+            level_db: power_to_db(self.level_power / frames as f32),
+            max_level_db: self.max_level_db.unwrap(),
+            dominant_frequency_in_hz: self
+                .dominant_frequencies
+                .get(self.dominant_frequencies.len() / 2)
+                .copied(),
+            bands: bands
+                .iter()
+                .zip(&self.band_powers)
+                .map(|(band, power)| BandPayload {
+                    low_hz: band.low_hz,
+                    high_hz: band.high_hz,
+                    level_db: power_to_db(power / frames as f32),
+                })
+                .collect(),
+            frames,
+            captured_at_s: self.last_captured_at.as_secs_f64(),
+        })
+    }
+}
+
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub topic: String,
+    pub interval: Duration,
+    pub credentials: Option<(String, String)>,
+}
+
+/**
+ * Connects to the broker and spawns a thread that publishes a summary every `config.interval`.
+ * The connection is driven by a second thread, rumqttc reconnects by itself when the broker
+ * goes away.
+ */
+pub fn spawn(
+    config: MqttConfig,
+    bands: Vec<Band>,
+    vad: Option<Vad>,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+) -> JoinHandle<()> {
+    let mut options = MqttOptions::new(
+        format!("fft-analyzer-{}", std::process::id()),
+        config.host,
+        config.port,
+    );
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some((username, password)) = config.credentials {
+        options.set_credentials(username, password);
+    }
+
+    let (client, mut connection) = Client::new(options, 10);
+
+    std::thread::spawn(move || {
+        for notification in connection.iter() {
+            if let Err(error) = notification {
+                eprintln!("MQTT connection error: {error}");
+                std::thread::sleep(Duration::from_secs(5));
+            }
+        }
+    });
+
+    // Samples the latest frame a few times per interval
+    let sample_interval = config.interval.min(Duration::from_millis(250));
+
+    std::thread::spawn(move || {
+        let mut accumulator = Accumulator::default();
+        let mut last_publish = Instant::now();
+
+        loop {
+            std::thread::sleep(sample_interval);
+            let frame = fft_transform.lock().unwrap().clone();
+            let magnitudes = frame.magnitudes;
+            let summary = Summary::from_magnitudes(&magnitudes, sample_rate, &bands);
+            if let Some(summary) = summary {
+                let voiced = vad.is_none_or(|vad| vad.is_voiced(&magnitudes, sample_rate));
+                accumulator.add(&summary, voiced);
+                accumulator.last_captured_at = frame.captured_at;
+            }
+
+            if last_publish.elapsed() < config.interval {
+                continue;
+            }
+            last_publish = Instant::now();
+
+            if let Some(payload) = accumulator.payload(&bands) {
+                let payload = serde_json::to_vec(&payload).unwrap();
+                if let Err(error) =
+                    client.try_publish(config.topic.clone(), QoS::AtMostOnce, false, payload)
+                {
+                    eprintln!("Could not publish to MQTT: {error}");
+                }
+            }
+            accumulator = Accumulator::default();
+        }
+    })
+}
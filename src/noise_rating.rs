@@ -0,0 +1,188 @@
+/**
+ * The curves background noise is rated against in room noise surveys. A rating is the lowest
+ * curve that no octave band of the noise goes over, found by interpolating between the curves.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Criterion {
+    // Noise Criterion (ANSI S12.2), for offices, studios and halls
+    Nc,
+    // Noise Rating (ISO R 1996), the one used in Europe
+    Nr,
+}
+
+// Octave bands of the NC curves
+const NC_BANDS_HZ: [f32; 8] = [63.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0];
+// Levels in dB SPL of the NC curves in each of NC_BANDS_HZ
+#[rustfmt::skip]
+const NC_CURVES: [(f32, [f32; 8]); 12] = [
+    (15.0, [47.0, 36.0, 29.0, 22.0, 17.0, 14.0, 12.0, 11.0]),
+    (20.0, [51.0, 40.0, 33.0, 26.0, 22.0, 19.0, 17.0, 16.0]),
+    (25.0, [54.0, 44.0, 37.0, 31.0, 27.0, 24.0, 22.0, 21.0]),
+    (30.0, [57.0, 48.0, 41.0, 35.0, 31.0, 29.0, 28.0, 27.0]),
+    (35.0, [60.0, 52.0, 45.0, 40.0, 36.0, 34.0, 33.0, 32.0]),
+    (40.0, [64.0, 56.0, 50.0, 45.0, 41.0, 39.0, 38.0, 37.0]),
+    (45.0, [67.0, 60.0, 54.0, 49.0, 46.0, 44.0, 43.0, 42.0]),
+    (50.0, [71.0, 64.0, 58.0, 54.0, 51.0, 49.0, 48.0, 47.0]),
+    (55.0, [74.0, 67.0, 62.0, 58.0, 56.0, 54.0, 53.0, 52.0]),
+    (60.0, [77.0, 71.0, 67.0, 63.0, 61.0, 59.0, 58.0, 57.0]),
+    (65.0, [80.0, 75.0, 71.0, 68.0, 66.0, 64.0, 63.0, 62.0]),
+    (70.0, [83.0, 79.0, 75.0, 72.0, 71.0, 70.0, 69.0, 68.0]),
+];
+
+// The NR curves are a line in each octave band: a + b * NR dB SPL, as (band, a, b)
+#[rustfmt::skip]
+const NR_BANDS: [(f32, f32, f32); 9] = [
+    (31.5, 55.4, 0.681),
+    (63.0, 35.5, 0.790),
+    (125.0, 22.0, 0.870),
+    (250.0, 12.0, 0.930),
+    (500.0, 4.8, 0.974),
+    (1000.0, 0.0, 1.000),
+    (2000.0, -3.5, 1.015),
+    (4000.0, -6.1, 1.025),
+    (8000.0, -8.0, 1.030),
+];
+
+/**
+ * The rating of a noise, and the octave band that set it
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rating {
+    pub value: f32,
+    pub band_hz: f32,
+}
+
+impl Criterion {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Nc => "NC",
+            Self::Nr => "NR",
+        }
+    }
+
+    /**
+     * Octave bands the curves are given in
+     */
+    pub fn bands_hz(self) -> Vec<f32> {
+        match self {
+            Self::Nc => NC_BANDS_HZ.to_vec(),
+            Self::Nr => NR_BANDS.iter().map(|(hz, _, _)| *hz).collect(),
+        }
+    }
+
+    /**
+     * Curves worth drawing behind a measurement, every 5 of the rating
+     */
+    pub fn drawn_values(self) -> Vec<f32> {
+        match self {
+            Self::Nc => NC_CURVES.iter().map(|(value, _)| *value).collect(),
+            Self::Nr => (0..=14).map(|i| i as f32 * 5.0).collect(),
+        }
+    }
+
+    /**
+     * Level in dB SPL of the curve of a rating in an octave band, None for a band the curves
+     * don't cover
+     */
+    pub fn level_at(self, value: f32, band_hz: f32) -> Option<f32> {
+        match self {
+            Self::Nc => {
+                let band = NC_BANDS_HZ.iter().position(|hz| *hz == band_hz)?;
+                // Linear between the curves, and along the nearest two past the ends
+                let upper = NC_CURVES
+                    .iter()
+                    .position(|(curve_value, _)| *curve_value >= value)
+                    .unwrap_or(NC_CURVES.len() - 1)
+                    .max(1);
+                let (low_value, low) = NC_CURVES[upper - 1];
+                let (high_value, high) = NC_CURVES[upper];
+                let t = (value - low_value) / (high_value - low_value);
+                Some(low[band] + (high[band] - low[band]) * t)
+            }
+            Self::Nr => NR_BANDS
+                .iter()
+                .find(|(hz, _, _)| *hz == band_hz)
+                .map(|(_, a, b)| a + b * value),
+        }
+    }
+
+    /**
+     * Rating a band would get on its own, where its curves cross the level. None for a band
+     * the curves don't cover.
+     */
+    fn band_rating(self, band_hz: f32, level_db: f32) -> Option<f32> {
+        match self {
+            Self::Nc => {
+                let band = NC_BANDS_HZ.iter().position(|hz| *hz == band_hz)?;
+                let upper = NC_CURVES
+                    .iter()
+                    .position(|(_, curve)| curve[band] >= level_db)
+                    .unwrap_or(NC_CURVES.len() - 1)
+                    .max(1);
+                let (low_value, low) = NC_CURVES[upper - 1];
+                let (high_value, high) = NC_CURVES[upper];
+                let t = (level_db - low[band]) / (high[band] - low[band]);
+                Some(low_value + (high_value - low_value) * t)
+            }
+            Self::Nr => NR_BANDS
+                .iter()
+                .find(|(hz, _, _)| *hz == band_hz)
+                .map(|(_, a, b)| (level_db - a) / b),
+        }
+    }
+
+    /**
+     * Rates octave band levels, as (center, dB SPL) pairs: the lowest curve none of them goes
+     * over, rounded up. None when no band is covered by the curves.
+     */
+    pub fn rate(self, levels: &[(f32, f32)]) -> Option<Rating> {
+        levels
+            .iter()
+            .filter_map(|(band_hz, level_db)| {
+                self.band_rating(*band_hz, *level_db).map(|value| Rating {
+                    value,
+                    band_hz: *band_hz,
+                })
+            })
+            .max_by(|a, b| a.value.partial_cmp(&b.value).unwrap())
+            .map(|rating| Rating {
+                value: rating.value.ceil(),
+                ..rating
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_noise_on_a_curve_gets_its_rating() {
+        let nc_35: Vec<(f32, f32)> = NC_BANDS_HZ
+            .iter()
+            .zip(NC_CURVES[4].1)
+            .map(|(hz, db)| (*hz, db))
+            .collect();
+        assert_eq!(
+            Criterion::Nc.rate(&nc_35).map(|rating| rating.value),
+            Some(35.0)
+        );
+
+        // The same noise with a hum in the 125 Hz band, halfway to NC-45
+        let mut hum = nc_35.clone();
+        hum[1].1 = 58.0;
+        let rating = Criterion::Nc.rate(&hum).unwrap();
+        assert_eq!(rating.band_hz, 125.0);
+        assert_eq!(rating.value, 43.0);
+    }
+
+    #[test]
+    fn nr_follows_the_iso_lines() {
+        // NR is the level of the 1 kHz band
+        let levels = [(1000.0, 40.0), (125.0, 40.0), (16000.0, 90.0)];
+        let rating = Criterion::Nr.rate(&levels).unwrap();
+        assert_eq!((rating.value, rating.band_hz), (40.0, 1000.0));
+        assert!((Criterion::Nr.level_at(40.0, 63.0).unwrap() - 67.1).abs() < 0.01);
+        assert_eq!(Criterion::Nr.rate(&[(16000.0, 90.0)]), None);
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    analysis::{find_peaks, magnitude_to_db, one_sided_amplitudes, BinMapping, Summary},
+    cli::AnalyzeArgs,
+    curve::{Calibration, Curve},
+    image::{spectrogram_color, write_png},
+    pitch::PitchTracker,
+};
+
+/**
+ * Reads any WAV file that hound understands and mixes its channels down to mono
+ */
+fn read_wav(path: &Path) -> hound::Result<(Vec<f32>, u32)> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let full_scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / full_scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    let channels = spec.channels as usize;
+    let samples = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    Ok((samples, spec.sample_rate))
+}
+
+/**
+ * Runs the FFT over the whole file, frame by frame, and writes the requested outputs
+ */
+pub fn run(args: &AnalyzeArgs) -> Result<(), String> {
+    if args.out.is_none() && args.csv.is_none() {
+        return Err("Nothing to do, pass --out and/or --csv".into());
+    }
+
+    let (samples, sample_rate) =
+        read_wav(&args.input).map_err(|error| format!("Could not read the input: {error}"))?;
+    let fft_size = args.fft_size;
+    let hop = args.hop.unwrap_or(fft_size / 4).max(1);
+
+    if samples.len() < fft_size {
+        return Err(format!(
+            "The file has {} samples, it needs at least {fft_size}",
+            samples.len()
+        ));
+    }
+
+    let mapping = BinMapping::new(sample_rate, fft_size);
+    let mut calibration = match &args.mic_calibration {
+        Some(path) => Some(Calibration::new(Curve::load(path)?, sample_rate)),
+        None => None,
+    };
+    let displayed_bins = mapping.bins_up_to(args.max_freq.unwrap_or(f32::MAX));
+    let vad = args.vad();
+
+    let mut csv = match &args.csv {
+        Some(path) => {
+            let mut csv = BufWriter::new(
+                File::create(path).map_err(|error| format!("Could not create the CSV: {error}"))?,
+            );
+            let peak_columns: String = (1..=args.peaks)
+                .map(|i| format!(",peak_{i}_hz,peak_{i}_db"))
+                .collect();
+            let voiced_column = if vad.is_some() { ",voiced" } else { "" };
+            writeln!(
+                csv,
+                "time_s,pitch_hz,pitch_db,pitch_confidence,level_db{peak_columns}{voiced_column}"
+            )
+            .map_err(|error| error.to_string())?;
+            Some(csv)
+        }
+        None => None,
+    };
+
+    let mut pitch_tracker = PitchTracker::new();
+    // Each frame becomes one column of the image
+    let mut columns: Vec<Vec<f32>> = vec![];
+
+    for start in (0..=samples.len() - fft_size).step_by(hop) {
+        let frame = &samples[start..start + fft_size];
+        let mut magnitudes = args.window.magnitudes(frame);
+        if let Some(calibration) = calibration.as_mut() {
+            calibration.apply(&mut magnitudes);
+        }
+
+        if let Some(csv) = csv.as_mut() {
+            let summary = Summary::from_magnitudes(&magnitudes, sample_rate, &[])
+                .ok_or_else(|| format!("Can't summarize a frame of {fft_size} samples"))?;
+            let voiced = vad.map(|vad| vad.is_voiced(&magnitudes, sample_rate));
+            // Updated on every frame, the octave correction needs the ones before
+            let pitch = pitch_tracker.update(&one_sided_amplitudes(&magnitudes), mapping);
+            let pitch_columns = match pitch.filter(|_| voiced.unwrap_or(true)) {
+                Some(pitch) => format!(
+                    "{:.2},{:.2},{:.2}",
+                    pitch.frequency_in_hz, pitch.level_db, pitch.confidence
+                ),
+                None => ",,".into(),
+            };
+            let voiced_column = match voiced {
+                Some(voiced) => format!(",{}", voiced as u8),
+                None => String::new(),
+            };
+            let peaks = find_peaks(&magnitudes, sample_rate, args.peaks);
+            let peak_columns: String = (0..args.peaks)
+                .map(|i| match peaks.get(i) {
+                    Some(peak) => format!(",{:.2},{:.2}", peak.frequency_in_hz, peak.level_db),
+                    None => ",,".into(),
+                })
+                .collect();
+            writeln!(
+                csv,
+                "{:.4},{pitch_columns},{:.2}{peak_columns}{voiced_column}",
+                start as f32 / sample_rate as f32,
+                summary.level_db
+            )
+            .map_err(|error| error.to_string())?;
+        }
+
+        if args.out.is_some() {
+            columns.push(
+                magnitudes[0..displayed_bins]
+                    .iter()
+                    .map(|magnitude| magnitude_to_db(*magnitude, fft_size))
+                    .collect(),
+            );
+        }
+    }
+
+    if let Some(mut csv) = csv {
+        csv.flush().map_err(|error| error.to_string())?;
+    }
+
+    if let Some(out) = &args.out {
+        let width = columns.len();
+        let height = displayed_bins;
+        let mut rgb = vec![0u8; width * height * 3];
+
+        for (x, column) in columns.iter().enumerate() {
+            for (bin, db) in column.iter().enumerate() {
+                // Low frequencies at the bottom of the image
+                let y = height - 1 - bin;
+                let t = (db - args.floor_db) / -args.floor_db;
+                let i = (y * width + x) * 3;
+                rgb[i..i + 3].copy_from_slice(&spectrogram_color(t));
+            }
+        }
+
+        write_png(out, width as u32, height as u32, &rgb)
+            .map_err(|error| format!("Could not write the image: {error}"))?;
+        println!(
+            "Wrote a {width}x{height} spectrogram ({:.2} Hz per pixel, {:.2} ms per column) to {}",
+            mapping.bin_width_hz(),
+            hop as f32 * 1000.0 / sample_rate as f32,
+            out.display()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use std::ops::Range;
+
+use crate::analysis::amplitude_to_db;
+
+// The level is followed in blocks this long, over a period of the lowest notes
+const BLOCK_SECS: f32 = 0.02;
+// A block this much louder than the one before starts a new note
+const ONSET_RISE_DB: f32 = 6.0;
+// The attack lasts until the level stopped rising for this long
+const SETTLE_SECS: f32 = 0.06;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotePhase {
+    Silence,
+    // From the onset until the level settled after its peak, where plucked strings ring sharp
+    Attack,
+    Sustain,
+}
+
+/**
+ * Splits the notes of the input into their attack and their sustain, from the level of short
+ * blocks: a note starts when the level jumps up, and its sustain once the level stopped rising
+ * for SETTLE_SECS
+ */
+pub struct NoteSegmenter {
+    min_level_db: f32,
+    phase: NotePhase,
+    // Sum of the squares and length of the block being measured
+    block: (f32, usize),
+    last_level_db: f32,
+    peak_db: f32,
+    blocks_since_peak: usize,
+}
+
+impl NoteSegmenter {
+    /**
+     * Quieter blocks than `min_level_db` (dBFS, the level of a sine) are silence
+     */
+    pub fn new(min_level_db: f32) -> Self {
+        Self {
+            min_level_db,
+            phase: NotePhase::Silence,
+            block: (0.0, 0),
+            last_level_db: f32::MIN,
+            peak_db: f32::MIN,
+            blocks_since_peak: 0,
+        }
+    }
+
+    pub fn phase(&self) -> NotePhase {
+        self.phase
+    }
+
+    fn next_block(&mut self, level_db: f32, settle_blocks: usize) {
+        let onset = level_db - self.last_level_db >= ONSET_RISE_DB;
+        self.phase = match self.phase {
+            _ if level_db < self.min_level_db => NotePhase::Silence,
+            NotePhase::Silence => NotePhase::Attack,
+            NotePhase::Sustain if !onset => NotePhase::Sustain,
+            NotePhase::Attack if level_db <= self.peak_db => {
+                self.blocks_since_peak += 1;
+                match self.blocks_since_peak >= settle_blocks {
+                    true => NotePhase::Sustain,
+                    false => NotePhase::Attack,
+                }
+            }
+            // A new note, or the attack still rising
+            _ => NotePhase::Attack,
+        };
+        if (self.phase == NotePhase::Attack && level_db > self.peak_db) || onset {
+            self.peak_db = level_db;
+            self.blocks_since_peak = 0;
+        }
+        if self.phase == NotePhase::Silence {
+            self.peak_db = f32::MIN;
+        }
+        self.last_level_db = level_db;
+    }
+
+    /**
+     * Takes the next samples and returns the range of them that belongs to the sustain of a
+     * note, the last one when there are several. It's empty when none does.
+     */
+    pub fn process(&mut self, samples: &[f32], sample_rate: u32) -> Range<usize> {
+        let block_len = ((BLOCK_SECS * sample_rate as f32) as usize).max(1);
+        let settle_blocks = (SETTLE_SECS / BLOCK_SECS).round() as usize;
+        let mut sustain = 0..0;
+        let mut sustain_start = (self.phase == NotePhase::Sustain).then_some(0);
+        // First sample of the block being measured, 0 when it started with the samples before
+        let mut block_start = 0;
+        for (i, sample) in samples.iter().enumerate() {
+            self.block.0 += sample * sample;
+            self.block.1 += 1;
+            if self.block.1 < block_len {
+                continue;
+            }
+            // The peak amplitude of a sine is sqrt(2) times its RMS
+            let level_db = amplitude_to_db((2.0 * self.block.0 / block_len as f32).sqrt());
+            self.block = (0.0, 0);
+            self.next_block(level_db, settle_blocks);
+            match (self.phase == NotePhase::Sustain, sustain_start) {
+                (true, None) => sustain_start = Some(block_start),
+                (false, Some(start)) => {
+                    sustain = start..block_start;
+                    sustain_start = None;
+                }
+                _ => {}
+            }
+            block_start = i + 1;
+        }
+        match sustain_start {
+            Some(start) => start..samples.len(),
+            None => sustain,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_sustain_starts_once_the_attack_settled() {
+        let sample_rate = 44100;
+        // Silence, then two plucks a second apart, decaying
+        let samples: Vec<f32> = (0..sample_rate as usize * 3)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                let since_pluck = match t {
+                    t if t < 0.5 => return 0.0,
+                    t if t < 1.5 => t - 0.5,
+                    t => t - 1.5,
+                };
+                0.5 * (-since_pluck / 0.4).exp() * (2.0 * std::f32::consts::PI * 110.0 * t).sin()
+            })
+            .collect();
+
+        let mut segmenter = NoteSegmenter::new(-50.0);
+        let mut sustained = vec![false; samples.len()];
+        for (index, frame) in samples.chunks(4096).enumerate() {
+            let sustain = segmenter.process(frame, sample_rate);
+            let start = index * 4096;
+            sustained[start + sustain.start..start + sustain.end].fill(true);
+        }
+        let first_sustained = |from_secs: f32| {
+            let from = (from_secs * sample_rate as f32) as usize;
+            (from..samples.len()).find(|i| sustained[*i]).unwrap() as f32 / sample_rate as f32
+        };
+        // The peak is in the first block, then it has to settle for 60 ms
+        let first = first_sustained(0.0);
+        assert!((0.55..0.62).contains(&first), "{first}");
+        assert!(!sustained[(1.52 * sample_rate as f32) as usize]);
+        let second = first_sustained(1.5);
+        assert!((1.55..1.62).contains(&second), "{second}");
+        assert_eq!(segmenter.phase(), NotePhase::Sustain);
+    }
+}
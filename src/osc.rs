@@ -0,0 +1,182 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::{
+    analysis::{Band, FftFrame, Summary},
+    vad::Vad,
+};
+
+/*
+ * Open Sound Control is simple enough that pulling a crate just for it is not worth it.
+ * A message is the address pattern, a type tag string and the arguments, where every string
+ * is null terminated and padded with zeros until its length is a multiple of 4.
+ * All numbers are big endian.
+ */
+pub enum OscArgument {
+    Float(f32),
+    // Optional in the spec, but most receivers know it
+    Double(f64),
+}
+
+fn push_padded_string(packet: &mut Vec<u8>, s: &str) {
+    packet.extend_from_slice(s.as_bytes());
+    // At least one null byte always has to be written
+    let padding = 4 - (s.len() % 4);
+    packet.extend(std::iter::repeat_n(0, padding));
+}
+
+pub fn encode_message(address: &str, arguments: &[OscArgument]) -> Vec<u8> {
+    let mut packet = vec![];
+    push_padded_string(&mut packet, address);
+
+    let type_tags: String = std::iter::once(',')
+        .chain(arguments.iter().map(|argument| match argument {
+            OscArgument::Float(_) => 'f',
+            OscArgument::Double(_) => 'd',
+        }))
+        .collect();
+    push_padded_string(&mut packet, &type_tags);
+
+    for argument in arguments {
+        match argument {
+            OscArgument::Float(value) => packet.extend_from_slice(&value.to_be_bytes()),
+            OscArgument::Double(value) => packet.extend_from_slice(&value.to_be_bytes()),
+        }
+    }
+
+    packet
+}
+
+pub struct OscSender {
+    socket: UdpSocket,
+    target: SocketAddr,
+    prefix: String,
+}
+
+impl OscSender {
+    pub fn new(target: SocketAddr, prefix: String) -> std::io::Result<Self> {
+        let bind_address: SocketAddr = if target.is_ipv4() {
+            "0.0.0.0:0".parse().unwrap()
+        } else {
+            "[::]:0".parse().unwrap()
+        };
+
+        Ok(Self {
+            socket: UdpSocket::bind(bind_address)?,
+            target,
+            prefix: prefix.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn send(&self, address: &str, arguments: &[OscArgument]) -> std::io::Result<()> {
+        let packet = encode_message(&format!("{}{address}", self.prefix), arguments);
+        self.socket.send_to(&packet, self.target)?;
+        Ok(())
+    }
+
+    /**
+     * Sends one message per value:
+     * <prefix>/pitch f (Hz), <prefix>/pitch/level f (dBFS), <prefix>/level f (dBFS)
+     * and <prefix>/band/<index> f (dBFS) for each configured band. The pitch ones are left
+     * out when `voiced` is false.
+     */
+    pub fn send_summary(
+        &self,
+        summary: &Summary,
+        captured_at: Duration,
+        voiced: bool,
+    ) -> std::io::Result<()> {
+        self.send("/time", &[OscArgument::Double(captured_at.as_secs_f64())])?;
+        if voiced {
+            self.send(
+                "/pitch",
+                &[OscArgument::Float(summary.dominant_frequency_in_hz)],
+            )?;
+            self.send(
+                "/pitch/level",
+                &[OscArgument::Float(summary.dominant_level_db)],
+            )?;
+        }
+        self.send("/level", &[OscArgument::Float(summary.level_db)])?;
+
+        for (i, level) in summary.band_levels_db.iter().enumerate() {
+            self.send(&format!("/band/{i}"), &[OscArgument::Float(*level)])?;
+        }
+
+        Ok(())
+    }
+}
+
+/**
+ * Spawns a thread that keeps sending the summary of the latest FFT result every `interval`
+ */
+pub fn spawn(
+    sender: OscSender,
+    interval: Duration,
+    bands: Vec<Band>,
+    vad: Option<Vad>,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || loop {
+        let frame = fft_transform.lock().unwrap().clone();
+        let magnitudes = frame.magnitudes;
+        let summary = Summary::from_magnitudes(&magnitudes, sample_rate, &bands);
+
+        if let Some(summary) = summary {
+            let voiced = vad.is_none_or(|vad| vad.is_voiced(&magnitudes, sample_rate));
+            if let Err(error) = sender.send_summary(&summary, frame.captured_at, voiced) {
+                eprintln!("Could not send OSC message: {error}");
+            }
+        }
+
+        std::thread::sleep(interval);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_are_padded_to_four_bytes() {
+        assert_eq!(
+            encode_message("/pitch", &[OscArgument::Float(440.0)]),
+            [
+                b'/', b'p', b'i', b't', b'c', b'h', 0, 0, // address
+                b',', b'f', 0, 0, // type tags
+                0x43, 0xDC, 0x00, 0x00, // 440.0
+            ]
+        );
+        // A string whose length is already a multiple of 4 still gets its null byte
+        assert_eq!(
+            encode_message("/abc", &[]),
+            [b'/', b'a', b'b', b'c', 0, 0, 0, 0, b',', 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn every_argument_gets_a_type_tag() {
+        assert_eq!(
+            encode_message(
+                "/t",
+                &[
+                    OscArgument::Float(-1.0),
+                    OscArgument::Double(0.5),
+                    OscArgument::Float(0.0),
+                ]
+            ),
+            [
+                b'/', b't', 0, 0, // address
+                b',', b'f', b'd', b'f', 0, 0, 0, 0, // type tags
+                0xBF, 0x80, 0x00, 0x00, // -1.0
+                0x3F, 0xE0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // 0.5
+                0x00, 0x00, 0x00, 0x00, // 0.0
+            ]
+        );
+    }
+}
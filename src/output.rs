@@ -0,0 +1,96 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    StreamConfig,
+};
+
+// Samples waiting to be played are capped to this, so the sound doesn't lag more and more
+// behind when the output runs slower than the input
+const MAX_QUEUED_SECS: f32 = 0.2;
+
+/**
+ * Samples waiting to be played by an output stream, filled from the input callback
+ */
+#[derive(Clone)]
+pub struct OutputQueue {
+    samples: Arc<Mutex<VecDeque<f32>>>,
+    max_queued: usize,
+}
+
+impl OutputQueue {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            samples: Arc::new(Mutex::new(VecDeque::new())),
+            max_queued: (sample_rate as f32 * MAX_QUEUED_SECS) as usize,
+        }
+    }
+
+    /**
+     * Queues samples to be played, dropping the oldest ones past MAX_QUEUED_SECS
+     */
+    pub fn extend(&self, samples: impl IntoIterator<Item = f32>) {
+        let mut queue = self.samples.lock().unwrap();
+        queue.extend(samples);
+        let excess = queue.len().saturating_sub(self.max_queued);
+        queue.drain(..excess);
+    }
+
+    /**
+     * Returns how many samples are waiting to be played
+     */
+    pub fn queued(&self) -> usize {
+        self.samples.lock().unwrap().len()
+    }
+}
+
+/**
+ * Opens the default output device, playing the same samples on all its channels. Returns the
+ * stream, which plays as long as it is kept, the queue feeding it and its sample rate.
+ */
+pub fn open(name: &'static str) -> Result<(cpal::Stream, OutputQueue, u32), String> {
+    let speaker = cpal::default_host()
+        .default_output_device()
+        .ok_or("No output device found")?;
+    let config: StreamConfig = speaker
+        .default_output_config()
+        .map_err(|error| error.to_string())?
+        .into();
+    let sample_rate = config.sample_rate.0;
+    let channels = config.channels as usize;
+
+    let queue = OutputQueue::new(sample_rate);
+    let stream_queue = queue.samples.clone();
+    let stream = speaker
+        .build_output_stream(
+            &config,
+            move |data: &mut [f32], _info| {
+                let mut queue = stream_queue.lock().unwrap();
+                for frame in data.chunks_mut(channels) {
+                    frame.fill(queue.pop_front().unwrap_or(0.0));
+                }
+            },
+            move |error| eprintln!("\n{name} output error: {error}"),
+            None,
+        )
+        .map_err(|error| error.to_string())?;
+    stream.play().map_err(|error| error.to_string())?;
+    Ok((stream, queue, sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_queue_keeps_the_newest_samples() {
+        let queue = OutputQueue::new(100);
+        queue.extend((0..50).map(|i| i as f32));
+        let samples = queue.samples.lock().unwrap();
+        assert_eq!(samples.len(), 20);
+        assert_eq!(samples.front(), Some(&30.0));
+    }
+}
@@ -0,0 +1,217 @@
+use std::path::{Path, PathBuf};
+
+use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window};
+
+use crate::{curve::Curve, text};
+
+// Colors of the takes, in the order they were given
+const COLORS: [Color; 6] = [
+    Color::RGB(230, 120, 20),
+    Color::RGB(30, 110, 200),
+    Color::RGB(170, 40, 160),
+    Color::RGB(20, 150, 60),
+    Color::RGB(200, 40, 40),
+    Color::RGB(110, 90, 40),
+];
+
+// Where the legend starts, clear of the labels of the dB scale
+const LEGEND_X: i32 = 70;
+const LEGEND_Y: i32 = 10;
+const LEGEND_SCALE: u32 = 2;
+// Length of the line drawn before each name
+const SWATCH_WIDTH: u32 = 20;
+
+/**
+ * A spectrum saved from an earlier session, drawn over the live one
+ */
+pub struct Take {
+    pub name: String,
+    pub curve: Curve,
+    pub visible: bool,
+}
+
+/**
+ * The saved spectra given with --overlay, with a legend whose entries can be clicked to show or
+ * hide each one
+ */
+pub struct Overlay {
+    takes: Vec<Take>,
+    // Hides all of them at once, without losing which ones were hidden
+    pub shown: bool,
+}
+
+/**
+ * Reads a spectrum written by a measurement (a CSV with frequency_hz and level_db columns), or
+ * any frequency / level curve the target and calibration curves are read from
+ */
+pub fn load_take(path: &Path) -> Result<Take, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|error| format!("Could not read {}: {error}", path.display()))?;
+    let header: Vec<&str> = text.lines().next().unwrap_or_default().split(',').collect();
+    let columns = (
+        header.iter().position(|column| *column == "frequency_hz"),
+        header.iter().position(|column| *column == "level_db"),
+    );
+    let curve = match columns {
+        (Some(frequency_column), Some(level_column)) => {
+            let mut points = vec![];
+            for (number, line) in text.lines().enumerate().skip(1) {
+                let values: Vec<&str> = line.split(',').collect();
+                let value = |column: usize| values.get(column).and_then(|v| v.parse().ok());
+                match (value(frequency_column), value(level_column)) {
+                    (Some(frequency_in_hz), Some(level_db)) => {
+                        points.push((frequency_in_hz, level_db))
+                    }
+                    _ if line.trim().is_empty() => {}
+                    _ => {
+                        return Err(format!(
+                            "{}: line {} has no frequency and level",
+                            path.display(),
+                            number + 1
+                        ))
+                    }
+                }
+            }
+            if points.is_empty() {
+                return Err(format!("{} has no levels", path.display()));
+            }
+            Curve::from_points(points)
+        }
+        _ => Curve::parse(&text).map_err(|error| format!("{}: {error}", path.display()))?,
+    };
+    Ok(Take {
+        name: path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+        curve,
+        visible: true,
+    })
+}
+
+impl Overlay {
+    pub fn load(paths: &[PathBuf]) -> Result<Self, String> {
+        Ok(Self {
+            takes: paths
+                .iter()
+                .map(|path| load_take(path))
+                .collect::<Result<_, _>>()?,
+            shown: true,
+        })
+    }
+
+    /**
+     * The takes to draw and their colors
+     */
+    pub fn visible(&self) -> impl Iterator<Item = (&Take, Color)> {
+        self.takes
+            .iter()
+            .enumerate()
+            .filter(|(_, take)| self.shown && take.visible)
+            .map(|(i, take)| (take, COLORS[i % COLORS.len()]))
+    }
+
+    fn entry_rect(&self, index: usize) -> Rect {
+        let line_height = text::line_height(LEGEND_SCALE);
+        let name_width = text::text_width(&self.takes[index].name, LEGEND_SCALE);
+        Rect::new(
+            LEGEND_X,
+            LEGEND_Y + (index as u32 * line_height) as i32,
+            SWATCH_WIDTH + 6 + name_width,
+            line_height,
+        )
+    }
+
+    /**
+     * Shows or hides the take whose legend entry is at the point, and returns it. None when
+     * the point is not on the legend.
+     */
+    pub fn toggle_at(&mut self, x: i32, y: i32) -> Option<&Take> {
+        if !self.shown {
+            return None;
+        }
+        let index = (0..self.takes.len()).find(|i| self.entry_rect(*i).contains_point((x, y)))?;
+        self.takes[index].visible = !self.takes[index].visible;
+        Some(&self.takes[index])
+    }
+
+    /**
+     * Draws the name of every take next to its color, greyed out when hidden
+     */
+    pub fn draw_legend(&self, canvas: &mut Canvas<Window>) {
+        if !self.shown {
+            return;
+        }
+        for (i, take) in self.takes.iter().enumerate() {
+            let rect = self.entry_rect(i);
+            let color = match take.visible {
+                true => COLORS[i % COLORS.len()],
+                false => Color::RGB(190, 190, 190),
+            };
+            let middle = rect.y() + rect.height() as i32 / 2;
+            canvas.set_draw_color(color);
+            for offset in -1..=1 {
+                canvas
+                    .draw_line(
+                        (rect.x(), middle + offset),
+                        (rect.x() + SWATCH_WIDTH as i32, middle + offset),
+                    )
+                    .unwrap();
+            }
+            text::draw_text(
+                canvas,
+                &take.name,
+                rect.x() + SWATCH_WIDTH as i32 + 6,
+                rect.y(),
+                LEGEND_SCALE,
+                color,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measurement_reports_load_as_takes() {
+        let directory = std::env::temp_dir().join(format!("overlay-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let report = directory.join("top-build-3.csv");
+        std::fs::write(
+            &report,
+            "frequency_hz,note,level_db\n100.000,G2 +14c,-40.00\n200.000,G3 +14c,-20.00\n",
+        )
+        .unwrap();
+        let curve = directory.join("top-build-2.txt");
+        std::fs::write(&curve, "* A curve\n100 -30\n200 -35\n").unwrap();
+
+        let overlay = Overlay::load(&[report, curve]).unwrap();
+        let takes: Vec<&Take> = overlay.visible().map(|(take, _)| take).collect();
+        assert_eq!(takes[0].name, "top-build-3");
+        assert_eq!(takes[0].curve.db_at(200.0), -20.0);
+        assert_eq!(takes[1].curve.db_at(100.0), -30.0);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn clicking_an_entry_hides_its_take() {
+        let take = |name: &str| Take {
+            name: name.into(),
+            curve: Curve::from_points(vec![(100.0, 0.0)]),
+            visible: true,
+        };
+        let mut overlay = Overlay {
+            takes: vec![take("a"), take("b")],
+            shown: true,
+        };
+        let second = overlay.entry_rect(1);
+        let hidden = overlay.toggle_at(second.x() + 2, second.y() + 2).unwrap();
+        assert_eq!(hidden.name, "b");
+        assert!(!hidden.visible);
+        assert_eq!(overlay.visible().count(), 1);
+        assert!(overlay.toggle_at(LEGEND_X + 500, LEGEND_Y).is_none());
+    }
+}
@@ -0,0 +1,138 @@
+use std::io::{BufWriter, ErrorKind, Read, Write};
+
+use serde::Serialize;
+
+use crate::{
+    analysis::BinMapping,
+    cli::{PcmFormat, PipeArgs, SpectrumFormat},
+    fft_magnitudes,
+};
+
+/*
+ * In pipe mode stdout only ever receives spectra, so it can be read by another program.
+ * Everything meant for humans (errors, the summary at the end) goes to stderr.
+ */
+
+#[derive(Serialize)]
+struct SpectrumLine<'a> {
+    time_s: f64,
+    sample_rate: u32,
+    fft_size: usize,
+    bin_width_hz: f32,
+    magnitudes: &'a [f32],
+}
+
+fn decode_samples(bytes: &[u8], format: PcmFormat) -> impl Iterator<Item = f32> + '_ {
+    let sample_size = match format {
+        PcmFormat::F32le => 4,
+        PcmFormat::S16le => 2,
+    };
+    bytes.chunks_exact(sample_size).map(move |b| match format {
+        PcmFormat::F32le => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        PcmFormat::S16le => i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+    })
+}
+
+fn write_spectrum(
+    out: &mut impl Write,
+    args: &PipeArgs,
+    time_s: f64,
+    magnitudes: &[f32],
+) -> std::io::Result<()> {
+    match args.output_format {
+        SpectrumFormat::Json => {
+            let line = SpectrumLine {
+                time_s,
+                sample_rate: args.sample_rate,
+                fft_size: args.fft_size,
+                bin_width_hz: BinMapping::new(args.sample_rate, args.fft_size).bin_width_hz(),
+                magnitudes,
+            };
+            serde_json::to_writer(&mut *out, &line)?;
+            out.write_all(b"\n")?;
+        }
+        SpectrumFormat::Binary => {
+            out.write_all(&(magnitudes.len() as u32).to_le_bytes())?;
+            for magnitude in magnitudes {
+                out.write_all(&magnitude.to_le_bytes())?;
+            }
+        }
+    }
+    // Flushes every frame, the reader is probably waiting for it
+    out.flush()
+}
+
+/**
+ * Reads stdin until it ends, writing a spectrum every `hop` samples
+ */
+pub fn run(args: &PipeArgs) -> Result<(), String> {
+    let fft_size = args.fft_size;
+    let hop = args.hop.unwrap_or(fft_size).max(1);
+    let channels = args.channels.max(1) as usize;
+    let bytes_per_frame = channels
+        * match args.input_format {
+            PcmFormat::F32le => 4,
+            PcmFormat::S16le => 2,
+        };
+    let mapping = BinMapping::new(args.sample_rate, fft_size);
+    let output_bins = mapping.bins_up_to(args.max_freq.unwrap_or(f32::MAX));
+
+    let mut stdin = std::io::stdin().lock();
+    let mut stdout = BufWriter::new(std::io::stdout().lock());
+
+    let mut bytes = vec![0u8; 16384];
+    // Bytes that didn't make a whole multichannel frame in the last read
+    let mut leftover: Vec<u8> = vec![];
+    let mut samples: Vec<f32> = Vec::with_capacity(fft_size * 2);
+    // When the hop is bigger than the FFT size, some samples are skipped between frames
+    let mut samples_to_skip = 0;
+    let mut consumed_samples: u64 = 0;
+    let mut frames = 0u64;
+
+    loop {
+        let read = match stdin.read(&mut bytes) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(format!("Could not read stdin: {error}")),
+        };
+        leftover.extend_from_slice(&bytes[0..read]);
+
+        let whole = leftover.len() - leftover.len() % bytes_per_frame;
+        let decoded: Vec<f32> = decode_samples(&leftover[0..whole], args.input_format).collect();
+        leftover.drain(0..whole);
+
+        for frame in decoded.chunks_exact(channels) {
+            if samples_to_skip > 0 {
+                samples_to_skip -= 1;
+                consumed_samples += 1;
+                continue;
+            }
+            samples.push(frame.iter().sum::<f32>() / channels as f32);
+        }
+
+        while samples.len() >= fft_size {
+            let magnitudes = fft_magnitudes(&samples[0..fft_size]);
+            let time_s = consumed_samples as f64 / args.sample_rate as f64;
+
+            match write_spectrum(&mut stdout, args, time_s, &magnitudes[0..output_bins]) {
+                Ok(()) => {}
+                // The reader went away, which is a normal way for a pipeline to end
+                Err(error) if error.kind() == ErrorKind::BrokenPipe => return Ok(()),
+                Err(error) => return Err(format!("Could not write stdout: {error}")),
+            }
+            frames += 1;
+
+            let drained = hop.min(samples.len());
+            samples.drain(0..drained);
+            samples_to_skip = hop - drained;
+            consumed_samples += drained as u64;
+        }
+    }
+
+    eprintln!(
+        "Wrote {frames} spectra of {output_bins} bins ({:.2} Hz each)",
+        mapping.bin_width_hz()
+    );
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use crate::analysis::{amplitude_to_db, cents_between, BinMapping};
+
+// Harmonics looked at when weighing a fundamental
+const MAX_HARMONICS: usize = 10;
+// Quieter than this, the loudest bin is only noise
+const MIN_LEVEL_DB: f32 = -90.0;
+// A fundamental under this bin can't be told from the DC offset
+const MIN_BIN: f32 = 2.0;
+// Share of the energy of the harmonics of a candidate that has to sit between them for its
+// half or third to be the fundamental instead
+const SUBHARMONIC_SHARE: f32 = 0.05;
+// How close to a whole number of octaves a jump from the last pitch counts as an octave error
+const OCTAVE_TOLERANCE_CENTS: f32 = 50.0;
+// Frames an octave jump has to hold before it's believed
+const HOLD_FRAMES: usize = 3;
+// Under this confidence the pitch is more likely noise than a note, and its note isn't shown
+pub const MIN_CONFIDENCE: f32 = 0.4;
+
+/**
+ * A fundamental frequency, with how periodic the frame is at its period, how much its
+ * harmonics explain the spectrum and how steady it was, from 0 to 1
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PitchEstimate {
+    pub frequency_in_hz: f32,
+    pub level_db: f32,
+    pub confidence: f32,
+}
+
+impl PitchEstimate {
+    pub fn is_confident(&self) -> bool {
+        self.confidence >= MIN_CONFIDENCE
+    }
+}
+
+/**
+ * Autocorrelation of the frame at the period of a fundamental at `fundamental_bin`, over the
+ * one at lag 0. It's worked out from the power spectrum: every harmonic of the fundamental adds
+ * up at its period, while noise averages out towards 0.
+ */
+fn clarity(amplitudes: &[f32], fundamental_bin: f32) -> f32 {
+    let (correlation, energy) = amplitudes.iter().enumerate().skip(1).fold(
+        (0.0f32, 0.0f32),
+        |(correlation, energy), (bin, amplitude)| {
+            let power = amplitude * amplitude;
+            let phase = 2.0 * std::f32::consts::PI * bin as f32 / fundamental_bin;
+            (correlation + power * phase.cos(), energy + power)
+        },
+    );
+    (correlation / energy.max(f32::MIN_POSITIVE)).clamp(0.0, 1.0)
+}
+
+/**
+ * Energy in the bins around each harmonic of a fundamental at `fundamental_bin`, from one bin
+ * under to one bin over so a peak between two bins counts whole. The harmonics whose number is
+ * a multiple of `skipped_multiple` are left out, with 1 none are.
+ */
+fn harmonic_power(amplitudes: &[f32], fundamental_bin: f32, skipped_multiple: usize) -> f32 {
+    (1..=MAX_HARMONICS)
+        .filter(|number| skipped_multiple == 1 || number % skipped_multiple != 0)
+        .map(|number| (fundamental_bin * number as f32).round() as usize)
+        .take_while(|bin| bin + 1 < amplitudes.len())
+        .map(|bin| {
+            amplitudes[bin - 1..=bin + 1]
+                .iter()
+                .map(|a| a * a)
+                .sum::<f32>()
+        })
+        .sum()
+}
+
+/**
+ * Fundamental of a one-sided amplitude spectrum as a fractional bin, and the share of the
+ * energy up to its last harmonic that its harmonics hold. Starts from the loudest bin and goes
+ * down to its half or third as long as there is energy between its harmonics, which the
+ * loudest bin being a harmonic leaves.
+ */
+fn fundamental(amplitudes: &[f32]) -> Option<(f32, f32)> {
+    let (loudest_bin, loudest) = amplitudes
+        .iter()
+        .enumerate()
+        .skip(1)
+        .max_by(|a, b| a.1.total_cmp(b.1))?;
+    if amplitude_to_db(*loudest) < MIN_LEVEL_DB || loudest_bin + 1 >= amplitudes.len() {
+        return None;
+    }
+
+    // A parabola through the peak, the harmonics are far enough up for the error to matter
+    let (before, after) = (amplitudes[loudest_bin - 1], amplitudes[loudest_bin + 1]);
+    let curvature = before - 2.0 * loudest + after;
+    let shift = if curvature.abs() > f32::EPSILON {
+        (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+    let mut bin = loudest_bin as f32 + shift;
+
+    while let Some(lower) = [2, 3].into_iter().find_map(|divisor| {
+        let candidate = bin / divisor as f32;
+        (candidate >= MIN_BIN
+            && harmonic_power(amplitudes, candidate, divisor)
+                >= SUBHARMONIC_SHARE * harmonic_power(amplitudes, bin, 1))
+        .then_some(candidate)
+    }) {
+        bin = lower;
+    }
+
+    let top = ((bin * MAX_HARMONICS as f32).round() as usize + 2).min(amplitudes.len());
+    let total: f32 = amplitudes[1..top].iter().map(|a| a * a).sum();
+    let harmonicity = (harmonic_power(amplitudes, bin, 1) / total.max(f32::MIN_POSITIVE)).min(1.0);
+    Some((bin, harmonicity))
+}
+
+/**
+ * Follows the pitch of the spectra frame after frame, correcting the octave errors of the
+ * loudest bin: a harmonic louder than the fundamental is found from the energy between its
+ * own harmonics, and a single frame jumping by whole octaves is folded back until the jump
+ * held for HOLD_FRAMES
+ */
+#[derive(Default)]
+pub struct PitchTracker {
+    last_in_hz: Option<f32>,
+    // Frequency an octave jump went to, and for how many frames in a row
+    jump: Option<(f32, usize)>,
+}
+
+impl PitchTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * Takes the one-sided amplitudes of the next frame. Returns None while nothing louder than
+     * the noise plays, which also forgets the last pitch.
+     */
+    pub fn update(&mut self, amplitudes: &[f32], mapping: BinMapping) -> Option<PitchEstimate> {
+        let Some((bin, harmonicity)) = fundamental(amplitudes) else {
+            self.last_in_hz = None;
+            self.jump = None;
+            return None;
+        };
+        let mut frequency_in_hz = bin * mapping.bin_width_hz();
+        let mut confidence = harmonicity * clarity(amplitudes, bin);
+
+        if let Some(last_in_hz) = self.last_in_hz {
+            let octaves = (cents_between(last_in_hz, frequency_in_hz) / 1200.0).round();
+            let off_cents = cents_between(last_in_hz, frequency_in_hz) - octaves * 1200.0;
+            if octaves != 0.0 && off_cents.abs() < OCTAVE_TOLERANCE_CENTS {
+                let held = match self.jump {
+                    Some((to_hz, count))
+                        if cents_between(to_hz, frequency_in_hz).abs() < OCTAVE_TOLERANCE_CENTS =>
+                    {
+                        count + 1
+                    }
+                    _ => 1,
+                };
+                if held < HOLD_FRAMES {
+                    self.jump = Some((frequency_in_hz, held));
+                    frequency_in_hz /= 2f32.powf(octaves);
+                    confidence *= 0.5;
+                } else {
+                    self.jump = None;
+                }
+            } else {
+                self.jump = None;
+            }
+        }
+        self.last_in_hz = Some(frequency_in_hz);
+
+        let fundamental_bin = mapping.bin_at(frequency_in_hz).min(amplitudes.len() - 1);
+        Some(PitchEstimate {
+            frequency_in_hz,
+            level_db: amplitude_to_db(amplitudes[fundamental_bin]),
+            confidence,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::windowing::WindowFunction;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    // Amplitudes of a Hann frame holding the given harmonics of `fundamental_in_hz`
+    fn spectrum(fundamental_in_hz: f32, harmonic_amplitudes: &[f32]) -> Vec<f32> {
+        let samples: Vec<f32> = (0..4096)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                harmonic_amplitudes
+                    .iter()
+                    .enumerate()
+                    .map(|(h, amplitude)| {
+                        let frequency_in_hz = fundamental_in_hz * (h + 1) as f32;
+                        amplitude * (2.0 * std::f32::consts::PI * frequency_in_hz * t).sin()
+                    })
+                    .sum::<f32>()
+            })
+            .collect();
+        WindowFunction::Hann.amplitudes(&samples)
+    }
+
+    #[test]
+    fn a_louder_harmonic_is_not_the_pitch() {
+        let mapping = BinMapping::new(SAMPLE_RATE, 4096);
+        // The second harmonic 10 dB over the fundamental, the third one in between
+        let amplitudes = spectrum(196.0, &[0.05, 0.16, 0.1, 0.03]);
+        let pitch = PitchTracker::new().update(&amplitudes, mapping).unwrap();
+        assert!(
+            (pitch.frequency_in_hz - 196.0).abs() < 1.0,
+            "{}",
+            pitch.frequency_in_hz
+        );
+        assert!(pitch.confidence > 0.9, "{}", pitch.confidence);
+
+        // A pure tone stays where it is
+        let pitch = PitchTracker::new()
+            .update(&spectrum(700.0, &[0.5]), mapping)
+            .unwrap();
+        assert!((pitch.frequency_in_hz - 700.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn an_octave_jump_has_to_hold() {
+        let mapping = BinMapping::new(SAMPLE_RATE, 4096);
+        let low = spectrum(220.0, &[0.5]);
+        let high = spectrum(440.0, &[0.5]);
+        let mut tracker = PitchTracker::new();
+        tracker.update(&low, mapping);
+
+        let folded = tracker.update(&high, mapping).unwrap();
+        assert!((folded.frequency_in_hz - 220.0).abs() < 1.0);
+        assert!(folded.confidence < 0.6);
+        // A single frame up there is forgotten
+        tracker.update(&low, mapping);
+        for _ in 1..HOLD_FRAMES {
+            tracker.update(&high, mapping);
+        }
+        let jumped = tracker.update(&high, mapping).unwrap();
+        assert!((jumped.frequency_in_hz - 440.0).abs() < 1.0);
+        assert!(jumped.confidence > 0.9);
+    }
+
+    #[test]
+    fn noise_has_no_confident_pitch() {
+        let mut seed = 3u32;
+        let noise: Vec<f32> = (0..4096)
+            .map(|_| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                seed as f32 / u32::MAX as f32 - 0.5
+            })
+            .collect();
+        let pitch = PitchTracker::new()
+            .update(
+                &WindowFunction::Hann.amplitudes(&noise),
+                BinMapping::new(SAMPLE_RATE, 4096),
+            )
+            .unwrap();
+        assert!(!pitch.is_confident(), "{pitch:?}");
+    }
+}
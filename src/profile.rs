@@ -0,0 +1,78 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Clone)]
+struct StageStats {
+    name: &'static str,
+    calls: u64,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+/**
+ * Collects how long each stage of the pipeline takes, from both the audio and the render
+ * threads. Stages are reported in the order they were first seen.
+ */
+#[derive(Default)]
+pub struct Profiler {
+    stages: Mutex<Vec<StageStats>>,
+}
+
+impl Profiler {
+    pub fn record(&self, name: &'static str, elapsed: Duration) {
+        let mut stages = self.stages.lock().unwrap();
+        match stages.iter_mut().find(|stage| stage.name == name) {
+            Some(stage) => {
+                stage.calls += 1;
+                stage.total += elapsed;
+                stage.min = stage.min.min(elapsed);
+                stage.max = stage.max.max(elapsed);
+            }
+            None => stages.push(StageStats {
+                name,
+                calls: 1,
+                total: elapsed,
+                min: elapsed,
+                max: elapsed,
+            }),
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let stages = self.stages.lock().unwrap().clone();
+        let mut report = format!(
+            "{:<14} {:>8} {:>11} {:>11} {:>11} {:>11}\n",
+            "stage", "calls", "mean (µs)", "min (µs)", "max (µs)", "total (ms)"
+        );
+        for stage in stages {
+            report += &format!(
+                "{:<14} {:>8} {:>11.1} {:>11.1} {:>11.1} {:>11.1}\n",
+                stage.name,
+                stage.calls,
+                stage.total.as_secs_f64() * 1e6 / stage.calls as f64,
+                stage.min.as_secs_f64() * 1e6,
+                stage.max.as_secs_f64() * 1e6,
+                stage.total.as_secs_f64() * 1e3,
+            );
+        }
+        report
+    }
+}
+
+/**
+ * Runs `f`, recording how long it took under `name` when profiling is enabled
+ */
+pub fn time<T>(profiler: &Option<Arc<Profiler>>, name: &'static str, f: impl FnOnce() -> T) -> T {
+    match profiler {
+        Some(profiler) => {
+            let start = Instant::now();
+            let result = f();
+            profiler.record(name, start.elapsed());
+            result
+        }
+        None => f(),
+    }
+}
@@ -0,0 +1,346 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::{
+    analysis::{two_sided_magnitudes, FftFrame},
+    marker::Marker,
+};
+
+/*
+ * A recording is a gzip stream starting with MAGIC and the sample rate (u32), followed by
+ * records made of a kind (u8), the milliseconds since the start (u64) and a length (u32):
+ * - SPECTRUM records hold the level of each bin of the one-sided spectrum. Levels are stored as
+ *   u16 hundredths of a dB above LEVEL_FLOOR_DB, which is way below what the FFT can resolve
+ *   and halves the size of floats.
+ * - MARKER records hold the label of a marker in UTF-8
+ * Everything is little endian. Recordings of the first version (MAGIC_V1) have no markers, and
+ * no kind in their records.
+ */
+
+const MAGIC: &[u8; 8] = b"FFTREC2\n";
+const MAGIC_V1: &[u8; 8] = b"FFTREC1\n";
+const SPECTRUM: u8 = 0;
+const MARKER: u8 = 1;
+const LEVEL_FLOOR_DB: f32 = -200.0;
+
+fn quantize(amplitude: f32) -> u16 {
+    let level_db = 20.0 * amplitude.max(1e-12).log10();
+    ((level_db - LEVEL_FLOOR_DB) * 100.0)
+        .round()
+        .clamp(0.0, u16::MAX as f32) as u16
+}
+
+fn dequantize(level: u16) -> f32 {
+    10f32.powf((level as f32 / 100.0 + LEVEL_FLOOR_DB) / 20.0)
+}
+
+/**
+ * Writes every new spectrum to a file from its own thread, until stopped
+ */
+pub struct Recorder {
+    path: PathBuf,
+    start: Instant,
+    // Markers waiting to be written by the thread, with their time in the recording
+    markers: Arc<Mutex<Vec<(u64, String)>>>,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<std::io::Result<usize>>,
+}
+
+impl Recorder {
+    pub fn start(
+        directory: &Path,
+        fft_transform: Arc<Mutex<FftFrame>>,
+        sample_rate: u32,
+    ) -> std::io::Result<Self> {
+        std::fs::create_dir_all(directory)?;
+        let started = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        let path = directory.join(format!("recording-{}.fftrec", started.replace(':', "-")));
+        let mut file = GzEncoder::new(BufWriter::new(File::create(&path)?), Compression::fast());
+        file.write_all(MAGIC)?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+
+        let start = Instant::now();
+        let markers: Arc<Mutex<Vec<(u64, String)>>> = Arc::default();
+        let thread_markers = markers.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let mut last_generation = fft_transform.lock().unwrap().generation;
+            let mut frames = 0;
+            let write_markers = |file: &mut GzEncoder<BufWriter<File>>| -> std::io::Result<()> {
+                for (time_ms, label) in thread_markers.lock().unwrap().drain(..) {
+                    file.write_all(&[MARKER])?;
+                    file.write_all(&time_ms.to_le_bytes())?;
+                    file.write_all(&(label.len() as u32).to_le_bytes())?;
+                    file.write_all(label.as_bytes())?;
+                }
+                Ok(())
+            };
+            while !thread_stop.load(Ordering::Relaxed) {
+                // Polled often enough for the smallest FFT sizes, which take ~20 ms of audio
+                std::thread::sleep(Duration::from_millis(5));
+                let frame = fft_transform.lock().unwrap().clone();
+                if frame.generation == last_generation {
+                    continue;
+                }
+                last_generation = frame.generation;
+
+                write_markers(&mut file)?;
+                file.write_all(&[SPECTRUM])?;
+                file.write_all(&(start.elapsed().as_millis() as u64).to_le_bytes())?;
+                file.write_all(&(frame.amplitudes.len() as u32).to_le_bytes())?;
+                for amplitude in frame.amplitudes.iter() {
+                    file.write_all(&quantize(*amplitude).to_le_bytes())?;
+                }
+                frames += 1;
+            }
+            write_markers(&mut file)?;
+            file.finish()?.flush()?;
+            Ok(frames)
+        });
+
+        Ok(Self {
+            path,
+            start,
+            markers,
+            stop,
+            thread,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn add_marker(&self, marker: &Marker) {
+        let time_ms = marker
+            .instant
+            .saturating_duration_since(self.start)
+            .as_millis() as u64;
+        self.markers
+            .lock()
+            .unwrap()
+            .push((time_ms, marker.label.clone()));
+    }
+
+    /**
+     * Stops the recording and returns the number of spectra written
+     */
+    pub fn stop(self) -> std::io::Result<usize> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.join().unwrap()
+    }
+}
+
+struct RecordedFrame {
+    time_ms: u64,
+    levels: Vec<u16>,
+}
+
+/**
+ * A recording loaded in memory, still quantized, so seeking anywhere is instant
+ */
+pub struct Recording {
+    pub sample_rate: u32,
+    frames: Vec<RecordedFrame>,
+    // Time in milliseconds and label of each marker
+    markers: Vec<(u64, String)>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let error = |error: std::io::Error| format!("Could not read {}: {error}", path.display());
+        let mut file = GzDecoder::new(BufReader::new(File::open(path).map_err(error)?));
+
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic).map_err(error)?;
+        let has_kinds = match &magic {
+            MAGIC => true,
+            MAGIC_V1 => false,
+            _ => return Err(format!("{} is not a spectrum recording", path.display())),
+        };
+        let sample_rate = u32::from_le_bytes(read_array(&mut file).map_err(error)?);
+
+        let mut frames = vec![];
+        let mut markers = vec![];
+        // A recording cut short (e.g. the program was killed) still replays up to the cut
+        loop {
+            let kind = match has_kinds {
+                true => match read_array::<1>(&mut file) {
+                    Ok([kind]) => kind,
+                    Err(_) => break,
+                },
+                false => SPECTRUM,
+            };
+            let (Ok(time), Ok(len)) = (read_array(&mut file), read_array(&mut file)) else {
+                break;
+            };
+            let len = u32::from_le_bytes(len) as usize;
+            let mut bytes = vec![0; if kind == SPECTRUM { len * 2 } else { len }];
+            if file.read_exact(&mut bytes).is_err() {
+                break;
+            }
+            if kind == MARKER {
+                markers.push((
+                    u64::from_le_bytes(time),
+                    String::from_utf8_lossy(&bytes).into_owned(),
+                ));
+                continue;
+            }
+            frames.push(RecordedFrame {
+                time_ms: u64::from_le_bytes(time),
+                levels: bytes
+                    .chunks_exact(2)
+                    .map(|level| u16::from_le_bytes([level[0], level[1]]))
+                    .collect(),
+            });
+        }
+
+        if frames.is_empty() {
+            return Err(format!("{} has no spectra", path.display()));
+        }
+        Ok(Self {
+            sample_rate,
+            frames,
+            markers,
+        })
+    }
+
+    fn duration_ms(&self) -> u64 {
+        self.frames.last().map_or(0, |frame| frame.time_ms)
+    }
+
+    /**
+     * Index of the frame on screen at `time_ms`
+     */
+    fn frame_at(&self, time_ms: u64) -> usize {
+        self.frames
+            .partition_point(|frame| frame.time_ms <= time_ms)
+            .saturating_sub(1)
+    }
+}
+
+fn read_array<const N: usize>(file: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/**
+ * Lets the window scrub through a replay and change its speed
+ */
+#[derive(Default)]
+pub struct ReplayHandle {
+    // Pending jump, in milliseconds of the recording
+    seek_ms: Mutex<i64>,
+    speed: Mutex<f32>,
+}
+
+impl ReplayHandle {
+    pub fn seek(&self, offset_ms: i64) {
+        *self.seek_ms.lock().unwrap() += offset_ms;
+    }
+
+    /**
+     * Multiplies the speed by `factor`, between 1/8 and 8 times the original one
+     */
+    pub fn change_speed(&self, factor: f32) {
+        let mut speed = self.speed.lock().unwrap();
+        *speed = (*speed * factor).clamp(0.125, 8.0);
+    }
+}
+
+fn format_time(time_ms: u64) -> String {
+    format!(
+        "{:02}:{:04.1}",
+        time_ms / 60000,
+        (time_ms % 60000) as f32 / 1000.0
+    )
+}
+
+/**
+ * Spawns a thread that publishes the spectra of the recording as if they came from the audio
+ * callback, with their original timing scaled by the speed. The clock stops while the graph is
+ * paused, and the last spectrum stays on screen when the recording ends.
+ */
+pub fn replay(
+    recording: Recording,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    paused: Arc<Mutex<bool>>,
+) -> Arc<ReplayHandle> {
+    let handle = Arc::new(ReplayHandle::default());
+    *handle.speed.lock().unwrap() = 1.0;
+    let thread_handle = handle.clone();
+
+    std::thread::spawn(move || {
+        let duration_ms = recording.duration_ms();
+        let mut position_ms = 0.0;
+        let mut shown: Option<usize> = None;
+        let mut last_tick = Instant::now();
+        let mut last_speed = 1.0;
+
+        loop {
+            std::thread::sleep(Duration::from_millis(5));
+            let speed = *thread_handle.speed.lock().unwrap();
+            let previous_ms = position_ms;
+            if !*paused.lock().unwrap() {
+                position_ms += last_tick.elapsed().as_secs_f64() * 1000.0 * speed as f64;
+            }
+            last_tick = Instant::now();
+
+            let seek_ms = std::mem::take(&mut *thread_handle.seek_ms.lock().unwrap());
+            position_ms = (position_ms + seek_ms as f64).clamp(0.0, duration_ms as f64);
+            // Markers are announced as the replay plays through them, not when jumping over
+            if seek_ms == 0 {
+                for (time_ms, label) in &recording.markers {
+                    if previous_ms < *time_ms as f64 && *time_ms as f64 <= position_ms {
+                        println!("\nMarker \"{label}\" at {}", format_time(*time_ms));
+                    }
+                }
+            }
+            if seek_ms != 0 || speed != last_speed {
+                last_speed = speed;
+                print!(
+                    "\nReplaying at {} / {}, {speed}x",
+                    format_time(position_ms as u64),
+                    format_time(duration_ms)
+                );
+            }
+
+            let index = recording.frame_at(position_ms as u64);
+            if shown == Some(index) {
+                continue;
+            }
+            if index + 1 == recording.frames.len() {
+                println!("\nEnd of the recording");
+            }
+            shown = Some(index);
+
+            let amplitudes: Vec<f32> = recording.frames[index]
+                .levels
+                .iter()
+                .map(|level| dequantize(*level))
+                .collect();
+            let mut result = fft_transform.lock().unwrap();
+            result.magnitudes = Arc::new(two_sided_magnitudes(&amplitudes));
+            result.amplitudes = Arc::new(amplitudes);
+            result.samples = Arc::new(vec![]);
+            // The time in the recording stands for the capture time, the original one is lost
+            result.captured_at = Duration::from_millis(recording.frames[index].time_ms);
+            result.generation += 1;
+        }
+    });
+
+    handle
+}
@@ -0,0 +1,1124 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use sdl2::{
+    pixels::Color,
+    rect::{Point, Rect},
+};
+
+use crate::{
+    analysis::{self, BinMapping, FftFrame, FrequencyScale},
+    curve,
+    image::amplitude_color,
+    keys, text, units, waterfall, NoteStatus,
+};
+
+/*
+ * I designed the code this way because creating a Graph
+ * gives you the freedom of having as many graphs with as many implementations of the data
+ * underneath it as you want, then you can just copy and paste the bar rendering loop and
+ * change it to the second graph.
+ *
+ * Tho, don't forget to create separate a "data_locker" for each one of the graphs or they will
+ * literally just output the same result, since the underlying data will be the same
+ */
+pub struct Graph {
+    pub width: u32,
+    pub height: u32,
+    pub buffer_size: usize,
+    pub max_displayed_frequency: usize,
+    // One-sided amplitude spectrum, only the bins from DC to the Nyquist frequency
+    pub data_buffer: Arc<Vec<f32>>,
+    // Length of the FFT that produced data_buffer
+    pub fft_len: usize,
+    // Magnitudes of the full FFT output behind data_buffer
+    pub magnitudes_buffer: Arc<Vec<f32>>,
+    pub samples_buffer: Arc<Vec<f32>>,
+    // Generation of the FftFrame currently in data_buffer
+    pub data_generation: u64,
+    // When the first sample of that frame was captured, on the capture clock
+    pub captured_at: Duration,
+    pub data_locker: Arc<Mutex<FftFrame>>,
+    pub paused: Arc<Mutex<bool>>,
+    pub mouse_x: Arc<Mutex<i32>>,
+    // The note of each bin only depends on the FFT length and the sample rate, so it's
+    // computed once for them instead of on every frame
+    pub note_statuses: Vec<NoteStatus>,
+    pub note_statuses_sample_rate: u32,
+    // Heights the bars should reach (from the last run) and the heights currently drawn
+    pub target_heights: Vec<f32>,
+    pub displayed_heights: Vec<f32>,
+    // One-sided amplitude spectrum the current one is compared to
+    pub reference: Option<Vec<f32>>,
+    // Draws the difference to the reference in dB instead of the spectrum
+    pub delta_view: bool,
+    // Amplitude drawn at the full height of the graph on the last run
+    pub amplitude_scale: f32,
+    // Levels at the top and the bottom of the graph, None scales it to the loudest bin
+    pub db_range: Option<analysis::DbRange>,
+    // Amplitude used instead of the loudest bin of each frame when the scale is locked
+    pub locked_amplitude: Option<f32>,
+    // Curve the spectrum of pink noise is compared to, when the target is shown
+    pub target: Option<curve::Curve>,
+    // Amplitudes of the target fitted to the spectrum on the last run
+    pub target_amplitudes: Vec<f32>,
+    // Width of each bar and number of bars on the last run
+    pub bar_width: f64,
+    pub bars_len: usize,
+    // Bins from DC shown on the last run
+    pub bins_len: usize,
+    // Groups the bins into bars on a logarithmic frequency axis instead of one bar per bin
+    pub log_frequency: bool,
+    // Bins behind each bar of the logarithmic axis on the last run, empty on the linear one
+    pub bar_groups: Vec<analysis::BinGroup>,
+}
+
+pub struct GraphBar {
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+    pub frequency_data: FrequencyData,
+}
+
+#[derive(Clone)]
+pub struct FrequencyData {
+    pub note_status: NoteStatus,
+    pub amplitude_percentage: u8,
+    // Level of the sine in the bin, in dBFS
+    pub level_db: f32,
+    // Level above (or below) the same bin of the reference, when there is one
+    pub delta_db: Option<f32>,
+    // Level of the smoothed spectrum above (or below) the target, when it's shown
+    pub deviation_db: Option<f32>,
+    pub analyzing_bin_index: usize,
+}
+
+impl FrequencyData {
+    /**
+     * One line describing the bin, meant to be pasted into notes or bug reports
+     */
+    pub fn readout(
+        &self,
+        unit: units::FrequencyUnit,
+        amplitude_scale: units::AmplitudeScale,
+    ) -> String {
+        format!(
+            "{}, note {}{}, {:+} cents, amplitude {}% ({}), at {}",
+            unit.format(self.note_status.get_frequency_in_hz()),
+            NoteStatus::note_number_to_name(self.note_status.note_number).trim(),
+            NoteStatus::get_octave_by_key_number(self.note_status.key_number),
+            self.note_status.error_percentage,
+            self.amplitude_percentage,
+            amplitude_scale.format(self.level_db),
+            humantime::format_rfc3339_seconds(std::time::SystemTime::now()),
+        )
+    }
+}
+
+impl Graph {
+    pub fn get_buffer_len(&self) -> usize {
+        self.fft_len
+    }
+
+    /**
+     * Returns the samples that produced the spectrum currently being displayed
+     */
+    pub fn get_samples(&self) -> &[f32] {
+        &self.samples_buffer
+    }
+    /**
+     * Returns the one-sided amplitude spectrum currently being displayed
+     */
+    pub fn get_amplitudes(&self) -> &[f32] {
+        &self.data_buffer
+    }
+
+    /**
+     * Returns the magnitudes of the full FFT output currently being displayed
+     */
+    pub fn get_magnitudes(&self) -> &[f32] {
+        &self.magnitudes_buffer
+    }
+
+    /**
+     * Bins of the spectrum currently being displayed
+     */
+    fn bin_mapping(&self, stream_sample_rate: u32) -> BinMapping {
+        BinMapping::new(stream_sample_rate, self.fft_len)
+    }
+
+    /**
+     * Rebuilds the bin to note lookup table when the FFT length or the sample rate changed
+     */
+    fn update_note_statuses(&mut self, stream_sample_rate: u32) {
+        let mapping = self.bin_mapping(stream_sample_rate);
+        let bins = self.data_buffer.len();
+        if self.note_statuses.len() == bins && self.note_statuses_sample_rate == stream_sample_rate
+        {
+            return;
+        }
+
+        self.note_statuses = (0..bins)
+            .map(|i| NoteStatus::new(mapping.frequency_in_hz(i)))
+            .collect();
+        self.note_statuses_sample_rate = stream_sample_rate;
+    }
+
+    /**
+     * Takes the latest FFT result unless the graph is paused.
+     * Returns true when there is something new to draw.
+     */
+    pub fn refresh_data(&mut self) -> bool {
+        if *self.paused.lock().unwrap() {
+            return false;
+        }
+
+        // Only the pointers are copied, and only when there is a new FFT result
+        let frame = {
+            let locker = self.data_locker.lock().unwrap();
+            if locker.generation == self.data_generation {
+                return false;
+            }
+            locker.clone()
+        };
+        self.show_frame(&frame);
+        true
+    }
+
+    /**
+     * Displays the given FFT result, even when paused
+     */
+    pub fn show_frame(&mut self, frame: &FftFrame) {
+        self.data_buffer = frame.amplitudes.clone();
+        self.fft_len = frame.magnitudes.len();
+        self.magnitudes_buffer = frame.magnitudes.clone();
+        self.samples_buffer = frame.samples.clone();
+        self.data_generation = frame.generation;
+        self.captured_at = frame.captured_at;
+    }
+
+    /**
+     * Moves the drawn height of each bar towards the height given by the last run.
+     * New FFT results arrive at the pace of the audio, which for big FFTs is way slower than
+     * the screen refresh, so the heights glide instead of jumping once per result.
+     * `smoothing` is the time it takes to cover ~63% of the way, zero disables it.
+     * Returns true while the bars are still moving.
+     */
+    pub fn animate(
+        &mut self,
+        bars: &mut [GraphBar],
+        elapsed: Duration,
+        smoothing: Duration,
+    ) -> bool {
+        // Delta bars grow from the middle of the graph, in both directions, so they aren't
+        // animated
+        if self.displayed_heights.len() != self.target_heights.len()
+            || smoothing.is_zero()
+            || self.delta_view
+        {
+            self.displayed_heights = self.target_heights.clone();
+        }
+
+        let step = 1.0 - (-elapsed.as_secs_f32() / smoothing.as_secs_f32().max(f32::EPSILON)).exp();
+        let mut moving = false;
+
+        for ((bar, displayed), target) in bars
+            .iter_mut()
+            .zip(self.displayed_heights.iter_mut())
+            .zip(&self.target_heights)
+        {
+            *displayed += (target - *displayed) * step;
+            if (target - *displayed).abs() < 0.5 {
+                *displayed = *target;
+            } else {
+                moving = true;
+            }
+
+            // The bottom of the bar stays where it is, only the top moves
+            let bottom = bar.y + bar.height as i32;
+            bar.height = displayed.round() as u32;
+            bar.y = bottom - bar.height as i32;
+        }
+
+        moving
+    }
+
+    pub fn run(&mut self, stream_sample_rate: u32) -> (Vec<GraphBar>, Option<usize>) {
+        self.update_note_statuses(stream_sample_rate);
+
+        // Gets the min number of bins required to be able to display
+        // the max desired frequency in Hz. It never goes past the Nyquist frequency, even when
+        // the max frequency is higher, since data_buffer only has the one-sided spectrum
+        let max_bins_displayed_len = self
+            .bin_mapping(stream_sample_rate)
+            .bins_up_to(self.max_displayed_frequency as f32)
+            .min(self.data_buffer.len());
+        self.bins_len = max_bins_displayed_len;
+        let padding_top = 10;
+        let ground_y = 30;
+
+        // Since the buffer_size may become large, it may take a few seconds or ms to start getting
+        // data and because of that it's good to prevent some errors that might rase like
+        // "deviding by zero"
+        if self.fft_len < self.buffer_size {
+            self.bars_len = 0;
+            self.bar_groups.clear();
+            return (vec![], None);
+        }
+
+        // On the logarithmic axis every bar stands for the loudest bin of the same musical
+        // interval, the way the LEDs of a WLED strip do, so the number of bars only depends on
+        // the width
+        self.bar_groups = if self.log_frequency {
+            let (min_hz, top_hz) = self.log_axis_hz(stream_sample_rate);
+            analysis::group_bins(
+                &self.data_buffer,
+                self.bin_mapping(stream_sample_rate),
+                (self.width / LOG_BAR_WIDTH).max(1) as usize,
+                min_hz,
+                top_hz,
+                FrequencyScale::Logarithmic,
+            )
+        } else {
+            vec![]
+        };
+        let bar_bins: Vec<usize> = if self.log_frequency {
+            self.bar_groups
+                .iter()
+                .map(|group| group.loudest_bin)
+                .collect()
+        } else {
+            (0..max_bins_displayed_len).collect()
+        };
+
+        // Gets some graph dimensions.
+        // The width is kept fractional, otherwise the truncated part of every bar adds up to an
+        // empty stripe on the right and the mouse ends up pointing at the wrong bin
+        self.bar_width = self.width as f64 / bar_bins.len().max(1) as f64;
+        self.bars_len = bar_bins.len();
+        let highest_amplitude_bin = self
+            .data_buffer
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .unwrap();
+
+        // Once locked, the bars and the percentages keep the same reference from frame to frame
+        let highest_amplitude = self.locked_amplitude.unwrap_or(*highest_amplitude_bin.1);
+        self.amplitude_scale = highest_amplitude * 1.1;
+        let graph_height = (self.height - ground_y - padding_top) as f32;
+        // A reference taken with another FFT size can't be compared bin by bin
+        let reference = self
+            .reference
+            .as_ref()
+            .filter(|reference| reference.len() == self.data_buffer.len());
+        let deviations_db = match &self.target {
+            Some(target) => {
+                let (target_db, deviations_db) = curve::fit_pink_target(
+                    target,
+                    &self.data_buffer,
+                    self.bin_mapping(stream_sample_rate),
+                );
+                self.target_amplitudes = target_db
+                    .into_iter()
+                    .map(analysis::db_to_amplitude)
+                    .collect();
+                Some(deviations_db)
+            }
+            None => {
+                self.target_amplitudes.clear();
+                None
+            }
+        };
+        let build_bar = |(i, bin): (usize, &usize)| {
+            let bin = *bin;
+            let data = &self.data_buffer[bin];
+            let delta_db = reference.map(|reference| {
+                analysis::amplitude_to_db(*data) - analysis::amplitude_to_db(reference[bin])
+            });
+            let (y, frequency_bar_height) = match delta_db.filter(|_| self.delta_view) {
+                Some(delta_db) => {
+                    let middle = padding_top as f32 + graph_height / 2.0;
+                    let height = delta_db.clamp(-DELTA_RANGE_DB, DELTA_RANGE_DB) / DELTA_RANGE_DB
+                        * graph_height
+                        / 2.0;
+                    if height >= 0.0 {
+                        ((middle - height) as i32, height as u32)
+                    } else {
+                        (middle as i32, -height as u32)
+                    }
+                }
+                None => {
+                    let height = self.height_of(*data, graph_height) as u32;
+                    ((self.height - ground_y - height) as i32, height)
+                }
+            };
+            // Each bar goes from its own rounded edge until the next one, so they tile the
+            // window without gaps. When there are more bins than pixels, bars overlap instead
+            // of disappearing
+            let x = (i as f64 * self.bar_width).floor() as i32;
+            let next_x = ((i + 1) as f64 * self.bar_width).floor() as i32;
+            GraphBar {
+                x,
+                y,
+                width: (next_x - x).max(1) as u32,
+                height: frequency_bar_height,
+                frequency_data: FrequencyData {
+                    note_status: self.note_statuses[bin].clone(),
+                    analyzing_bin_index: bin,
+                    amplitude_percentage: ((data / highest_amplitude) * 100.0).round() as u8,
+                    level_db: analysis::amplitude_to_db(*data),
+                    delta_db,
+                    deviation_db: deviations_db
+                        .as_ref()
+                        .map(|deviations_db| deviations_db[bin]),
+                },
+            }
+        };
+
+        // With tens of thousands of bins, building the bars one by one dominates the frame time
+        #[cfg(feature = "rayon")]
+        let bars: Vec<GraphBar> = {
+            use rayon::prelude::*;
+            bar_bins.par_iter().enumerate().map(build_bar).collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let bars: Vec<GraphBar> = bar_bins.iter().enumerate().map(build_bar).collect();
+
+        self.target_heights = bars.iter().map(|bar| bar.height as f32).collect();
+
+        let mouse_x = {
+            let mouse_x = self.mouse_x.lock().unwrap();
+            *mouse_x
+        };
+
+        (bars, self.bar_at_x(mouse_x))
+    }
+
+    /**
+     * Returns the hovered bin followed by its multiples, as they are in the spectrum
+     */
+    pub fn bin_multiples(&self, bin: usize, stream_sample_rate: u32) -> Vec<analysis::Harmonic> {
+        analysis::bin_multiples(
+            &self.data_buffer,
+            self.bin_mapping(stream_sample_rate),
+            bin,
+            HOVER_MULTIPLES,
+        )
+    }
+
+    /**
+     * Returns the harmonics of the fundamental in the spectrum currently being displayed
+     */
+    pub fn harmonics(
+        &self,
+        fundamental_bin: Option<usize>,
+        count: usize,
+        stream_sample_rate: u32,
+    ) -> Vec<analysis::Harmonic> {
+        match fundamental_bin {
+            Some(bin) => analysis::harmonics(
+                &self.data_buffer,
+                self.bin_mapping(stream_sample_rate),
+                bin,
+                count,
+            ),
+            None => vec![],
+        }
+    }
+
+    /**
+     * Returns the index of the bar under the x coordinate of the window, as laid out by the
+     * last run
+     */
+    pub fn bar_at_x(&self, x: i32) -> Option<usize> {
+        if x < 0 || x >= self.width as i32 || self.bars_len == 0 || self.bar_width <= 0.0 {
+            return None;
+        }
+        Some(((x as f64 / self.bar_width) as usize).min(self.bars_len - 1))
+    }
+
+    /**
+     * Returns the bin shown by the bar under the x coordinate of the window: the bin of the
+     * same index on the linear axis, the loudest one of the group on the logarithmic one
+     */
+    pub fn bin_at_x(&self, x: i32) -> Option<usize> {
+        self.bar_at_x(x).map(|bar| match self.bar_groups.get(bar) {
+            Some(group) => group.loudest_bin,
+            None => bar,
+        })
+    }
+
+    /**
+     * Returns the index of the bar that shows a bin, as laid out by the last run, or None when
+     * the bin isn't on the graph
+     */
+    fn bar_of_bin(&self, bin: usize) -> Option<usize> {
+        match self.log_frequency {
+            true => self
+                .bar_groups
+                .iter()
+                .position(|group| group.bins.contains(&bin)),
+            false => (bin < self.bars_len).then_some(bin),
+        }
+    }
+
+    /**
+     * Returns the bar that shows a bin among the bars of the last run
+     */
+    pub fn bar_showing<'b>(&self, bars: &'b [GraphBar], bin: usize) -> Option<&'b GraphBar> {
+        self.bar_of_bin(bin).and_then(|bar| bars.get(bar))
+    }
+
+    /**
+     * Returns the first and the last bar of the part of a band that is on the graph, as laid
+     * out by the last run
+     */
+    pub fn bars_of_band(
+        &self,
+        band: &analysis::Band,
+        stream_sample_rate: u32,
+    ) -> Option<(usize, usize)> {
+        let mapping = self.bin_mapping(stream_sample_rate);
+        let low = mapping.bin_at(band.low_hz);
+        let high = mapping.bin_at(band.high_hz).max(low + 1);
+        let (first, end) = match self.log_frequency {
+            true => (
+                self.bar_groups
+                    .partition_point(|group| group.bins.end <= low),
+                self.bar_groups
+                    .partition_point(|group| group.bins.start < high),
+            ),
+            false => (low, high.min(self.bars_len)),
+        };
+        (first < end).then(|| (first, end - 1))
+    }
+
+    /**
+     * Lowest and highest frequency of the logarithmic axis, as laid out by the last run: as
+     * many decades as LOG_AXIS_RANGE spans, or from the first bin above DC when the bins are
+     * wider
+     */
+    fn log_axis_hz(&self, stream_sample_rate: u32) -> (f32, f32) {
+        let bin_width_hz = self.bin_mapping(stream_sample_rate).bin_width_hz();
+        let top_hz = self.bins_len as f32 * bin_width_hz;
+        ((top_hz / LOG_AXIS_RANGE).max(bin_width_hz), top_hz)
+    }
+
+    /**
+     * Returns the x coordinate of the middle of a frequency, as laid out by the last run, or
+     * None when it's out of the graph
+     */
+    pub fn x_at_frequency(&self, frequency_in_hz: f32, stream_sample_rate: u32) -> Option<i32> {
+        if self.bars_len == 0 {
+            return None;
+        }
+        let x = match self.log_frequency {
+            true => {
+                let (min_hz, top_hz) = self.log_axis_hz(stream_sample_rate);
+                if frequency_in_hz < min_hz || top_hz <= min_hz {
+                    return None;
+                }
+                self.width as f32 * (frequency_in_hz / min_hz).ln() / (top_hz / min_hz).ln()
+            }
+            false => {
+                let bin = frequency_in_hz / self.bin_mapping(stream_sample_rate).bin_width_hz();
+                if bin >= self.bars_len as f32 {
+                    return None;
+                }
+                ((bin as f64 + 0.5) * self.bar_width) as f32
+            }
+        } as i32;
+        (x < self.width as i32).then_some(x)
+    }
+
+    /**
+     * The ticks of frequency_ticks that fit on the graph as laid out by the last run, with their
+     * x coordinate. On the logarithmic axis the ones crowding the previous label are left out.
+     */
+    pub fn frequency_axis(
+        &self,
+        stream_sample_rate: u32,
+        unit: units::FrequencyUnit,
+    ) -> Vec<(i32, String)> {
+        let top_hz = self.bins_len as f32 * self.bin_mapping(stream_sample_rate).bin_width_hz();
+        let mut ticks: Vec<(i32, String)> = vec![];
+        for (frequency_in_hz, label) in
+            frequency_ticks(top_hz, self.width, self.log_frequency, unit)
+        {
+            let Some(x) = self.x_at_frequency(frequency_in_hz, stream_sample_rate) else {
+                continue;
+            };
+            let crowded = ticks.last().is_some_and(|(last_x, last_label)| {
+                x - last_x
+                    < (text::text_width(last_label, 1) + text::text_width(&label, 1)) as i32 / 2 + 8
+            });
+            if !crowded {
+                ticks.push((x, label));
+            }
+        }
+        ticks
+    }
+
+    /**
+     * Zooms so that a frequency is in the middle of the graph, which starts at 0 Hz, or as far
+     * right as the Nyquist frequency allows
+     */
+    pub fn go_to(&mut self, frequency_in_hz: f32, stream_sample_rate: u32) -> Result<(), String> {
+        let nyquist = stream_sample_rate as f32 / 2.0;
+        if frequency_in_hz >= nyquist {
+            return Err(format!(
+                "{frequency_in_hz} Hz is above the Nyquist frequency, {nyquist} Hz"
+            ));
+        }
+        self.max_displayed_frequency = (2.0 * frequency_in_hz)
+            .clamp(MIN_GO_TO_VIEW_HZ, nyquist)
+            .ceil() as usize;
+        Ok(())
+    }
+
+    /**
+     * Locks the scale to the loudest bin of the spectrum on screen, or unlocks it. Returns the
+     * locked amplitude.
+     */
+    pub fn toggle_scale_lock(&mut self) -> Option<f32> {
+        self.locked_amplitude = match self.locked_amplitude {
+            Some(_) => None,
+            None => self
+                .data_buffer
+                .iter()
+                .copied()
+                .reduce(f32::max)
+                .filter(|amplitude| *amplitude > 0.0),
+        };
+        self.locked_amplitude
+    }
+
+    /**
+     * Returns the points of the line that overlays the reference on the bars, on the same
+     * scale as them
+     */
+    pub fn reference_line(&self, bars: &[GraphBar]) -> Vec<Point> {
+        match &self.reference {
+            Some(reference) => self.amplitude_line(reference, bars),
+            None => vec![],
+        }
+    }
+
+    /**
+     * Returns the points of the target fitted on the last run
+     */
+    pub fn target_line(&self, bars: &[GraphBar]) -> Vec<Point> {
+        self.amplitude_line(&self.target_amplitudes, bars)
+    }
+
+    /**
+     * Height an amplitude is drawn at in a graph `graph_height` tall
+     */
+    fn height_of(&self, amplitude: f32, graph_height: f32) -> f32 {
+        match self.db_range {
+            Some(range) => graph_height * range.fraction(analysis::amplitude_to_db(amplitude)),
+            // Bins louder than a locked scale are cut at the top
+            None => (graph_height * amplitude / self.amplitude_scale).min(graph_height),
+        }
+    }
+
+    /**
+     * Returns the points of a line going through the given amplitudes of the bin of each bar
+     */
+    fn amplitude_line(&self, amplitudes: &[f32], bars: &[GraphBar]) -> Vec<Point> {
+        if amplitudes.len() != self.data_buffer.len() {
+            return vec![];
+        }
+        self.bar_line(
+            bars,
+            bars.iter()
+                .map(|bar| amplitudes[bar.frequency_data.analyzing_bin_index]),
+        )
+    }
+
+    /**
+     * Returns the points of the line a curve of levels in dBFS makes at the frequency of each
+     * bar
+     */
+    pub fn curve_line(&self, curve: &curve::Curve, bars: &[GraphBar]) -> Vec<Point> {
+        self.bar_line(
+            bars,
+            bars.iter().map(|bar| {
+                analysis::db_to_amplitude(
+                    curve.db_at(bar.frequency_data.note_status.get_frequency_in_hz()),
+                )
+            }),
+        )
+    }
+
+    fn bar_line(&self, bars: &[GraphBar], amplitudes: impl Iterator<Item = f32>) -> Vec<Point> {
+        if self.amplitude_scale <= 0.0 {
+            return vec![];
+        }
+
+        let padding_top = 10;
+        let ground_y = 30;
+        let graph_height = (self.height - ground_y - padding_top) as f32;
+        bars.iter()
+            .zip(amplitudes)
+            .map(|(bar, amplitude)| {
+                let height = self.height_of(amplitude, graph_height).min(graph_height);
+                Point::new(
+                    bar.x + bar.width as i32 / 2,
+                    (self.height - ground_y) as i32 - height as i32,
+                )
+            })
+            .collect()
+    }
+}
+
+// Difference in dB drawn at the top (and the bottom) of the graph in the delta view
+pub const DELTA_RANGE_DB: f32 = 24.0;
+
+// Multiples of the hovered bin listed next to the cursor, the bin itself being the first
+pub const HOVER_MULTIPLES: usize = 4;
+
+// Narrowest view the graph zooms to when going to a frequency, below it the bars get too wide
+pub const MIN_GO_TO_VIEW_HZ: f32 = 20.0;
+
+// Ratio of the top of the logarithmic frequency axis to its bottom, three decades like 20 Hz to
+// 20 kHz
+const LOG_AXIS_RANGE: f32 = 1000.0;
+// Width of the bars of the logarithmic axis, in pixels
+const LOG_BAR_WIDTH: u32 = 4;
+
+// Labels of the frequency axis are at least this many pixels apart
+const MIN_TICK_SPACING: f32 = 80.0;
+
+/**
+ * Frequencies up to `top_hz` to put a tick at on an axis `width` pixels wide, with their label:
+ * round numbers of the unit on a linear axis, 1, 2 and 5 times the powers of ten on a
+ * logarithmic one, and every C when the frequencies are written as notes or MIDI numbers
+ */
+pub fn frequency_ticks(
+    top_hz: f32,
+    width: u32,
+    log_frequency: bool,
+    unit: units::FrequencyUnit,
+) -> Vec<(f32, String)> {
+    let label = |frequency_in_hz: f32| match unit {
+        units::FrequencyUnit::Khz => format!("{} kHz", frequency_in_hz / 1000.0),
+        _ => format!("{frequency_in_hz} Hz"),
+    };
+    let ticks: Vec<(f32, String)> = match (unit, log_frequency) {
+        (units::FrequencyUnit::Note | units::FrequencyUnit::Midi, _) => (0..=11)
+            .map(|octave| {
+                let midi_note = 12 * (octave + 1);
+                let frequency_in_hz = 440.0 * 2f32.powf((midi_note - 69) as f32 / 12.0);
+                match unit {
+                    units::FrequencyUnit::Note => (frequency_in_hz, units::note_name(midi_note)),
+                    _ => (frequency_in_hz, midi_note.to_string()),
+                }
+            })
+            .collect(),
+        (_, true) => (0..=5)
+            .flat_map(|power| [1.0, 2.0, 5.0].map(|factor| factor * 10f32.powi(power)))
+            .map(|frequency_in_hz| (frequency_in_hz, label(frequency_in_hz)))
+            .collect(),
+        (_, false) => {
+            let step_hz = waterfall::tick_step(top_hz * MIN_TICK_SPACING / width.max(1) as f32);
+            (0..=(top_hz / step_hz) as usize)
+                .map(|index| index as f32 * step_hz)
+                .map(|frequency_in_hz| (frequency_in_hz, label(frequency_in_hz)))
+                .collect()
+        }
+    };
+    ticks
+        .into_iter()
+        .filter(|(frequency_in_hz, _)| *frequency_in_hz < top_hz)
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+pub enum DisplayColors {
+    Amplitude,
+    // How far the bar is from the target curve
+    Deviation,
+}
+
+pub fn bar_color(display_colors: &DisplayColors, bar: &GraphBar) -> Color {
+    match display_colors {
+        DisplayColors::Amplitude => {
+            let (r, g, b) = amplitude_color(bar.frequency_data.amplitude_percentage);
+            Color::RGBA(r, g, b, 255)
+        }
+        DisplayColors::Deviation => {
+            let tolerance_db = 3.0;
+            match bar.frequency_data.deviation_db {
+                Some(deviation_db) if deviation_db > tolerance_db => Color::RGBA(239, 71, 111, 255),
+                Some(deviation_db) if deviation_db < -tolerance_db => {
+                    Color::RGBA(255, 209, 102, 255)
+                }
+                Some(_) => Color::RGBA(6, 214, 160, 255),
+                None => Color::RGBA(180, 180, 180, 255),
+            }
+        }
+    }
+}
+
+/**
+ * Draws the note of the hovered bin, how far it is from the reference and the target when
+ * they're shown, then the rows of the bin and its multiples, next to the line over the bar, on
+ * its right unless that goes out of the window
+ */
+pub fn draw_multiples(
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    frequency_data: &FrequencyData,
+    multiples: &[analysis::Harmonic],
+    x: i32,
+    window_width: u32,
+    unit: units::FrequencyUnit,
+    amplitude_scale: units::AmplitudeScale,
+) {
+    let note = format!(
+        "{}{} {:+} cents, {}%",
+        NoteStatus::note_number_to_name(frequency_data.note_status.note_number).trim(),
+        NoteStatus::get_octave_by_key_number(frequency_data.note_status.key_number),
+        frequency_data.note_status.error_percentage,
+        frequency_data.amplitude_percentage,
+    );
+    let comparisons = [
+        frequency_data
+            .delta_db
+            .map(|delta_db| format!("vs reference {delta_db:+6.1} dB")),
+        frequency_data
+            .deviation_db
+            .map(|deviation_db| format!("vs target {deviation_db:+6.1} dB")),
+    ];
+    let rows: Vec<String> = std::iter::once(note)
+        .chain(comparisons.into_iter().flatten())
+        .chain(multiples.iter().map(|multiple| match multiple.number {
+            1 => format!(
+                "1x {:>12} {:>14}",
+                unit.format(multiple.frequency_in_hz),
+                amplitude_scale.format(multiple.level_db)
+            ),
+            number => format!(
+                "{number}x {:>12} {:>14} {:>+6.1} dB",
+                unit.format(multiple.frequency_in_hz),
+                amplitude_scale.format(multiple.level_db),
+                multiple.ratio_db
+            ),
+        }))
+        .collect();
+
+    let scale = 1;
+    let padding = 6;
+    let width = rows
+        .iter()
+        .map(|row| text::text_width(row, scale))
+        .max()
+        .unwrap_or(0)
+        + padding * 2;
+    let line_height = text::line_height(scale);
+    let left = if x + 8 + width as i32 <= window_width as i32 {
+        x + 8
+    } else {
+        x - 8 - width as i32
+    };
+
+    canvas.set_draw_color(Color::RGB(30, 30, 30));
+    canvas
+        .fill_rect(Rect::new(
+            left,
+            30,
+            width,
+            line_height * rows.len() as u32 + padding * 2,
+        ))
+        .unwrap();
+    for (i, row) in rows.iter().enumerate() {
+        text::draw_text(
+            canvas,
+            row,
+            left + padding as i32,
+            30 + (padding + line_height * i as u32) as i32,
+            scale,
+            Color::RGB(230, 230, 230),
+        );
+    }
+}
+
+/**
+ * Draws a grid line over the graph at each tick of the frequency axis, with a tick mark and
+ * its label in the strip under the bars unless `labels` is false. Meant to be drawn before the
+ * bars, so they cover the grid.
+ */
+pub fn draw_frequency_axis(
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    ticks: &[(i32, String)],
+    height: u32,
+    labels: bool,
+) {
+    // Same margins as the bars
+    let (padding_top, ground_y) = (10, 30);
+    let axis_y = height.saturating_sub(ground_y) as i32;
+    for (x, label) in ticks {
+        canvas.set_draw_color(Color::RGB(220, 220, 220));
+        canvas.draw_line((*x, padding_top), (*x, axis_y)).unwrap();
+        if !labels {
+            continue;
+        }
+        canvas.set_draw_color(Color::RGB(100, 100, 100));
+        canvas.draw_line((*x, axis_y), (*x, axis_y + 4)).unwrap();
+        let label_x = x - text::text_width(label, 1) as i32 / 2;
+        text::draw_text(
+            canvas,
+            label,
+            label_x.max(2),
+            axis_y + 8,
+            1,
+            Color::RGB(100, 100, 100),
+        );
+    }
+}
+
+/**
+ * Labels the bars scaled to the loudest one on the left of the graph, with a line at every
+ * quarter of its amplitude and its level in the amplitude unit
+ */
+pub fn draw_percentage_axis(
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    graph: &Graph,
+    amplitude_scale: units::AmplitudeScale,
+) {
+    let (padding_top, ground_y) = (10, 30);
+    let graph_height = graph.height.saturating_sub(ground_y + padding_top) as f32;
+    // The loudest bar takes 1 / 1.1 of the height, see Graph::run
+    let loudest = graph.amplitude_scale / 1.1;
+    if loudest <= 0.0 {
+        return;
+    }
+    for percentage in [25, 50, 75, 100] {
+        let amplitude = loudest * percentage as f32 / 100.0;
+        let y = graph.height.saturating_sub(ground_y) as i32
+            - graph.height_of(amplitude, graph_height) as i32;
+        canvas.set_draw_color(Color::RGB(215, 215, 215));
+        canvas.draw_line((0, y), (graph.width as i32, y)).unwrap();
+        text::draw_text(
+            canvas,
+            &format!(
+                "{percentage}% ({})",
+                amplitude_scale.format(analysis::amplitude_to_db(amplitude))
+            ),
+            4,
+            y - text::line_height(1) as i32,
+            1,
+            Color::RGB(100, 100, 100),
+        );
+    }
+}
+
+/**
+ * Labels the levels of the dB scale on the left of the graph, with a line every 10 dB (every
+ * 20 dB on wide ranges) and the top and the floor always labeled
+ */
+pub fn draw_db_axis(
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    range: analysis::DbRange,
+    width: u32,
+    height: u32,
+    amplitude_scale: units::AmplitudeScale,
+) {
+    // Same margins as the bars
+    let (padding_top, ground_y) = (10, 30);
+    let graph_height = height.saturating_sub(ground_y + padding_top) as f32;
+    let y_of = |level_db: f32| {
+        (height - ground_y) as i32 - (graph_height * range.fraction(level_db)) as i32
+    };
+    let step_db = if range.top_db - range.floor_db > 60.0 {
+        20.0
+    } else {
+        10.0
+    };
+    let mut levels = vec![range.top_db];
+    let mut level_db = (range.top_db / step_db).floor() * step_db;
+    while level_db > range.floor_db {
+        // Too close to the top to get its own label
+        if range.top_db - level_db >= step_db / 2.0 {
+            levels.push(level_db);
+        }
+        level_db -= step_db;
+    }
+    levels.push(range.floor_db);
+
+    for level_db in levels {
+        let y = y_of(level_db);
+        canvas.set_draw_color(Color::RGB(215, 215, 215));
+        canvas.draw_line((0, y), (width as i32, y)).unwrap();
+        text::draw_text(
+            canvas,
+            &amplitude_scale.format(level_db),
+            4,
+            y - text::line_height(1) as i32,
+            1,
+            Color::RGB(100, 100, 100),
+        );
+    }
+}
+
+/**
+ * Draws the bound keys and what they do in a box over the top left corner of the window
+ */
+pub fn draw_help(
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    bindings: &keys::KeyBindings,
+) {
+    let scale = 2;
+    let help = bindings.help();
+    let key_width = help
+        .iter()
+        .map(|(binding, _)| text::text_width(&binding.to_string(), scale))
+        .max()
+        .unwrap_or(0);
+    let text_width = help
+        .iter()
+        .map(|(_, description)| text::text_width(description, scale))
+        .max()
+        .unwrap_or(0);
+    let line_height = text::line_height(scale);
+    let padding = 12;
+
+    canvas.set_draw_color(Color::RGB(30, 30, 30));
+    canvas
+        .fill_rect(Rect::new(
+            0,
+            0,
+            key_width + text_width + padding * 3,
+            line_height * help.len() as u32 + padding * 2,
+        ))
+        .unwrap();
+    for (i, (binding, description)) in help.iter().enumerate() {
+        let y = (padding + line_height * i as u32) as i32;
+        text::draw_text(
+            canvas,
+            &binding.to_string(),
+            padding as i32,
+            y,
+            scale,
+            Color::RGB(255, 170, 60),
+        );
+        text::draw_text(
+            canvas,
+            description,
+            (padding * 2 + key_width) as i32,
+            y,
+            scale,
+            Color::RGB(230, 230, 230),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequency_ticks_are_round() {
+        let hz = |ticks: Vec<(f32, String)>| -> Vec<f32> {
+            ticks
+                .into_iter()
+                .map(|(frequency_in_hz, _)| frequency_in_hz)
+                .collect()
+        };
+        // 80 pixels are 500 Hz
+        let linear = frequency_ticks(5000.0, 800, false, units::FrequencyUnit::Hz);
+        assert_eq!(hz(linear.clone()).len(), 10);
+        assert_eq!(linear[3], (1500.0, "1500 Hz".to_string()));
+        let log = frequency_ticks(5000.0, 800, true, units::FrequencyUnit::Khz);
+        assert_eq!(hz(log.clone())[..4], [1.0, 2.0, 5.0, 10.0]);
+        assert_eq!(log.last().unwrap().1, "2 kHz");
+        let notes = frequency_ticks(1000.0, 800, false, units::FrequencyUnit::Note);
+        assert_eq!(notes[4].1, "C4");
+        assert!((notes[4].0 - 261.63).abs() < 0.01);
+    }
+
+    /**
+     * A graph 1000 pixels wide up to 20 kHz that ran once over a flat spectrum of fft_len points
+     */
+    fn graph_of(fft_len: usize, log_frequency: bool) -> (Graph, Vec<GraphBar>) {
+        let frame = FftFrame {
+            samples: Arc::new(vec![0.0; fft_len]),
+            magnitudes: Arc::new(vec![1.0; fft_len]),
+            amplitudes: Arc::new(vec![1.0; fft_len / 2 + 1]),
+            generation: 1,
+            captured_at: Duration::ZERO,
+        };
+        let mut graph = Graph {
+            width: 1000,
+            height: 600,
+            buffer_size: fft_len,
+            max_displayed_frequency: 20000,
+            data_buffer: Arc::new(vec![]),
+            fft_len: 0,
+            magnitudes_buffer: Arc::new(vec![]),
+            samples_buffer: Arc::new(vec![]),
+            data_generation: 0,
+            captured_at: Duration::ZERO,
+            data_locker: Arc::new(Mutex::new(FftFrame::default())),
+            paused: Arc::new(Mutex::new(false)),
+            mouse_x: Arc::new(Mutex::new(0)),
+            note_statuses: vec![],
+            note_statuses_sample_rate: 48000,
+            target_heights: vec![],
+            displayed_heights: vec![],
+            reference: None,
+            delta_view: false,
+            amplitude_scale: 0.0,
+            db_range: None,
+            locked_amplitude: None,
+            target: None,
+            target_amplitudes: vec![],
+            bar_width: 0.0,
+            bars_len: 0,
+            bins_len: 0,
+            log_frequency,
+            bar_groups: vec![],
+        };
+        graph.show_frame(&frame);
+        let (bars, _) = graph.run(48000);
+        (graph, bars)
+    }
+
+    #[test]
+    fn the_log_axis_has_as_many_bars_for_any_fft_size() {
+        let (small, small_bars) = graph_of(4096, true);
+        let (big, big_bars) = graph_of(65536, true);
+        assert_eq!(small_bars.len(), (1000 / LOG_BAR_WIDTH) as usize);
+        assert_eq!(big_bars.len(), small_bars.len());
+
+        // Each bar stands for a whole group of bins, hovering it points at the loudest one
+        let bar = big.bar_at_x(999).unwrap();
+        let group = &big.bar_groups[bar];
+        assert!(group.bins.len() > 1);
+        assert_eq!(big.bin_at_x(999), Some(group.loudest_bin));
+        assert_eq!(
+            big.bar_showing(&big_bars, group.bins.end - 1).unwrap().x,
+            big_bars[bar].x
+        );
+
+        // Every octave gets the same width, whatever the bins below
+        for graph in [&small, &big] {
+            let x = |frequency_in_hz| graph.x_at_frequency(frequency_in_hz, 48000).unwrap();
+            assert!(((x(400.0) - x(200.0)) - (x(8000.0) - x(4000.0))).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn the_linear_axis_has_one_bar_per_bin() {
+        let (graph, bars) = graph_of(4096, false);
+        assert_eq!(bars.len(), BinMapping::new(48000, 4096).bins_up_to(20000.0));
+        assert!(graph.bar_groups.is_empty());
+        assert_eq!(graph.bin_at_x(0), Some(0));
+        assert_eq!(graph.bar_showing(&bars, 100).unwrap().x, bars[100].x);
+        assert!(graph.bar_showing(&bars, bars.len()).is_none());
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    fs::File,
+    io::{stdout, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use cpal::traits::StreamTrait;
+
+use crate::{
+    analysis::{goertzel_level_db, power_to_db},
+    cli::ResonanceArgs,
+    generator::{Generator, Sound},
+    open_input_stream, SampleCallback,
+};
+
+// Part of each step left for the room and the object to settle on the new frequency, and for
+// the latency of the devices
+const SETTLE_FRACTION: f32 = 0.4;
+// How far above the levels within a third of an octave around it a peak has to be
+const RESONANCE_PROMINENCE_DB: f32 = 6.0;
+// How far above its median over the sweep the residual has to go for a rattle
+const RATTLE_MARGIN_DB: f32 = 10.0;
+
+/**
+ * What the mic heard while the sweep stayed on one frequency
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub frequency_in_hz: f32,
+    // Level of the played frequency, the response of the room or the object
+    pub level_db: f32,
+    // Everything else (harmonics, buzzing, noise) relative to the played frequency
+    pub residual_db: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Finding {
+    // The response peaks there
+    Resonance { prominence_db: f32 },
+    // Something buzzes when driven there
+    Rattle { residual_db: f32 },
+}
+
+/**
+ * Frequencies from `from_hz` to `to_hz` spaced `steps_per_octave` per octave
+ */
+pub fn sweep_frequencies(from_hz: f32, to_hz: f32, steps_per_octave: f32) -> Vec<f32> {
+    let steps = ((to_hz / from_hz).log2() * steps_per_octave).ceil() as usize;
+    (0..=steps)
+        .map(|step| from_hz * 2f32.powf(step as f32 / steps_per_octave))
+        .take_while(|frequency_in_hz| *frequency_in_hz <= to_hz * 1.0001)
+        .collect()
+}
+
+/**
+ * Measures the played frequency and the residual in the recording of a step. Only whole
+ * periods are kept, the Goertzel filter then doesn't leak the played frequency into the
+ * residual.
+ */
+pub fn measure_step(samples: &[f32], frequency_in_hz: f32, sample_rate: u32) -> Option<Step> {
+    let period = sample_rate as f32 / frequency_in_hz;
+    let periods = (samples.len() as f32 / period).floor();
+    if periods < 1.0 {
+        return None;
+    }
+    let samples = &samples[samples.len() - (periods * period).round() as usize..];
+
+    let level_db = goertzel_level_db(samples, frequency_in_hz, sample_rate);
+    // A sine of amplitude A has a power of A² / 2
+    let played_power = 10f32.powf(level_db / 10.0) / 2.0;
+    let total_power = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+    let residual_power = (total_power - played_power).max(total_power * 1e-6);
+    Some(Step {
+        frequency_in_hz,
+        level_db,
+        residual_db: power_to_db(residual_power) - power_to_db(played_power),
+    })
+}
+
+fn median(values: impl Iterator<Item = f32>) -> Option<f32> {
+    let mut sorted: Vec<f32> = values.collect();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    sorted.get(sorted.len() / 2).copied()
+}
+
+/**
+ * Finds the resonances, steps louder than the steps within a third of an octave around them,
+ * and the rattles, steps whose residual jumps above the usual one of the sweep
+ */
+pub fn find_resonances(steps: &[Step]) -> Vec<(f32, Finding)> {
+    let usual_residual_db = median(steps.iter().map(|step| step.residual_db));
+    let mut findings = vec![];
+    for (index, step) in steps.iter().enumerate() {
+        let around = steps.iter().filter(|other| {
+            (other.frequency_in_hz / step.frequency_in_hz).log2().abs() <= 1.0 / 6.0
+        });
+        let is_local_maximum = around.clone().all(|other| other.level_db <= step.level_db);
+        let prominence_db = median(around.map(|other| other.level_db))
+            .map_or(0.0, |median_db| step.level_db - median_db);
+        if is_local_maximum && prominence_db >= RESONANCE_PROMINENCE_DB {
+            findings.push((step.frequency_in_hz, Finding::Resonance { prominence_db }));
+        }
+
+        // A rattle usually spreads over a few steps, only the worst one is listed, the first one
+        // when they are even
+        let is_worst_neighbour = index
+            .checked_sub(1)
+            .is_none_or(|previous| steps[previous].residual_db < step.residual_db)
+            && steps
+                .get(index + 1)
+                .is_none_or(|next| next.residual_db <= step.residual_db);
+        if usual_residual_db.is_some_and(|usual_db| step.residual_db >= usual_db + RATTLE_MARGIN_DB)
+            && is_worst_neighbour
+        {
+            findings.push((
+                step.frequency_in_hz,
+                Finding::Rattle {
+                    residual_db: step.residual_db,
+                },
+            ));
+        }
+    }
+    findings
+}
+
+fn save_steps(path: &Path, steps: &[Step]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "frequency_hz,level_db,residual_db")?;
+    for step in steps {
+        writeln!(
+            file,
+            "{:.2},{:.2},{:.2}",
+            step.frequency_in_hz, step.level_db, step.residual_db
+        )?;
+    }
+    file.flush()
+}
+
+/**
+ * Plays a slow stepped sine through the band and lists where the room or the object resonates
+ * or rattles
+ */
+pub fn run(args: &ResonanceArgs) -> Result<(), String> {
+    let frequencies = sweep_frequencies(args.band.low_hz, args.band.high_hz, args.steps_per_octave);
+    if frequencies.is_empty() {
+        return Err(format!(
+            "The band {} - {} Hz is empty",
+            args.band.low_hz, args.band.high_hz
+        ));
+    }
+    let step_duration = Duration::from_secs_f32(args.secs / frequencies.len() as f32);
+
+    let recording = Arc::new(Mutex::new(Vec::<f32>::new()));
+    let stream_recording = recording.clone();
+    let on_samples: SampleCallback = Arc::new(Mutex::new(move |data: &[f32], _: Instant| {
+        stream_recording.lock().unwrap().extend_from_slice(data)
+    }));
+    let (input, _) = open_input_stream(None, args.sample_rate, on_samples, |error| {
+        eprintln!("\nInput error: {error}")
+    })?;
+    input.play().map_err(|error| error.to_string())?;
+    let generator = Generator::open()?;
+
+    println!(
+        "Sweeping {} - {} Hz in {} s, {} steps",
+        args.band.low_hz,
+        args.band.high_hz,
+        args.secs,
+        frequencies.len()
+    );
+    let mut steps = vec![];
+    for frequency_in_hz in frequencies {
+        generator.play(Sound::Tone {
+            frequency_in_hz,
+            level_db: args.level_db,
+        });
+        std::thread::sleep(step_duration.mul_f32(SETTLE_FRACTION));
+        recording.lock().unwrap().clear();
+        std::thread::sleep(step_duration.mul_f32(1.0 - SETTLE_FRACTION));
+        let samples = std::mem::take(&mut *recording.lock().unwrap());
+
+        if let Some(step) = measure_step(&samples, frequency_in_hz, args.sample_rate) {
+            print!(
+                "\r{:>7.1} Hz {:>6.1} dBFS, residual {:>6.1} dB",
+                step.frequency_in_hz, step.level_db, step.residual_db
+            );
+            stdout().flush().unwrap();
+            steps.push(step);
+        }
+    }
+    generator.play(Sound::Silence);
+    println!();
+
+    let findings = find_resonances(&steps);
+    if findings.is_empty() {
+        println!("Nothing resonates or rattles in this band");
+    }
+    for (frequency_in_hz, finding) in findings {
+        match finding {
+            Finding::Resonance { prominence_db } => {
+                println!("{frequency_in_hz:>7.1} Hz resonance, {prominence_db:.1} dB above the band around it")
+            }
+            Finding::Rattle { residual_db } => println!(
+                "{frequency_in_hz:>7.1} Hz rattle, distortion and noise at {residual_db:.1} dB from the tone"
+            ),
+        }
+    }
+
+    if let Some(path) = &args.out {
+        save_steps(path, &steps).map_err(|error| error.to_string())?;
+        println!("Saved the sweep to {}", path.display());
+    }
+    // The last step fades out before the stream closes
+    std::thread::sleep(Duration::from_millis(50));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn residual_of_a_tone_with_a_harmonic() {
+        let sample_rate = 48000;
+        let samples: Vec<f32> = (0..9000)
+            .map(|i| {
+                let phase = 2.0 * PI * 73.3 * i as f32 / sample_rate as f32;
+                0.5 * phase.sin() + 0.05 * (3.0 * phase).sin()
+            })
+            .collect();
+        let step = measure_step(&samples, 73.3, sample_rate).unwrap();
+        assert!((step.level_db - -6.02).abs() < 0.1, "{step:?}");
+        assert!((step.residual_db - -20.0).abs() < 0.5, "{step:?}");
+    }
+
+    #[test]
+    fn finds_a_mode_and_a_rattle() {
+        let steps: Vec<Step> = sweep_frequencies(30.0, 300.0, 24.0)
+            .into_iter()
+            .map(|frequency_in_hz| Step {
+                frequency_in_hz,
+                // A room mode of 12 dB at 50 Hz on a flat response
+                level_db: -30.0 + 12.0 * (-(frequency_in_hz / 50.0).log2().powi(2) * 400.0).exp(),
+                // Something buzzing around 120 Hz
+                residual_db: if (118.0..125.0).contains(&frequency_in_hz) {
+                    -15.0
+                } else {
+                    -40.0
+                },
+            })
+            .collect();
+        let findings = find_resonances(&steps);
+        assert_eq!(findings.len(), 2, "{findings:?}");
+        assert!((findings[0].0 - 50.0).abs() < 1.0);
+        assert!(matches!(findings[0].1, Finding::Resonance { .. }));
+        assert!((findings[1].0 - 121.0).abs() < 4.0);
+        assert!(matches!(findings[1].1, Finding::Rattle { .. }));
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    analysis::{find_peaks, BinMapping},
+    cli::SelfTestArgs,
+    source::{SynthSpec, SynthTone, SyntheticSource},
+    windowing::WindowFunction,
+    NoteStatus,
+};
+
+// A peak found on a bin can be up to half a bin from the tone, the window blurs it a bit more
+const FREQUENCY_TOLERANCE_BINS: f32 = 0.6;
+// The Hann window reads a tone between two bins up to 1.42 dB low
+const LEVEL_TOLERANCE_DB: f32 = 1.5;
+
+/**
+ * Tones played through the pipeline, and the note each one should be read as
+ */
+struct Case {
+    name: &'static str,
+    tones: &'static [(f32, f32, &'static str)],
+    noise_db: Option<f32>,
+}
+
+const CASES: [Case; 6] = [
+    Case {
+        name: "A4",
+        tones: &[(440.0, -20.0, "A4")],
+        noise_db: None,
+    },
+    Case {
+        name: "Middle C",
+        tones: &[(261.63, -20.0, "C4")],
+        noise_db: None,
+    },
+    Case {
+        name: "Notes around the octave change",
+        tones: &[
+            (246.94, -20.0, "B3"),
+            (233.08, -26.0, "A#3"),
+            (523.25, -32.0, "C5"),
+        ],
+        noise_db: None,
+    },
+    Case {
+        name: "Low E of a guitar",
+        tones: &[(82.41, -20.0, "E2")],
+        noise_db: None,
+    },
+    Case {
+        name: "Quiet tone next to a loud one",
+        tones: &[(1000.0, -6.0, "B5"), (1500.0, -50.0, "F#6")],
+        noise_db: None,
+    },
+    Case {
+        name: "Tone in noise",
+        tones: &[(3520.0, -30.0, "A7")],
+        noise_db: Some(-50.0),
+    },
+];
+
+fn note_name(frequency_in_hz: f32) -> String {
+    let status = NoteStatus::new(frequency_in_hz);
+    format!(
+        "{}{}",
+        NoteStatus::note_number_to_name(status.note_number).trim(),
+        NoteStatus::get_octave_by_key_number(status.key_number)
+    )
+}
+
+/**
+ * Runs every case through the window, the FFT, the peak picking and the note names, and
+ * returns a line per tone along with whether it passed
+ */
+pub fn run_checks(
+    sample_rate: u32,
+    fft_size: usize,
+    window: WindowFunction,
+) -> Vec<(bool, String)> {
+    let mapping = BinMapping::new(sample_rate, fft_size);
+    let mut lines = vec![];
+    for case in &CASES {
+        let spec = SynthSpec {
+            tones: case
+                .tones
+                .iter()
+                .map(|(frequency_in_hz, level_db, _)| SynthTone {
+                    frequency_in_hz: *frequency_in_hz,
+                    level_db: *level_db,
+                })
+                .collect(),
+            noise_db: case.noise_db,
+        };
+        let samples = SyntheticSource::new(spec, sample_rate).next_block(fft_size);
+        let peaks = find_peaks(&window.magnitudes(&samples), sample_rate, 32);
+
+        for (frequency_in_hz, level_db, note) in case.tones {
+            let found = peaks.iter().find(|peak| {
+                (peak.frequency_in_hz - frequency_in_hz).abs()
+                    <= FREQUENCY_TOLERANCE_BINS * mapping.bin_width_hz()
+            });
+            let line = match found {
+                Some(peak) => {
+                    let found_note = note_name(peak.frequency_in_hz);
+                    let passed = (peak.level_db - level_db).abs() <= LEVEL_TOLERANCE_DB
+                        && found_note == *note;
+                    (
+                        passed,
+                        format!(
+                            "{}: {frequency_in_hz} Hz at {level_db} dBFS ({note}) read as {:.2} Hz at {:.1} dBFS ({found_note})",
+                            case.name, peak.frequency_in_hz, peak.level_db
+                        ),
+                    )
+                }
+                None => (
+                    false,
+                    format!(
+                        "{}: {frequency_in_hz} Hz at {level_db} dBFS ({note}) not found",
+                        case.name
+                    ),
+                ),
+            };
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+/**
+ * Checks the whole analysis on known tones and prints a pass/fail report
+ */
+pub fn run(args: &SelfTestArgs) -> Result<(), String> {
+    let mapping = BinMapping::new(args.sample_rate, args.fft_size);
+    println!(
+        "Self-test at {} Hz, FFT of {} points ({:.2} Hz per bin), {} window",
+        args.sample_rate,
+        args.fft_size,
+        mapping.bin_width_hz(),
+        args.window.name()
+    );
+    let lines = run_checks(args.sample_rate, args.fft_size, args.window);
+    for (passed, line) in &lines {
+        println!("{} {line}", if *passed { "PASS" } else { "FAIL" });
+    }
+
+    let failed = lines.iter().filter(|(passed, _)| !passed).count();
+    match failed {
+        0 => {
+            println!("All {} checks passed", lines.len());
+            Ok(())
+        }
+        _ => Err(format!("{failed} of {} checks failed", lines.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_pipeline_passes_its_own_checks() {
+        let lines = run_checks(48000, 32768, WindowFunction::Hann);
+        for (passed, line) in &lines {
+            assert!(passed, "{line}");
+        }
+    }
+
+    #[test]
+    fn notes_are_named_across_the_keyboard() {
+        assert_eq!(note_name(440.0), "A4");
+        assert_eq!(note_name(466.16), "A#4");
+        assert_eq!(note_name(493.88), "B4");
+        assert_eq!(note_name(261.63), "C4");
+        assert_eq!(note_name(27.5), "A0");
+        // 40 cents sharp of B3 is still B3
+        assert_eq!(note_name(246.94 * 2f32.powf(40.0 / 1200.0)), "B3");
+        assert_eq!(
+            NoteStatus::new(246.94 * 2f32.powf(40.0 / 1200.0)).error_percentage,
+            40
+        );
+    }
+}
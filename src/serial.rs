@@ -0,0 +1,107 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::analysis::{level_to_fraction, Band, FftFrame, Summary};
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "app", derive(clap::ValueEnum))]
+pub enum SerialFormat {
+    /// 0xFF, the number of bands, one byte per band (0 to 254) and the XOR of the band bytes.
+    /// The levels never reach 0xFF, so the receiver can always find the start of a frame
+    Binary,
+    /// The band levels (0 to 254) separated by commas and ended by a new line, which is easy to
+    /// read with Serial.parseInt() on an Arduino
+    Text,
+}
+
+pub fn encode_frame(levels: &[u8], format: SerialFormat) -> Vec<u8> {
+    match format {
+        SerialFormat::Binary => {
+            let mut frame = Vec::with_capacity(levels.len() + 3);
+            frame.push(0xFF);
+            frame.push(levels.len() as u8);
+            frame.extend_from_slice(levels);
+            frame.push(levels.iter().fold(0, |checksum, level| checksum ^ level));
+            frame
+        }
+        SerialFormat::Text => {
+            let mut line = levels
+                .iter()
+                .map(|level| level.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            line.push('\n');
+            line.into_bytes()
+        }
+    }
+}
+
+pub struct SerialConfig {
+    pub path: String,
+    pub baud_rate: u32,
+    pub format: SerialFormat,
+    pub frames_per_second: u32,
+    pub floor_db: f32,
+}
+
+/**
+ * Opens the serial port and spawns a thread that writes the band levels of the latest FFT
+ * result `frames_per_second` times per second
+ */
+pub fn spawn(
+    config: SerialConfig,
+    bands: Vec<Band>,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+) -> serialport::Result<JoinHandle<()>> {
+    let mut port = serialport::new(&config.path, config.baud_rate)
+        .timeout(Duration::from_millis(100))
+        .open()?;
+    let interval = Duration::from_secs_f32(1.0 / config.frames_per_second.max(1) as f32);
+
+    Ok(std::thread::spawn(move || loop {
+        let magnitudes = fft_transform.lock().unwrap().magnitudes.clone();
+        let summary = Summary::from_magnitudes(&magnitudes, sample_rate, &bands);
+
+        if let Some(summary) = summary {
+            let levels: Vec<u8> = summary
+                .band_levels_db
+                .iter()
+                .map(|level| (level_to_fraction(*level, config.floor_db) * 254.0).round() as u8)
+                .collect();
+            if let Err(error) = port.write_all(&encode_frame(&levels, config.format)) {
+                eprintln!("Could not write to {}: {error}", config.path);
+            }
+        }
+
+        std::thread::sleep(interval);
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_frames_carry_the_count_and_the_checksum() {
+        assert_eq!(
+            encode_frame(&[0x01, 0x80, 0xFE], SerialFormat::Binary),
+            // 0x01 ^ 0x80 ^ 0xFE = 0x7F
+            [0xFF, 0x03, 0x01, 0x80, 0xFE, 0x7F]
+        );
+        assert_eq!(encode_frame(&[], SerialFormat::Binary), [0xFF, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn text_frames_are_one_line() {
+        assert_eq!(
+            encode_frame(&[0, 17, 254], SerialFormat::Text),
+            b"0,17,254\n"
+        );
+        assert_eq!(encode_frame(&[], SerialFormat::Text), b"\n");
+    }
+}
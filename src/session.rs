@@ -0,0 +1,384 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    analysis::{amplitude_to_db, power_to_db, BinMapping},
+    marker::Marker,
+    noise_rating::{Criterion, Rating},
+    units::FrequencyUnit,
+};
+
+/**
+ * Everything needed to reproduce a measurement later, written at the top of the report
+ */
+pub struct SessionMetadata {
+    pub device: String,
+    pub sample_rate: u32,
+    pub fft_size: usize,
+    // No weighting curve is applied to the spectrum yet, so this is always Z
+    pub weighting: &'static str,
+    // Calibration file applied to the spectrum, if any
+    pub mic_calibration: Option<String>,
+    // Offset from dBFS to dB SPL, the noise ratings are only right with a calibrated one
+    pub spl_offset_db: f32,
+    pub started: SystemTime,
+}
+
+/**
+ * Averages a fixed number of spectra, so a measurement isn't just whatever the last frame
+ * happened to look like
+ */
+pub struct Session {
+    metadata: SessionMetadata,
+    target_frames: usize,
+    // Sum of the squared amplitudes of each bin, averaging powers instead of amplitudes keeps
+    // the result right for noise
+    power_sums: Vec<f64>,
+    frames: usize,
+    markers: Vec<Marker>,
+}
+
+impl Session {
+    pub fn new(metadata: SessionMetadata, target_frames: usize) -> Self {
+        Self {
+            metadata,
+            target_frames: target_frames.max(1),
+            power_sums: vec![],
+            frames: 0,
+            markers: vec![],
+        }
+    }
+
+    /**
+     * Lists a marker dropped during the measurement in its report
+     */
+    pub fn add_marker(&mut self, marker: &Marker) {
+        self.markers.push(marker.clone());
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.frames >= self.target_frames
+    }
+
+    /**
+     * Adds a one-sided amplitude spectrum. Spectra of another FFT size than the session's are
+     * ignored, they can't be averaged with the others.
+     */
+    pub fn add(&mut self, amplitudes: &[f32]) {
+        if amplitudes.len() != self.metadata.fft_size / 2 + 1 || self.is_complete() {
+            return;
+        }
+        if self.power_sums.is_empty() {
+            self.power_sums = vec![0.0; amplitudes.len()];
+        }
+        for (sum, amplitude) in self.power_sums.iter_mut().zip(amplitudes) {
+            *sum += (*amplitude as f64).powi(2);
+        }
+        self.frames += 1;
+    }
+
+    /**
+     * Returns the averaged one-sided amplitude spectrum
+     */
+    pub fn averaged_amplitudes(&self) -> Vec<f32> {
+        self.power_sums
+            .iter()
+            .map(|sum| (sum / self.frames.max(1) as f64).sqrt() as f32)
+            .collect()
+    }
+
+    /**
+     * Levels of the octave bands of the averaged spectrum in dB SPL
+     */
+    fn octave_bands_spl(&self) -> Vec<(f32, f32)> {
+        let mapping = BinMapping::new(self.metadata.sample_rate, self.metadata.fft_size);
+        octave_band_levels(&self.averaged_amplitudes(), mapping)
+            .into_iter()
+            .map(|(center_hz, level_db)| (center_hz, level_db + self.metadata.spl_offset_db))
+            .collect()
+    }
+
+    /**
+     * Rates the averaged spectrum as background noise, against the NC and the NR curves
+     */
+    pub fn noise_ratings(&self) -> Vec<(Criterion, Rating)> {
+        let levels = self.octave_bands_spl();
+        [Criterion::Nc, Criterion::Nr]
+            .into_iter()
+            .filter_map(|criterion| Some((criterion, criterion.rate(&levels)?)))
+            .collect()
+    }
+
+    /**
+     * Writes the averaged spectrum as a CSV and a self-contained HTML report (no scripts or
+     * external files) inside `directory`, the frequencies of the CSV being also written in
+     * `unit` when it isn't Hz. Returns the paths of both files.
+     */
+    pub fn write_report(
+        &self,
+        directory: &Path,
+        unit: FrequencyUnit,
+    ) -> std::io::Result<(PathBuf, PathBuf)> {
+        std::fs::create_dir_all(directory)?;
+        let started = humantime::format_rfc3339_seconds(self.metadata.started).to_string();
+        let name = format!("session-{}", started.replace(':', "-"));
+        let csv_path = directory.join(format!("{name}.csv"));
+        let html_path = directory.join(format!("{name}.html"));
+
+        let mapping = BinMapping::new(self.metadata.sample_rate, self.metadata.fft_size);
+        let levels_db: Vec<f32> = self
+            .averaged_amplitudes()
+            .iter()
+            .map(|amplitude| amplitude_to_db(*amplitude))
+            .collect();
+
+        let mut csv = BufWriter::new(File::create(&csv_path)?);
+        // Hz stays the first column, so the file reads the same whatever the unit
+        let extra_column = (unit != FrequencyUnit::Hz).then(|| unit.header().to_lowercase());
+        match &extra_column {
+            Some(column) => writeln!(csv, "frequency_hz,{column},level_db")?,
+            None => writeln!(csv, "frequency_hz,level_db")?,
+        }
+        for (i, level_db) in levels_db.iter().enumerate() {
+            let frequency_in_hz = mapping.frequency_in_hz(i);
+            match extra_column {
+                Some(_) => writeln!(
+                    csv,
+                    "{frequency_in_hz:.3},{},{level_db:.2}",
+                    unit.value(frequency_in_hz)
+                )?,
+                None => writeln!(csv, "{frequency_in_hz:.3},{level_db:.2}")?,
+            }
+        }
+        csv.flush()?;
+
+        let mut html = BufWriter::new(File::create(&html_path)?);
+        write!(
+            html,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Measurement {started}</title>\
+            <style>body{{font-family:sans-serif;margin:2em}}td,th{{padding:2px 12px;text-align:left}}</style>\
+            </head><body>\n<h1>Measurement {started}</h1>\n<table>\
+            <tr><th>Device</th><td>{}</td></tr>\
+            <tr><th>Sample rate</th><td>{} Hz</td></tr>\
+            <tr><th>FFT size</th><td>{} ({:.2} Hz per bin)</td></tr>\
+            <tr><th>Averaged spectra</th><td>{}</td></tr>\
+            <tr><th>Weighting</th><td>{}</td></tr>\
+            <tr><th>Mic calibration</th><td>{}</td></tr></table>\n",
+            escape_html(&self.metadata.device),
+            self.metadata.sample_rate,
+            self.metadata.fft_size,
+            mapping.bin_width_hz(),
+            self.frames,
+            self.metadata.weighting,
+            escape_html(self.metadata.mic_calibration.as_deref().unwrap_or("none")),
+        )?;
+        html.write_all(spectrum_svg(&levels_db, mapping).as_bytes())?;
+        writeln!(
+            html,
+            "\n<h2>Octave bands</h2>\n<table><tr><th>Center ({})</th><th>Level (dBFS)</th>\
+            <th>Level (dB SPL)</th></tr>",
+            unit.header()
+        )?;
+        let octave_bands_spl = self.octave_bands_spl();
+        for (center_hz, level_spl) in &octave_bands_spl {
+            let center = match unit {
+                // The nominal centers, 31.5 rather than 31.50
+                FrequencyUnit::Hz => center_hz.to_string(),
+                _ => unit.value(*center_hz),
+            };
+            writeln!(
+                html,
+                "<tr><td>{center}</td><td>{:.1}</td><td>{level_spl:.1}</td></tr>",
+                level_spl - self.metadata.spl_offset_db
+            )?;
+        }
+        writeln!(html, "</table>")?;
+        writeln!(
+            html,
+            "<h2>Noise criteria</h2>\n<p>With {:+.1} dB from dBFS to dB SPL.</p>",
+            self.metadata.spl_offset_db
+        )?;
+        for (criterion, rating) in self.noise_ratings() {
+            writeln!(
+                html,
+                "<h3>{0}-{1}</h3>\n<p>The noise meets the {0}-{1} curve, set by the {2} Hz band.</p>",
+                criterion.name(),
+                rating.value,
+                rating.band_hz
+            )?;
+            html.write_all(criteria_svg(criterion, &octave_bands_spl).as_bytes())?;
+        }
+        if !self.markers.is_empty() {
+            writeln!(
+                html,
+                "<h2>Markers</h2>\n<table><tr><th>Time</th><th>Since the start</th><th>Label</th></tr>"
+            )?;
+            for marker in &self.markers {
+                let offset = marker
+                    .at
+                    .duration_since(self.metadata.started)
+                    .unwrap_or_default();
+                writeln!(
+                    html,
+                    "<tr><td>{}</td><td>{:.1} s</td><td>{}</td></tr>",
+                    humantime::format_rfc3339_seconds(marker.at),
+                    offset.as_secs_f32(),
+                    escape_html(&marker.label)
+                )?;
+            }
+            writeln!(html, "</table>")?;
+        }
+        writeln!(html, "</body></html>")?;
+        html.flush()?;
+
+        Ok((csv_path, html_path))
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/**
+ * Returns the levels of the standard octave bands (31.5 Hz to 16 kHz) below the Nyquist
+ * frequency, summing the power of the bins inside each one
+ */
+fn octave_band_levels(amplitudes: &[f32], mapping: BinMapping) -> Vec<(f32, f32)> {
+    [
+        31.5, 63.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+    ]
+    .into_iter()
+    .filter(|center_hz| center_hz * 2f32.sqrt() <= mapping.sample_rate as f32 / 2.0)
+    .map(|center_hz: f32| {
+        let low = mapping.bin_at(center_hz / 2f32.sqrt());
+        let high = mapping.bin_at(center_hz * 2f32.sqrt());
+        // The power of a sine is half its squared amplitude
+        let power: f32 = amplitudes[low..high.min(amplitudes.len())]
+            .iter()
+            .map(|amplitude| amplitude * amplitude / 2.0)
+            .sum();
+        (center_hz, power_to_db(power))
+    })
+    .collect()
+}
+
+/**
+ * Draws the curves of a criterion from 0 to 90 dB SPL with the octave band levels over them
+ */
+fn criteria_svg(criterion: Criterion, levels: &[(f32, f32)]) -> String {
+    let (width, height) = (600.0, 360.0);
+    let (min_db, max_db) = (0.0, 90.0);
+    let bands = criterion.bands_hz();
+    // The bands are evenly spaced, leaving room for the names of the curves on the right
+    let x = |band: usize| 40.0 + band as f32 * (width - 100.0) / (bands.len() - 1) as f32;
+    let y = |db: f32| (max_db - db.clamp(min_db, max_db)) / (max_db - min_db) * height;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{}\" \
+        font-size=\"11\"><rect width=\"{width}\" height=\"{height}\" fill=\"#f0f0f0\"/>",
+        height + 20.0
+    );
+    for (band, hz) in bands.iter().enumerate() {
+        svg += &format!(
+            "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{hz}</text>",
+            x(band),
+            height + 14.0
+        );
+    }
+    for value in criterion.drawn_values() {
+        let points: Vec<String> = bands
+            .iter()
+            .enumerate()
+            .filter_map(|(band, hz)| {
+                let level_db = criterion.level_at(value, *hz)?;
+                Some(format!("{:.1},{:.1}", x(band), y(level_db)))
+            })
+            .collect();
+        let last_db = criterion
+            .level_at(value, bands[bands.len() - 1])
+            .unwrap_or(0.0);
+        if last_db > max_db {
+            continue;
+        }
+        svg += &format!(
+            "<polyline fill=\"none\" stroke=\"#bbb\" points=\"{}\"/>\
+            <text x=\"{:.1}\" y=\"{:.1}\">{}-{value}</text>",
+            points.join(" "),
+            x(bands.len() - 1) + 6.0,
+            y(last_db) + 4.0,
+            criterion.name()
+        );
+    }
+    let measured: Vec<String> = bands
+        .iter()
+        .enumerate()
+        .filter_map(|(band, hz)| {
+            let (_, level_db) = levels.iter().find(|(center_hz, _)| center_hz == hz)?;
+            Some(format!("{:.1},{:.1}", x(band), y(*level_db)))
+        })
+        .collect();
+    svg += &format!(
+        "<polyline fill=\"none\" stroke=\"rgb(200,36,104)\" stroke-width=\"2\" points=\"{}\"/></svg>",
+        measured.join(" ")
+    );
+    svg
+}
+
+/**
+ * Draws the spectrum as an SVG line over a logarithmic frequency axis from 20 Hz to the
+ * Nyquist frequency and a level axis from -120 to 0 dBFS
+ */
+fn spectrum_svg(levels_db: &[f32], mapping: BinMapping) -> String {
+    let (width, height) = (900.0, 360.0);
+    let (min_hz, max_hz) = (20.0f32, mapping.sample_rate as f32 / 2.0);
+    let (min_db, max_db) = (-120.0, 0.0);
+    let x = |hz: f32| (hz / min_hz).log10() / (max_hz / min_hz).log10() * width;
+    let y = |db: f32| (max_db - db.clamp(min_db, max_db)) / (max_db - min_db) * height;
+
+    let points: Vec<String> = levels_db
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| mapping.frequency_in_hz(*i) >= min_hz)
+        .map(|(i, db)| format!("{:.1},{:.1}", x(mapping.frequency_in_hz(i)), y(*db)))
+        .collect();
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{}\" \
+        font-size=\"11\"><rect width=\"{width}\" height=\"{height}\" fill=\"#f0f0f0\"/>",
+        height + 20.0
+    );
+    for hz in [
+        50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0, 10000.0, 20000.0,
+    ] {
+        if hz < max_hz {
+            svg += &format!(
+                "<line x1=\"{0:.1}\" x2=\"{0:.1}\" y1=\"0\" y2=\"{height}\" stroke=\"#ccc\"/>\
+                <text x=\"{0:.1}\" y=\"{1}\" text-anchor=\"middle\">{hz}</text>",
+                x(hz),
+                height + 14.0
+            );
+        }
+    }
+    for db in (-100..=-20).step_by(20) {
+        svg += &format!(
+            "<line x1=\"0\" x2=\"{width}\" y1=\"{0:.1}\" y2=\"{0:.1}\" stroke=\"#ccc\"/>\
+            <text x=\"2\" y=\"{0:.1}\">{db} dB</text>",
+            y(db as f32)
+        );
+    }
+    svg += &format!(
+        "<polyline fill=\"none\" stroke=\"rgb(200,36,104)\" points=\"{}\"/></svg>",
+        points.join(" ")
+    );
+    svg
+}
@@ -0,0 +1,113 @@
+use std::{
+    fs::OpenOptions,
+    path::Path,
+    sync::atomic::{fence, AtomicU64, Ordering},
+};
+
+use memmap2::MmapMut;
+
+/*
+ * Layout of the shared memory region, every number is little endian:
+ *
+ * offset  size  field
+ *      0     4  magic, the bytes "FFTA"
+ *      4     4  layout version (u32), currently 1
+ *      8     8  sequence (u64), odd while a frame is being written
+ *     16     4  sample rate (u32)
+ *     20     4  FFT size (u32)
+ *     24     4  number of bins that follow the header (u32), FFT size / 2
+ *                (both can change between frames when the FFT size is switched)
+ *     28     4  reserved
+ *     32     8  frame counter (u64)
+ *     40    24  reserved
+ *     64   4*n  magnitudes of the one-sided spectrum (f32)
+ *
+ * The region is sized for the biggest FFT the app can switch to, so it never has to be
+ * resized while other processes have it mapped.
+ *
+ * Readers copy the magnitudes between two reads of the sequence and retry when the two values
+ * differ or are odd, that is a seqlock, so the writer never has to wait for anyone.
+ */
+const MAGIC: &[u8; 4] = b"FFTA";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 64;
+const SEQUENCE_OFFSET: usize = 8;
+const FFT_SIZE_OFFSET: usize = 20;
+const BINS_OFFSET: usize = 24;
+const FRAME_COUNTER_OFFSET: usize = 32;
+
+pub struct ShmPublisher {
+    map: MmapMut,
+    max_bins: usize,
+    frames: u64,
+}
+
+impl ShmPublisher {
+    /**
+     * Creates (or truncates) the file backing the region. On Linux, a path inside /dev/shm
+     * keeps it in memory only
+     */
+    pub fn create(
+        path: &Path,
+        sample_rate: u32,
+        fft_size: usize,
+        max_fft_size: usize,
+    ) -> std::io::Result<Self> {
+        let bins = fft_size / 2;
+        let max_bins = max_fft_size.max(fft_size) / 2;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len((HEADER_SIZE + max_bins * 4) as u64)?;
+
+        // Safety: the file was just sized by us. Other processes are only expected to read it,
+        // and the seqlock tells them when what they've read is not consistent
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+
+        map[0..4].copy_from_slice(MAGIC);
+        map[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        map[16..20].copy_from_slice(&sample_rate.to_le_bytes());
+        map[FFT_SIZE_OFFSET..FFT_SIZE_OFFSET + 4].copy_from_slice(&(fft_size as u32).to_le_bytes());
+        map[BINS_OFFSET..BINS_OFFSET + 4].copy_from_slice(&(bins as u32).to_le_bytes());
+
+        Ok(Self {
+            map,
+            max_bins,
+            frames: 0,
+        })
+    }
+
+    fn sequence(&self) -> &AtomicU64 {
+        // Safety: the offset is 8 byte aligned since mappings start at a page boundary, and the
+        // map lives as long as self
+        unsafe { &*(self.map.as_ptr().add(SEQUENCE_OFFSET) as *const AtomicU64) }
+    }
+
+    /**
+     * Gets the magnitudes of the full FFT output and writes its first half into the region
+     */
+    pub fn publish(&mut self, magnitudes: &[f32]) {
+        let bins = self.max_bins.min(magnitudes.len() / 2);
+
+        let sequence = self.sequence().fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        self.map[FFT_SIZE_OFFSET..FFT_SIZE_OFFSET + 4]
+            .copy_from_slice(&(magnitudes.len() as u32).to_le_bytes());
+        self.map[BINS_OFFSET..BINS_OFFSET + 4].copy_from_slice(&(bins as u32).to_le_bytes());
+
+        for (i, magnitude) in magnitudes[0..bins].iter().enumerate() {
+            let offset = HEADER_SIZE + i * 4;
+            self.map[offset..offset + 4].copy_from_slice(&magnitude.to_le_bytes());
+        }
+        self.frames += 1;
+        self.map[FRAME_COUNTER_OFFSET..FRAME_COUNTER_OFFSET + 8]
+            .copy_from_slice(&self.frames.to_le_bytes());
+
+        fence(Ordering::Release);
+        self.sequence().store(sequence + 2, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::analysis::power_to_db;
+
+// While the capture is released, the mic is opened this often to check for a signal
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+const PROBE_DURATION: Duration = Duration::from_millis(250);
+
+/**
+ * Stops the analysis once the input stayed below a level for a while, and starts it again on
+ * the first block of samples above it
+ */
+pub struct SilenceDetector {
+    threshold_db: f32,
+    hold_samples: usize,
+    quiet_samples: usize,
+    // Shared with the window, which stops drawing and may close the stream meanwhile
+    asleep: Arc<AtomicBool>,
+}
+
+impl SilenceDetector {
+    pub fn new(threshold_db: f32, hold: Duration, sample_rate: u32) -> Self {
+        Self {
+            threshold_db,
+            hold_samples: (hold.as_secs_f32() * sample_rate as f32) as usize,
+            quiet_samples: 0,
+            asleep: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn asleep(&self) -> Arc<AtomicBool> {
+        self.asleep.clone()
+    }
+
+    /**
+     * Takes a block of captured samples and returns whether they should be analyzed
+     */
+    pub fn process(&mut self, samples: &[f32]) -> bool {
+        if samples.is_empty() {
+            return !self.asleep.load(Ordering::Relaxed);
+        }
+        let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+        if power_to_db(mean_square) >= self.threshold_db {
+            self.quiet_samples = 0;
+            self.asleep.store(false, Ordering::Relaxed);
+            return true;
+        }
+        self.quiet_samples += samples.len();
+        let asleep = self.quiet_samples >= self.hold_samples;
+        self.asleep.store(asleep, Ordering::Relaxed);
+        !asleep
+    }
+}
+
+/**
+ * Decides when the capture stream is closed and opened again while the detector is asleep: it's
+ * closed right away, then opened for short probes until one of them hears something
+ */
+pub struct CaptureRelease {
+    opened_at: Instant,
+    closed_at: Option<Instant>,
+}
+
+impl CaptureRelease {
+    pub fn new() -> Self {
+        Self {
+            opened_at: Instant::now(),
+            closed_at: None,
+        }
+    }
+
+    /**
+     * Returns Some(true) when the stream has to be opened, Some(false) when it has to be closed
+     */
+    pub fn update(&mut self, asleep: bool, now: Instant) -> Option<bool> {
+        match self.closed_at {
+            None if asleep && now - self.opened_at >= PROBE_DURATION => {
+                self.closed_at = Some(now);
+                Some(false)
+            }
+            Some(closed_at) if !asleep || now - closed_at >= PROBE_INTERVAL => {
+                self.opened_at = now;
+                self.closed_at = None;
+                Some(true)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for CaptureRelease {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detector_sleeps_after_the_hold_and_wakes_on_the_first_loud_block() {
+        let sample_rate = 1000;
+        let mut detector = SilenceDetector::new(-60.0, Duration::from_secs(2), sample_rate);
+        let quiet = vec![0.0001; 100];
+        let loud = vec![0.1; 100];
+
+        for _ in 0..19 {
+            assert!(detector.process(&quiet));
+        }
+        assert!(!detector.process(&quiet));
+        assert!(detector.asleep().load(Ordering::Relaxed));
+        assert!(!detector.process(&quiet));
+
+        assert!(detector.process(&loud));
+        assert!(!detector.asleep().load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn release_probes_until_the_detector_wakes() {
+        let start = Instant::now();
+        let mut release = CaptureRelease::new();
+        assert_eq!(release.update(false, start + Duration::from_secs(5)), None);
+        assert_eq!(
+            release.update(true, start + Duration::from_secs(5)),
+            Some(false)
+        );
+        assert_eq!(
+            release.update(true, start + Duration::from_millis(5500)),
+            None
+        );
+        // A probe, closed again when it heard nothing
+        assert_eq!(
+            release.update(true, start + Duration::from_secs(6)),
+            Some(true)
+        );
+        assert_eq!(
+            release.update(true, start + Duration::from_millis(6100)),
+            None
+        );
+        assert_eq!(
+            release.update(true, start + Duration::from_millis(6250)),
+            Some(false)
+        );
+        assert_eq!(
+            release.update(true, start + Duration::from_millis(7250)),
+            Some(true)
+        );
+        // This one heard something, the stream stays open
+        assert_eq!(
+            release.update(false, start + Duration::from_millis(7500)),
+            None
+        );
+    }
+}
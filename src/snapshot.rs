@@ -0,0 +1,38 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/**
+ * Writes the samples as a mono 32 bit float WAV file inside `directory`.
+ * The file name holds the current unix time in milliseconds, so snapshots never overwrite
+ * each other. Returns the path of the new file.
+ */
+pub fn save_wav(directory: &Path, samples: &[f32], sample_rate: u32) -> hound::Result<PathBuf> {
+    std::fs::create_dir_all(directory)?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let path = directory.join(format!("snapshot-{timestamp}.wav"));
+    write_wav(&path, samples, sample_rate)?;
+    Ok(path)
+}
+
+/**
+ * Writes the samples as a mono 32 bit float WAV file at `path`
+ */
+pub fn write_wav(path: &Path, samples: &[f32], sample_rate: u32) -> hound::Result<()> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for sample in samples {
+        writer.write_sample(*sample)?;
+    }
+    writer.finalize()
+}
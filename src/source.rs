@@ -0,0 +1,217 @@
+use std::{
+    f64::consts::PI,
+    fmt,
+    str::FromStr,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{analysis::db_to_amplitude, clock, SampleCallback};
+
+// Level of the tones of a synthetic input given without one
+const DEFAULT_TONE_DB: f32 = -20.0;
+// The synthetic input hands its samples over in blocks this long, like a sound card would
+const BLOCK_MS: u64 = 10;
+
+/**
+ * Where the analyzer takes its samples from
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Input {
+    #[default]
+    Mic,
+    // Sines and noise computed on the fly, no audio hardware needed
+    Synth(SynthSpec),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SynthTone {
+    pub frequency_in_hz: f32,
+    pub level_db: f32,
+}
+
+/**
+ * Sum of sines and white noise, written like `synth:440,1000@-30,noise@-60`: each tone is a
+ * frequency in Hz with an optional level in dBFS, the noise has the level of a sine with the
+ * same power
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SynthSpec {
+    pub tones: Vec<SynthTone>,
+    pub noise_db: Option<f32>,
+}
+
+impl FromStr for Input {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "mic" {
+            return Ok(Self::Mic);
+        }
+        let spec = s
+            .strip_prefix("synth:")
+            .ok_or_else(|| format!("\"{s}\" is neither mic nor synth:..."))?;
+
+        let mut synth = SynthSpec::default();
+        for component in spec.split(',').map(str::trim) {
+            let (name, level_db) = match component.split_once('@') {
+                Some((name, level)) => (
+                    name,
+                    Some(
+                        level
+                            .parse::<f32>()
+                            .map_err(|_| format!("\"{level}\" is not a level in dBFS"))?,
+                    ),
+                ),
+                None => (component, None),
+            };
+            if name == "noise" {
+                synth.noise_db = Some(level_db.ok_or("The noise needs a level, like noise@-60")?);
+                continue;
+            }
+            let frequency_in_hz = name
+                .parse::<f32>()
+                .ok()
+                .filter(|frequency_in_hz| *frequency_in_hz > 0.0)
+                .ok_or_else(|| format!("\"{name}\" is neither a frequency in Hz nor noise"))?;
+            synth.tones.push(SynthTone {
+                frequency_in_hz,
+                level_db: level_db.unwrap_or(DEFAULT_TONE_DB),
+            });
+        }
+        Ok(Self::Synth(synth))
+    }
+}
+
+impl fmt::Display for SynthSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut components: Vec<String> = self
+            .tones
+            .iter()
+            .map(|tone| format!("{}@{}", tone.frequency_in_hz, tone.level_db))
+            .collect();
+        if let Some(noise_db) = self.noise_db {
+            components.push(format!("noise@{noise_db}"));
+        }
+        write!(f, "synth:{}", components.join(","))
+    }
+}
+
+/**
+ * Computes the samples of a synthetic input. The phases come from the position of the sample,
+ * so tones don't drift however long it runs, and the noise always starts from the same seed so
+ * runs can be reproduced.
+ */
+pub struct SyntheticSource {
+    spec: SynthSpec,
+    sample_rate: u32,
+    position: u64,
+    noise_state: u32,
+}
+
+impl SyntheticSource {
+    pub fn new(spec: SynthSpec, sample_rate: u32) -> Self {
+        Self {
+            spec,
+            sample_rate,
+            position: 0,
+            noise_state: 0x2545_F491,
+        }
+    }
+
+    pub fn next_block(&mut self, len: usize) -> Vec<f32> {
+        // White noise from -1 to 1 has a power of 1/3, a sine of amplitude A one of A² / 2
+        let noise_amplitude = self
+            .spec
+            .noise_db
+            .map_or(0.0, |noise_db| db_to_amplitude(noise_db) * 1.5f32.sqrt());
+        (0..len)
+            .map(|_| {
+                let t = self.position as f64 / self.sample_rate as f64;
+                self.position += 1;
+                let tones: f32 = self
+                    .spec
+                    .tones
+                    .iter()
+                    .map(|tone| {
+                        db_to_amplitude(tone.level_db)
+                            * (2.0 * PI * tone.frequency_in_hz as f64 * t).sin() as f32
+                    })
+                    .sum();
+                self.noise_state ^= self.noise_state << 13;
+                self.noise_state ^= self.noise_state >> 17;
+                self.noise_state ^= self.noise_state << 5;
+                let white = self.noise_state as f32 / u32::MAX as f32 * 2.0 - 1.0;
+                tones + white * noise_amplitude
+            })
+            .collect()
+    }
+}
+
+/**
+ * Feeds the synthetic input to `on_samples` in real time, as the mic would
+ */
+pub fn spawn(spec: SynthSpec, sample_rate: u32, on_samples: SampleCallback) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut source = SyntheticSource::new(spec, sample_rate);
+        let block_len = (sample_rate as u64 * BLOCK_MS / 1000) as usize;
+        let started = Instant::now();
+        for block in 1u32.. {
+            let samples = source.next_block(block_len);
+            // As if the block had just been captured, like a sound card hands it over
+            let captured = clock::start_of(Instant::now(), block_len, sample_rate);
+            on_samples.lock().unwrap()(&samples, captured);
+            // Paced on the start, so the time spent computing doesn't add up
+            let due = started + Duration::from_millis(BLOCK_MS) * block;
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::goertzel_level_db;
+
+    #[test]
+    fn parses_a_synthetic_input() {
+        let Ok(Input::Synth(spec)) = "synth:440,1000.5@-30,noise@-60".parse::<Input>() else {
+            panic!("not a synthetic input");
+        };
+        assert_eq!(spec.tones.len(), 2);
+        assert_eq!(spec.tones[0].level_db, DEFAULT_TONE_DB);
+        assert_eq!(spec.tones[1].frequency_in_hz, 1000.5);
+        assert_eq!(spec.noise_db, Some(-60.0));
+        assert_eq!(spec.to_string(), "synth:440@-20,1000.5@-30,noise@-60");
+        assert_eq!("mic".parse::<Input>(), Ok(Input::Mic));
+        assert!("synth:noise".parse::<Input>().is_err());
+        assert!("line-in".parse::<Input>().is_err());
+    }
+
+    #[test]
+    fn tones_and_noise_have_their_levels() {
+        let sample_rate = 48000;
+        let Ok(Input::Synth(spec)) = "synth:1000@-12,noise@-40".parse::<Input>() else {
+            panic!("not a synthetic input");
+        };
+        let mut source = SyntheticSource::new(spec, sample_rate);
+        // Blocks of any length join seamlessly
+        let mut samples = source.next_block(1000);
+        samples.extend(source.next_block(47000));
+        let tone_db = goertzel_level_db(&samples, 1000.0, sample_rate);
+        assert!((tone_db - -12.0).abs() < 0.1, "{tone_db}");
+
+        let mut noise_only = SyntheticSource::new(
+            SynthSpec {
+                tones: vec![],
+                noise_db: Some(-40.0),
+            },
+            sample_rate,
+        );
+        let noise = noise_only.next_block(48000);
+        let power = noise.iter().map(|s| s * s).sum::<f32>() / noise.len() as f32;
+        // The power of a sine at -40 dBFS
+        let expected = db_to_amplitude(-40.0).powi(2) / 2.0;
+        assert!((power / expected - 1.0).abs() < 0.05, "{power} {expected}");
+    }
+}
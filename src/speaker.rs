@@ -0,0 +1,373 @@
+use std::{
+    f32::consts::PI,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    StreamConfig,
+};
+
+use crate::{
+    analysis::{amplitude_to_db, db_to_amplitude, octave_smoothed, BinMapping},
+    cli::{SpeakerArgs, SpeakerCheck},
+    curve::Curve,
+    fft_magnitudes, open_input_stream,
+    sti::{self, STI_BANDS_HZ},
+    SampleCallback,
+};
+
+// Level of the clicks and of the sweep, loud enough to stand out of the room noise
+const TEST_LEVEL_DB: f32 = -12.0;
+
+const CLICKS: usize = 6;
+const CLICK_INTERVAL_SECS: f32 = 0.5;
+// Short enough to stay a single push of the cone, long enough for a woofer to follow it
+const CLICK_SECS: f32 = 0.001;
+// The first arrival has to come within this long after each click
+const MAX_LATENCY_SECS: f32 = 0.3;
+
+const SWEEP_LOW_HZ: f32 = 20.0;
+const SWEEP_HIGH_HZ: f32 = 20000.0;
+// Recorded after the end of the signal, for the latency of the devices and the room
+const TAIL_SECS: f32 = 0.5;
+
+/**
+ * A Hann shaped pulse going positive only, which pushes the cone of a speaker wired in
+ * polarity forwards
+ */
+fn click(sample_rate: u32) -> Vec<f32> {
+    let len = ((CLICK_SECS * sample_rate as f32) as usize).max(3);
+    (0..len)
+        .map(|i| (PI * i as f32 / (len - 1) as f32).sin().powi(2))
+        .collect()
+}
+
+/**
+ * Clicks every CLICK_INTERVAL_SECS, starting after one interval of silence
+ */
+fn click_train(sample_rate: u32) -> (Vec<f32>, Vec<usize>) {
+    let interval = (CLICK_INTERVAL_SECS * sample_rate as f32) as usize;
+    let click = click(sample_rate);
+    let mut signal = vec![0.0; interval * (CLICKS + 1)];
+    let starts: Vec<usize> = (1..=CLICKS).map(|i| i * interval).collect();
+    for start in &starts {
+        for (i, sample) in click.iter().enumerate() {
+            signal[start + i] = sample * db_to_amplitude(TEST_LEVEL_DB);
+        }
+    }
+    (signal, starts)
+}
+
+/**
+ * Exponential sine sweep, which spends the same time on every octave, with short fades so its
+ * ends don't click
+ */
+fn sweep(sample_rate: u32, secs: f32) -> Vec<f32> {
+    let high_hz = SWEEP_HIGH_HZ.min(sample_rate as f32 * 0.45);
+    let len = (secs * sample_rate as f32) as usize;
+    let ratio = (high_hz / SWEEP_LOW_HZ).ln();
+    let fade = (0.01 * sample_rate as f32) as usize;
+    (0..len)
+        .map(|i| {
+            let t = i as f32 / sample_rate as f32;
+            let phase = 2.0 * PI * SWEEP_LOW_HZ * secs / ratio * ((t / secs * ratio).exp() - 1.0);
+            let gain = (i.min(len - 1 - i) as f32 / fade as f32).min(1.0);
+            phase.sin() * gain * db_to_amplitude(TEST_LEVEL_DB)
+        })
+        .collect()
+}
+
+/**
+ * Plays the signal through the default output while recording the default input, and returns
+ * the recording with its sample rate
+ */
+fn play_and_record(make_signal: impl FnOnce(u32) -> Vec<f32>) -> Result<(Vec<f32>, u32), String> {
+    let speaker = cpal::default_host()
+        .default_output_device()
+        .ok_or("No output device found")?;
+    let config: StreamConfig = speaker
+        .default_output_config()
+        .map_err(|error| error.to_string())?
+        .into();
+    let sample_rate = config.sample_rate.0;
+    let channels = config.channels as usize;
+    let signal = make_signal(sample_rate);
+    let duration = Duration::from_secs_f32(signal.len() as f32 / sample_rate as f32 + TAIL_SECS);
+
+    let recording = Arc::new(Mutex::new(Vec::<f32>::new()));
+    let stream_recording = recording.clone();
+    let on_samples: SampleCallback = Arc::new(Mutex::new(move |data: &[f32], _: Instant| {
+        stream_recording.lock().unwrap().extend_from_slice(data)
+    }));
+    let (input, _) = open_input_stream(None, sample_rate, on_samples, |error| {
+        eprintln!("\nInput error: {error}")
+    })?;
+
+    let mut position = 0;
+    let output = speaker
+        .build_output_stream(
+            &config,
+            move |data: &mut [f32], _info| {
+                for frame in data.chunks_mut(channels) {
+                    frame.fill(signal.get(position).copied().unwrap_or(0.0));
+                    position += 1;
+                }
+            },
+            |error| eprintln!("\nOutput error: {error}"),
+            None,
+        )
+        .map_err(|error| error.to_string())?;
+
+    input.play().map_err(|error| error.to_string())?;
+    output.play().map_err(|error| error.to_string())?;
+    std::thread::sleep(duration);
+    drop(output);
+    drop(input);
+
+    let recording = recording.lock().unwrap().clone();
+    Ok((recording, sample_rate))
+}
+
+/**
+ * Direction of the first arrival of each click: the recording is correlated with the click and
+ * the strongest value after each one keeps its sign. Returns +1 for a positive arrival, -1 for
+ * an inverted one and 0 when it didn't stand out of the noise.
+ */
+fn arrival_signs(recording: &[f32], click: &[f32], starts: &[usize], sample_rate: u32) -> Vec<i8> {
+    let correlation: Vec<f32> = (0..recording.len().saturating_sub(click.len()))
+        .map(|lag| {
+            click
+                .iter()
+                .zip(&recording[lag..])
+                .map(|(c, r)| c * r)
+                .sum()
+        })
+        .collect();
+    let Some(first) = starts.first() else {
+        return vec![];
+    };
+    // The silence before the first click tells how strong the noise is
+    let noise = correlation[..(*first).min(correlation.len())]
+        .iter()
+        .fold(0.0f32, |peak, value| peak.max(value.abs()));
+
+    let window = (MAX_LATENCY_SECS * sample_rate as f32) as usize;
+    starts
+        .iter()
+        .map(|start| {
+            let end = (start + window).min(correlation.len());
+            let strongest = correlation
+                .get(*start..end)
+                .unwrap_or_default()
+                .iter()
+                .max_by(|a, b| a.abs().total_cmp(&b.abs()));
+            match strongest {
+                Some(value) if value.abs() > noise * 4.0 => value.signum() as i8,
+                _ => 0,
+            }
+        })
+        .collect()
+}
+
+/**
+ * Frequency response of whatever the sweep went through, in dB at 1/12 octave steps: the 1/6
+ * octave smoothed spectrum of the recording over the one of the sweep
+ */
+fn sweep_response(sweep: &[f32], recording: &[f32], sample_rate: u32) -> Vec<(f32, f32)> {
+    let len = recording.len().max(sweep.len()).next_power_of_two();
+    let padded = |samples: &[f32]| {
+        let mut padded = samples.to_vec();
+        padded.resize(len, 0.0);
+        padded
+    };
+    let mapping = BinMapping::new(sample_rate, len);
+    let smoothed = |samples: &[f32]| {
+        let magnitudes = fft_magnitudes(&padded(samples));
+        octave_smoothed(&magnitudes[..len / 2], mapping, 6.0)
+    };
+    let played = smoothed(sweep);
+    let heard = smoothed(recording);
+
+    let high_hz = SWEEP_HIGH_HZ.min(sample_rate as f32 * 0.45);
+    (0..)
+        .map(|step| SWEEP_LOW_HZ * 2f32.powf(step as f32 / 12.0))
+        .take_while(|frequency_in_hz| *frequency_in_hz <= high_hz)
+        .map(|frequency_in_hz| {
+            let bin = mapping.bin_at(frequency_in_hz);
+            (
+                frequency_in_hz,
+                amplitude_to_db(heard[bin]) - amplitude_to_db(played[bin]),
+            )
+        })
+        .collect()
+}
+
+fn save_curve(path: &Path, points: &[(f32, f32)]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "* Frequency response measured with a sweep, Hz dB")?;
+    for (frequency_in_hz, level_db) in points {
+        writeln!(file, "{frequency_in_hz:.1} {level_db:.2}")?;
+    }
+    file.flush()
+}
+
+/**
+ * Frequencies where the level difference between the two curves changes sign, between 50 Hz
+ * and 15 kHz, with the level there
+ */
+fn crossings(a: &Curve, b: &Curve) -> Vec<(f32, f32)> {
+    let frequencies: Vec<f32> = (0..)
+        .map(|step| 50.0 * 2f32.powf(step as f32 / 48.0))
+        .take_while(|frequency_in_hz| *frequency_in_hz <= 15000.0)
+        .collect();
+    frequencies
+        .windows(2)
+        .filter_map(|pair| {
+            let before = a.db_at(pair[0]) - b.db_at(pair[0]);
+            let after = a.db_at(pair[1]) - b.db_at(pair[1]);
+            (before.signum() != after.signum()).then(|| {
+                // Where the difference crosses zero between the two steps, on a log axis
+                let t = before / (before - after);
+                let frequency_in_hz = pair[0] * (pair[1] / pair[0]).powf(t);
+                (frequency_in_hz, a.db_at(frequency_in_hz))
+            })
+        })
+        .collect()
+}
+
+pub fn run(args: &SpeakerArgs) -> Result<(), String> {
+    match &args.check {
+        SpeakerCheck::Polarity => {
+            println!("Playing {CLICKS} clicks, keep the mic close to the driver");
+            let mut starts = vec![];
+            let (recording, sample_rate) = play_and_record(|sample_rate| {
+                let (signal, click_starts) = click_train(sample_rate);
+                starts = click_starts;
+                signal
+            })?;
+            let signs = arrival_signs(&recording, &click(sample_rate), &starts, sample_rate);
+            let positive = signs.iter().filter(|sign| **sign > 0).count();
+            let negative = signs.iter().filter(|sign| **sign < 0).count();
+            println!(
+                "{positive} positive, {negative} inverted, {} not heard",
+                signs.len() - positive - negative
+            );
+            match (positive, negative) {
+                (0, 0) => println!("No click was heard, turn the volume up or move the mic closer"),
+                _ if positive > negative * 3 => println!("In polarity: the first push is positive"),
+                _ if negative > positive * 3 => println!("Inverted: swap the wires of this driver"),
+                _ => {
+                    println!("Inconclusive, the clicks disagree: move the mic closer to the driver")
+                }
+            }
+            println!(
+                "Mics and interfaces can invert too, check a speaker known to be right first."
+            );
+        }
+        SpeakerCheck::Sweep { out, secs } => {
+            println!(
+                "Playing a {secs} s sweep, keep the mic and the volume the same for every driver"
+            );
+            let mut played = vec![];
+            let (recording, sample_rate) = play_and_record(|sample_rate| {
+                played = sweep(sample_rate, *secs);
+                played.clone()
+            })?;
+            let response = sweep_response(&played, &recording, sample_rate);
+            save_curve(out, &response).map_err(|error| error.to_string())?;
+            for (frequency_in_hz, level_db) in response.iter().step_by(4) {
+                println!("{frequency_in_hz:8.0} Hz {level_db:7.1} dB");
+            }
+            println!("Saved the response to {}", out.display());
+        }
+        SpeakerCheck::Compare { first, second } => {
+            let a = Curve::load(first)?;
+            let b = Curve::load(second)?;
+            println!("{:>8}  {:>8}  {:>8}", "Hz", "first", "second");
+            for step in 0..=30 {
+                let frequency_in_hz = 25.0 * 2f32.powf(step as f32 / 3.0);
+                println!(
+                    "{frequency_in_hz:8.0}  {:8.1}  {:8.1}",
+                    a.db_at(frequency_in_hz),
+                    b.db_at(frequency_in_hz)
+                );
+            }
+            let crossings = crossings(&a, &b);
+            if crossings.is_empty() {
+                println!("The responses don't cross between 50 Hz and 15 kHz");
+            }
+            for (frequency_in_hz, level_db) in crossings {
+                println!("The responses cross at {frequency_in_hz:.0} Hz, {level_db:.1} dB");
+            }
+        }
+        SpeakerCheck::Sti { secs } => {
+            println!("Playing a {secs} s sweep, put the mic where the listeners are");
+            let mut played = vec![];
+            let (recording, sample_rate) = play_and_record(|sample_rate| {
+                played = sweep(sample_rate, *secs);
+                played.clone()
+            })?;
+            let response = sti::impulse_response(&played, &recording, sample_rate);
+            let transmission = sti::estimate(&response, sample_rate);
+            for (center_in_hz, transfer) in STI_BANDS_HZ.iter().zip(&transmission.band_transfers) {
+                println!("{center_in_hz:8.0} Hz  MTI {transfer:.2}");
+            }
+            println!("STI {:.2} ({})", transmission.index, transmission.rating());
+            println!("The background noise is left out, a noisy room understands less than this.");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverted_and_delayed_clicks_read_as_inverted() {
+        let sample_rate = 48000;
+        let (signal, starts) = click_train(sample_rate);
+        let delay = 700;
+        let mut recording = vec![0.0; delay];
+        // A speaker wired the other way, ringing a little after each click
+        recording.extend(signal.iter().enumerate().map(|(i, sample)| {
+            let ring = if i >= 5 { 0.3 * signal[i - 5] } else { 0.0 };
+            -0.5 * sample + ring + 0.0001 * ((i * 7919) % 13) as f32
+        }));
+
+        let signs = arrival_signs(&recording, &click(sample_rate), &starts, sample_rate);
+        assert_eq!(signs, vec![-1; CLICKS]);
+    }
+
+    #[test]
+    fn sweep_through_a_gain_measures_flat() {
+        let sample_rate = 8000;
+        let played = sweep(sample_rate, 1.0);
+        let mut recording = vec![0.0; 100];
+        recording.extend(played.iter().map(|sample| sample * 0.5));
+
+        let response = sweep_response(&played, &recording, sample_rate);
+        for (frequency_in_hz, level_db) in response {
+            if (100.0..3000.0).contains(&frequency_in_hz) {
+                assert!(
+                    (level_db + 6.0).abs() < 0.5,
+                    "{frequency_in_hz}: {level_db}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn crossing_of_a_low_pass_and_a_high_pass() {
+        let woofer = Curve::parse("100 0\n1000 0\n4000 -24").unwrap();
+        let tweeter = Curve::parse("500 -24\n2000 0\n10000 0").unwrap();
+        let crossings = crossings(&woofer, &tweeter);
+        assert_eq!(crossings.len(), 1);
+        assert!((crossings[0].0 - 1414.0).abs() < 60.0, "{:?}", crossings);
+    }
+}
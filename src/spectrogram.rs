@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+
+use sdl2::{
+    pixels::PixelFormatEnum,
+    rect::Rect,
+    render::{Canvas, TextureCreator},
+    video::{Window, WindowContext},
+};
+
+use crate::{
+    analysis::{amplitude_to_db, BinMapping},
+    image::spectrogram_color,
+};
+
+// Levels this far below the loudest one on screen are drawn black
+const SPECTROGRAM_RANGE_DB: f32 = 60.0;
+
+/**
+ * The other way of looking at the live spectrum: the last `max_columns` frames side by side
+ * with the time going to the right and the frequency going up, the level as the color
+ */
+pub struct Spectrogram {
+    max_columns: usize,
+    // Level in dBFS of each bin up to the displayed frequency of the frames, the newest last
+    columns: VecDeque<Vec<f32>>,
+}
+
+impl Spectrogram {
+    pub fn new(max_columns: usize) -> Self {
+        Self {
+            max_columns: max_columns.max(1),
+            columns: VecDeque::new(),
+        }
+    }
+
+    /**
+     * Adds the one-sided amplitudes of a frame, keeping the bins up to `max_frequency_in_hz`.
+     * A frame with another number of bins (the FFT size or the range changed) starts over.
+     */
+    pub fn push(&mut self, amplitudes: &[f32], mapping: BinMapping, max_frequency_in_hz: f32) {
+        let bins = mapping
+            .bins_up_to(max_frequency_in_hz)
+            .min(amplitudes.len());
+        if self
+            .columns
+            .back()
+            .is_some_and(|column| column.len() != bins)
+        {
+            self.columns.clear();
+        }
+        self.columns.push_back(
+            amplitudes[..bins]
+                .iter()
+                .map(|a| amplitude_to_db(*a))
+                .collect(),
+        );
+        if self.columns.len() > self.max_columns {
+            self.columns.pop_front();
+        }
+    }
+
+    /**
+     * Stretches the frames over `area`, the newest at the right edge and the columns not
+     * filled yet left black
+     */
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        texture_creator: &TextureCreator<WindowContext>,
+        area: Rect,
+    ) {
+        let Some(bins) = self
+            .columns
+            .back()
+            .map(|column| column.len())
+            .filter(|bins| *bins > 0)
+        else {
+            return;
+        };
+        let loudest_db = self
+            .columns
+            .iter()
+            .flatten()
+            .fold(f32::MIN, |loudest, level_db| loudest.max(*level_db));
+        let first_x = self.max_columns - self.columns.len();
+
+        let mut texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, self.max_columns as u32, bins as u32)
+            .unwrap();
+        texture
+            .with_lock(None, |pixels, pitch| {
+                pixels.fill(0);
+                for (x, column) in self.columns.iter().enumerate() {
+                    for (bin, level_db) in column.iter().enumerate() {
+                        let t = 1.0 - (loudest_db - level_db) / SPECTROGRAM_RANGE_DB;
+                        // The low frequencies at the bottom
+                        let offset = (bins - 1 - bin) * pitch + (first_x + x) * 3;
+                        pixels[offset..offset + 3].copy_from_slice(&spectrogram_color(t));
+                    }
+                }
+            })
+            .unwrap();
+        canvas.copy(&texture, None, area).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_last_frames_are_kept() {
+        let mapping = BinMapping::new(1000, 64);
+        let mut spectrogram = Spectrogram::new(3);
+        for level in [0.1, 0.2, 0.3, 0.4] {
+            spectrogram.push(&[level; 33], mapping, 250.0);
+        }
+        assert_eq!(spectrogram.columns.len(), 3);
+        assert_eq!(spectrogram.columns[0][0], amplitude_to_db(0.2));
+        // The bins up to 250 Hz, its own included
+        assert_eq!(spectrogram.columns[0].len(), 17);
+
+        // Zooming out starts over
+        spectrogram.push(&[0.5; 33], mapping, 500.0);
+        assert_eq!(spectrogram.columns.len(), 1);
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use num_complex::Complex;
+use sdl2::{
+    event::Event, keyboard::Keycode, pixels::Color, rect::Rect, render::Canvas, video::Window,
+};
+
+use crate::{
+    analysis::{amplitude_to_db, BinMapping, FftPlan},
+    audio::open_stereo_input_stream,
+    cli::StereoArgs,
+    text,
+    windowing::WindowFunction,
+    SampleCallback,
+};
+
+const FRAME_LEN: usize = 4096;
+const HOP: usize = FRAME_LEN / 2;
+// Centers of the octave bands the stereo field is split into
+const BANDS_HZ: [f32; 10] = [
+    31.5, 63.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+// Share of the running sums kept at every frame, about a second of memory at 48 kHz
+const SMOOTHING: f32 = 0.9;
+// Bands quieter than this in both channels have no place in the field
+const QUIET_DB: f32 = -80.0;
+// Level difference drawn at the edges of the field, like a source panned hard to one side
+const FIELD_DB: f32 = 20.0;
+
+// Where the rows of the bands and the field are drawn
+const LABEL_X: i32 = 20;
+const FIELD_LEFT: i32 = 120;
+const FIELD_WIDTH: i32 = 440;
+const READOUT_X: i32 = 590;
+const TOP_Y: i32 = 80;
+const ROW_HEIGHT: i32 = 44;
+
+/**
+ * Where a band sits between the two channels
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct BandImage {
+    pub center_hz: f32,
+    // Level of the louder channel in dBFS
+    pub level_db: f32,
+    // Left over right, positive when the band leans left
+    pub level_difference_db: f32,
+    // Phase of the left channel ahead of the right one, from -180 to 180
+    pub phase_difference_degrees: f32,
+    // 1 for the same signal in both channels, 0 for unrelated ones, -1 for opposite ones
+    pub correlation: f32,
+    // Level of the mono sum against the average of the channels: 0 dB when they add up, -3 dB
+    // when they are unrelated, far below when they cancel out
+    pub mono_change_db: f32,
+}
+
+/**
+ * Running sums of the power of each channel, of the cross spectrum and of the mono sum, per
+ * octave band
+ */
+pub struct StereoImage {
+    mapping: BinMapping,
+    plan: FftPlan,
+    window: Vec<f32>,
+    left_power: [f32; BANDS_HZ.len()],
+    right_power: [f32; BANDS_HZ.len()],
+    mono_power: [f32; BANDS_HZ.len()],
+    cross: [Complex<f32>; BANDS_HZ.len()],
+}
+
+impl StereoImage {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            mapping: BinMapping::new(sample_rate, FRAME_LEN),
+            plan: FftPlan::new(FRAME_LEN),
+            window: WindowFunction::Hann.coefficients(FRAME_LEN),
+            left_power: [0.0; BANDS_HZ.len()],
+            right_power: [0.0; BANDS_HZ.len()],
+            mono_power: [0.0; BANDS_HZ.len()],
+            cross: [Complex::new(0.0, 0.0); BANDS_HZ.len()],
+        }
+    }
+
+    /**
+     * One-sided spectrum of a frame, scaled so a sine on a bin has its amplitude
+     */
+    fn spectrum(&self, samples: &[f32]) -> Vec<Complex<f32>> {
+        let scale = 2.0 / self.window.iter().sum::<f32>();
+        let mut spectrum: Vec<Complex<f32>> = samples
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, w)| Complex::from(sample * w * scale))
+            .collect();
+        self.plan.process(&mut spectrum);
+        spectrum.truncate(FRAME_LEN / 2);
+        spectrum
+    }
+
+    /**
+     * Adds a frame of FRAME_LEN samples of each channel
+     */
+    pub fn push(&mut self, left: &[f32], right: &[f32]) {
+        let left = self.spectrum(left);
+        let right = self.spectrum(right);
+        let nyquist = self.mapping.frequency_in_hz(FRAME_LEN / 2);
+        for (band, center_hz) in BANDS_HZ.iter().enumerate() {
+            let mut sums = (0.0, 0.0, 0.0, Complex::new(0.0, 0.0));
+            let low = self.mapping.bin_at(center_hz / 2f32.sqrt());
+            let high = self
+                .mapping
+                .bin_at((center_hz * 2f32.sqrt()).min(nyquist))
+                .min(FRAME_LEN / 2 - 1);
+            for bin in low..=high {
+                let (l, r) = (left[bin], right[bin]);
+                sums.0 += l.norm_sqr();
+                sums.1 += r.norm_sqr();
+                sums.2 += ((l + r) / 2.0).norm_sqr();
+                sums.3 += l * r.conj();
+            }
+            self.left_power[band] = self.left_power[band] * SMOOTHING + sums.0;
+            self.right_power[band] = self.right_power[band] * SMOOTHING + sums.1;
+            self.mono_power[band] = self.mono_power[band] * SMOOTHING + sums.2;
+            self.cross[band] = self.cross[band] * SMOOTHING + sums.3;
+        }
+    }
+
+    /**
+     * The bands the sample rate reaches, quiet ones included
+     */
+    pub fn bands(&self) -> Vec<BandImage> {
+        let nyquist = self.mapping.frequency_in_hz(FRAME_LEN / 2);
+        let smoothed_frames = 1.0 / (1.0 - SMOOTHING);
+        BANDS_HZ
+            .iter()
+            .enumerate()
+            .take_while(|(_, center_hz)| **center_hz < nyquist)
+            .map(|(band, center_hz)| {
+                let (left, right) = (self.left_power[band], self.right_power[band]);
+                let both = (left * right).sqrt().max(f32::MIN_POSITIVE);
+                let average = ((left + right) / 2.0).max(f32::MIN_POSITIVE);
+                BandImage {
+                    center_hz: *center_hz,
+                    level_db: amplitude_to_db((left.max(right) / smoothed_frames).sqrt()),
+                    level_difference_db: 10.0
+                        * ((left + f32::MIN_POSITIVE) / (right + f32::MIN_POSITIVE)).log10(),
+                    phase_difference_degrees: self.cross[band].arg().to_degrees(),
+                    correlation: (self.cross[band].re / both).clamp(-1.0, 1.0),
+                    mono_change_db: 10.0
+                        * (self.mono_power[band].max(f32::MIN_POSITIVE) / average).log10(),
+                }
+            })
+            .collect()
+    }
+}
+
+/**
+ * Green for a band that adds up in mono, through yellow, to red for one that cancels out
+ */
+fn correlation_color(correlation: f32) -> Color {
+    let t = (correlation + 1.0) / 2.0;
+    Color::RGB(
+        (255.0 * (2.0 - 2.0 * t).min(1.0)) as u8,
+        (220.0 * (2.0 * t).min(1.0)) as u8,
+        60,
+    )
+}
+
+fn draw(canvas: &mut Canvas<Window>, bands: &[BandImage]) {
+    canvas.set_draw_color(Color::RGB(30, 30, 30));
+    canvas.clear();
+    let white = Color::RGB(230, 230, 230);
+    let grey = Color::RGB(150, 150, 150);
+    text::draw_text(
+        canvas,
+        "Stereo image per octave band",
+        LABEL_X,
+        12,
+        2,
+        white,
+    );
+    text::draw_text(
+        canvas,
+        "Position: level difference   Color: correlation, red cancels in mono   Esc: quit",
+        LABEL_X,
+        12 + text::line_height(2) as i32,
+        1,
+        grey,
+    );
+    let center_x = FIELD_LEFT + FIELD_WIDTH / 2;
+    text::draw_text(canvas, "L", FIELD_LEFT, TOP_Y - 16, 1, white);
+    text::draw_text(canvas, "C", center_x - 3, TOP_Y - 16, 1, white);
+    text::draw_text(
+        canvas,
+        "R",
+        FIELD_LEFT + FIELD_WIDTH - 6,
+        TOP_Y - 16,
+        1,
+        white,
+    );
+    canvas.set_draw_color(Color::RGB(80, 80, 80));
+    let bottom_y = TOP_Y + ROW_HEIGHT * bands.len() as i32;
+    for x in [FIELD_LEFT, center_x, FIELD_LEFT + FIELD_WIDTH] {
+        canvas.draw_line((x, TOP_Y), (x, bottom_y)).unwrap();
+    }
+
+    for (row, band) in bands.iter().enumerate() {
+        let y = TOP_Y + row as i32 * ROW_HEIGHT;
+        let label = match band.center_hz {
+            hz if hz >= 1000.0 => format!("{} kHz", hz / 1000.0),
+            hz => format!("{hz} Hz"),
+        };
+        text::draw_text(canvas, &label, LABEL_X, y + ROW_HEIGHT / 3, 2, white);
+        if band.level_db < QUIET_DB {
+            text::draw_text(canvas, "quiet", READOUT_X, y + ROW_HEIGHT / 3, 1, grey);
+            continue;
+        }
+
+        // The louder left channel pulls the band to the left
+        let pan = (-band.level_difference_db / FIELD_DB).clamp(-1.0, 1.0);
+        let x = center_x + (pan * FIELD_WIDTH as f32 / 2.0) as i32;
+        canvas.set_draw_color(correlation_color(band.correlation));
+        canvas
+            .fill_rect(Rect::new(x - 6, y + 8, 12, (ROW_HEIGHT - 16) as u32))
+            .unwrap();
+        text::draw_text(
+            canvas,
+            &format!(
+                "{:+5.1} dB  {:+4.0} deg  corr {:+.2}  mono {:+5.1} dB",
+                band.level_difference_db,
+                band.phase_difference_degrees,
+                band.correlation,
+                band.mono_change_db
+            ),
+            READOUT_X,
+            y + ROW_HEIGHT / 3,
+            1,
+            correlation_color(band.correlation),
+        );
+    }
+    canvas.present();
+}
+
+/**
+ * Opens the input in stereo and shows where each octave band sits between the channels
+ */
+pub fn run(args: &StereoArgs) -> Result<(), String> {
+    let image = Arc::new(Mutex::new(StereoImage::new(args.sample_rate)));
+    let stream_image = image.clone();
+    let mut pending = Vec::<f32>::new();
+    let on_samples: SampleCallback = Arc::new(Mutex::new(move |data: &[f32], _: Instant| {
+        pending.extend_from_slice(data);
+        while pending.len() >= FRAME_LEN * 2 {
+            let (left, right): (Vec<f32>, Vec<f32>) = pending[..FRAME_LEN * 2]
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .unzip();
+            stream_image.lock().unwrap().push(&left, &right);
+            pending.drain(..HOP * 2);
+        }
+    }));
+    let (input, device) = open_stereo_input_stream(
+        args.device.as_deref(),
+        args.sample_rate,
+        on_samples,
+        |error| eprintln!("\nInput error: {error}"),
+    )?;
+    println!(
+        "Listening to both channels of {}",
+        device.name().unwrap_or_default()
+    );
+    input.play().map_err(|error| error.to_string())?;
+
+    let sdl_context = sdl2::init()?;
+    let height = (TOP_Y + ROW_HEIGHT * BANDS_HZ.len() as i32 + 20) as u32;
+    let window = sdl_context
+        .video()?
+        .window("Stereo image", 1000, height)
+        .position_centered()
+        .build()
+        .map_err(|error| error.to_string())?;
+    let mut canvas = window
+        .into_canvas()
+        .build()
+        .map_err(|error| error.to_string())?;
+    let mut event_pump = sdl_context.event_pump()?;
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            if let Event::Quit { .. }
+            | Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            } = event
+            {
+                break 'running;
+            }
+        }
+        let bands = image.lock().unwrap().bands();
+        draw(&mut canvas, &bands);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_of(left: impl Fn(usize) -> f32, right: impl Fn(usize) -> f32) -> BandImage {
+        let mut image = StereoImage::new(48000);
+        for frame in 0..20 {
+            let start = frame * HOP;
+            let left: Vec<f32> = (start..start + FRAME_LEN).map(&left).collect();
+            let right: Vec<f32> = (start..start + FRAME_LEN).map(&right).collect();
+            image.push(&left, &right);
+        }
+        // The 1 kHz band
+        image.bands()[5]
+    }
+
+    #[test]
+    fn bands_are_placed_by_their_level_and_phase() {
+        let sine = |delay: usize, gain: f32| {
+            move |i: usize| {
+                gain * (2.0 * std::f32::consts::PI * 1000.0 * (i as f32 - delay as f32) / 48000.0)
+                    .sin()
+            }
+        };
+
+        let centered = image_of(sine(0, 0.5), sine(0, 0.5));
+        assert!(centered.level_difference_db.abs() < 0.1);
+        assert!(centered.correlation > 0.99);
+        assert!(centered.mono_change_db.abs() < 0.1);
+
+        // Half as loud on the right, so 6 dB to the left
+        let left = image_of(sine(0, 0.5), sine(0, 0.25));
+        assert!((left.level_difference_db - 6.02).abs() < 0.1, "{left:?}");
+
+        // A wire swapped on one side cancels out in mono
+        let inverted = image_of(sine(0, 0.5), sine(0, -0.5));
+        assert!(inverted.correlation < -0.99);
+        assert!(inverted.mono_change_db < -30.0, "{inverted:?}");
+
+        // The right channel a quarter of a period late
+        let late = image_of(sine(0, 0.5), sine(12, 0.5));
+        assert!(
+            (late.phase_difference_degrees - 90.0).abs() < 2.0,
+            "{late:?}"
+        );
+        assert!(late.correlation.abs() < 0.05);
+    }
+}
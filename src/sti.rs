@@ -0,0 +1,229 @@
+use std::f32::consts::PI;
+
+use ndarray::Array1;
+use num_complex::Complex;
+
+use crate::{fft, filter::Biquad, ifft};
+
+// Octave bands of the speech transmission index
+pub const STI_BANDS_HZ: [f32; 7] = [125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0];
+
+// Rates at which the envelope of speech goes up and down, in third octaves
+const MODULATION_HZ: [f32; 14] = [
+    0.63, 0.8, 1.0, 1.25, 1.6, 2.0, 2.5, 3.15, 4.0, 5.0, 6.3, 8.0, 10.0, 12.5,
+];
+
+// Weight of each band, and the redundancy between a band and the next one (male speech, IEC
+// 60268-16 revision 4)
+const BAND_WEIGHTS: [f32; 7] = [0.085, 0.127, 0.230, 0.233, 0.309, 0.224, 0.173];
+const REDUNDANCIES: [f32; 6] = [0.085, 0.078, 0.065, 0.011, 0.047, 0.095];
+
+// Part of the impulse response kept before its peak, and its whole length
+const PRE_PEAK_SECS: f32 = 0.005;
+const RESPONSE_SECS: f32 = 2.0;
+// Below this fraction of the strongest bin of the sweep, the division by it is held back
+const REGULARIZATION: f32 = 1e-4;
+
+/**
+ * How well speech gets through, as the index and the transmission of each octave band
+ */
+#[derive(Clone, Debug)]
+pub struct SpeechTransmission {
+    pub index: f32,
+    // Modulation transfer index of each of the STI_BANDS_HZ, from 0 to 1
+    pub band_transfers: Vec<f32>,
+}
+
+impl SpeechTransmission {
+    /**
+     * The qualification of the index in IEC 60268-16
+     */
+    pub fn rating(&self) -> &'static str {
+        match self.index {
+            index if index < 0.30 => "bad",
+            index if index < 0.45 => "poor",
+            index if index < 0.60 => "fair",
+            index if index < 0.75 => "good",
+            _ => "excellent",
+        }
+    }
+}
+
+/**
+ * Impulse response of whatever the sweep went through, by dividing the spectrum of the
+ * recording by the one of the sweep. Starts just before its strongest sample and lasts
+ * RESPONSE_SECS at most.
+ */
+pub fn impulse_response(sweep: &[f32], recording: &[f32], sample_rate: u32) -> Vec<f32> {
+    let len = (recording.len() + sweep.len()).next_power_of_two();
+    let spectrum = |samples: &[f32]| {
+        let mut padded = Array1::<Complex<f32>>::zeros(len);
+        for (bin, sample) in padded.iter_mut().zip(samples) {
+            *bin = Complex::from(sample);
+        }
+        fft(&padded)
+    };
+    let played = spectrum(sweep);
+    let heard = spectrum(recording);
+    let floor = played.iter().fold(0.0f32, |peak, x| peak.max(x.norm_sqr())) * REGULARIZATION;
+    let transfer = Array1::from_iter(
+        played
+            .iter()
+            .zip(&heard)
+            .map(|(played, heard)| heard * played.conj() / (played.norm_sqr() + floor)),
+    );
+    let response: Vec<f32> = ifft(&transfer).iter().map(|x| x.re).collect();
+
+    let peak = response
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+        .map_or(0, |(index, _)| index);
+    let start = peak.saturating_sub((PRE_PEAK_SECS * sample_rate as f32) as usize);
+    let end = (start + (RESPONSE_SECS * sample_rate as f32) as usize).min(response.len());
+    response[start..end].to_vec()
+}
+
+/**
+ * Modulation transfer function of an impulse response, for each of the MODULATION_HZ: how
+ * much of the swing of an envelope going up and down at that rate survives (Schroeder)
+ */
+fn modulation_transfer(response: &[f32], sample_rate: u32) -> [f32; 14] {
+    let energy: f32 = response.iter().map(|x| x * x).sum();
+    MODULATION_HZ.map(|modulation_hz| {
+        if energy == 0.0 {
+            return 0.0;
+        }
+        let step = 2.0 * PI * modulation_hz / sample_rate as f32;
+        let swing = response
+            .iter()
+            .enumerate()
+            .fold(Complex::new(0.0f32, 0.0), |sum, (i, x)| {
+                sum + Complex::from_polar(x * x, -step * i as f32)
+            });
+        swing.norm() / energy
+    })
+}
+
+/**
+ * Transmission index of a band, the modulation transfers turned into apparent signal to noise
+ * ratios between -15 and 15 dB, mapped to 0 - 1 and averaged
+ */
+fn transmission_index(transfers: &[f32]) -> f32 {
+    let sum: f32 = transfers
+        .iter()
+        .map(|m| {
+            let m = m.clamp(1e-6, 1.0 - 1e-6);
+            let snr_db = (10.0 * (m / (1.0 - m)).log10()).clamp(-15.0, 15.0);
+            (snr_db + 15.0) / 30.0
+        })
+        .sum();
+    sum / transfers.len() as f32
+}
+
+/**
+ * Estimates the speech transmission index from an impulse response, each octave band cut out
+ * of it with a pair of band-passes. The noise and the masking between bands are left out, the
+ * sweep plays far above the background noise.
+ */
+pub fn estimate(response: &[f32], sample_rate: u32) -> SpeechTransmission {
+    let band_transfers: Vec<f32> = STI_BANDS_HZ
+        .iter()
+        .map(|center_in_hz| {
+            if *center_in_hz * 2f32.sqrt() >= sample_rate as f32 / 2.0 {
+                return 0.0;
+            }
+            let mut filters = [
+                Biquad::band_pass(*center_in_hz, 2f32.sqrt(), sample_rate),
+                Biquad::band_pass(*center_in_hz, 2f32.sqrt(), sample_rate),
+            ];
+            let band: Vec<f32> = response
+                .iter()
+                .map(|sample| {
+                    filters
+                        .iter_mut()
+                        .fold(*sample, |sample, filter| filter.process(sample))
+                })
+                .collect();
+            transmission_index(&modulation_transfer(&band, sample_rate))
+        })
+        .collect();
+
+    let weighted: f32 = BAND_WEIGHTS
+        .iter()
+        .zip(&band_transfers)
+        .map(|(weight, transfer)| weight * transfer)
+        .sum();
+    let redundant: f32 = REDUNDANCIES
+        .iter()
+        .zip(band_transfers.windows(2))
+        .map(|(redundancy, pair)| redundancy * (pair[0] * pair[1]).sqrt())
+        .sum();
+    SpeechTransmission {
+        index: (weighted - redundant).clamp(0.0, 1.0),
+        band_transfers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Noise decaying by 60 dB in `reverberation_secs`, like the tail of a room
+    fn reverberant_response(reverberation_secs: f32, sample_rate: u32) -> Vec<f32> {
+        let mut seed = 1u32;
+        (0..(reverberation_secs * 1.5 * sample_rate as f32) as usize)
+            .map(|i| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = seed as f32 / u32::MAX as f32 - 0.5;
+                let t = i as f32 / sample_rate as f32;
+                noise * (-6.9 * t / reverberation_secs).exp()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reverberation_follows_the_theory() {
+        let sample_rate = 16000;
+        let transfers = modulation_transfer(&reverberant_response(1.0, sample_rate), sample_rate);
+        for (modulation_hz, transfer) in MODULATION_HZ.iter().zip(transfers) {
+            let expected = 1.0 / (1.0 + (2.0 * PI * modulation_hz / 13.8).powi(2)).sqrt();
+            assert!(
+                (transfer - expected).abs() < 0.05,
+                "{modulation_hz} Hz: {transfer} instead of {expected}"
+            );
+        }
+
+        // A dry room is excellent, a reverberant one much worse
+        let sample_rate = 48000;
+        let mut click = vec![0.0; 24000];
+        click[10] = 1.0;
+        let dry = estimate(&click, sample_rate);
+        assert!(dry.index > 0.95, "{dry:?}");
+        let reverberant = estimate(&reverberant_response(2.0, sample_rate), sample_rate);
+        assert!(
+            (0.35..0.55).contains(&reverberant.index),
+            "{}",
+            reverberant.index
+        );
+    }
+
+    #[test]
+    fn the_sweep_is_taken_out_of_the_recording() {
+        let sample_rate = 8000;
+        let sweep: Vec<f32> = (0..sample_rate as usize)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                (2.0 * PI * (50.0 * t + 1500.0 * t * t)).sin()
+            })
+            .collect();
+        let mut recording = vec![0.0; 300];
+        recording.extend(sweep.iter().map(|sample| sample * 0.5));
+
+        let response = impulse_response(&sweep, &recording, sample_rate);
+        // The peak comes PRE_PEAK_SECS after the start
+        let peak = (PRE_PEAK_SECS * sample_rate as f32) as usize;
+        assert!((response[peak] - 0.5).abs() < 0.05, "{}", response[peak]);
+        assert!(response[peak + 20].abs() < 0.05);
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    analysis::{amplitude_to_db, yin_estimate},
+    snapshot::write_wav,
+};
+
+// The input is checked for a pitch in blocks this long
+const BLOCK_SECS: f32 = 0.05;
+// Range of the pitches that count as playing
+const MIN_PITCH_HZ: f32 = 60.0;
+const MAX_PITCH_HZ: f32 = 2000.0;
+// A block is playing when it's this loud and its period this clear
+const MIN_LEVEL_DB: f32 = -50.0;
+const MIN_CLARITY: f32 = 0.85;
+// Blocks in a row with a pitch needed to start a take, so a door slam or a cough doesn't
+const START_BLOCKS: usize = 3;
+// Audio kept from before the take started, so its first note isn't cut
+const PRE_ROLL_SECS: f32 = 1.0;
+// A longer take is closed and the next one starts with the next note
+const MAX_TAKE_SECS: f32 = 1800.0;
+
+/**
+ * Cuts the input into takes: a take starts when a pitch was heard for START_BLOCKS blocks and
+ * ends once none was heard for the silence given. Everything is kept in memory until the take
+ * ends.
+ */
+pub struct TakeRecorder {
+    sample_rate: u32,
+    block_len: usize,
+    silence_blocks: usize,
+    // The block being filled
+    block: Vec<f32>,
+    // The last PRE_ROLL_SECS while no take is running, the whole take while one is
+    samples: VecDeque<f32>,
+    recording: bool,
+    // Blocks in a row with a pitch, and without one
+    pitched_blocks: usize,
+    quiet_blocks: usize,
+}
+
+impl TakeRecorder {
+    pub fn new(sample_rate: u32, silence: Duration) -> Self {
+        Self {
+            sample_rate,
+            block_len: ((BLOCK_SECS * sample_rate as f32) as usize).max(1),
+            silence_blocks: (silence.as_secs_f32() / BLOCK_SECS).ceil() as usize,
+            block: vec![],
+            samples: VecDeque::new(),
+            recording: false,
+            pitched_blocks: 0,
+            quiet_blocks: 0,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    fn is_pitched(&self, block: &[f32]) -> bool {
+        let peak = block.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        amplitude_to_db(peak) >= MIN_LEVEL_DB
+            && yin_estimate(block, self.sample_rate, MIN_PITCH_HZ, MAX_PITCH_HZ)
+                .is_some_and(|(_, clarity)| clarity >= MIN_CLARITY)
+    }
+
+    /**
+     * Takes the next captured samples and returns the samples of a take when it just ended
+     */
+    pub fn process(&mut self, samples: &[f32]) -> Option<Vec<f32>> {
+        let mut finished = None;
+        for sample in samples {
+            self.block.push(*sample);
+            if self.block.len() < self.block_len {
+                continue;
+            }
+            let block = std::mem::take(&mut self.block);
+            let pitched = self.is_pitched(&block);
+            self.samples.extend(block);
+            (self.pitched_blocks, self.quiet_blocks) = match pitched {
+                true => (self.pitched_blocks + 1, 0),
+                false => (0, self.quiet_blocks + 1),
+            };
+
+            if !self.recording {
+                self.recording = self.pitched_blocks >= START_BLOCKS;
+                let pre_roll = (PRE_ROLL_SECS * self.sample_rate as f32) as usize;
+                let excess = self.samples.len().saturating_sub(pre_roll);
+                if !self.recording {
+                    self.samples.drain(..excess);
+                }
+            } else if self.quiet_blocks >= self.silence_blocks
+                || self.samples.len() as f32 >= MAX_TAKE_SECS * self.sample_rate as f32
+            {
+                self.recording = false;
+                self.pitched_blocks = 0;
+                finished = Some(self.samples.drain(..).collect());
+            }
+        }
+        finished
+    }
+
+    /**
+     * Ends the take being recorded, when quitting, and returns its samples. None when no take
+     * was running.
+     */
+    pub fn finish(&mut self) -> Option<Vec<f32>> {
+        if !self.recording {
+            return None;
+        }
+        self.recording = false;
+        self.pitched_blocks = 0;
+        self.quiet_blocks = 0;
+        let block = std::mem::take(&mut self.block);
+        self.samples.extend(block);
+        Some(self.samples.drain(..).collect())
+    }
+}
+
+/**
+ * Writes a take as a WAV file named after the time it's saved at, to the millisecond so two
+ * takes never get the same name, inside `directory`, and returns its path
+ */
+pub fn save_take(directory: &Path, samples: &[f32], sample_rate: u32) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(directory).map_err(|error| error.to_string())?;
+    let saved = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+    let path = directory.join(format!("take-{}.wav", saved.replace(':', "-")));
+    write_wav(&path, samples, sample_rate).map_err(|error| error.to_string())?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_take_runs_from_the_first_note_to_the_silence() {
+        let sample_rate = 8000;
+        let silence = |secs: f32| vec![0.0; (secs * sample_rate as f32) as usize];
+        let tone: Vec<f32> = (0..sample_rate as usize * 2)
+            .map(|i| 0.3 * (2.0 * std::f32::consts::PI * 220.0 * i as f32 / 8000.0).sin())
+            .collect();
+        let mut recorder = TakeRecorder::new(sample_rate, Duration::from_secs(1));
+
+        assert_eq!(recorder.process(&silence(3.0)), None);
+        // A click isn't playing
+        let mut click = silence(0.5);
+        click[100] = 0.9;
+        assert_eq!(recorder.process(&click), None);
+        assert!(!recorder.is_recording());
+
+        assert_eq!(recorder.process(&tone), None);
+        assert!(recorder.is_recording());
+        let take = recorder.process(&silence(3.0)).unwrap();
+        // The pre-roll, the tone and the second of silence it took to stop
+        let take_secs = take.len() as f32 / sample_rate as f32;
+        assert!((take_secs - 4.0).abs() < 0.1, "{take_secs}");
+        assert!(!recorder.is_recording());
+        assert_eq!(recorder.finish(), None);
+
+        // Quitting in the middle of a take keeps it
+        recorder.process(&tone);
+        let take = recorder.finish().unwrap();
+        let take_secs = take.len() as f32 / sample_rate as f32;
+        assert!((take_secs - 3.0).abs() < 0.1, "{take_secs}");
+        assert!(!recorder.is_recording());
+    }
+}
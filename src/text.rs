@@ -0,0 +1,494 @@
+#[cfg(feature = "ttf")]
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, HashMap},
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "ttf")]
+use sdl2::ttf::{Font, Sdl2TtfContext};
+#[cfg(feature = "app")]
+use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window};
+
+/*
+ * A tiny 5x7 pixel font covering printable ASCII, enough for labels and help text without
+ * depending on SDL_ttf and a font file. Each glyph is 7 rows from top to bottom, the 5 lowest
+ * bits of a row being its pixels from left to right.
+ * Built with the ttf feature, the window draws its text with the TrueType font given to
+ * use_font instead, and only falls back to this one when there is none.
+ */
+
+const GLYPH_WIDTH: i32 = 5;
+const GLYPH_HEIGHT: i32 = 7;
+// Gap after each character and below each line, in font pixels
+const SPACING: i32 = 1;
+
+const GLYPHS: [[u8; 7]; 95] = [
+    [
+        0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000,
+    ], // space
+    [
+        0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100,
+    ], // !
+    [
+        0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000,
+    ], // "
+    [
+        0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010,
+    ], // #
+    [
+        0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100,
+    ], // $
+    [
+        0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011,
+    ], // %
+    [
+        0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101,
+    ], // &
+    [
+        0b00100, 0b00100, 0b00100, 0b00000, 0b00000, 0b00000, 0b00000,
+    ], // '
+    [
+        0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010,
+    ], // (
+    [
+        0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000,
+    ], // )
+    [
+        0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000,
+    ], // *
+    [
+        0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000,
+    ], // +
+    [
+        0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000,
+    ], // ,
+    [
+        0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000,
+    ], // -
+    [
+        0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100,
+    ], // .
+    [
+        0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000,
+    ], // /
+    [
+        0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+    ], // 0
+    [
+        0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ], // 1
+    [
+        0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+    ], // 2
+    [
+        0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+    ], // 3
+    [
+        0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+    ], // 4
+    [
+        0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+    ], // 5
+    [
+        0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+    ], // 6
+    [
+        0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+    ], // 7
+    [
+        0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+    ], // 8
+    [
+        0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+    ], // 9
+    [
+        0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000,
+    ], // :
+    [
+        0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000,
+    ], // ;
+    [
+        0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010,
+    ], // <
+    [
+        0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000,
+    ], // =
+    [
+        0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000,
+    ], // >
+    [
+        0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100,
+    ], // ?
+    [
+        0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110,
+    ], // @
+    [
+        0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+    ], // A
+    [
+        0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+    ], // B
+    [
+        0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+    ], // C
+    [
+        0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100,
+    ], // D
+    [
+        0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+    ], // E
+    [
+        0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+    ], // F
+    [
+        0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+    ], // G
+    [
+        0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+    ], // H
+    [
+        0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ], // I
+    [
+        0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+    ], // J
+    [
+        0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+    ], // K
+    [
+        0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+    ], // L
+    [
+        0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+    ], // M
+    [
+        0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+    ], // N
+    [
+        0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+    ], // O
+    [
+        0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+    ], // P
+    [
+        0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+    ], // Q
+    [
+        0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+    ], // R
+    [
+        0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+    ], // S
+    [
+        0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+    ], // T
+    [
+        0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+    ], // U
+    [
+        0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+    ], // V
+    [
+        0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+    ], // W
+    [
+        0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+    ], // X
+    [
+        0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100,
+    ], // Y
+    [
+        0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+    ], // Z
+    [
+        0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110,
+    ], // [
+    [
+        0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000,
+    ], // \
+    [
+        0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110,
+    ], // ]
+    [
+        0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000,
+    ], // ^
+    [
+        0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111,
+    ], // _
+    [
+        0b01000, 0b00100, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000,
+    ], // `
+    [
+        0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111,
+    ], // a
+    [
+        0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110,
+    ], // b
+    [
+        0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110,
+    ], // c
+    [
+        0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111,
+    ], // d
+    [
+        0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110,
+    ], // e
+    [
+        0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000,
+    ], // f
+    [
+        0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110,
+    ], // g
+    [
+        0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001,
+    ], // h
+    [
+        0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ], // i
+    [
+        0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100,
+    ], // j
+    [
+        0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010,
+    ], // k
+    [
+        0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ], // l
+    [
+        0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001,
+    ], // m
+    [
+        0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001,
+    ], // n
+    [
+        0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110,
+    ], // o
+    [
+        0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000,
+    ], // p
+    [
+        0b00000, 0b00000, 0b01101, 0b10011, 0b01111, 0b00001, 0b00001,
+    ], // q
+    [
+        0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000,
+    ], // r
+    [
+        0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110,
+    ], // s
+    [
+        0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110,
+    ], // t
+    [
+        0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b10011, 0b01101,
+    ], // u
+    [
+        0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+    ], // v
+    [
+        0b00000, 0b00000, 0b10001, 0b10001, 0b10101, 0b10101, 0b01010,
+    ], // w
+    [
+        0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001,
+    ], // x
+    [
+        0b00000, 0b00000, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110,
+    ], // y
+    [
+        0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111,
+    ], // z
+    [
+        0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010,
+    ], // {
+    [
+        0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+    ], // |
+    [
+        0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000,
+    ], // }
+    [
+        0b00000, 0b00000, 0b01000, 0b10101, 0b00010, 0b00000, 0b00000,
+    ], // ~
+];
+
+// Monospace fonts tried in this order when no font is given to use_font
+#[cfg(feature = "ttf")]
+const SYSTEM_FONTS: [&str; 4] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf",
+    "/usr/share/fonts/TTF/DejaVuSansMono.ttf",
+    "/System/Library/Fonts/Menlo.ttc",
+    "C:\\Windows\\Fonts\\consola.ttf",
+];
+
+/**
+ * A TrueType font opened at the size of each scale it was asked for
+ */
+#[cfg(feature = "ttf")]
+struct TtfFont {
+    context: &'static Sdl2TtfContext,
+    path: PathBuf,
+    sizes: HashMap<u32, Font<'static, 'static>>,
+}
+
+#[cfg(feature = "ttf")]
+thread_local! {
+    // Set by use_font, the 5x7 font is drawn while it's None
+    static TTF_FONT: RefCell<Option<TtfFont>> = const { RefCell::new(None) };
+}
+
+/**
+ * Draws the text of the window with the TrueType font at `path`, or with the first of
+ * SYSTEM_FONTS found when it's None. SDL fonts can't be shared between threads, so it's the
+ * text drawn by the calling thread that changes.
+ */
+#[cfg(feature = "ttf")]
+pub fn use_font(path: Option<&Path>) -> Result<(), String> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => SYSTEM_FONTS
+            .iter()
+            .map(PathBuf::from)
+            .find(|path| path.exists())
+            .ok_or("No monospace font found, pass one with --font")?,
+    };
+    // The context has to outlive the fonts, which live as long as the thread
+    let context: &'static Sdl2TtfContext = Box::leak(Box::new(
+        sdl2::ttf::init().map_err(|error| error.to_string())?,
+    ));
+    let font = context.load_font(&path, point_size(1))?;
+    TTF_FONT.with(|ttf_font| {
+        *ttf_font.borrow_mut() = Some(TtfFont {
+            context,
+            path,
+            sizes: HashMap::from([(1, font)]),
+        })
+    });
+    Ok(())
+}
+
+/**
+ * Size of the TrueType font standing in for the 5x7 one at `scale`, as tall as its lines
+ */
+#[cfg(feature = "ttf")]
+fn point_size(scale: u32) -> u16 {
+    ((GLYPH_HEIGHT + SPACING * 2) as u32 * scale).min(u16::MAX as u32) as u16
+}
+
+/**
+ * Calls `f` with the TrueType font at `scale`, or returns None when there is none
+ */
+#[cfg(feature = "ttf")]
+fn with_ttf_font<T>(scale: u32, f: impl FnOnce(&Font<'static, 'static>) -> T) -> Option<T> {
+    TTF_FONT.with(|ttf_font| {
+        let mut ttf_font = ttf_font.borrow_mut();
+        let ttf_font = ttf_font.as_mut()?;
+        let font = match ttf_font.sizes.entry(scale) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(
+                ttf_font
+                    .context
+                    .load_font(&ttf_font.path, point_size(scale))
+                    .ok()?,
+            ),
+        };
+        Some(f(font))
+    })
+}
+
+/**
+ * Width in screen pixels of `text` drawn with draw_text at `scale`
+ */
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    #[cfg(feature = "ttf")]
+    if let Some(Ok((width, _))) = with_ttf_font(scale, |font| font.size_of(text)) {
+        return width;
+    }
+    text.chars().count() as u32 * (GLYPH_WIDTH + SPACING) as u32 * scale
+}
+
+/**
+ * Height in screen pixels of a line of text at `scale`, gap included
+ */
+pub fn line_height(scale: u32) -> u32 {
+    #[cfg(feature = "ttf")]
+    if let Some(height) = with_ttf_font(scale, |font| font.recommended_line_spacing()) {
+        return height.max(1) as u32;
+    }
+    (GLYPH_HEIGHT + SPACING * 2) as u32 * scale
+}
+
+/**
+ * Returns the lit pixels of `text` in font pixels, relative to its top left corner.
+ * Characters the font doesn't have are drawn as '?'.
+ */
+fn text_points(text: &str) -> Vec<(i32, i32)> {
+    let mut points = vec![];
+    for (i, character) in text.chars().enumerate() {
+        let index = match character {
+            ' '..='~' => character as usize - ' ' as usize,
+            _ => '?' as usize - ' ' as usize,
+        };
+        let left = i as i32 * (GLYPH_WIDTH + SPACING);
+        for (row, bits) in GLYPHS[index].iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                    points.push((left + column, row as i32));
+                }
+            }
+        }
+    }
+    points
+}
+
+/**
+ * Draws `text` with its top left corner at (x, y), each pixel of the font being a square of
+ * `scale` screen pixels, or with the TrueType font at the same height when there is one
+ */
+#[cfg(feature = "app")]
+pub fn draw_text(
+    canvas: &mut Canvas<Window>,
+    text: &str,
+    x: i32,
+    y: i32,
+    scale: u32,
+    color: Color,
+) {
+    #[cfg(feature = "ttf")]
+    if let Some(Ok(surface)) = with_ttf_font(scale, |font| font.render(text).blended(color)) {
+        let texture_creator = canvas.texture_creator();
+        let texture = texture_creator.create_texture_from_surface(&surface);
+        if let Ok(texture) = &texture {
+            canvas
+                .copy(
+                    texture,
+                    None,
+                    Rect::new(x, y, surface.width(), surface.height()),
+                )
+                .unwrap();
+            return;
+        }
+    }
+    let size = scale as i32;
+    let pixels: Vec<Rect> = text_points(text)
+        .into_iter()
+        .map(|(column, row)| Rect::new(x + column * size, y + row * size, scale, scale))
+        .collect();
+    canvas.set_draw_color(color);
+    canvas.fill_rects(&pixels).unwrap();
+}
+
+/**
+ * Same as draw_text at scale 1, into an RGB image `width` pixels wide, for images written to
+ * files. Pixels falling outside of the image are skipped.
+ */
+pub fn draw_text_rgb(rgb: &mut [u8], width: usize, text: &str, x: i32, y: i32, color: [u8; 3]) {
+    let height = rgb.len() / 3 / width.max(1);
+    for (column, row) in text_points(text) {
+        let (px, py) = (x + column, y + row);
+        if px < 0 || py < 0 || px as usize >= width || py as usize >= height {
+            continue;
+        }
+        let i = (py as usize * width + px as usize) * 3;
+        rgb[i..i + 3].copy_from_slice(&color);
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::analysis::{db_to_amplitude, Harmonic};
+
+// Extension of the fingerprint files, the name of a fingerprint is the rest of its file name
+const EXTENSION: &str = "timbre";
+
+/**
+ * The part of the timbre of a note that doesn't depend on how loud it was played: the level of
+ * each harmonic relative to the fundamental
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fingerprint {
+    pub name: String,
+    pub fundamental_in_hz: f32,
+    // Level in dB of each harmonic relative to the fundamental, starting with the fundamental
+    ratios_db: Vec<f32>,
+}
+
+impl Fingerprint {
+    pub fn from_harmonics(name: &str, harmonics: &[Harmonic]) -> Option<Self> {
+        let fundamental = harmonics.first()?;
+        Some(Self {
+            name: name.to_string(),
+            fundamental_in_hz: fundamental.frequency_in_hz,
+            ratios_db: harmonics.iter().map(|harmonic| harmonic.ratio_db).collect(),
+        })
+    }
+
+    /**
+     * Amplitudes of the harmonics scaled so that their squares add up to 1
+     */
+    fn normalized(&self) -> Vec<f32> {
+        let amplitudes: Vec<f32> = self.ratios_db.iter().map(|r| db_to_amplitude(*r)).collect();
+        let norm = amplitudes.iter().map(|a| a * a).sum::<f32>().sqrt();
+        amplitudes
+            .iter()
+            .map(|a| a / norm.max(f32::EPSILON))
+            .collect()
+    }
+
+    /**
+     * How alike the shapes of the two sets of harmonics are, from 0 to 1 for identical ones.
+     * Harmonics only one of them has count as silent in the other.
+     */
+    pub fn similarity(&self, other: &Fingerprint) -> f32 {
+        self.normalized()
+            .iter()
+            .zip(other.normalized())
+            .map(|(a, b)| a * b)
+            .sum::<f32>()
+            .clamp(0.0, 1.0)
+    }
+
+    /**
+     * Parses the format `save` writes: lines starting with * are comments, then the fundamental
+     * and one "number level" pair per harmonic
+     */
+    pub fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('*'));
+        let fundamental_in_hz = lines
+            .next()
+            .and_then(|line| line.strip_prefix("fundamental_hz"))
+            .and_then(|value| value.trim().parse::<f32>().ok())
+            .ok_or("The fingerprint doesn't start with its fundamental_hz")?;
+        let ratios_db = lines
+            .map(|line| {
+                line.split_whitespace()
+                    .nth(1)
+                    .and_then(|level| level.parse::<f32>().ok())
+                    .ok_or(format!("\"{line}\" is not a harmonic and its level"))
+            })
+            .collect::<Result<Vec<f32>, String>>()?;
+        if ratios_db.is_empty() {
+            return Err("The fingerprint has no harmonics".into());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            fundamental_in_hz,
+            ratios_db,
+        })
+    }
+
+    /**
+     * Writes the fingerprint as `<name>.timbre` inside `directory` and returns its path
+     */
+    pub fn save(&self, directory: &Path) -> std::io::Result<PathBuf> {
+        std::fs::create_dir_all(directory)?;
+        let path = directory.join(format!("{}.{EXTENSION}", self.name));
+        let mut file = BufWriter::new(File::create(&path)?);
+        writeln!(
+            file,
+            "* Levels of the harmonics in dB relative to the fundamental"
+        )?;
+        writeln!(file, "fundamental_hz {}", self.fundamental_in_hz)?;
+        for (i, ratio_db) in self.ratios_db.iter().enumerate() {
+            writeln!(file, "{} {ratio_db:.2}", i + 1)?;
+        }
+        file.flush()?;
+        Ok(path)
+    }
+}
+
+/**
+ * Turns text from the clipboard into a file name for a fingerprint
+ */
+pub fn file_name(text: &str) -> Option<String> {
+    let name: String = text
+        .trim()
+        .chars()
+        .map(|c| match c.is_alphanumeric() || c == '-' || c == '_' {
+            true => c,
+            false => '_',
+        })
+        .take(64)
+        .collect();
+    (!name.is_empty()).then_some(name)
+}
+
+/**
+ * Loads every fingerprint saved in `directory`
+ */
+pub fn load_all(directory: &Path) -> Result<Vec<Fingerprint>, String> {
+    let entries = std::fs::read_dir(directory)
+        .map_err(|error| format!("Could not read {}: {error}", directory.display()))?;
+    let mut fingerprints = vec![];
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path
+            .extension()
+            .is_none_or(|extension| extension != EXTENSION)
+        {
+            continue;
+        }
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let text = std::fs::read_to_string(&path)
+            .map_err(|error| format!("Could not read {}: {error}", path.display()))?;
+        fingerprints.push(
+            Fingerprint::parse(&name, &text)
+                .map_err(|error| format!("{}: {error}", path.display()))?,
+        );
+    }
+    Ok(fingerprints)
+}
+
+/**
+ * Compares a fingerprint to the saved ones, the most similar first
+ */
+pub fn rank<'a>(
+    fingerprint: &Fingerprint,
+    saved: &'a [Fingerprint],
+) -> Vec<(&'a Fingerprint, f32)> {
+    let mut ranked: Vec<(&Fingerprint, f32)> = saved
+        .iter()
+        .map(|other| (other, fingerprint.similarity(other)))
+        .collect();
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(name: &str, ratios_db: &[f32]) -> Fingerprint {
+        Fingerprint {
+            name: name.into(),
+            fundamental_in_hz: 110.0,
+            ratios_db: ratios_db.to_vec(),
+        }
+    }
+
+    #[test]
+    fn the_closest_timbre_ranks_first() {
+        let bright = fingerprint("bright", &[0.0, -3.0, -6.0, -9.0, -12.0]);
+        let dull = fingerprint("dull", &[0.0, -20.0, -40.0, -60.0, -80.0]);
+        let new_take = fingerprint("", &[0.0, -4.0, -5.0, -10.0]);
+        // Louder or softer, the shape is the same
+        assert!((bright.similarity(&bright) - 1.0).abs() < 1e-5);
+
+        let saved = [dull, bright];
+        let ranked = rank(&new_take, &saved);
+        assert_eq!(ranked[0].0.name, "bright");
+        assert!(ranked[0].1 > 0.95);
+        assert!(ranked[1].1 < 0.8);
+    }
+
+    #[test]
+    fn fingerprints_survive_a_save() {
+        let directory = std::env::temp_dir().join(format!("timbre-{}", std::process::id()));
+        let saved = fingerprint("reed_a", &[0.0, -6.5, -12.25]);
+        saved.save(&directory).unwrap();
+        assert_eq!(load_all(&directory).unwrap(), vec![saved]);
+        std::fs::remove_dir_all(directory).unwrap();
+        assert_eq!(file_name(" Brand X/E2 "), Some("Brand_X_E2".into()));
+    }
+}
@@ -0,0 +1,320 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    time::{Duration, Instant, SystemTime},
+};
+
+use sdl2::{
+    event::Event,
+    keyboard::{Keycode, Mod},
+    pixels::Color,
+};
+
+use crate::{
+    cli::TinnitusArgs,
+    generator::{Generator, Sound},
+    text,
+};
+
+// Kept low, the point is to match a faint sound, not to add to the damage
+const START_LEVEL_DB: f32 = -60.0;
+const MAX_LEVEL_DB: f32 = -10.0;
+const MIN_LEVEL_DB: f32 = -100.0;
+
+// The bracketing stops once the two candidates would be less than half a semitone apart
+const BRACKET_START_OCTAVES: f32 = 1.0;
+const BRACKET_END_OCTAVES: f32 = 1.0 / 24.0;
+// Each candidate plays this long, then there is a short gap before the other one
+const CANDIDATE_DURATION: Duration = Duration::from_millis(1200);
+const GAP_DURATION: Duration = Duration::from_millis(400);
+
+/**
+ * Two sounds around a frequency, played one after the other until the closest to the tinnitus
+ * is picked. The picked one becomes the center of the next, twice narrower, pair.
+ */
+struct Bracket {
+    center_in_hz: f32,
+    span_octaves: f32,
+    started: Instant,
+}
+
+impl Bracket {
+    fn candidates(&self) -> [f32; 2] {
+        let ratio = 2f32.powf(self.span_octaves / 2.0);
+        [self.center_in_hz / ratio, self.center_in_hz * ratio]
+    }
+
+    /**
+     * Index of the candidate playing now, None during the gaps
+     */
+    fn playing(&self) -> Option<usize> {
+        let cycle = (CANDIDATE_DURATION + GAP_DURATION) * 2;
+        let position =
+            Duration::from_secs_f32(self.started.elapsed().as_secs_f32() % cycle.as_secs_f32());
+        let slot = CANDIDATE_DURATION + GAP_DURATION;
+        let candidate = (position >= slot) as usize;
+        let in_slot = position - slot * candidate as u32;
+        (in_slot < CANDIDATE_DURATION).then_some(candidate)
+    }
+}
+
+struct State {
+    frequency_in_hz: f32,
+    level_db: f32,
+    noise: bool,
+    muted: bool,
+    bracket: Option<Bracket>,
+    status: String,
+}
+
+impl State {
+    fn sound(&self) -> Sound {
+        let frequency_in_hz = match &self.bracket {
+            Some(bracket) => match bracket.playing() {
+                Some(candidate) => bracket.candidates()[candidate],
+                None => return Sound::Silence,
+            },
+            None => self.frequency_in_hz,
+        };
+        let level_db = self.level_db;
+        match (self.muted, self.noise) {
+            (true, _) => Sound::Silence,
+            (false, false) => Sound::Tone {
+                frequency_in_hz,
+                level_db,
+            },
+            (false, true) => Sound::NarrowbandNoise {
+                frequency_in_hz,
+                level_db,
+            },
+        }
+    }
+
+    fn handle_key(&mut self, keycode: Keycode, keymod: Mod, args: &TinnitusArgs) {
+        let fine = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+        let semitones = if fine { 0.25 } else { 1.0 };
+        let level_step_db = if fine { 0.5 } else { 2.0 };
+        match keycode {
+            Keycode::Left | Keycode::Right if self.bracket.is_none() => {
+                let direction = if keycode == Keycode::Left { -1.0 } else { 1.0 };
+                self.frequency_in_hz = (self.frequency_in_hz
+                    * 2f32.powf(direction * semitones / 12.0))
+                .clamp(125.0, 16000.0);
+            }
+            Keycode::Up => {
+                self.level_db = (self.level_db + level_step_db).min(MAX_LEVEL_DB);
+            }
+            Keycode::Down => {
+                self.level_db = (self.level_db - level_step_db).max(MIN_LEVEL_DB);
+            }
+            Keycode::N => self.noise = !self.noise,
+            Keycode::Space => self.muted = !self.muted,
+            Keycode::B => {
+                self.bracket = Some(Bracket {
+                    center_in_hz: self.frequency_in_hz,
+                    span_octaves: BRACKET_START_OCTAVES,
+                    started: Instant::now(),
+                });
+                self.status = "Press 1 or 2 for the sound closest to your tinnitus".into();
+            }
+            Keycode::Num1 | Keycode::Num2 | Keycode::Kp1 | Keycode::Kp2 => {
+                let Some(bracket) = self.bracket.as_mut() else {
+                    return;
+                };
+                let candidate = matches!(keycode, Keycode::Num2 | Keycode::Kp2) as usize;
+                bracket.center_in_hz = bracket.candidates()[candidate];
+                bracket.span_octaves /= 2.0;
+                bracket.started = Instant::now();
+                if bracket.span_octaves < BRACKET_END_OCTAVES {
+                    self.frequency_in_hz = bracket.center_in_hz;
+                    self.bracket = None;
+                    self.status = format!(
+                        "Bracketed to {:.0} Hz, adjust the level then press Enter",
+                        self.frequency_in_hz
+                    );
+                }
+            }
+            Keycode::Return | Keycode::KpEnter if self.bracket.is_none() => {
+                self.status = match self.save(args) {
+                    Ok(()) => format!("Saved to {}", args.out.display()),
+                    Err(error) => format!("Could not save: {error}"),
+                };
+                println!("{}", self.status);
+            }
+            _ => {}
+        }
+    }
+
+    fn save(&self, args: &TinnitusArgs) -> std::io::Result<()> {
+        let is_new = !args.out.exists();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&args.out)?;
+        if is_new {
+            writeln!(file, "time,sound,frequency_hz,level_dbfs")?;
+        }
+        writeln!(
+            file,
+            "{},{},{:.1},{:.1}",
+            humantime::format_rfc3339_seconds(SystemTime::now()),
+            if self.noise { "noise" } else { "tone" },
+            self.frequency_in_hz,
+            self.level_db
+        )
+    }
+
+    fn lines(&self) -> Vec<(String, Color)> {
+        let white = Color::RGB(230, 230, 230);
+        let gray = Color::RGB(150, 150, 150);
+        let orange = Color::RGB(255, 170, 60);
+        let mut lines = vec![
+            (
+                "Tinnitus matching. Not a medical test: start quiet and stop if it hurts".into(),
+                orange,
+            ),
+            (String::new(), white),
+            (
+                format!(
+                    "Sound: {} (N)",
+                    if self.noise {
+                        "narrowband noise"
+                    } else {
+                        "pure tone"
+                    }
+                ),
+                white,
+            ),
+        ];
+        match &self.bracket {
+            Some(bracket) => {
+                let [low, high] = bracket.candidates();
+                let playing = match bracket.playing() {
+                    Some(candidate) => format!("playing {}", candidate + 1),
+                    None => String::new(),
+                };
+                lines.push((
+                    format!("Closest: 1 ({low:.0} Hz) or 2 ({high:.0} Hz)? {playing}"),
+                    white,
+                ));
+            }
+            None => lines.push((
+                format!(
+                    "Frequency: {:.0} Hz (Left/Right, Shift for finer steps)",
+                    self.frequency_in_hz
+                ),
+                white,
+            )),
+        }
+        lines.push((
+            format!(
+                "Level: {:.1} dBFS (Up/Down, Shift for finer steps){}",
+                self.level_db,
+                if self.muted { ", muted" } else { "" }
+            ),
+            white,
+        ));
+        lines.push((String::new(), white));
+        lines.push((
+            "Space: mute   B: bracket the frequency   Enter: save   Esc: quit".into(),
+            gray,
+        ));
+        lines.push((self.status.clone(), orange));
+        lines
+    }
+}
+
+/**
+ * Opens a window where the user tunes a tone or a narrowband noise until it sounds like their
+ * tinnitus, and saves the matches to a CSV file
+ */
+pub fn run(args: &TinnitusArgs) -> Result<(), String> {
+    let generator = Generator::open()?;
+    let sdl_context = sdl2::init()?;
+    let window = sdl_context
+        .video()?
+        .window("Tinnitus matching", 1100, 300)
+        .position_centered()
+        .build()
+        .map_err(|error| error.to_string())?;
+    let mut canvas = window
+        .into_canvas()
+        .build()
+        .map_err(|error| error.to_string())?;
+    let mut event_pump = sdl_context.event_pump()?;
+
+    let mut state = State {
+        frequency_in_hz: args.start_hz,
+        level_db: START_LEVEL_DB,
+        noise: false,
+        muted: false,
+        bracket: None,
+        status: String::new(),
+    };
+
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } => state.handle_key(keycode, keymod, args),
+                _ => {}
+            }
+        }
+        generator.play(state.sound());
+
+        canvas.set_draw_color(Color::RGB(30, 30, 30));
+        canvas.clear();
+        let scale = 2;
+        for (i, (line, color)) in state.lines().iter().enumerate() {
+            let y = 20 + (text::line_height(scale) * i as u32) as i32;
+            text::draw_text(&mut canvas, line, 20, y, scale, *color);
+        }
+        canvas.present();
+        std::thread::sleep(Duration::from_millis(16));
+    }
+
+    generator.play(Sound::Silence);
+    // Lets the level ramp down instead of cutting the sound
+    std::thread::sleep(Duration::from_millis(50));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bracketing_narrows_down_to_the_picked_side() {
+        let args = TinnitusArgs {
+            out: "tinnitus.csv".into(),
+            start_hz: 4000.0,
+        };
+        let mut state = State {
+            frequency_in_hz: args.start_hz,
+            level_db: START_LEVEL_DB,
+            noise: false,
+            muted: false,
+            bracket: None,
+            status: String::new(),
+        };
+
+        state.handle_key(Keycode::B, Mod::NOMOD, &args);
+        let mut presses = 0;
+        while state.bracket.is_some() {
+            state.handle_key(Keycode::Num2, Mod::NOMOD, &args);
+            presses += 1;
+        }
+        // Each press moves up by half of the span, which halves from an octave
+        assert_eq!(presses, 5);
+        let expected_hz = 4000.0 * 2f32.powf(0.5 + 0.25 + 0.125 + 0.0625 + 0.03125);
+        assert!((state.frequency_in_hz - expected_hz).abs() < 1.0);
+    }
+}
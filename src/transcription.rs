@@ -0,0 +1,451 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    image::write_png,
+    onset::NoteSegmenter,
+    pitch::PitchEstimate,
+    text,
+    units::{midi_number, note_name},
+};
+
+// Frames in a row a note has to be heard for to be written down, shorter ones are glides
+// between notes or a harmonic catching the tracker for a moment
+const MIN_NOTE_FRAMES: usize = 3;
+// A note is in tune within this many cents on average
+const IN_TUNE_CENTS: f32 = 10.0;
+// Quieter frames than this (dBFS) are silence for the segmenter, like for the guitar check
+const MIN_LEVEL_DB: f32 = -50.0;
+
+// MIDI timing: 480 ticks per quarter note at 120 beats per minute, 960 ticks a second
+const TICKS_PER_QUARTER: u16 = 480;
+const MICROSECONDS_PER_QUARTER: u32 = 500_000;
+const VELOCITY: u8 = 96;
+
+// Layout of the piano roll, in pixels
+const ROW_HEIGHT: usize = 9;
+const AXIS_LEFT: usize = 36;
+const AXIS_BOTTOM: usize = 14;
+const PIXELS_PER_SEC: f32 = 50.0;
+// Longer sessions are squeezed to this width
+const MAX_ROLL_WIDTH: usize = 4000;
+// Time labels are at least this far apart
+const LABEL_SPACING: f32 = 60.0;
+
+/**
+ * A note held long enough to be written down, with how far from it it was played on average
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TranscribedNote {
+    // Capture time of the first frame it was heard in
+    pub onset: Duration,
+    pub duration: Duration,
+    pub midi_note: i32,
+    // Over the frames of its sustain, or over all its frames when it had none
+    pub mean_cents: f32,
+}
+
+// The note being heard: its number, when it started, and the frames and cents added up so far,
+// over the whole note and over its sustain
+struct HeldNote {
+    midi_note: i32,
+    onset: Duration,
+    frames: usize,
+    cents_sum: f32,
+    sustain_frames: usize,
+    sustain_cents_sum: f32,
+}
+
+/**
+ * Writes down the notes of a monophonic session from the pitch of each frame: a note starts
+ * when the nearest note of a confident pitch changes and ends when it changes again or the
+ * pitch goes away. How far from the note it was played is measured on the frames of its
+ * sustain, the attack of a plucked string ringing sharp.
+ */
+pub struct Transcriber {
+    notes: Vec<TranscribedNote>,
+    held: Option<HeldNote>,
+    segmenter: NoteSegmenter,
+}
+
+impl Default for Transcriber {
+    fn default() -> Self {
+        Self {
+            notes: vec![],
+            held: None,
+            segmenter: NoteSegmenter::new(MIN_LEVEL_DB),
+        }
+    }
+}
+
+impl Transcriber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn notes(&self) -> &[TranscribedNote] {
+        &self.notes
+    }
+
+    /**
+     * Takes the pitch of the next frame, its samples and its capture time. The frames follow
+     * each other without overlapping, for the segmenter.
+     */
+    pub fn push(
+        &mut self,
+        pitch: Option<PitchEstimate>,
+        samples: &[f32],
+        sample_rate: u32,
+        captured_at: Duration,
+    ) {
+        let sustain = self.segmenter.process(samples, sample_rate);
+        // Only a frame sustained from its start to its end has a pitch free of the attack
+        let sustained = !samples.is_empty() && sustain == (0..samples.len());
+        let heard = pitch.filter(PitchEstimate::is_confident).map(|pitch| {
+            let midi = midi_number(pitch.frequency_in_hz);
+            (midi.round() as i32, (midi - midi.round()) * 100.0)
+        });
+        match (self.held.as_mut(), heard) {
+            (Some(held), Some((midi_note, cents))) if held.midi_note == midi_note => {
+                held.frames += 1;
+                held.cents_sum += cents;
+                if sustained {
+                    held.sustain_frames += 1;
+                    held.sustain_cents_sum += cents;
+                }
+            }
+            _ => {
+                self.finish(captured_at);
+                self.held = heard.map(|(midi_note, cents)| HeldNote {
+                    midi_note,
+                    onset: captured_at,
+                    frames: 1,
+                    cents_sum: cents,
+                    sustain_frames: sustained as usize,
+                    sustain_cents_sum: if sustained { cents } else { 0.0 },
+                });
+            }
+        }
+    }
+
+    /**
+     * Ends the note being heard at `captured_at`, at the end of the session
+     */
+    pub fn finish(&mut self, captured_at: Duration) {
+        if let Some(held) = self
+            .held
+            .take()
+            .filter(|held| held.frames >= MIN_NOTE_FRAMES)
+        {
+            self.notes.push(TranscribedNote {
+                onset: held.onset,
+                duration: captured_at.saturating_sub(held.onset),
+                midi_note: held.midi_note,
+                mean_cents: match held.sustain_frames {
+                    0 => held.cents_sum / held.frames as f32,
+                    frames => held.sustain_cents_sum / frames as f32,
+                },
+            });
+        }
+    }
+
+    /**
+     * Writes the notes as a CSV timeline, a standard MIDI file and a piano roll image named
+     * after the time they're saved at in `directory`, and returns their paths
+     */
+    pub fn export(&self, directory: &Path) -> Result<Vec<PathBuf>, String> {
+        if self.notes.is_empty() {
+            return Err("No note was held long enough to be written down".into());
+        }
+        std::fs::create_dir_all(directory).map_err(|error| error.to_string())?;
+        let saved = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        let name = format!("notes-{}", saved.replace(':', "-"));
+
+        let csv_path = directory.join(format!("{name}.csv"));
+        let mut csv = String::from("onset_s,duration_s,note,midi_note,mean_cents\n");
+        for note in &self.notes {
+            csv += &format!(
+                "{:.3},{:.3},{},{},{:.1}\n",
+                note.onset.as_secs_f64(),
+                note.duration.as_secs_f64(),
+                note_name(note.midi_note),
+                note.midi_note,
+                note.mean_cents
+            );
+        }
+        std::fs::write(&csv_path, csv).map_err(|error| error.to_string())?;
+
+        let midi_path = directory.join(format!("{name}.mid"));
+        std::fs::write(&midi_path, midi_file(&self.notes)).map_err(|error| error.to_string())?;
+
+        let png_path = directory.join(format!("{name}.png"));
+        let (width, height, rgb) = piano_roll(&self.notes);
+        write_png(&png_path, width as u32, height as u32, &rgb)?;
+
+        Ok(vec![csv_path, midi_path, png_path])
+    }
+}
+
+/**
+ * Appends a MIDI variable-length quantity: 7 bits per byte, the highest bit set on all but the
+ * last one
+ */
+fn push_variable_length(bytes: &mut Vec<u8>, value: u32) {
+    let mut groups = vec![(value & 0x7f) as u8];
+    let mut rest = value >> 7;
+    while rest > 0 {
+        groups.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    bytes.extend(groups.iter().rev());
+}
+
+/**
+ * A format 0 standard MIDI file of the notes, starting with the first one
+ */
+fn midi_file(notes: &[TranscribedNote]) -> Vec<u8> {
+    let start = notes.first().map_or(Duration::ZERO, |note| note.onset);
+    let ticks_per_sec = TICKS_PER_QUARTER as f64 * 1e6 / MICROSECONDS_PER_QUARTER as f64;
+    let tick = |time: Duration| ((time - start).as_secs_f64() * ticks_per_sec).round() as u32;
+    // Tick, whether it's a note on, and the note. At the same tick the notes end first.
+    let mut events: Vec<(u32, bool, u8)> = notes
+        .iter()
+        .flat_map(|note| {
+            let midi_note = note.midi_note.clamp(0, 127) as u8;
+            [
+                (tick(note.onset), true, midi_note),
+                (tick(note.onset + note.duration), false, midi_note),
+            ]
+        })
+        .collect();
+    events.sort_by_key(|(tick, on, _)| (*tick, *on));
+
+    let mut track = vec![0x00, 0xff, 0x51, 0x03];
+    track.extend(&MICROSECONDS_PER_QUARTER.to_be_bytes()[1..]);
+    let mut last_tick = 0;
+    for (tick, on, midi_note) in events {
+        push_variable_length(&mut track, tick - last_tick);
+        last_tick = tick;
+        match on {
+            true => track.extend([0x90, midi_note, VELOCITY]),
+            false => track.extend([0x80, midi_note, 0]),
+        }
+    }
+    track.extend([0x00, 0xff, 0x2f, 0x00]);
+
+    let mut bytes = b"MThd".to_vec();
+    bytes.extend(6u32.to_be_bytes());
+    bytes.extend(0u16.to_be_bytes());
+    bytes.extend(1u16.to_be_bytes());
+    bytes.extend(TICKS_PER_QUARTER.to_be_bytes());
+    bytes.extend(b"MTrk");
+    bytes.extend((track.len() as u32).to_be_bytes());
+    bytes.extend(track);
+    bytes
+}
+
+/**
+ * Draws the notes as a piano roll: one row per note from the lowest to the highest one played,
+ * the time going to the right, each note colored like the bars by how in tune it was on
+ * average. Returns the width, height and RGB pixels of the image.
+ */
+fn piano_roll(notes: &[TranscribedNote]) -> (usize, usize, Vec<u8>) {
+    let lowest = notes.iter().map(|note| note.midi_note).min().unwrap_or(60);
+    let highest = notes.iter().map(|note| note.midi_note).max().unwrap_or(60);
+    let start = notes.first().map_or(Duration::ZERO, |note| note.onset);
+    let end = notes
+        .iter()
+        .map(|note| note.onset + note.duration)
+        .max()
+        .unwrap_or(start);
+    let session_secs = (end - start).as_secs_f32().max(1.0);
+    let pixels_per_sec = PIXELS_PER_SEC.min(MAX_ROLL_WIDTH as f32 / session_secs);
+
+    let rows = (highest - lowest + 1) as usize;
+    let width = AXIS_LEFT + (session_secs * pixels_per_sec).ceil() as usize + 1;
+    let height = rows * ROW_HEIGHT + AXIS_BOTTOM;
+    let mut rgb = vec![0u8; width * height * 3];
+    let fill = |rgb: &mut [u8], x: usize, y: usize, w: usize, h: usize, color: [u8; 3]| {
+        for row in y..(y + h).min(height) {
+            for column in x..(x + w).min(width) {
+                let i = (row * width + column) * 3;
+                rgb[i..i + 3].copy_from_slice(&color);
+            }
+        }
+    };
+    let white = [230, 230, 230];
+    let row_y = |midi_note: i32| (highest - midi_note) as usize * ROW_HEIGHT;
+
+    // The rows of the black keys darker, like on a piano roll editor
+    for midi_note in lowest..=highest {
+        let black_key = matches!(midi_note.rem_euclid(12), 1 | 3 | 6 | 8 | 10);
+        let shade = if black_key {
+            [20, 20, 20]
+        } else {
+            [40, 40, 40]
+        };
+        fill(
+            &mut rgb,
+            AXIS_LEFT,
+            row_y(midi_note),
+            width,
+            ROW_HEIGHT,
+            shade,
+        );
+        let label = note_name(midi_note);
+        text::draw_text_rgb(
+            &mut rgb,
+            width,
+            &label,
+            2,
+            row_y(midi_note) as i32 + 1,
+            white,
+        );
+    }
+
+    let label_secs = [1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0]
+        .into_iter()
+        .find(|secs| secs * pixels_per_sec >= LABEL_SPACING)
+        .unwrap_or(1200.0);
+    let axis_y = rows * ROW_HEIGHT;
+    let mut secs = 0.0;
+    while secs <= session_secs {
+        let x = AXIS_LEFT + (secs * pixels_per_sec).round() as usize;
+        fill(&mut rgb, x, 0, 1, axis_y + 3, [90, 90, 90]);
+        let label = format!("{:.0} s", start.as_secs_f32() + secs);
+        text::draw_text_rgb(
+            &mut rgb,
+            width,
+            &label,
+            x as i32 + 2,
+            axis_y as i32 + 5,
+            white,
+        );
+        secs += label_secs;
+    }
+
+    for note in notes {
+        let color = match note.mean_cents {
+            cents if cents > IN_TUNE_CENTS => [239, 71, 111],
+            cents if cents < -IN_TUNE_CENTS => [255, 209, 102],
+            _ => [6, 214, 160],
+        };
+        let x = AXIS_LEFT + ((note.onset - start).as_secs_f32() * pixels_per_sec) as usize;
+        let w = ((note.duration.as_secs_f32() * pixels_per_sec) as usize).max(1);
+        fill(
+            &mut rgb,
+            x,
+            row_y(note.midi_note) + 1,
+            w,
+            ROW_HEIGHT - 2,
+            color,
+        );
+    }
+    (width, height, rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pitch(frequency_in_hz: f32) -> Option<PitchEstimate> {
+        Some(PitchEstimate {
+            frequency_in_hz,
+            level_db: -20.0,
+            confidence: 0.9,
+        })
+    }
+
+    #[test]
+    fn held_notes_are_written_down() {
+        let mut transcriber = Transcriber::new();
+        let frame = |index: u64| Duration::from_millis(100 * index);
+        // A4 a bit sharp for 5 frames, a blip of E5, then C4 until the end
+        let pitches = [
+            pitch(442.0),
+            pitch(442.0),
+            pitch(443.0),
+            pitch(442.0),
+            pitch(442.0),
+            pitch(659.0),
+            pitch(261.6),
+            pitch(261.6),
+            pitch(261.6),
+            None,
+        ];
+        // Silence for the segmenter, the cents are then averaged over every frame
+        for (index, pitch) in pitches.into_iter().enumerate() {
+            transcriber.push(pitch, &[0.0; 800], 8000, frame(index as u64));
+        }
+        transcriber.finish(frame(10));
+
+        let notes = transcriber.notes();
+        assert_eq!(notes.len(), 2);
+        assert_eq!((notes[0].midi_note, notes[0].onset), (69, frame(0)));
+        assert_eq!(notes[0].duration, frame(5));
+        assert!(
+            (notes[0].mean_cents - 8.6).abs() < 0.1,
+            "{}",
+            notes[0].mean_cents
+        );
+        assert_eq!((notes[1].midi_note, notes[1].duration), (60, frame(3)));
+    }
+
+    #[test]
+    fn the_attack_is_left_out_of_the_tuning() {
+        let sample_rate = 8000;
+        let mut transcriber = Transcriber::new();
+        // 100 ms frames of A4, rising for the first one and then steady, played 39 and 23 cents
+        // sharp while the level settles
+        let frame_samples = |index: usize| -> Vec<f32> {
+            (index * 800..(index + 1) * 800)
+                .map(|i| {
+                    let t = i as f32 / sample_rate as f32;
+                    let level = (t / 0.1).min(1.0) * 0.3;
+                    level * (2.0 * std::f32::consts::PI * 440.0 * t).sin()
+                })
+                .collect()
+        };
+        for (index, frequency_in_hz) in [450.0, 446.0, 440.0, 440.0, 440.0].iter().enumerate() {
+            let captured_at = Duration::from_millis(100 * index as u64);
+            let samples = frame_samples(index);
+            transcriber.push(pitch(*frequency_in_hz), &samples, sample_rate, captured_at);
+        }
+        transcriber.finish(Duration::from_millis(500));
+
+        let notes = transcriber.notes();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].onset, Duration::ZERO);
+        assert!(notes[0].mean_cents.abs() < 0.1, "{}", notes[0].mean_cents);
+    }
+
+    #[test]
+    fn the_midi_file_holds_every_note() {
+        let notes = [
+            TranscribedNote {
+                onset: Duration::from_secs(10),
+                duration: Duration::from_millis(500),
+                midi_note: 69,
+                mean_cents: 0.0,
+            },
+            TranscribedNote {
+                onset: Duration::from_millis(10500),
+                duration: Duration::from_secs(1),
+                midi_note: 71,
+                mean_cents: 0.0,
+            },
+        ];
+        let bytes = midi_file(&notes);
+        assert_eq!(&bytes[..4], b"MThd");
+        let track_len = u32::from_be_bytes(bytes[18..22].try_into().unwrap()) as usize;
+        assert_eq!(bytes.len(), 22 + track_len);
+        let track = &bytes[22..];
+        // Tempo, then A4 on at 0, off after 480 ticks (0x83 0x60) with B4 on right after it
+        assert_eq!(&track[7..11], &[0x00, 0x90, 69, VELOCITY]);
+        assert_eq!(&track[11..15], &[0x83, 0x60, 0x80, 69]);
+        assert_eq!(&track[16..20], &[0x00, 0x90, 71, VELOCITY]);
+        assert_eq!(&track[track.len() - 3..], &[0xff, 0x2f, 0x00]);
+    }
+}
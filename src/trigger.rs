@@ -0,0 +1,106 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::analysis::{Band, FftFrame, Summary};
+
+pub struct TriggerConfig {
+    pub threshold_db: f32,
+    // Band whose level is compared to the threshold, the whole spectrum when None
+    pub band: Option<Band>,
+    pub pre_trigger_samples: usize,
+    pub sample_rate: u32,
+}
+
+/**
+ * What was captured when the trigger fired: the frame that crossed the threshold and the
+ * audio before it
+ */
+pub struct Capture {
+    pub frame: FftFrame,
+    pub level_db: f32,
+    // The pre-trigger history followed by the samples of the frame
+    pub samples: Vec<f32>,
+    // Index in samples where the triggering frame starts
+    pub frame_start: usize,
+}
+
+/**
+ * The part of the trigger shared with the window, which shows the captures and re-arms it
+ */
+#[derive(Default)]
+pub struct TriggerHandle {
+    armed: AtomicBool,
+    capture: Mutex<Option<Capture>>,
+}
+
+impl TriggerHandle {
+    pub fn arm(&self) {
+        self.armed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn take_capture(&self) -> Option<Capture> {
+        self.capture.lock().unwrap().take()
+    }
+}
+
+/**
+ * Watches every FFT result from the audio callback, so no frame is missed even when the
+ * window is busy. It fires once and stays disarmed until the window arms it again.
+ */
+pub struct Trigger {
+    config: TriggerConfig,
+    bands: Vec<Band>,
+    history: VecDeque<f32>,
+    handle: Arc<TriggerHandle>,
+}
+
+impl Trigger {
+    pub fn new(config: TriggerConfig) -> (Self, Arc<TriggerHandle>) {
+        let handle = Arc::new(TriggerHandle::default());
+        handle.arm();
+        let trigger = Self {
+            bands: config.band.iter().cloned().collect(),
+            history: VecDeque::with_capacity(config.pre_trigger_samples),
+            config,
+            handle: handle.clone(),
+        };
+        (trigger, handle)
+    }
+
+    pub fn process(&mut self, frame: &FftFrame) {
+        if self.handle.armed.load(Ordering::Relaxed) {
+            let level_db =
+                Summary::from_magnitudes(&frame.magnitudes, self.config.sample_rate, &self.bands)
+                    .map(|summary| match self.config.band {
+                        Some(_) => summary.band_levels_db[0],
+                        None => summary.level_db,
+                    });
+
+            if let Some(level_db) = level_db.filter(|level| *level >= self.config.threshold_db) {
+                self.handle.armed.store(false, Ordering::Relaxed);
+                let mut samples: Vec<f32> = self.history.iter().cloned().collect();
+                let frame_start = samples.len();
+                samples.extend_from_slice(&frame.samples);
+                *self.handle.capture.lock().unwrap() = Some(Capture {
+                    frame: frame.clone(),
+                    level_db,
+                    samples,
+                    frame_start,
+                });
+            }
+        }
+
+        // The frames are consecutive, so their samples make up a continuous history
+        self.history.extend(frame.samples.iter());
+        let excess = self
+            .history
+            .len()
+            .saturating_sub(self.config.pre_trigger_samples);
+        self.history.drain(0..excess);
+    }
+}
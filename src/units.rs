@@ -0,0 +1,286 @@
+use crate::analysis::db_to_amplitude;
+
+// dBV is referenced to 1 V and dBu to 0.775 V, 2.2 dB below
+const DBU_TO_DBV: f32 = -2.2185;
+
+// Scientific pitch notation, C4 being the middle C
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/**
+ * How frequencies are written in the readouts, the tables and the reports
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "app", derive(clap::ValueEnum))]
+pub enum FrequencyUnit {
+    #[default]
+    Hz,
+    Khz,
+    // Nearest note and how far from it in cents, like A4 +12c
+    Note,
+    // MIDI note number with decimals, 69 being A4 at 440 Hz
+    Midi,
+}
+
+/**
+ * MIDI note number of a frequency, not rounded
+ */
+pub fn midi_number(frequency_in_hz: f32) -> f32 {
+    69.0 + 12.0 * (frequency_in_hz / 440.0).log2()
+}
+
+/**
+ * Name and octave of a MIDI note in scientific pitch notation, like A4 for 69
+ */
+pub fn note_name(midi_note: i32) -> String {
+    format!(
+        "{}{}",
+        NOTE_NAMES[midi_note.rem_euclid(12) as usize],
+        midi_note.div_euclid(12) - 1
+    )
+}
+
+/**
+ * Reads a frequency typed as a number of Hz (`440`, `440 Hz`, `1.2 kHz`, `1.2k`) or as a note
+ * (`A4`, `C#3`, `Bb2`, optionally followed by cents like `A4 +12c`)
+ */
+pub fn parse_frequency(text: &str) -> Result<f32, String> {
+    let text = text.trim();
+    let lowercase = text.to_lowercase();
+    let number = |digits: &str| digits.trim().parse::<f32>().ok();
+    let frequency_in_hz = if let Some(khz) = lowercase
+        .strip_suffix("khz")
+        .or_else(|| lowercase.strip_suffix('k'))
+    {
+        number(khz).map(|khz| khz * 1000.0)
+    } else {
+        number(lowercase.strip_suffix("hz").unwrap_or(&lowercase)).or_else(|| parse_note(text))
+    };
+    match frequency_in_hz {
+        Some(frequency_in_hz) if frequency_in_hz > 0.0 && frequency_in_hz.is_finite() => {
+            Ok(frequency_in_hz)
+        }
+        _ => Err(format!(
+            "\"{text}\" is neither a frequency (like 440, 1.2 kHz) nor a note (like A4, C#3)"
+        )),
+    }
+}
+
+fn parse_note(text: &str) -> Option<f32> {
+    let (note, cents) = match text.split_once(char::is_whitespace) {
+        Some((note, cents)) => (note, cents.trim().strip_suffix('c')?.parse::<f32>().ok()?),
+        None => (text, 0.0),
+    };
+    let mut chars = note.chars();
+    let letter = chars.next()?.to_ascii_uppercase().to_string();
+    let mut semitone = NOTE_NAMES.iter().position(|name| *name == letter)? as f32;
+    let rest = chars.as_str();
+    let octave = if let Some(octave) = rest.strip_prefix('#') {
+        semitone += 1.0;
+        octave
+    } else if let Some(octave) = rest.strip_prefix('b') {
+        semitone -= 1.0;
+        octave
+    } else {
+        rest
+    };
+    let midi = (octave.parse::<i32>().ok()? + 1) as f32 * 12.0 + semitone + cents / 100.0;
+    Some(440.0 * 2f32.powf((midi - 69.0) / 12.0))
+}
+
+impl FrequencyUnit {
+    /**
+     * The unit after this one, for switching between them with a key
+     */
+    pub fn next(self) -> Self {
+        match self {
+            Self::Hz => Self::Khz,
+            Self::Khz => Self::Note,
+            Self::Note => Self::Midi,
+            Self::Midi => Self::Hz,
+        }
+    }
+
+    /**
+     * Name of the unit for table headers
+     */
+    pub fn header(self) -> &'static str {
+        match self {
+            Self::Hz => "Hz",
+            Self::Khz => "kHz",
+            Self::Note => "note",
+            Self::Midi => "MIDI",
+        }
+    }
+
+    /**
+     * Writes the frequency without the unit, for table cells
+     */
+    pub fn value(self, frequency_in_hz: f32) -> String {
+        match self {
+            Self::Hz => format!("{frequency_in_hz:.2}"),
+            Self::Khz => format!("{:.4}", frequency_in_hz / 1000.0),
+            // Notes and MIDI numbers don't go down to DC
+            _ if frequency_in_hz <= 0.0 => "-".into(),
+            Self::Note => {
+                let midi = midi_number(frequency_in_hz);
+                let nearest = midi.round();
+                format!(
+                    "{} {:+.0}c",
+                    note_name(nearest as i32),
+                    (midi - nearest) * 100.0
+                )
+            }
+            Self::Midi => format!("{:.2}", midi_number(frequency_in_hz)),
+        }
+    }
+
+    /**
+     * Writes the frequency with its unit, for readouts
+     */
+    pub fn format(self, frequency_in_hz: f32) -> String {
+        match self {
+            Self::Hz | Self::Khz => format!("{} {}", self.value(frequency_in_hz), self.header()),
+            Self::Note => self.value(frequency_in_hz),
+            Self::Midi => format!("MIDI {}", self.value(frequency_in_hz)),
+        }
+    }
+}
+
+/**
+ * How levels are written in the readouts and the tables
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "app", derive(clap::ValueEnum))]
+pub enum AmplitudeUnit {
+    // Amplitude of the sine, 1 being full scale
+    Linear,
+    #[default]
+    Dbfs,
+    // Voltage at the input of the interface, given its full scale level in dBu
+    Dbu,
+    Dbv,
+    // Sound pressure, given the offset from dBFS found by calibrating the mic
+    Spl,
+}
+
+impl AmplitudeUnit {
+    /**
+     * The unit after this one, for switching between them with Shift and the same key
+     */
+    pub fn next(self) -> Self {
+        match self {
+            Self::Linear => Self::Dbfs,
+            Self::Dbfs => Self::Dbu,
+            Self::Dbu => Self::Dbv,
+            Self::Dbv => Self::Spl,
+            Self::Spl => Self::Linear,
+        }
+    }
+}
+
+/**
+ * An amplitude unit with the calibration it needs to be reached from dBFS
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmplitudeScale {
+    pub unit: AmplitudeUnit,
+    // Level in dBu of a full scale sine, +24 dBu on most studio interfaces
+    pub full_scale_dbu: f32,
+    pub spl_offset_db: f32,
+}
+
+impl AmplitudeScale {
+    /**
+     * Name of the unit for table headers
+     */
+    pub fn header(&self) -> &'static str {
+        match self.unit {
+            AmplitudeUnit::Linear => "linear",
+            AmplitudeUnit::Dbfs => "dBFS",
+            AmplitudeUnit::Dbu => "dBu",
+            AmplitudeUnit::Dbv => "dBV",
+            AmplitudeUnit::Spl => "dB SPL",
+        }
+    }
+
+    /**
+     * Writes a level in dBFS in the unit, without it, for table cells
+     */
+    pub fn value(&self, level_db: f32) -> String {
+        match self.unit {
+            AmplitudeUnit::Linear => format!("{:.5}", db_to_amplitude(level_db)),
+            AmplitudeUnit::Dbfs => format!("{level_db:.1}"),
+            AmplitudeUnit::Dbu => format!("{:.1}", level_db + self.full_scale_dbu),
+            AmplitudeUnit::Dbv => format!("{:.1}", level_db + self.full_scale_dbu + DBU_TO_DBV),
+            AmplitudeUnit::Spl => format!("{:.1}", level_db + self.spl_offset_db),
+        }
+    }
+
+    /**
+     * Writes a level in dBFS in the unit, with it, for readouts
+     */
+    pub fn format(&self, level_db: f32) -> String {
+        match self.unit {
+            AmplitudeUnit::Linear => format!("{} FS", self.value(level_db)),
+            _ => format!("{} {}", self.value(level_db), self.header()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_levels_from_dbfs() {
+        let mut scale = AmplitudeScale {
+            unit: AmplitudeUnit::Dbu,
+            full_scale_dbu: 24.0,
+            spl_offset_db: 120.0,
+        };
+        // -18 dBFS is the +4 dBu studio level of 1.228 V on such an interface
+        assert_eq!(scale.format(-20.0), "4.0 dBu");
+        scale.unit = AmplitudeUnit::Dbv;
+        assert_eq!(scale.format(-20.0), "1.8 dBV");
+        scale.unit = AmplitudeUnit::Spl;
+        assert_eq!(scale.format(-26.0), "94.0 dB SPL");
+        scale.unit = AmplitudeUnit::Linear;
+        assert_eq!(scale.format(-6.0206), "0.50000 FS");
+    }
+
+    #[test]
+    fn parses_frequencies_and_notes() {
+        assert_eq!(parse_frequency("440"), Ok(440.0));
+        assert_eq!(parse_frequency(" 1.2 kHz"), Ok(1200.0));
+        assert_eq!(parse_frequency("3k"), Ok(3000.0));
+        assert_eq!(parse_frequency("50hz"), Ok(50.0));
+        let close =
+            |text: &str, expected: f32| (parse_frequency(text).unwrap() - expected).abs() < 0.01;
+        assert!(close("A4", 440.0));
+        assert!(close("c4", 261.63));
+        assert!(close("Bb3", 233.08));
+        assert!(close("C#3", 138.59));
+        // What the note unit writes reads back, to the cent
+        let read_back = parse_frequency(&FrequencyUnit::Note.format(250.0)).unwrap();
+        assert!((read_back - 250.0).abs() < 0.15, "{read_back}");
+        assert!(parse_frequency("H4").is_err());
+        assert!(parse_frequency("-20").is_err());
+    }
+
+    #[test]
+    fn formats_in_every_unit() {
+        assert_eq!(FrequencyUnit::Hz.format(440.0), "440.00 Hz");
+        assert_eq!(FrequencyUnit::Khz.format(12345.0), "12.3450 kHz");
+        assert_eq!(FrequencyUnit::Note.format(440.0), "A4 +0c");
+        assert_eq!(FrequencyUnit::Note.format(261.63), "C4 +0c");
+        // 20 cents above B3
+        assert_eq!(
+            FrequencyUnit::Note.format(246.94 * 2f32.powf(20.0 / 1200.0)),
+            "B3 +20c"
+        );
+        assert_eq!(FrequencyUnit::Midi.format(880.0), "MIDI 81.00");
+        assert_eq!(FrequencyUnit::Note.format(0.0), "-");
+    }
+}
@@ -0,0 +1,97 @@
+use crate::analysis::{one_sided_amplitudes, power_to_db, BinMapping};
+
+// Band of the voice that counts, from the lowest sung notes to the top of the formants
+const VOICE_LOW_HZ: f32 = 80.0;
+const VOICE_HIGH_HZ: f32 = 4000.0;
+
+/**
+ * Voice activity detection: a frame is voiced when the voice band is loud enough and its
+ * spectrum is peaky (harmonics) rather than flat (breaths, hiss, room noise)
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct Vad {
+    pub min_level_db: f32,
+    // Spectral flatness above which the frame is noise, from 0 (a single sine) to 1 (white noise)
+    pub max_flatness: f32,
+}
+
+impl Vad {
+    /**
+     * Takes the two-sided magnitudes of an FFT result, like the ones in FftFrame
+     */
+    pub fn is_voiced(&self, magnitudes: &[f32], sample_rate: u32) -> bool {
+        let mapping = BinMapping::new(sample_rate, magnitudes.len());
+        let amplitudes = one_sided_amplitudes(magnitudes);
+        let low = mapping.bin_at(VOICE_LOW_HZ).max(1);
+        let high = mapping
+            .bin_at(VOICE_HIGH_HZ)
+            .min(amplitudes.len().saturating_sub(1));
+        if low >= high {
+            return false;
+        }
+        let powers: Vec<f32> = amplitudes[low..=high]
+            .iter()
+            .map(|amplitude| amplitude * amplitude / 2.0)
+            .collect();
+        power_to_db(powers.iter().sum()) >= self.min_level_db
+            && spectral_flatness(&powers) <= self.max_flatness
+    }
+}
+
+/**
+ * Ratio of the geometric mean to the arithmetic mean of the powers
+ */
+pub fn spectral_flatness(powers: &[f32]) -> f32 {
+    if powers.is_empty() {
+        return 1.0;
+    }
+    // The floor keeps a single empty bin from making the geometric mean zero
+    let floor = 1e-20;
+    let mean_log =
+        powers.iter().map(|power| (power + floor).ln()).sum::<f32>() / powers.len() as f32;
+    let mean = powers.iter().sum::<f32>() / powers.len() as f32 + floor;
+    mean_log.exp() / mean
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fft_magnitudes;
+
+    #[test]
+    fn harmonics_are_voiced_but_noise_and_silence_are_not() {
+        let sample_rate = 44100;
+        let fft_len = 4096;
+        let vad = Vad {
+            min_level_db: -50.0,
+            max_flatness: 0.3,
+        };
+
+        let voice: Vec<f32> = (0..fft_len)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                (1..=10)
+                    .map(|harmonic| {
+                        (2.0 * std::f32::consts::PI * 220.0 * harmonic as f32 * t).sin() * 0.2
+                            / harmonic as f32
+                    })
+                    .sum()
+            })
+            .collect();
+        assert!(vad.is_voiced(&fft_magnitudes(&voice), sample_rate));
+
+        let mut state = 0x1234_5678u32;
+        let noise: Vec<f32> = (0..fft_len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as f32 / u32::MAX as f32 - 0.5) * 0.5
+            })
+            .collect();
+        assert!(!vad.is_voiced(&fft_magnitudes(&noise), sample_rate));
+
+        let quiet: Vec<f32> = voice.iter().map(|sample| sample * 1e-4).collect();
+        assert!(!vad.is_voiced(&fft_magnitudes(&quiet), sample_rate));
+    }
+}
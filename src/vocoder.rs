@@ -0,0 +1,185 @@
+use std::f32::consts::PI;
+
+use ndarray::Array1;
+use num_complex::Complex;
+
+use crate::{filter::Resampler, output, windowing::WindowFunction};
+
+// Frames of the short-time FFT, long enough to tell the harmonics of a voice apart
+const FRAME_LEN: usize = 2048;
+// The frames overlap by 7/8, so that the stretched hops still overlap by at least 3/4
+const ANALYSIS_HOP: usize = FRAME_LEN / 8;
+
+/**
+ * Shifts the pitch of a stream with a phase vocoder: the short-time FFT frames are laid out
+ * `ratio` times further apart, with the phase of each bin moved on by the frequency measured in
+ * it, which stretches the sound without changing its pitch. Playing it back `ratio` times faster
+ * brings it back to its length, with every frequency multiplied by `ratio`.
+ */
+pub struct PitchShifter {
+    window: Vec<f32>,
+    synthesis_hop: usize,
+    // Sum of the squared windows laid out every synthesis hop, which the output is divided by
+    gain: f32,
+    // Input not analyzed yet, starting at the next frame
+    input: Vec<f32>,
+    // Phase of each bin in the last input frame, none before the first one
+    last_phases: Option<Vec<f32>>,
+    // Phase of each bin in the last output frame
+    output_phases: Vec<f32>,
+    // Sum of the frames still being added to, the first synthesis hop of it is complete
+    overlap: Vec<f32>,
+    resampler: Resampler,
+}
+
+impl PitchShifter {
+    pub fn new(semitones: f32, input_sample_rate: u32, output_sample_rate: u32) -> Self {
+        let synthesis_hop =
+            ((ANALYSIS_HOP as f32 * 2f32.powf(semitones / 12.0)).round() as usize).max(1);
+        // The hops are whole samples, the ratio played back has to be the one they make
+        let ratio = synthesis_hop as f32 / ANALYSIS_HOP as f32;
+        let window = WindowFunction::Hann.coefficients(FRAME_LEN);
+        let gain = window.iter().map(|w| w * w).sum::<f32>() / synthesis_hop as f32;
+        let bins = FRAME_LEN / 2 + 1;
+        Self {
+            window,
+            synthesis_hop,
+            gain,
+            input: vec![],
+            last_phases: None,
+            output_phases: vec![0.0; bins],
+            overlap: vec![0.0; FRAME_LEN],
+            resampler: Resampler::new(
+                (input_sample_rate as f32 * ratio).round() as u32,
+                output_sample_rate,
+            ),
+        }
+    }
+
+    /**
+     * Gets input samples and returns the output samples they make, a frame later
+     */
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.input.extend_from_slice(samples);
+        let mut output = vec![];
+        while self.input.len() >= FRAME_LEN {
+            let frame = Array1::from_iter(
+                self.input[..FRAME_LEN]
+                    .iter()
+                    .zip(&self.window)
+                    .map(|(sample, w)| Complex::from(sample * w)),
+            );
+            let spectrum = crate::fft(&frame);
+
+            let phases: Vec<f32> = (0..self.output_phases.len())
+                .map(|k| spectrum[k].arg())
+                .collect();
+            match &self.last_phases {
+                Some(last_phases) => {
+                    for (k, output_phase) in self.output_phases.iter_mut().enumerate() {
+                        // Advance of a sine right on the bin, what is left over tells how far
+                        // off it is
+                        let expected = 2.0 * PI * k as f32 * ANALYSIS_HOP as f32 / FRAME_LEN as f32;
+                        let deviation = phases[k] - last_phases[k] - expected;
+                        let deviation = deviation - 2.0 * PI * (deviation / (2.0 * PI)).round();
+                        *output_phase += (expected + deviation) * self.synthesis_hop as f32
+                            / ANALYSIS_HOP as f32;
+                    }
+                }
+                // The bins of a peak start out of phase with each other like in the input,
+                // or they cancel out
+                None => self.output_phases.clone_from(&phases),
+            }
+            self.last_phases = Some(phases);
+
+            let mut shifted = Array1::<Complex<f32>>::zeros(FRAME_LEN);
+            for k in 0..self.output_phases.len() {
+                shifted[k] = Complex::from_polar(spectrum[k].norm(), self.output_phases[k]);
+                if k > 0 && k < FRAME_LEN / 2 {
+                    shifted[FRAME_LEN - k] = shifted[k].conj();
+                }
+            }
+
+            let frame = crate::ifft(&shifted);
+            for ((sum, sample), w) in self.overlap.iter_mut().zip(&frame).zip(&self.window) {
+                *sum += sample.re * w / self.gain;
+            }
+            for sample in self.overlap.drain(..self.synthesis_hop) {
+                self.resampler.push(sample, &mut output);
+            }
+            self.overlap.resize(FRAME_LEN, 0.0);
+            self.input.drain(..ANALYSIS_HOP);
+        }
+        output
+    }
+}
+
+/**
+ * Opens the default output device and plays the samples given to the returned function shifted
+ * by `semitones`
+ */
+pub fn play(
+    semitones: f32,
+    input_sample_rate: u32,
+) -> Result<(cpal::Stream, impl FnMut(&[f32]) + Send), String> {
+    let (stream, queue, output_sample_rate) = output::open("Pitch shift")?;
+    let mut shifter = PitchShifter::new(semitones, input_sample_rate, output_sample_rate);
+    let on_samples = move |samples: &[f32]| queue.extend(shifter.process(samples));
+    Ok((stream, on_samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loudest_frequency(samples: &[f32], sample_rate: u32) -> (f32, f32) {
+        let fft_len = 8192;
+        let magnitudes = crate::fft_magnitudes(&samples[samples.len() - fft_len..]);
+        let amplitudes = crate::analysis::one_sided_amplitudes(&magnitudes);
+        let (loudest, _) = amplitudes
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .unwrap();
+        let mapping = crate::analysis::BinMapping::new(sample_rate, fft_len);
+        let peak = samples[samples.len() - fft_len..]
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        (mapping.frequency_in_hz(loudest), peak)
+    }
+
+    #[test]
+    fn a_fifth_up_keeps_the_length_and_the_level() {
+        let sine: Vec<f32> = (0..24000)
+            .map(|i| 0.5 * (2.0 * PI * 440.0 * i as f32 / 48000.0).sin())
+            .collect();
+        let mut shifter = PitchShifter::new(7.0, 48000, 44100);
+        // Fed in blocks, the way the input callback does
+        let output: Vec<f32> = sine
+            .chunks(480)
+            .flat_map(|block| shifter.process(block))
+            .collect();
+
+        // A frame behind the input
+        let expected_len = (24000 - FRAME_LEN) as f32 * 44100.0 / 48000.0;
+        assert!(
+            (output.len() as f32 - expected_len).abs() < 500.0,
+            "{}",
+            output.len()
+        );
+        let (frequency_in_hz, peak) = loudest_frequency(&output, 44100);
+        assert!((frequency_in_hz - 659.26).abs() < 8.0, "{frequency_in_hz}");
+        assert!(peak > 0.4 && peak < 0.6, "{peak}");
+    }
+
+    #[test]
+    fn an_octave_down() {
+        let sine: Vec<f32> = (0..24000)
+            .map(|i| 0.5 * (2.0 * PI * 1000.0 * i as f32 / 48000.0).sin())
+            .collect();
+        let mut shifter = PitchShifter::new(-12.0, 48000, 48000);
+        let output = shifter.process(&sine);
+        let (frequency_in_hz, _) = loudest_frequency(&output, 48000);
+        assert!((frequency_in_hz - 500.0).abs() < 6.0, "{frequency_in_hz}");
+    }
+}
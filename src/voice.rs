@@ -0,0 +1,269 @@
+use std::{collections::VecDeque, fs::OpenOptions, io::Write, path::Path, time::SystemTime};
+
+#[cfg(feature = "app")]
+use sdl2::{
+    pixels::Color,
+    rect::{Point, Rect},
+    render::Canvas,
+    video::Window,
+};
+
+use crate::analysis::{yin_pitch, Band};
+#[cfg(feature = "app")]
+use crate::text;
+
+// Range of speaking and singing voices the pitch is looked for in
+const MIN_F0_HZ: f32 = 60.0;
+const MAX_F0_HZ: f32 = 500.0;
+
+// Time shown by the pitch track
+const TRACK_SECS: f32 = 20.0;
+
+/**
+ * Estimates the fundamental frequency of a voice, see [`yin_pitch`]
+ */
+pub fn estimate_f0(samples: &[f32], sample_rate: u32) -> Option<f32> {
+    yin_pitch(samples, sample_rate, MIN_F0_HZ, MAX_F0_HZ)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoiceStats {
+    pub median_hz: f32,
+    // 10th and 90th percentiles, so a few octave errors don't stretch the range
+    pub low_hz: f32,
+    pub high_hz: f32,
+    pub voiced_secs: f32,
+    // Share of the voiced time spent inside the target, when there is one
+    pub in_target_percentage: Option<f32>,
+}
+
+impl VoiceStats {
+    pub fn readout(&self) -> String {
+        let mut readout = format!(
+            "Median {:.0} Hz   Range {:.0} - {:.0} Hz   Voiced {:.0} s",
+            self.median_hz, self.low_hz, self.high_hz, self.voiced_secs
+        );
+        if let Some(percentage) = self.in_target_percentage {
+            readout += &format!("   In target {percentage:.0}%");
+        }
+        readout
+    }
+
+    /**
+     * Appends the statistics as a line of a CSV file, with a header when the file is new
+     */
+    pub fn append_to(&self, path: &Path) -> std::io::Result<()> {
+        let is_new = !path.exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if is_new {
+            writeln!(
+                file,
+                "time,voiced_s,median_hz,low_hz,high_hz,in_target_percentage"
+            )?;
+        }
+        writeln!(
+            file,
+            "{},{:.1},{:.1},{:.1},{:.1},{}",
+            humantime::format_rfc3339_seconds(SystemTime::now()),
+            self.voiced_secs,
+            self.median_hz,
+            self.low_hz,
+            self.high_hz,
+            self.in_target_percentage
+                .map(|percentage| format!("{percentage:.1}"))
+                .unwrap_or_default()
+        )
+    }
+}
+
+/**
+ * The pitch of the voice over the last TRACK_SECS, and every voiced pitch of the session for
+ * the statistics
+ */
+pub struct VoiceTrack {
+    target: Option<Band>,
+    // Time of each frame since the start, with its pitch when it was voiced
+    recent: VecDeque<(f32, Option<f32>)>,
+    session: Vec<f32>,
+    elapsed_secs: f32,
+    voiced_secs: f32,
+}
+
+impl VoiceTrack {
+    pub fn new(target: Option<Band>) -> Self {
+        Self {
+            target,
+            recent: VecDeque::new(),
+            session: vec![],
+            elapsed_secs: 0.0,
+            voiced_secs: 0.0,
+        }
+    }
+
+    /**
+     * Adds the pitch of a frame lasting `frame_secs`, None when it wasn't voiced
+     */
+    pub fn push(&mut self, f0_in_hz: Option<f32>, frame_secs: f32) {
+        self.elapsed_secs += frame_secs;
+        self.recent.push_back((self.elapsed_secs, f0_in_hz));
+        while self
+            .recent
+            .front()
+            .is_some_and(|(time, _)| self.elapsed_secs - time > TRACK_SECS)
+        {
+            self.recent.pop_front();
+        }
+        if let Some(f0_in_hz) = f0_in_hz {
+            self.session.push(f0_in_hz);
+            self.voiced_secs += frame_secs;
+        }
+    }
+
+    pub fn stats(&self) -> Option<VoiceStats> {
+        if self.session.is_empty() {
+            return None;
+        }
+        let mut sorted = self.session.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let percentile = |p: f32| sorted[((sorted.len() - 1) as f32 * p).round() as usize];
+        Some(VoiceStats {
+            median_hz: percentile(0.5),
+            low_hz: percentile(0.1),
+            high_hz: percentile(0.9),
+            voiced_secs: self.voiced_secs,
+            in_target_percentage: self.target.as_ref().map(|target| {
+                let inside = self
+                    .session
+                    .iter()
+                    .filter(|f0| (target.low_hz..=target.high_hz).contains(*f0))
+                    .count();
+                inside as f32 / self.session.len() as f32 * 100.0
+            }),
+        })
+    }
+
+    /**
+     * Draws the pitch track with time going right and pitch going up on a log axis, over the
+     * target band and the session median
+     */
+    #[cfg(feature = "app")]
+    pub fn draw(&self, canvas: &mut Canvas<Window>, area: Rect) {
+        canvas.set_draw_color(Color::RGB(25, 25, 25));
+        canvas.fill_rect(area).unwrap();
+
+        let y_of = |frequency_in_hz: f32| {
+            area.bottom()
+                - ((frequency_in_hz / MIN_F0_HZ).ln() / (MAX_F0_HZ / MIN_F0_HZ).ln()
+                    * area.height() as f32) as i32
+        };
+        let x_of = |time: f32| {
+            area.right() - ((self.elapsed_secs - time) / TRACK_SECS * area.width() as f32) as i32
+        };
+
+        if let Some(target) = &self.target {
+            let top = y_of(target.high_hz.min(MAX_F0_HZ));
+            let bottom = y_of(target.low_hz.max(MIN_F0_HZ));
+            if bottom > top {
+                canvas.set_draw_color(Color::RGB(30, 70, 40));
+                canvas
+                    .fill_rect(Rect::new(
+                        area.x(),
+                        top,
+                        area.width(),
+                        (bottom - top) as u32,
+                    ))
+                    .unwrap();
+            }
+        }
+
+        let label_color = Color::RGB(150, 150, 150);
+        for frequency_in_hz in [75.0, 100.0, 150.0, 200.0, 300.0, 400.0] {
+            let y = y_of(frequency_in_hz);
+            canvas.set_draw_color(Color::RGB(55, 55, 55));
+            canvas.draw_line((area.x(), y), (area.right(), y)).unwrap();
+            text::draw_text(
+                canvas,
+                &format!("{frequency_in_hz} Hz"),
+                area.x() + 4,
+                y - 9,
+                1,
+                label_color,
+            );
+        }
+
+        let stats = self.stats();
+        if let Some(stats) = &stats {
+            let y = y_of(stats.median_hz);
+            canvas.set_draw_color(Color::RGB(200, 160, 60));
+            canvas.draw_line((area.x(), y), (area.right(), y)).unwrap();
+        }
+
+        // Unvoiced frames break the line
+        canvas.set_draw_color(Color::RGB(230, 230, 230));
+        let mut segment: Vec<Point> = vec![];
+        for (time, f0_in_hz) in self.recent.iter().chain([&(f32::MAX, None)]) {
+            match f0_in_hz {
+                Some(f0_in_hz) => segment.push(Point::new(x_of(*time), y_of(*f0_in_hz))),
+                None => {
+                    if segment.len() == 1 {
+                        canvas
+                            .fill_rect(Rect::from_center(segment[0], 3, 3))
+                            .unwrap();
+                    }
+                    canvas.draw_lines(segment.as_slice()).unwrap();
+                    segment.clear();
+                }
+            }
+        }
+
+        let readout = stats.map_or("Waiting for a voice".into(), |stats| stats.readout());
+        text::draw_text(
+            canvas,
+            &readout,
+            area.x() + 60,
+            area.y() + 8,
+            2,
+            Color::RGB(230, 230, 230),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yin_finds_the_fundamental_under_a_louder_harmonic() {
+        let sample_rate = 44100;
+        for f0_in_hz in [95.0, 140.0, 220.0, 410.0] {
+            let samples: Vec<f32> = (0..4096)
+                .map(|i| {
+                    let phase =
+                        2.0 * std::f32::consts::PI * f0_in_hz * i as f32 / sample_rate as f32;
+                    0.2 * phase.sin() + 0.5 * (2.0 * phase).sin() + 0.3 * (3.0 * phase).sin()
+                })
+                .collect();
+            let estimate = estimate_f0(&samples, sample_rate).unwrap();
+            assert!(
+                (estimate / f0_in_hz - 1.0).abs() < 0.01,
+                "{f0_in_hz}: {estimate}"
+            );
+        }
+    }
+
+    #[test]
+    fn stats_leave_out_the_unvoiced_frames() {
+        let mut track = VoiceTrack::new(Some(Band {
+            low_hz: 165.0,
+            high_hz: 255.0,
+        }));
+        for f0_in_hz in [150.0, 170.0, 180.0, 190.0, 200.0] {
+            track.push(Some(f0_in_hz), 0.1);
+            track.push(None, 0.1);
+        }
+        let stats = track.stats().unwrap();
+        assert_eq!(stats.median_hz, 180.0);
+        assert!((stats.voiced_secs - 0.5).abs() < 1e-6);
+        assert_eq!(stats.in_target_percentage, Some(80.0));
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use sdl2::{
+    pixels::PixelFormatEnum,
+    rect::Rect,
+    render::{Canvas, TextureCreator},
+    video::{Window, WindowContext},
+};
+
+use crate::{
+    analysis::{amplitude_to_db, BinMapping},
+    clock::CaptureClock,
+    image::{spectrogram_color, write_png},
+    text,
+};
+
+/**
+ * A spectrogram scrolling down, one row per FFT result with the newest one at the top.
+ * Only the newest rows are drawn, but older ones are kept up to `history_rows` so the whole
+ * session can be exported.
+ */
+pub struct Waterfall {
+    max_frequency_in_hz: f32,
+    max_rows: usize,
+    history_rows: usize,
+    // Level in dBFS of each displayed bin of each row, and when the frame of the row was captured
+    rows: VecDeque<(Duration, Vec<f32>)>,
+    // Bins of the rows, set by the last push
+    mapping: Option<BinMapping>,
+}
+
+// Levels this far below the loudest one of the whole waterfall are drawn black
+const WATERFALL_RANGE_DB: f32 = 60.0;
+
+// Margins of the exported images holding the axes: the times on the left, the frequencies at
+// the bottom and the title at the top
+const AXIS_LEFT: usize = 64;
+const AXIS_BOTTOM: usize = 16;
+const TITLE_TOP: usize = 14;
+// Rows of a single exported image, longer histories are split into a sequence of them
+const MAX_EXPORT_ROWS: usize = 4000;
+// Minimum distance in pixels between two labels of an axis
+const LABEL_SPACING: usize = 60;
+
+/**
+ * Distance between the ticks of an axis, a round number (1, 2 or 5 times a power of ten)
+ * leaving at least `min_spacing` of it between them
+ */
+pub fn tick_step(min_spacing: f32) -> f32 {
+    let magnitude = 10f32.powf(min_spacing.max(f32::MIN_POSITIVE).log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|step| *step >= min_spacing)
+        .unwrap()
+}
+
+impl Waterfall {
+    pub fn new(max_frequency_in_hz: f32, max_rows: usize, history_rows: usize) -> Self {
+        Self {
+            max_frequency_in_hz,
+            max_rows,
+            history_rows: history_rows.max(max_rows),
+            rows: VecDeque::new(),
+            mapping: None,
+        }
+    }
+
+    pub fn push(&mut self, amplitudes: &[f32], mapping: BinMapping, captured_at: Duration) {
+        let bins = mapping
+            .bins_up_to(self.max_frequency_in_hz)
+            .min(amplitudes.len());
+        // Rows of another FFT size don't line up with the new ones
+        if self.rows.front().is_some_and(|(_, row)| row.len() != bins) {
+            self.rows.clear();
+        }
+        self.rows.push_front((
+            captured_at,
+            amplitudes[..bins]
+                .iter()
+                .map(|a| amplitude_to_db(*a))
+                .collect(),
+        ));
+        self.rows.truncate(self.history_rows);
+        self.mapping = Some(mapping);
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        texture_creator: &TextureCreator<WindowContext>,
+        area: Rect,
+    ) {
+        let Some(columns) = self
+            .rows
+            .front()
+            .map(|(_, row)| row.len())
+            .filter(|columns| *columns > 0)
+        else {
+            return;
+        };
+        let visible = || self.rows.iter().take(self.max_rows).map(|(_, row)| row);
+        let loudest_db = visible()
+            .flatten()
+            .fold(f32::MIN, |loudest, level_db| loudest.max(*level_db));
+
+        let mut texture = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGB24, columns as u32, self.max_rows as u32)
+            .unwrap();
+        texture
+            .with_lock(None, |pixels, pitch| {
+                pixels.fill(0);
+                for (y, row) in visible().enumerate() {
+                    for (x, level_db) in row.iter().enumerate() {
+                        let t = 1.0 - (loudest_db - level_db) / WATERFALL_RANGE_DB;
+                        let offset = y * pitch + x * 3;
+                        pixels[offset..offset + 3].copy_from_slice(&spectrogram_color(t));
+                    }
+                }
+            })
+            .unwrap();
+        canvas.copy(&texture, None, area).unwrap();
+    }
+
+    /**
+     * Writes the whole history into `directory` as PNGs with the time going down, one pixel
+     * per bin and per row, labeled with the capture times on the left and the frequencies at
+     * the bottom. Histories longer than MAX_EXPORT_ROWS are split into numbered images. Returns
+     * the paths written.
+     */
+    pub fn export(&self, directory: &Path, clock: &CaptureClock) -> Result<Vec<PathBuf>, String> {
+        let (Some(mapping), Some((_, newest))) = (self.mapping, self.rows.front()) else {
+            return Err("The spectrogram is still empty".into());
+        };
+        let columns = newest.len();
+        let loudest_db = self
+            .rows
+            .iter()
+            .flat_map(|(_, row)| row)
+            .fold(f32::MIN, |loudest, level_db| loudest.max(*level_db));
+
+        std::fs::create_dir_all(directory).map_err(|error| error.to_string())?;
+        let started = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        let name = format!("spectrogram-{}", started.replace(':', "-"));
+        // Oldest first, the image reads from top to bottom
+        let rows: Vec<&(Duration, Vec<f32>)> = self.rows.iter().rev().collect();
+        let chunks: Vec<&[&(Duration, Vec<f32>)]> = rows.chunks(MAX_EXPORT_ROWS).collect();
+
+        let mut paths = vec![];
+        for (index, chunk) in chunks.iter().enumerate() {
+            let width = AXIS_LEFT + columns;
+            let height = TITLE_TOP + chunk.len() + AXIS_BOTTOM;
+            let mut rgb = vec![0u8; width * height * 3];
+            let white = [230, 230, 230];
+
+            let (first, last) = (chunk[0].0, chunk[chunk.len() - 1].0);
+            let title = format!(
+                "{} to {}",
+                humantime::format_rfc3339_seconds(clock.wall_time(first)),
+                humantime::format_rfc3339_seconds(clock.wall_time(last))
+            );
+            text::draw_text_rgb(&mut rgb, width, &title, 2, 3, white);
+
+            for (y, (_, row)) in chunk.iter().enumerate() {
+                for (x, level_db) in row.iter().enumerate() {
+                    let t = 1.0 - (loudest_db - level_db) / WATERFALL_RANGE_DB;
+                    let i = ((TITLE_TOP + y) * width + AXIS_LEFT + x) * 3;
+                    rgb[i..i + 3].copy_from_slice(&spectrogram_color(t));
+                }
+            }
+
+            // Times in seconds on the capture clock, on the first row after each LABEL_SPACING
+            let mut next_label = 0;
+            for (y, (captured_at, _)) in chunk.iter().enumerate() {
+                if y < next_label {
+                    continue;
+                }
+                next_label = y + LABEL_SPACING;
+                let label = format!("{:.1} s", captured_at.as_secs_f64());
+                let row_y = (TITLE_TOP + y) as i32;
+                text::draw_text_rgb(&mut rgb, width, &label, 2, row_y, white);
+                for x in AXIS_LEFT - 4..AXIS_LEFT {
+                    let i = ((TITLE_TOP + y) * width + x) * 3;
+                    rgb[i..i + 3].copy_from_slice(&white);
+                }
+            }
+
+            let top_hz = mapping.frequency_in_hz(columns);
+            let step_hz = tick_step(top_hz * LABEL_SPACING as f32 / columns.max(1) as f32);
+            let axis_y = TITLE_TOP + chunk.len();
+            let mut frequency_in_hz = 0.0;
+            while frequency_in_hz < top_hz {
+                let x = AXIS_LEFT + (frequency_in_hz / mapping.bin_width_hz()).round() as usize;
+                if x >= width {
+                    break;
+                }
+                for y in axis_y..axis_y + 3 {
+                    let i = (y * width + x) * 3;
+                    rgb[i..i + 3].copy_from_slice(&white);
+                }
+                text::draw_text_rgb(
+                    &mut rgb,
+                    width,
+                    &format!("{frequency_in_hz} Hz"),
+                    x as i32,
+                    axis_y as i32 + 5,
+                    white,
+                );
+                frequency_in_hz += step_hz;
+            }
+
+            let path = match chunks.len() {
+                1 => directory.join(format!("{name}.png")),
+                _ => directory.join(format!("{name}-{:03}.png", index + 1)),
+            };
+            write_png(&path, width as u32, height as u32, &rgb)?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_fall_on_round_numbers() {
+        assert_eq!(tick_step(0.3), 0.5);
+        assert_eq!(tick_step(130.0), 200.0);
+        assert_eq!(tick_step(1000.0), 1000.0);
+        assert_eq!(tick_step(6.0), 10.0);
+    }
+
+    #[test]
+    fn the_whole_history_is_exported() {
+        let mapping = BinMapping::new(1000, 64);
+        let mut waterfall = Waterfall::new(250.0, 10, MAX_EXPORT_ROWS + 5);
+        for i in 0..MAX_EXPORT_ROWS + 5 {
+            waterfall.push(&[0.5; 33], mapping, Duration::from_millis(i as u64 * 64));
+        }
+        let directory = std::env::temp_dir().join(format!("waterfall-{}", std::process::id()));
+        let paths = waterfall
+            .export(&directory, &CaptureClock::start())
+            .unwrap();
+        // The last 5 rows go to a second image
+        assert_eq!(paths.len(), 2);
+        let decoder = png::Decoder::new(std::io::BufReader::new(
+            std::fs::File::open(&paths[1]).unwrap(),
+        ));
+        let info = decoder.read_info().unwrap().info().clone();
+        // The bins up to 250 Hz, its own included
+        assert_eq!(info.width as usize, AXIS_LEFT + 17);
+        assert_eq!(info.height as usize, TITLE_TOP + 5 + AXIS_BOTTOM);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    analysis::{amplitude_to_db, Band, BinMapping},
+    keys::Action,
+};
+
+/**
+ * An action triggered by whistling inside a band, written as "low-high=action" with the
+ * action names of the key bindings (e.g. "1500-2000=pause")
+ */
+#[derive(Clone, Debug)]
+pub struct WhistleCommand {
+    pub band: Band,
+    pub action: Action,
+}
+
+impl FromStr for WhistleCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (band, action) = s.split_once('=').ok_or_else(|| {
+            format!("Invalid whistle command \"{s}\", expected something like 1500-2000=pause")
+        })?;
+        Ok(Self {
+            band: band.parse()?,
+            action: action.parse()?,
+        })
+    }
+}
+
+/**
+ * Listens for whistles: a single tone standing way above the rest of the spectrum. A command
+ * fires once the tone stayed inside its band for the hold time, and can't fire again before the
+ * tone stops, so holding a whistle doesn't pause and resume over and over.
+ */
+pub struct WhistleDetector {
+    commands: Vec<WhistleCommand>,
+    hold: Duration,
+    // How far above the median of the spectrum the tone has to be
+    margin_db: f32,
+    // When the tone entered the band of each command, and whether that command fired since
+    started: Vec<Option<(Instant, bool)>>,
+}
+
+impl WhistleDetector {
+    pub fn new(commands: Vec<WhistleCommand>, hold: Duration, margin_db: f32) -> Self {
+        Self {
+            started: vec![None; commands.len()],
+            commands,
+            hold,
+            margin_db,
+        }
+    }
+
+    /**
+     * Gets the one-sided amplitudes of a new FFT result and returns the actions to run
+     */
+    pub fn process(&mut self, amplitudes: &[f32], mapping: BinMapping) -> Vec<Action> {
+        let tone_in_hz = whistle_frequency(amplitudes, mapping, self.margin_db);
+
+        let now = Instant::now();
+        let mut actions = vec![];
+        for (command, started) in self.commands.iter().zip(self.started.iter_mut()) {
+            let in_band = tone_in_hz.is_some_and(|hz| command.band.contains(hz));
+            if !in_band {
+                *started = None;
+                continue;
+            }
+            let (since, fired) = started.get_or_insert((now, false));
+            if !*fired && now.duration_since(*since) >= self.hold {
+                *fired = true;
+                actions.push(command.action);
+            }
+        }
+        actions
+    }
+}
+
+/**
+ * Returns the frequency of the loudest bin when it stands `margin_db` above the median level,
+ * which a whistle does and voices or music, with their harmonics and noise, rarely do
+ */
+fn whistle_frequency(amplitudes: &[f32], mapping: BinMapping, margin_db: f32) -> Option<f32> {
+    // The DC bin is skipped, it's just the offset of the signal
+    let (bin, loudest) = amplitudes
+        .iter()
+        .enumerate()
+        .skip(1)
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())?;
+
+    let mut levels_db: Vec<f32> = amplitudes.iter().map(|a| amplitude_to_db(*a)).collect();
+    levels_db.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median_db = levels_db[levels_db.len() / 2];
+
+    (amplitude_to_db(*loudest) >= median_db + margin_db).then(|| mapping.frequency_in_hz(bin))
+}
@@ -0,0 +1,291 @@
+use std::f32::consts::PI;
+
+#[cfg(feature = "app")]
+use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window};
+
+#[cfg(feature = "app")]
+use crate::{analysis::amplitude_to_db, text};
+use crate::{
+    analysis::{one_sided_amplitudes, BinMapping},
+    fft_magnitudes,
+};
+
+// Bottom of the level axis of the comparison, low enough for the sidelobes of Blackman-Harris
+#[cfg(feature = "app")]
+const FLOOR_DB: f32 = -140.0;
+
+/**
+ * Window functions the samples can be multiplied by before the FFT. Without one (rectangular),
+ * a frequency that doesn't fall exactly on a bin leaks into the whole spectrum.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "app", derive(clap::ValueEnum))]
+pub enum WindowFunction {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+    BlackmanHarris,
+    // Reads the level of a sine right wherever it falls between the bins, at the cost of a very
+    // wide peak
+    FlatTop,
+}
+
+impl WindowFunction {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rectangular => "Rectangular",
+            Self::Hann => "Hann",
+            Self::Hamming => "Hamming",
+            Self::Blackman => "Blackman",
+            Self::BlackmanHarris => "Blackman-Harris",
+            Self::FlatTop => "Flat top",
+        }
+    }
+
+    /**
+     * The window after this one, to go through all of them with a key
+     */
+    pub fn next(self) -> Self {
+        match self {
+            Self::Rectangular => Self::Hann,
+            Self::Hann => Self::Hamming,
+            Self::Hamming => Self::Blackman,
+            Self::Blackman => Self::BlackmanHarris,
+            Self::BlackmanHarris => Self::FlatTop,
+            Self::FlatTop => Self::Rectangular,
+        }
+    }
+
+    /**
+     * Level of the highest sidelobe relative to the peak, and width of the main lobe from null
+     * to null in bins, the usual figures of merit of a window
+     */
+    pub fn figures(self) -> (f32, f32) {
+        match self {
+            Self::Rectangular => (-13.3, 2.0),
+            Self::Hann => (-31.5, 4.0),
+            Self::Hamming => (-42.7, 4.0),
+            Self::Blackman => (-58.1, 6.0),
+            Self::BlackmanHarris => (-92.0, 8.0),
+            Self::FlatTop => (-93.0, 10.0),
+        }
+    }
+
+    /**
+     * Cosine sum coefficients of the window, a0 - a1 cos(x) + a2 cos(2x) - ...
+     */
+    fn cosine_terms(self) -> &'static [f32] {
+        match self {
+            Self::Rectangular => &[1.0],
+            Self::Hann => &[0.5, 0.5],
+            Self::Hamming => &[0.54, 0.46],
+            Self::Blackman => &[0.42, 0.5, 0.08],
+            Self::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            Self::FlatTop => &[0.21557895, 0.41663158, 0.27726316, 0.08357895, 0.006947368],
+        }
+    }
+
+    /**
+     * Returns the `len` points of the window. It's periodic, as used for spectral analysis, so
+     * the last point is not back to the first one.
+     */
+    pub fn coefficients(self, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let x = 2.0 * PI * i as f32 / len as f32;
+                self.cosine_terms()
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| match k % 2 {
+                        0 => a * (k as f32 * x).cos(),
+                        _ => -a * (k as f32 * x).cos(),
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+
+    /**
+     * Mean of the window over `len` points, the factor it scales the level of a sine by
+     */
+    pub fn coherent_gain(self, len: usize) -> f32 {
+        self.coefficients(len).iter().sum::<f32>() / len.max(1) as f32
+    }
+
+    /**
+     * Runs the FFT over the windowed samples and returns the magnitudes of the full output,
+     * corrected by the coherent gain of the window so a sine on a bin reads the same with any
+     * of them
+     */
+    pub fn magnitudes(self, samples: &[f32]) -> Vec<f32> {
+        let windowed: Vec<f32> = samples
+            .iter()
+            .zip(self.coefficients(samples.len()))
+            .map(|(sample, coefficient)| sample * coefficient)
+            .collect();
+        let coherent_gain = self.coherent_gain(samples.len());
+        fft_magnitudes(&windowed)
+            .into_iter()
+            .map(|magnitude| magnitude / coherent_gain)
+            .collect()
+    }
+
+    /**
+     * Same as `magnitudes`, as one-sided amplitudes
+     */
+    pub fn amplitudes(self, samples: &[f32]) -> Vec<f32> {
+        one_sided_amplitudes(&self.magnitudes(samples))
+    }
+}
+
+/**
+ * Draws a one-sided amplitude spectrum in dB from 0 Hz to `max_frequency_in_hz`, 0 dBFS at
+ * the top of the area and FLOOR_DB at the bottom, with lines every 20 dB. The loudest bin under
+ * each pixel is drawn so narrow peaks don't disappear.
+ */
+#[cfg(feature = "app")]
+pub fn draw_spectrum(
+    canvas: &mut Canvas<Window>,
+    area: Rect,
+    amplitudes: &[f32],
+    mapping: BinMapping,
+    max_frequency_in_hz: f32,
+    color: Color,
+) {
+    let y_of = |level_db: f32| {
+        area.y() + (level_db.clamp(FLOOR_DB, 0.0) / FLOOR_DB * area.height() as f32) as i32
+    };
+    for level_db in [-20.0, -40.0, -60.0, -80.0, -100.0, -120.0] {
+        canvas.set_draw_color(Color::RGB(45, 45, 45));
+        let y = y_of(level_db);
+        canvas.draw_line((area.x(), y), (area.right(), y)).unwrap();
+    }
+
+    let bins = mapping
+        .bins_up_to(max_frequency_in_hz)
+        .min(amplitudes.len())
+        .max(1);
+    let points: Vec<(i32, i32)> = (0..area.width() as usize)
+        .filter_map(|x| {
+            let first = x * bins / area.width() as usize;
+            let last = ((x + 1) * bins / area.width() as usize).max(first + 1);
+            let loudest = amplitudes
+                .get(first..last.min(amplitudes.len()))?
+                .iter()
+                .fold(0f32, |loudest, amplitude| loudest.max(*amplitude));
+            Some((area.x() + x as i32, y_of(amplitude_to_db(loudest))))
+        })
+        .collect();
+    canvas.set_draw_color(color);
+    for pair in points.windows(2) {
+        canvas.draw_line(pair[0], pair[1]).unwrap();
+    }
+}
+
+/**
+ * The same frame analyzed through two windows, drawn one above the other in dB so the leakage
+ * of each one can be compared
+ */
+pub struct WindowComparison {
+    windows: [WindowFunction; 2],
+    spectra: [Vec<f32>; 2],
+    mapping: Option<BinMapping>,
+}
+
+impl WindowComparison {
+    pub fn new(windows: [WindowFunction; 2]) -> Self {
+        Self {
+            windows,
+            spectra: [vec![], vec![]],
+            mapping: None,
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32], sample_rate: u32) {
+        self.spectra = self.windows.map(|window| window.amplitudes(samples));
+        self.mapping = Some(BinMapping::new(sample_rate, samples.len()));
+    }
+
+    /**
+     * Draws each spectrum from 0 Hz to `max_frequency_in_hz` in half of the area
+     */
+    #[cfg(feature = "app")]
+    pub fn draw(&self, canvas: &mut Canvas<Window>, area: Rect, max_frequency_in_hz: f32) {
+        canvas.set_draw_color(Color::RGB(25, 25, 25));
+        canvas.fill_rect(area).unwrap();
+        let Some(mapping) = self.mapping else {
+            return;
+        };
+
+        let half_height = area.height() / 2;
+        for (index, (window, spectrum)) in self.windows.iter().zip(&self.spectra).enumerate() {
+            let top = area.y() + (half_height * index as u32) as i32;
+            draw_spectrum(
+                canvas,
+                Rect::new(area.x(), top, area.width(), half_height),
+                spectrum,
+                mapping,
+                max_frequency_in_hz,
+                match index {
+                    0 => Color::RGB(6, 214, 160),
+                    _ => Color::RGB(255, 170, 60),
+                },
+            );
+
+            let (sidelobe_db, main_lobe_bins) = window.figures();
+            text::draw_text(
+                canvas,
+                &format!(
+                    "{}: highest sidelobe {sidelobe_db} dB, main lobe {main_lobe_bins} bins wide",
+                    window.name()
+                ),
+                area.x() + 10,
+                top + 6,
+                1,
+                Color::RGB(230, 230, 230),
+            );
+        }
+        canvas.set_draw_color(Color::RGB(90, 90, 90));
+        let middle = area.y() + half_height as i32;
+        canvas
+            .draw_line((area.x(), middle), (area.right(), middle))
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::amplitude_to_db;
+
+    fn sine(bins: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| 0.5 * (2.0 * PI * bins * i as f32 / len as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn every_window_reads_a_sine_on_a_bin_right() {
+        // next() goes through all of them before coming back
+        let windows = std::iter::successors(Some(WindowFunction::Rectangular), |window| {
+            Some(window.next()).filter(|next| *next != WindowFunction::Rectangular)
+        });
+        for window in windows {
+            let amplitudes = window.amplitudes(&sine(100.0, 4096));
+            assert!((amplitudes[100] - 0.5).abs() < 1e-3, "{window:?}");
+        }
+    }
+
+    #[test]
+    fn hann_leaks_less_than_rectangular() {
+        // Halfway between two bins, the worst case for leakage
+        let samples = sine(100.5, 4096);
+        let leak_db = |window: WindowFunction| amplitude_to_db(window.amplitudes(&samples)[130]);
+        assert!(leak_db(WindowFunction::Rectangular) > -50.0);
+        assert!(leak_db(WindowFunction::Hann) < -80.0);
+        // The flat top reads the level between the bins too
+        let flat_top = WindowFunction::FlatTop.amplitudes(&samples);
+        assert!((flat_top[100].max(flat_top[101]) - 0.5).abs() < 0.01);
+    }
+}
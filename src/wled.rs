@@ -0,0 +1,230 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::{
+    analysis::{group_bins, magnitude_to_db, BinMapping, FftFrame, FrequencyScale},
+    image::{amplitude_color, spectrogram_color},
+};
+
+pub const WLED_PORT: u16 = 21324;
+
+/*
+ * WLED's realtime UDP protocol: the first byte is the protocol and the second one is how many
+ * seconds WLED waits without packets before going back to its own effects.
+ * DRGB (2) is followed by the RGB of each LED, up to 490 of them.
+ * DNRGB (4) is followed by the index of the first LED (u16, big endian) and then the RGB of
+ * up to 489 LEDs, so longer strips are sent in chunks.
+ */
+const DRGB: u8 = 2;
+const DNRGB: u8 = 4;
+const DRGB_MAX_LEDS: usize = 490;
+const DNRGB_MAX_LEDS: usize = 489;
+const TIMEOUT_SECS: u8 = 2;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "app", derive(clap::ValueEnum))]
+pub enum Colormap {
+    /// The same blue to red colors of the bars on the screen
+    Amplitude,
+    /// The colors of the offline spectrogram
+    Spectrogram,
+    /// Each LED gets a hue by its position on the strip
+    Rainbow,
+}
+
+fn hue_to_rgb(hue: f32) -> (u8, u8, u8) {
+    let h = (hue.fract() * 6.0).max(0.0);
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u8 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    ((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+}
+
+/**
+ * Gets the amplitude of a LED relative to the loudest one (0 to 100) and its position on the
+ * strip (0 to 1) and returns its color. Since a LED has no height, the amplitude also dims it.
+ */
+pub fn led_color(colormap: Colormap, amplitude_percentage: u8, position: f32) -> (u8, u8, u8) {
+    let brightness = amplitude_percentage as f32 / 100.0;
+    let (r, g, b) = match colormap {
+        Colormap::Amplitude => amplitude_color(amplitude_percentage),
+        Colormap::Spectrogram => {
+            let [r, g, b] = spectrogram_color(brightness);
+            (r, g, b)
+        }
+        Colormap::Rainbow => hue_to_rgb(position * 0.8),
+    };
+    let dim = |c: u8| (c as f32 * brightness).round() as u8;
+    (dim(r), dim(g), dim(b))
+}
+
+pub fn encode_packets(colors: &[(u8, u8, u8)]) -> Vec<Vec<u8>> {
+    if colors.len() <= DRGB_MAX_LEDS {
+        let mut packet = vec![DRGB, TIMEOUT_SECS];
+        packet.extend(colors.iter().flat_map(|(r, g, b)| [*r, *g, *b]));
+        return vec![packet];
+    }
+
+    colors
+        .chunks(DNRGB_MAX_LEDS)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let start = (i * DNRGB_MAX_LEDS) as u16;
+            let mut packet = vec![DNRGB, TIMEOUT_SECS];
+            packet.extend_from_slice(&start.to_be_bytes());
+            packet.extend(chunk.iter().flat_map(|(r, g, b)| [*r, *g, *b]));
+            packet
+        })
+        .collect()
+}
+
+pub struct WledConfig {
+    pub target: SocketAddr,
+    pub leds: usize,
+    pub scale: FrequencyScale,
+    pub colormap: Colormap,
+    pub min_hz: f32,
+    pub max_hz: f32,
+    pub frames_per_second: u32,
+    pub floor_db: f32,
+}
+
+/**
+ * Spawns a thread that renders the latest spectrum on the strip `frames_per_second` times per
+ * second. The LEDs are normalized by the loudest one, just like the bars on the screen, and
+ * the strip goes dark when even the loudest one is below `floor_db`.
+ */
+pub fn spawn(
+    config: WledConfig,
+    fft_transform: Arc<Mutex<FftFrame>>,
+    sample_rate: u32,
+) -> std::io::Result<JoinHandle<()>> {
+    let bind_address: SocketAddr = if config.target.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+    let socket = UdpSocket::bind(bind_address)?;
+    let interval = Duration::from_secs_f32(1.0 / config.frames_per_second.max(1) as f32);
+
+    Ok(std::thread::spawn(move || loop {
+        let magnitudes = fft_transform.lock().unwrap().magnitudes.clone();
+        let fft_len = magnitudes.len();
+        let mut groups: Vec<f32> = group_bins(
+            &magnitudes,
+            BinMapping::new(sample_rate, fft_len),
+            config.leds,
+            config.min_hz,
+            config.max_hz,
+            config.scale,
+        )
+        .iter()
+        .map(|group| magnitudes[group.loudest_bin])
+        .collect();
+        // The strip stays dark until there is a spectrum
+        groups.resize(config.leds, 0.0);
+
+        let highest = groups.iter().cloned().fold(0.0, f32::max);
+        let audible = fft_len > 0 && magnitude_to_db(highest, fft_len) > config.floor_db;
+
+        let colors: Vec<(u8, u8, u8)> = groups
+            .iter()
+            .enumerate()
+            .map(|(i, magnitude)| {
+                if !audible {
+                    return (0, 0, 0);
+                }
+                let amplitude_percentage = (magnitude / highest * 100.0).round() as u8;
+                led_color(
+                    config.colormap,
+                    amplitude_percentage,
+                    i as f32 / config.leds as f32,
+                )
+            })
+            .collect();
+
+        for packet in encode_packets(&colors) {
+            if let Err(error) = socket.send_to(&packet, config.target) {
+                eprintln!("Could not send to WLED: {error}");
+            }
+        }
+
+        std::thread::sleep(interval);
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_strips_get_one_drgb_packet() {
+        assert_eq!(
+            encode_packets(&[(1, 2, 3), (4, 5, 6)]),
+            [vec![DRGB, TIMEOUT_SECS, 1, 2, 3, 4, 5, 6]]
+        );
+        let packets = encode_packets(&[(9, 9, 9); DRGB_MAX_LEDS]);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].len(), 2 + 3 * DRGB_MAX_LEDS);
+    }
+
+    #[test]
+    fn long_strips_are_split_in_dnrgb_packets() {
+        let colors: Vec<_> = (0..1000).map(|i| (i as u8, 0, 0)).collect();
+        let packets = encode_packets(&colors);
+        assert_eq!(packets.len(), 3);
+        // Protocol, timeout and the index of the first LED, big endian
+        assert_eq!(packets[0][..5], [DNRGB, TIMEOUT_SECS, 0x00, 0x00, 0]);
+        assert_eq!(packets[0].len(), 4 + 3 * 489);
+        assert_eq!(
+            packets[1][..5],
+            [DNRGB, TIMEOUT_SECS, 0x01, 0xE9, (489 % 256) as u8]
+        );
+        assert_eq!(packets[1].len(), 4 + 3 * 489);
+        assert_eq!(
+            packets[2][..5],
+            [DNRGB, TIMEOUT_SECS, 0x03, 0xD2, (978 % 256) as u8]
+        );
+        assert_eq!(packets[2].len(), 4 + 3 * 22);
+        assert_eq!(packets[2][4 + 3 * 21..], [(999 % 256) as u8, 0, 0]);
+    }
+
+    #[test]
+    fn the_strip_can_be_an_ipv6_node() {
+        let node = UdpSocket::bind("[::1]:0").unwrap();
+        node.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        spawn(
+            WledConfig {
+                target: node.local_addr().unwrap(),
+                leds: 3,
+                scale: FrequencyScale::Linear,
+                colormap: Colormap::Amplitude,
+                min_hz: 20.0,
+                max_hz: 4000.0,
+                frames_per_second: 30,
+                floor_db: -60.0,
+            },
+            Arc::new(Mutex::new(FftFrame::default())),
+            8000,
+        )
+        .unwrap();
+
+        let mut packet = [0; 64];
+        let len = node.recv(&mut packet).unwrap();
+        // No spectrum yet, so the strip is dark
+        assert_eq!(
+            packet[..len],
+            [DRGB, TIMEOUT_SECS, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+    }
+}